@@ -0,0 +1,3 @@
+ALTER TABLE payments
+    DROP COLUMN net_amount,
+    DROP COLUMN fee_amount;
@@ -0,0 +1,5 @@
+ALTER TABLE payments
+    ADD COLUMN fee_amount integer NOT NULL DEFAULT 0,
+    ADD COLUMN net_amount integer NOT NULL DEFAULT 0;
+
+UPDATE payments SET net_amount = amount;
@@ -0,0 +1,3 @@
+ALTER TABLE payments
+    DROP CONSTRAINT payments_refunded_amount_check,
+    DROP CONSTRAINT payments_amount_check;
@@ -0,0 +1,3 @@
+ALTER TABLE payments
+    ADD CONSTRAINT payments_amount_check CHECK (amount > 0),
+    ADD CONSTRAINT payments_refunded_amount_check CHECK (refunded_amount >= 0 AND refunded_amount <= amount);
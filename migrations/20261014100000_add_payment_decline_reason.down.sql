@@ -0,0 +1,5 @@
+-- retried cards may collide: the index can't be restored as it was
+DROP INDEX payments_card_number_index;
+CREATE INDEX payments_card_number_index ON payments(card_number text_ops);
+
+ALTER TABLE payments DROP COLUMN decline_reason;
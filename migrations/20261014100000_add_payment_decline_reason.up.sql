@@ -0,0 +1,5 @@
+ALTER TABLE payments ADD COLUMN decline_reason character varying(255);
+
+-- declined and failed payments are persisted too, and mustn't keep their card from being retried
+DROP INDEX payments_card_number_index;
+CREATE UNIQUE INDEX payments_card_number_index ON payments(card_number text_ops) WHERE status NOT IN ('Declined', 'Failed');
@@ -0,0 +1,4 @@
+DROP INDEX webhook_deliveries_pending_index;
+DROP TABLE webhook_deliveries;
+
+DROP TYPE WebhookDeliveryStatus
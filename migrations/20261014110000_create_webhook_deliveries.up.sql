@@ -0,0 +1,15 @@
+CREATE TYPE WebhookDeliveryStatus AS ENUM ('Pending', 'Delivered', 'Failed');
+
+CREATE TABLE webhook_deliveries (
+    id uuid PRIMARY KEY,
+    url character varying(2048) NOT NULL,
+    payload jsonb NOT NULL,
+    status WebhookDeliveryStatus NOT NULL,
+    attempts integer NOT NULL DEFAULT 0,
+    last_error text,
+    next_attempt_at timestamp(0) without time zone NOT NULL,
+    inserted_at timestamp(0) without time zone NOT NULL,
+    updated_at timestamp(0) without time zone NOT NULL
+);
+
+CREATE INDEX webhook_deliveries_pending_index ON webhook_deliveries(next_attempt_at) WHERE status = 'Pending';
@@ -0,0 +1,6 @@
+ALTER TABLE payments
+    DROP CONSTRAINT payments_hold_amount_check,
+    DROP COLUMN hold_id,
+    DROP COLUMN hold_amount;
+
+-- postgres can't drop enum values: 'Authorized' and 'Capturing' are left in the Status type
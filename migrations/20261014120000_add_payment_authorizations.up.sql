@@ -0,0 +1,11 @@
+ALTER TYPE Status ADD VALUE 'Authorized';
+ALTER TYPE Status ADD VALUE 'Capturing';
+
+ALTER TABLE payments
+    ADD COLUMN hold_amount integer NOT NULL DEFAULT 0,
+    ADD COLUMN hold_id uuid;
+
+UPDATE payments SET hold_amount = amount;
+
+ALTER TABLE payments
+    ADD CONSTRAINT payments_hold_amount_check CHECK (hold_amount >= amount);
@@ -0,0 +1 @@
+ALTER TABLE payments ADD COLUMN metadata jsonb NOT NULL DEFAULT '{}';
@@ -0,0 +1,4 @@
+ALTER TABLE refunds DROP COLUMN destination_card_number;
+ALTER TABLE refunds DROP COLUMN status;
+
+DROP TYPE RefundStatus;
@@ -0,0 +1,4 @@
+CREATE TYPE RefundStatus AS ENUM ('Pending', 'Approved', 'Rejected');
+
+ALTER TABLE refunds ADD COLUMN status RefundStatus NOT NULL DEFAULT 'Approved';
+ALTER TABLE refunds ADD COLUMN destination_card_number character varying(255);
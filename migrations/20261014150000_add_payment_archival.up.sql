@@ -0,0 +1,5 @@
+ALTER TABLE payments ADD COLUMN archived_at timestamp(0) without time zone;
+
+-- archived card numbers are masked down to their last digits, so they are no longer unique
+DROP INDEX payments_card_number_index;
+CREATE UNIQUE INDEX payments_card_number_index ON payments(card_number text_ops) WHERE archived_at IS NULL AND status NOT IN ('Declined', 'Failed');
@@ -0,0 +1,4 @@
+DROP TRIGGER payments_record_event ON payments;
+DROP FUNCTION record_payment_event();
+DROP TABLE payment_events;
+DROP TYPE PaymentEventKind;
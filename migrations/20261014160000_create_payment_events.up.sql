@@ -0,0 +1,43 @@
+CREATE TYPE PaymentEventKind AS ENUM ('Created', 'StatusChanged', 'Refunded', 'Archived');
+
+CREATE TABLE payment_events (
+    id bigserial PRIMARY KEY,
+    payment_id uuid REFERENCES payments(id) NOT NULL,
+    kind PaymentEventKind NOT NULL,
+    status Status NOT NULL,
+    amount integer NOT NULL,
+    reason character varying(255),
+    inserted_at timestamp(0) without time zone NOT NULL
+);
+
+CREATE INDEX payment_events_payment_id_index ON payment_events(payment_id, id);
+
+-- Events are recorded by a trigger so that no write to payments can skip them. They are
+-- timestamped with the payment's own timestamps, which the application sets from its clock.
+CREATE FUNCTION record_payment_event() RETURNS trigger AS $$
+BEGIN
+    IF TG_OP = 'INSERT' THEN
+        INSERT INTO payment_events ( payment_id, kind, status, amount, reason, inserted_at )
+        VALUES ( NEW.id, 'Created', NEW.status, NEW.amount, NEW.decline_reason, NEW.inserted_at );
+        RETURN NULL;
+    END IF;
+
+    IF NEW.status IS DISTINCT FROM OLD.status THEN
+        INSERT INTO payment_events ( payment_id, kind, status, amount, reason, inserted_at )
+        VALUES ( NEW.id, 'StatusChanged', NEW.status, NEW.amount, NEW.decline_reason, NEW.updated_at );
+    END IF;
+    IF NEW.refunded_amount <> OLD.refunded_amount THEN
+        INSERT INTO payment_events ( payment_id, kind, status, amount, reason, inserted_at )
+        VALUES ( NEW.id, 'Refunded', NEW.status, NEW.refunded_amount - OLD.refunded_amount, NULL, NEW.updated_at );
+    END IF;
+    IF NEW.archived_at IS NOT NULL AND OLD.archived_at IS NULL THEN
+        INSERT INTO payment_events ( payment_id, kind, status, amount, reason, inserted_at )
+        VALUES ( NEW.id, 'Archived', NEW.status, NEW.amount, NULL, NEW.archived_at );
+    END IF;
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER payments_record_event
+    AFTER INSERT OR UPDATE ON payments
+    FOR EACH ROW EXECUTE FUNCTION record_payment_event();
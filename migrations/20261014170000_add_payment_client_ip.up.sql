@@ -0,0 +1,2 @@
+-- textual IPv4 or IPv6 address, of at most 45 characters
+ALTER TABLE payments ADD COLUMN client_ip character varying(45);
@@ -0,0 +1,2 @@
+-- ISO 4217 code, NULL for payments made without a currency
+ALTER TABLE payments ADD COLUMN currency character(3);
@@ -0,0 +1,2 @@
+DROP INDEX refunds_payment_id_idempotency_key_index;
+ALTER TABLE refunds DROP COLUMN idempotency_key;
@@ -0,0 +1,3 @@
+ALTER TABLE refunds ADD COLUMN idempotency_key character varying(255);
+-- refunds without a key are never considered duplicates, as NULLs are distinct
+CREATE UNIQUE INDEX refunds_payment_id_idempotency_key_index ON refunds(payment_id uuid_ops, idempotency_key text_ops);
@@ -0,0 +1,3 @@
+-- the currency of the refunded payment, which refunds can't override
+ALTER TABLE refunds ADD COLUMN currency character(3);
+UPDATE refunds SET currency = payments.currency FROM payments WHERE payments.id = refunds.payment_id;
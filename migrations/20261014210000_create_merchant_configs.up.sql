@@ -0,0 +1,7 @@
+CREATE TABLE merchant_configs (
+    merchant_id uuid PRIMARY KEY,
+    webhook_url character varying(2048) NOT NULL,
+    webhook_secret character varying(255) NOT NULL,
+    inserted_at timestamp(0) without time zone NOT NULL,
+    updated_at timestamp(0) without time zone NOT NULL
+);
@@ -0,0 +1,3 @@
+-- the merchant's identifier of the customer, for grouping their payments
+ALTER TABLE payments ADD COLUMN customer_id character varying(255);
+CREATE INDEX payments_customer_id_index ON payments(customer_id text_ops);
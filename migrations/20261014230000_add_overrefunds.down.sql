@@ -0,0 +1,2 @@
+ALTER TABLE refunds DROP COLUMN allow_overrefund;
+ALTER TABLE payments DROP COLUMN overrefunded_amount;
@@ -0,0 +1,5 @@
+-- goodwill refunds credit more than the payment's amount: the excess isn't counted in
+-- refunded_amount, which stays within the amount
+ALTER TABLE payments
+    ADD COLUMN overrefunded_amount integer NOT NULL DEFAULT 0 CHECK (overrefunded_amount >= 0);
+ALTER TABLE refunds ADD COLUMN allow_overrefund boolean NOT NULL DEFAULT false;
@@ -0,0 +1,3 @@
+DROP INDEX payments_card_number_index;
+CREATE UNIQUE INDEX payments_card_number_index ON payments(card_number text_ops) WHERE archived_at IS NULL AND status NOT IN ('Declined', 'Failed');
+ALTER TABLE payments DROP COLUMN card_fingerprint;
@@ -0,0 +1,7 @@
+-- hex-encoded HMAC-SHA256 of the normalized card number, which detects duplicated cards in
+-- place of the card number itself. Existing payments are fingerprinted on startup.
+ALTER TABLE payments ADD COLUMN card_fingerprint character(64);
+CREATE UNIQUE INDEX payments_card_fingerprint_index ON payments(card_fingerprint) WHERE archived_at IS NULL AND status NOT IN ('Declined', 'Failed');
+-- still used to look cards up
+DROP INDEX payments_card_number_index;
+CREATE INDEX payments_card_number_index ON payments(card_number text_ops);
@@ -0,0 +1,5 @@
+-- the merchant a payment was made to, and the merchant's own reference of it (e.g. order
+-- number), which identifies at most one of its unarchived payments
+ALTER TABLE payments ADD COLUMN merchant_id uuid;
+ALTER TABLE payments ADD COLUMN merchant_reference character varying(255);
+CREATE UNIQUE INDEX payments_merchant_reference_index ON payments(merchant_id, merchant_reference) WHERE archived_at IS NULL;
@@ -0,0 +1,2 @@
+-- when a payment created processing in sandbox mode was settled
+ALTER TABLE payments ADD COLUMN settled_at timestamp without time zone;
@@ -0,0 +1,5 @@
+CREATE TABLE card_tokens (
+    token character varying(64) PRIMARY KEY,
+    card_number character varying(255) NOT NULL UNIQUE,
+    inserted_at timestamp(0) without time zone NOT NULL
+);
@@ -0,0 +1,3 @@
+-- whether the refund was made by refunding whatever remained, so that repeating it returns
+-- the same refund
+ALTER TABLE refunds ADD COLUMN full_refund boolean NOT NULL DEFAULT false;
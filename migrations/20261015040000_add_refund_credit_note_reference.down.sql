@@ -0,0 +1 @@
+ALTER TABLE refunds DROP COLUMN credit_note_reference;
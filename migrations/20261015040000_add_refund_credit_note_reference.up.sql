@@ -0,0 +1,3 @@
+-- reference of the credit note the merchant's accounting issued for the refund
+ALTER TABLE refunds ADD COLUMN credit_note_reference character varying(255);
+CREATE INDEX refunds_credit_note_reference_index ON refunds(credit_note_reference);
@@ -0,0 +1,2 @@
+-- when the authorization was captured, telling captured payments from direct ones
+ALTER TABLE payments ADD COLUMN captured_at timestamp(0);
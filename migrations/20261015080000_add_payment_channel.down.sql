@@ -0,0 +1,3 @@
+DROP INDEX payments_channel_index;
+ALTER TABLE payments DROP COLUMN channel;
+DROP TYPE Channel;
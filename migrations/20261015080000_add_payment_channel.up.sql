@@ -0,0 +1,4 @@
+-- where the payment was made from, if the merchant told
+CREATE TYPE Channel AS ENUM ('Web', 'Mobile', 'Pos', 'Api');
+ALTER TABLE payments ADD COLUMN channel Channel;
+CREATE INDEX payments_channel_index ON payments(customer_id, channel);
@@ -0,0 +1,8 @@
+-- the items of an itemized order a refund is made for, summing to its amount
+CREATE TABLE refund_line_items (
+    refund_id uuid REFERENCES refunds(id) ON DELETE CASCADE NOT NULL,
+    position integer NOT NULL,
+    sku character varying(255) NOT NULL,
+    amount integer NOT NULL,
+    PRIMARY KEY (refund_id, position)
+);
@@ -0,0 +1,2 @@
+-- the fees, currency and limits the payment was created under, for disputes
+ALTER TABLE payments ADD COLUMN config_snapshot jsonb;
@@ -0,0 +1,8 @@
+-- how the amount of a refund made in another currency than its source was converted
+CREATE TABLE refund_conversions (
+    refund_id uuid PRIMARY KEY REFERENCES refunds(id) ON DELETE CASCADE,
+    exchange_rate numeric NOT NULL CHECK (exchange_rate > 0),
+    source_amount integer NOT NULL,
+    source_currency character(3) NOT NULL,
+    converted_at timestamp(0) without time zone NOT NULL
+);
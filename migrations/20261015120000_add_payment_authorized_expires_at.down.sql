@@ -0,0 +1,2 @@
+DROP INDEX payments_authorized_expires_at_index;
+ALTER TABLE payments DROP COLUMN authorized_expires_at;
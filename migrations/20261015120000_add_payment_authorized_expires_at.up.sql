@@ -0,0 +1,3 @@
+-- when the hold of an authorization lapses unless it's captured or voided first
+ALTER TABLE payments ADD COLUMN authorized_expires_at timestamp(0) without time zone;
+CREATE INDEX payments_authorized_expires_at_index ON payments(authorized_expires_at) WHERE status = 'Authorized';
@@ -0,0 +1,2 @@
+-- the idempotency key of the capture request, so that retrying it doesn't capture twice
+ALTER TABLE payments ADD COLUMN capture_idempotency_key varchar(255);
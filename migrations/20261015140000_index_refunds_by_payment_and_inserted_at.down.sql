@@ -0,0 +1,2 @@
+DROP INDEX refunds_payment_id_inserted_at_index;
+CREATE INDEX refunds_payment_id_index ON refunds(payment_id uuid_ops);
@@ -0,0 +1,3 @@
+-- refunds of a payment are listed oldest first, which the payment id index alone can't order
+DROP INDEX refunds_payment_id_index;
+CREATE INDEX refunds_payment_id_inserted_at_index ON refunds(payment_id uuid_ops, inserted_at, id);
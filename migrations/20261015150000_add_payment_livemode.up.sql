@@ -0,0 +1,3 @@
+-- whether the payment was made live rather than in sandbox mode, to keep test payments out
+-- of reports
+ALTER TABLE payments ADD COLUMN livemode boolean NOT NULL DEFAULT true;
@@ -0,0 +1 @@
+DROP INDEX payments_hold_id_index;
@@ -0,0 +1,2 @@
+-- holds confirmed asynchronously by the account service identify their payment by hold id
+CREATE INDEX payments_hold_id_index ON payments(hold_id uuid_ops) WHERE hold_id IS NOT NULL;
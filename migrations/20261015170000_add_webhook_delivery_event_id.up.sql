@@ -0,0 +1,5 @@
+-- the event a delivery is about, shared by every delivery of the event so that receivers can
+-- ignore duplicates: deliveries made so far were each about their own event
+ALTER TABLE webhook_deliveries ADD COLUMN event_id uuid;
+UPDATE webhook_deliveries SET event_id = id;
+ALTER TABLE webhook_deliveries ALTER COLUMN event_id SET NOT NULL;
@@ -0,0 +1,4 @@
+DROP INDEX refunds_execute_at_index;
+ALTER TABLE refunds DROP COLUMN execute_at;
+
+-- postgres can't drop enum values: 'Scheduled' is left in the RefundStatus type
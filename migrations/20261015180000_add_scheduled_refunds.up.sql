@@ -0,0 +1,6 @@
+ALTER TYPE RefundStatus ADD VALUE 'Scheduled';
+
+-- when a scheduled refund is due, NULL for refunds made right away
+ALTER TABLE refunds ADD COLUMN execute_at timestamp(0) without time zone;
+-- the new enum value can't be used in this transaction, so the index isn't partial on it
+CREATE INDEX refunds_execute_at_index ON refunds(execute_at) WHERE execute_at IS NOT NULL;
@@ -1,4 +1,26 @@
+pub mod accepting_window;
 pub mod accounts;
+pub mod amount_bounds;
+pub mod circuit_breaker;
+pub mod clock;
+pub mod concurrency_limiter;
+pub mod config_snapshots;
+pub mod currencies;
+pub mod fees;
+pub mod fingerprints;
+pub mod merchants;
+pub mod money;
+pub mod payment_events;
 pub mod payment_instruments;
+pub mod payment_queue;
 pub mod payments;
 pub mod refunds;
+pub mod repository;
+pub mod retry_budget;
+pub mod sandbox;
+pub mod schema;
+pub mod settlement;
+pub mod tokens;
+pub mod vault;
+pub mod velocity;
+pub mod webhooks;
@@ -0,0 +1,141 @@
+use std::str::FromStr;
+
+use time::{OffsetDateTime, Time, UtcOffset, Weekday};
+
+/// Days and hours during which a merchant accepts payments.
+///
+/// Hours are local to `offset`, and the window is open from `opens_at` included to
+/// `closes_at` excluded on each of `days`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AcceptingWindow {
+    pub days: Vec<Weekday>,
+    pub opens_at: Time,
+    pub closes_at: Time,
+    pub offset: UtcOffset,
+}
+
+impl AcceptingWindow {
+    /// Returns whether payments are accepted at `datetime`.
+    pub fn contains(&self, datetime: OffsetDateTime) -> bool {
+        let datetime = datetime.to_offset(self.offset);
+        self.days.contains(&datetime.weekday())
+            && self.opens_at <= datetime.time()
+            && datetime.time() < self.closes_at
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidAcceptingWindow;
+
+/// Parses a window such as `mon,tue,wed,thu,fri 09:00-17:00 +01:00`.
+impl FromStr for AcceptingWindow {
+    type Err = InvalidAcceptingWindow;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split_whitespace();
+        let (Some(days), Some(hours), Some(offset), None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(InvalidAcceptingWindow);
+        };
+        let days = days
+            .split(',')
+            .map(parse_weekday)
+            .collect::<Result<_, _>>()?;
+        let (opens_at, closes_at) = hours.split_once('-').ok_or(InvalidAcceptingWindow)?;
+
+        Ok(Self {
+            days,
+            opens_at: parse_time(opens_at)?,
+            closes_at: parse_time(closes_at)?,
+            offset: parse_offset(offset)?,
+        })
+    }
+}
+
+fn parse_weekday(s: &str) -> Result<Weekday, InvalidAcceptingWindow> {
+    match s.to_ascii_lowercase().as_str() {
+        "mon" => Ok(Weekday::Monday),
+        "tue" => Ok(Weekday::Tuesday),
+        "wed" => Ok(Weekday::Wednesday),
+        "thu" => Ok(Weekday::Thursday),
+        "fri" => Ok(Weekday::Friday),
+        "sat" => Ok(Weekday::Saturday),
+        "sun" => Ok(Weekday::Sunday),
+        _ => Err(InvalidAcceptingWindow),
+    }
+}
+
+/// Parses `HH:MM` into its hours and minutes.
+fn parse_hours_minutes(s: &str) -> Result<(u8, u8), InvalidAcceptingWindow> {
+    let (hours, minutes) = s.split_once(':').ok_or(InvalidAcceptingWindow)?;
+    Ok((
+        hours.parse().map_err(|_| InvalidAcceptingWindow)?,
+        minutes.parse().map_err(|_| InvalidAcceptingWindow)?,
+    ))
+}
+
+fn parse_time(s: &str) -> Result<Time, InvalidAcceptingWindow> {
+    let (hours, minutes) = parse_hours_minutes(s)?;
+    Time::from_hms(hours, minutes, 0).map_err(|_| InvalidAcceptingWindow)
+}
+
+fn parse_offset(s: &str) -> Result<UtcOffset, InvalidAcceptingWindow> {
+    let (sign, s) = if let Some(s) = s.strip_prefix('+') {
+        (1, s)
+    } else if let Some(s) = s.strip_prefix('-') {
+        (-1, s)
+    } else {
+        return Err(InvalidAcceptingWindow);
+    };
+    let (hours, minutes) = parse_hours_minutes(s)?;
+    let hours = i8::try_from(hours).map_err(|_| InvalidAcceptingWindow)?;
+    let minutes = i8::try_from(minutes).map_err(|_| InvalidAcceptingWindow)?;
+    UtcOffset::from_hms(sign * hours, sign * minutes, 0).map_err(|_| InvalidAcceptingWindow)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contains() {
+        let window: AcceptingWindow = "mon,tue,wed,thu,fri 09:00-17:00 +01:00".parse().unwrap();
+        // 2000-01-03 is a Monday
+        let monday = OffsetDateTime::from_unix_timestamp(946_857_600).unwrap();
+
+        assert!(!window.contains(monday + time::Duration::hours(7)));
+        assert!(window.contains(monday + time::Duration::hours(8)));
+        assert!(window.contains(monday + time::Duration::minutes(15 * 60 + 59)));
+        assert!(!window.contains(monday + time::Duration::hours(16)));
+        assert!(!window.contains(monday - time::Duration::hours(12)));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "sat,SUN 22:30-23:45 -05:30".parse(),
+            Ok(AcceptingWindow {
+                days: vec![Weekday::Saturday, Weekday::Sunday],
+                opens_at: Time::from_hms(22, 30, 0).unwrap(),
+                closes_at: Time::from_hms(23, 45, 0).unwrap(),
+                offset: UtcOffset::from_hms(-5, -30, 0).unwrap(),
+            })
+        );
+        for invalid in [
+            "",
+            "mon 09:00-17:00",
+            "mon 09:00-17:00 +01:00 extra",
+            "monday 09:00-17:00 +01:00",
+            "mon 09:00 +01:00",
+            "mon 25:00-26:00 +01:00",
+            "mon 09:00-17:00 01:00",
+        ] {
+            assert_eq!(
+                invalid.parse::<AcceptingWindow>(),
+                Err(InvalidAcceptingWindow),
+                "{invalid:?}"
+            );
+        }
+    }
+}
@@ -1,3 +1,13 @@
+#[cfg(test)]
+use std::collections::HashMap;
+#[cfg(test)]
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+#[cfg(test)]
+use std::sync::Mutex;
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use uuid::Uuid;
 
 /// Represents a hold on a bank customer's funds within their account.
@@ -9,27 +19,55 @@ use uuid::Uuid;
 /// reference contains this information.
 #[derive(Debug, Clone, Copy)]
 pub struct HoldRef {
-    #[allow(dead_code)]
     id: Uuid,
 }
 
+impl HoldRef {
+    /// Returns the identifier under which the hold can be persisted, to be restored
+    /// with `HoldRef::from` when the hold is later withdrawn or released.
+    pub fn id(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl From<Uuid> for HoldRef {
+    fn from(id: Uuid) -> Self {
+        Self { id }
+    }
+}
+
 /// Client to interact with a remote service that manages customer accounts.
+///
+/// The trait is object-safe, so that the backend can be chosen at runtime through a
+/// `DynAccountService`.
 #[async_trait::async_trait]
-pub trait AccountService: Clone + Send + Sync + 'static {
+pub trait AccountService: Send + Sync + 'static {
+    /// Returns the `account_number` account's available balance, if the service exposes it.
+    ///
+    /// This allows rejecting payments that obviously exceed the balance without placing
+    /// a hold. Services that can't tell the balance return `None`, which is the default.
+    async fn query_balance(&self, account_number: &str) -> Option<i32> {
+        let _ = account_number;
+        None
+    }
+
+    /// Checks that the service is reachable, for readiness probes.
+    ///
+    /// This should be cheap: it is called on every probe. The default assumes the service
+    /// is always reachable.
+    async fn ping(&self) -> Result<(), String> {
+        Ok(())
+    }
+
     /// Places a hold on the account.
     ///
     /// Reduces the `account_number` account's actual balance by `amount`.
     ///
     /// Placing a hold does NOT remove or transfer money from the account, it
-    /// merely prevents the money from being otherwise spent until either
-    ///
-    /// * the money is removed from the account and sent to the proper recipient
-    /// via `withdraw_funds` (in case the payment is concluded);
-    /// * the hold is released via `release_hold` and the account holder may
-    /// once again spec the money as they wish (in case the payment is canceled)
-    ///
-    /// In other words, for every call to `place_hold`, there MUST be a matching
-    /// call to either `release_hold` or `withdraw_funds`.
+    /// merely prevents the money from being otherwise spent until the hold is
+    /// released via `release_hold` and the account holder may once again spend
+    /// the money as they wish (in case the payment is canceled), or captured via
+    /// `capture_hold` (in case the payment is concluded).
     async fn place_hold(&self, account_number: &str, amount: i32) -> Result<HoldRef, String>;
 
     /// Releases a hold on the account.
@@ -43,14 +81,97 @@ pub trait AccountService: Clone + Send + Sync + 'static {
     /// on the funds.
     async fn release_hold(&self, hold_ref: HoldRef) -> Result<(), String>;
 
-    /// Withdraws the held money from the account.
+    /// Releases a hold of `amount` on the `card_number` card's account, for holds whose
+    /// reference was lost (e.g. payments persisted before their hold id was).
+    ///
+    /// This is best-effort: a card and an amount don't identify a hold, so when the account
+    /// holds the same amount more than once, any one of those holds may be released. Use
+    /// `release_hold` whenever the hold reference is known.
+    async fn release_hold_by_card(&self, card_number: &str, amount: i32) -> Result<(), String>;
+
+    /// Withdraws `amount` from the held money, and releases the rest of the hold.
+    ///
+    /// `amount` is at most the amount previously held, less when less money is settled
+    /// than was held (e.g. a hotel holding more than the eventual bill). This is how money
+    /// is transferred out of the customer's account: like `release_hold`, it is a matching
+    /// call to `place_hold`.
+    ///
+    /// Capturing an already captured hold again, for the same amount, has no further effect:
+    /// the hold reference makes the capture idempotent, so an interrupted capture can be
+    /// retried.
+    async fn capture_hold(&self, hold_ref: HoldRef, amount: i32) -> Result<(), String>;
+
+    /// Credits `amount` to the account of the `card_number` card.
     ///
-    /// Decreases the current balance of the account linked to the hold reference by the amount previously held.
-    /// The hold on the customer's funds is implicitly released atomically.
+    /// This is how refunds are paid back, either to the card the payment was made with,
+    /// or to another card of the customer's (e.g. when the original one was closed).
+    async fn credit(&self, card_number: &str, amount: i32) -> Result<(), String>;
+
+    /// Checks that `signature` is the service's signature of `body`, authenticating the
+    /// callbacks it sends (e.g. to confirm holds asynchronously).
     ///
-    /// This is the mechanism by which money is transferred out from the customer's account and
-    /// into the merchant's account during the settlement process.
-    async fn withdraw_funds(&self, hold_ref: HoldRef) -> Result<(), String>;
+    /// Services that don't call back reject every callback, which is the default.
+    fn verify_callback(&self, body: &[u8], signature: &str) -> bool {
+        let _ = (body, signature);
+        false
+    }
+}
+
+/// Returns the hex-encoded HMAC-SHA256 of a callback's `body` with `secret`, as services
+/// verifying callbacks with `verify_callback_signature` sign them.
+#[cfg(test)]
+pub fn sign_callback(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks, in constant time, that `signature` is the `sign_callback` signature of `body`.
+pub fn verify_callback_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// An account service whose implementation is chosen at runtime.
+pub type DynAccountService = Arc<dyn AccountService>;
+
+#[async_trait::async_trait]
+impl<S: AccountService + ?Sized> AccountService for Arc<S> {
+    async fn ping(&self) -> Result<(), String> {
+        (**self).ping().await
+    }
+
+    async fn query_balance(&self, account_number: &str) -> Option<i32> {
+        (**self).query_balance(account_number).await
+    }
+
+    async fn place_hold(&self, account_number: &str, amount: i32) -> Result<HoldRef, String> {
+        (**self).place_hold(account_number, amount).await
+    }
+
+    async fn release_hold(&self, hold_ref: HoldRef) -> Result<(), String> {
+        (**self).release_hold(hold_ref).await
+    }
+
+    async fn release_hold_by_card(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        (**self).release_hold_by_card(card_number, amount).await
+    }
+
+    async fn capture_hold(&self, hold_ref: HoldRef, amount: i32) -> Result<(), String> {
+        (**self).capture_hold(hold_ref, amount).await
+    }
+
+    async fn credit(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        (**self).credit(card_number, amount).await
+    }
+
+    fn verify_callback(&self, body: &[u8], signature: &str) -> bool {
+        (**self).verify_callback(body, signature)
+    }
 }
 
 /// A naive implementation of the `Bank.Accounts.Service` behavior.
@@ -64,6 +185,39 @@ pub trait AccountService: Clone + Send + Sync + 'static {
 pub struct DummyService {
     #[cfg(test)]
     pub response: Option<String>,
+    #[cfg(test)]
+    pub balance: Option<i32>,
+    #[cfg(test)]
+    pub hold_calls: Arc<AtomicUsize>,
+    #[cfg(test)]
+    pub capture_response: Option<String>,
+    #[cfg(test)]
+    pub ping_error: Option<String>,
+    /// Number of holds placed per account number and amount, and not released by card yet.
+    #[cfg(test)]
+    pub holds_by_card: Arc<Mutex<HashMap<(String, i32), usize>>>,
+    /// Number of holds failing with `service_unavailable` before the next ones are placed.
+    #[cfg(test)]
+    pub unavailable_holds: Arc<AtomicUsize>,
+    /// Number of holds failing with `insufficient_funds` before the next ones are placed,
+    /// whatever the `balance`.
+    #[cfg(test)]
+    pub insufficient_holds: Arc<AtomicUsize>,
+    /// Ids of the holds released with `release_hold`.
+    #[cfg(test)]
+    pub released_holds: Arc<Mutex<Vec<Uuid>>>,
+    /// Ids of the holds captured with `capture_hold`.
+    #[cfg(test)]
+    pub captured_holds: Arc<Mutex<Vec<Uuid>>>,
+    /// How long placing a hold takes, if it's slow.
+    #[cfg(test)]
+    pub hold_delay: Option<std::time::Duration>,
+    /// Most holds placed at once so far, when placing them is slow.
+    #[cfg(test)]
+    pub max_concurrent_holds: Arc<AtomicUsize>,
+    /// Holds being placed right now.
+    #[cfg(test)]
+    pub concurrent_holds: Arc<AtomicUsize>,
 }
 
 impl DummyService {
@@ -71,10 +225,31 @@ impl DummyService {
     pub const MIN_VALID_AMOUNT: i32 = 0;
     #[allow(clippy::inconsistent_digit_grouping)]
     pub const MAX_VALID_AMOUNT: i32 = 1_000_000_00;
+    /// Secret the service's callbacks are signed with, through `sign_callback`.
+    pub const CALLBACK_SECRET: &str = "dummy-callback-secret";
 }
 
 #[async_trait::async_trait]
 impl AccountService for DummyService {
+    async fn ping(&self) -> Result<(), String> {
+        #[cfg(test)]
+        if let Some(error) = &self.ping_error {
+            return Err(error.clone());
+        }
+
+        Ok(())
+    }
+
+    async fn query_balance(&self, account_number: &str) -> Option<i32> {
+        let _ = account_number;
+
+        #[cfg(test)]
+        return self.balance;
+
+        #[cfg(not(test))]
+        None
+    }
+
     /// Places a hold on the account.
     ///
     /// - If the `account_number` is `DummyService::INVALID_ACCOUNT_NUMBER`, returns `invalid_account_number`.
@@ -83,11 +258,45 @@ impl AccountService for DummyService {
     ///
     /// Returns `HoldRef` otherwise.
     async fn place_hold(&self, account_number: &str, amount: i32) -> Result<HoldRef, String> {
+        #[cfg(test)]
+        self.hold_calls.fetch_add(1, Ordering::SeqCst);
+
+        #[cfg(test)]
+        if let Some(delay) = self.hold_delay {
+            let concurrent_holds = self.concurrent_holds.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_concurrent_holds
+                .fetch_max(concurrent_holds, Ordering::SeqCst);
+            tokio::time::sleep(delay).await;
+            self.concurrent_holds.fetch_sub(1, Ordering::SeqCst);
+        }
+
         #[cfg(test)]
         if let Some(response) = &self.response {
             return Err(response.into());
         }
 
+        #[cfg(test)]
+        if self
+            .unavailable_holds
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |holds| {
+                holds.checked_sub(1)
+            })
+            .is_ok()
+        {
+            return Err("service_unavailable".into());
+        }
+
+        #[cfg(test)]
+        if self
+            .insufficient_holds
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |holds| {
+                holds.checked_sub(1)
+            })
+            .is_ok()
+        {
+            return Err("insufficient_funds".into());
+        }
+
         if account_number == Self::INVALID_ACCOUNT_NUMBER {
             Err("invalid_account_number".into())
         } else if amount < Self::MIN_VALID_AMOUNT {
@@ -95,17 +304,126 @@ impl AccountService for DummyService {
         } else if amount > Self::MAX_VALID_AMOUNT {
             Err("insufficient_funds".into())
         } else {
+            #[cfg(test)]
+            {
+                let mut holds_by_card = self.holds_by_card.lock().unwrap();
+                *holds_by_card
+                    .entry((account_number.to_string(), amount))
+                    .or_default() += 1;
+            }
+
             Ok(HoldRef { id: Uuid::new_v4() })
         }
     }
 
     async fn release_hold(&self, hold_ref: HoldRef) -> Result<(), String> {
         let _ = hold_ref;
+
+        #[cfg(test)]
+        self.released_holds.lock().unwrap().push(hold_ref.id());
+
         Ok(())
     }
 
-    async fn withdraw_funds(&self, hold_ref: HoldRef) -> Result<(), String> {
-        let _ = hold_ref;
+    /// Releases a hold by card and amount.
+    ///
+    /// In tests, returns `hold_not_found` unless a hold of `amount` was placed on the
+    /// `card_number` account and not released by card yet. Returns `Ok` otherwise.
+    async fn release_hold_by_card(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        let _ = (card_number, amount);
+
+        #[cfg(test)]
+        {
+            let mut holds_by_card = self.holds_by_card.lock().unwrap();
+            let key = (card_number.to_string(), amount);
+            match holds_by_card.get_mut(&key) {
+                Some(count) if *count > 1 => *count -= 1,
+                Some(_) => {
+                    holds_by_card.remove(&key);
+                }
+                None => return Err("hold_not_found".into()),
+            }
+        }
+
         Ok(())
     }
+
+    /// Captures the hold.
+    ///
+    /// Returns `Ok(())` unless a test response is set: the hold is then captured, but the
+    /// response returned in its stead, as when the answer of the remote service is lost.
+    async fn capture_hold(&self, hold_ref: HoldRef, amount: i32) -> Result<(), String> {
+        #[cfg(test)]
+        self.captured_holds.lock().unwrap().push(hold_ref.id());
+        let _ = (hold_ref, amount);
+
+        #[cfg(test)]
+        if let Some(response) = &self.capture_response {
+            return Err(response.into());
+        }
+
+        Ok(())
+    }
+
+    /// Credits the account.
+    ///
+    /// - If the `card_number` is `DummyService::INVALID_ACCOUNT_NUMBER`, returns `invalid_account_number`.
+    ///
+    /// Returns `Ok` otherwise.
+    async fn credit(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        let _ = amount;
+
+        #[cfg(test)]
+        if let Some(response) = &self.response {
+            return Err(response.into());
+        }
+
+        if card_number == Self::INVALID_ACCOUNT_NUMBER {
+            Err("invalid_account_number".into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn verify_callback(&self, body: &[u8], signature: &str) -> bool {
+        verify_callback_signature(Self::CALLBACK_SECRET.as_bytes(), body, signature)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank::payment_instruments::Card;
+
+    #[tokio::test]
+    async fn test_release_hold_by_card() {
+        let account_service = DummyService::default();
+        let card_number: String = Card::new_test().into();
+        let card_number = card_number.as_str();
+
+        account_service
+            .place_hold(card_number, 12_05)
+            .await
+            .expect("failed to place hold");
+
+        assert_eq!(
+            account_service
+                .release_hold_by_card(card_number, 10_00)
+                .await,
+            Err("hold_not_found".into())
+        );
+        assert_eq!(
+            account_service
+                .release_hold_by_card(card_number, 12_05)
+                .await,
+            Ok(())
+        );
+        // already released
+        assert_eq!(
+            account_service
+                .release_hold_by_card(card_number, 12_05)
+                .await,
+            Err("hold_not_found".into())
+        );
+    }
 }
@@ -0,0 +1,78 @@
+use std::{collections::HashMap, str::FromStr};
+
+use super::payments::InvalidArgumentError;
+
+/// Smallest and largest amounts, in cents, accepted for a payment in a currency.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AmountBounds {
+    pub min: i32,
+    pub max: i32,
+}
+
+/// Bounds of the payment amounts, by currency code.
+///
+/// Payments in currencies without bounds, or without a currency, are only required to
+/// have a positive amount.
+pub type AmountBoundsTable = HashMap<String, AmountBounds>;
+
+impl AmountBounds {
+    pub fn new(min: i32, max: i32) -> Self {
+        Self { min, max }
+    }
+
+    pub fn check(&self, amount: i32) -> Result<(), InvalidArgumentError> {
+        if amount < self.min {
+            Err(InvalidArgumentError::AmountTooSmall)
+        } else if amount > self.max {
+            Err(InvalidArgumentError::AmountTooLarge)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidAmountBounds;
+
+/// Parses bounds such as `50-1000000`.
+impl FromStr for AmountBounds {
+    type Err = InvalidAmountBounds;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s.split_once('-').ok_or(InvalidAmountBounds)?;
+        let min = min.trim().parse().map_err(|_| InvalidAmountBounds)?;
+        let max = max.trim().parse().map_err(|_| InvalidAmountBounds)?;
+        if min > max {
+            return Err(InvalidAmountBounds);
+        }
+        Ok(Self::new(min, max))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let bounds = AmountBounds::new(50, 100_000);
+
+        assert!(matches!(
+            bounds.check(49),
+            Err(InvalidArgumentError::AmountTooSmall)
+        ));
+        assert!(bounds.check(50).is_ok());
+        assert!(bounds.check(100_000).is_ok());
+        assert!(matches!(
+            bounds.check(100_001),
+            Err(InvalidArgumentError::AmountTooLarge)
+        ));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("50-100000".parse(), Ok(AmountBounds::new(50, 100_000)));
+        assert_eq!("100-50".parse::<AmountBounds>(), Err(InvalidAmountBounds));
+        assert_eq!("50".parse::<AmountBounds>(), Err(InvalidAmountBounds));
+    }
+}
@@ -0,0 +1,244 @@
+use std::{
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use time::{Duration, OffsetDateTime};
+
+use crate::bank::{
+    accounts::{AccountService, HoldRef},
+    clock::Clock,
+    payments::AccountServiceError,
+};
+
+/// When the breaker opens, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CircuitBreakerConfig {
+    /// Holds failing in a row with `service_unavailable` before the breaker opens.
+    pub failure_threshold: u32,
+    /// How long holds fail fast once the breaker opens, before one is tried again.
+    pub cooldown: Duration,
+}
+
+impl CircuitBreakerConfig {
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            failure_threshold,
+            cooldown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CircuitState {
+    /// Holds are placed, counting how many failed in a row.
+    Closed { failures: u32 },
+    /// Holds fail fast until then.
+    Open { until: OffsetDateTime },
+    /// A single hold is being tried: the others fail fast until it's done, or until then
+    /// if it never is (e.g. its request was dropped).
+    HalfOpen { until: OffsetDateTime },
+}
+
+/// Account service failing holds fast with `service_unavailable` while the wrapped one
+/// keeps being unavailable, rather than waiting on a dead backend for each of them.
+///
+/// Only `place_hold` is short-circuited: releasing, withdrawing and crediting are always
+/// attempted, since they settle holds already placed.
+pub struct CircuitBreaker<S> {
+    service: S,
+    config: CircuitBreakerConfig,
+    clock: Arc<dyn Clock>,
+    state: Mutex<CircuitState>,
+}
+
+impl<S: AccountService> CircuitBreaker<S> {
+    pub fn new(service: S, config: CircuitBreakerConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            service,
+            config,
+            clock,
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn state(&self) -> CircuitState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Returns whether a hold may be placed, half-opening the breaker once its cooldown
+    /// elapsed.
+    fn try_acquire(&self) -> bool {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } => true,
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if now >= until => {
+                *state = CircuitState::HalfOpen {
+                    until: now + self.config.cooldown,
+                };
+                true
+            }
+            CircuitState::Open { .. } | CircuitState::HalfOpen { .. } => false,
+        }
+    }
+
+    fn record(&self, unavailable: bool) {
+        let now = self.clock.now();
+        let mut state = self.state.lock().unwrap();
+        *state = match (*state, unavailable) {
+            (_, false) => CircuitState::Closed { failures: 0 },
+            (CircuitState::Closed { failures }, true)
+                if failures + 1 < self.config.failure_threshold =>
+            {
+                CircuitState::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (CircuitState::Closed { .. } | CircuitState::HalfOpen { .. }, true) => {
+                tracing::warn!("account service unavailable, opening circuit breaker");
+                CircuitState::Open {
+                    until: now + self.config.cooldown,
+                }
+            }
+            (open @ CircuitState::Open { .. }, true) => open,
+        };
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AccountService> AccountService for CircuitBreaker<S> {
+    async fn ping(&self) -> Result<(), String> {
+        self.service.ping().await
+    }
+
+    async fn query_balance(&self, account_number: &str) -> Option<i32> {
+        self.service.query_balance(account_number).await
+    }
+
+    async fn place_hold(&self, account_number: &str, amount: i32) -> Result<HoldRef, String> {
+        if !self.try_acquire() {
+            return Err(AccountServiceError::ServiceUnavailable.to_string());
+        }
+        let result = self.service.place_hold(account_number, amount).await;
+        self.record(matches!(
+            &result,
+            Err(msg) if AccountServiceError::from_str(msg) == Ok(AccountServiceError::ServiceUnavailable)
+        ));
+        result
+    }
+
+    async fn release_hold(&self, hold_ref: HoldRef) -> Result<(), String> {
+        self.service.release_hold(hold_ref).await
+    }
+
+    async fn release_hold_by_card(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        self.service.release_hold_by_card(card_number, amount).await
+    }
+
+    async fn capture_hold(&self, hold_ref: HoldRef, amount: i32) -> Result<(), String> {
+        self.service.capture_hold(hold_ref, amount).await
+    }
+
+    async fn credit(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        self.service.credit(card_number, amount).await
+    }
+
+    // callbacks come from the service rather than calling it, so they aren't guarded
+    fn verify_callback(&self, body: &[u8], signature: &str) -> bool {
+        self.service.verify_callback(body, signature)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidCircuitBreakerConfig;
+
+/// Parses a config such as `5/30s`: the failure threshold, then the cooldown in seconds.
+impl FromStr for CircuitBreakerConfig {
+    type Err = InvalidCircuitBreakerConfig;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (failure_threshold, cooldown) = s.split_once('/').ok_or(InvalidCircuitBreakerConfig)?;
+        let failure_threshold = failure_threshold
+            .trim()
+            .parse()
+            .map_err(|_| InvalidCircuitBreakerConfig)?;
+        let seconds: i64 = cooldown
+            .trim()
+            .strip_suffix('s')
+            .and_then(|seconds| seconds.parse().ok())
+            .ok_or(InvalidCircuitBreakerConfig)?;
+        if failure_threshold == 0 || seconds <= 0 {
+            return Err(InvalidCircuitBreakerConfig);
+        }
+        Ok(Self::new(failure_threshold, Duration::seconds(seconds)))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::bank::{accounts::DummyService, clock::tests::MockClock};
+
+    #[tokio::test]
+    async fn test_circuit_breaker() {
+        let clock = MockClock::new(OffsetDateTime::from_unix_timestamp(1_000_000_000).unwrap());
+        let service = DummyService::default();
+        let hold_calls = service.hold_calls.clone();
+        service.unavailable_holds.store(3, Ordering::SeqCst);
+        let breaker = CircuitBreaker::new(
+            service,
+            CircuitBreakerConfig::new(2, Duration::seconds(30)),
+            Arc::new(clock.clone()),
+        );
+        let card_number = "123451234512345";
+
+        for _ in 0..2 {
+            assert_eq!(
+                breaker.place_hold(card_number, 10_00).await.unwrap_err(),
+                "service_unavailable"
+            );
+        }
+        assert!(matches!(breaker.state(), CircuitState::Open { .. }));
+
+        // fails fast, without calling the service
+        assert!(breaker.place_hold(card_number, 10_00).await.is_err());
+        assert_eq!(hold_calls.load(Ordering::SeqCst), 2);
+
+        // the hold tried once the cooldown elapsed still fails: the breaker opens again
+        clock.advance(Duration::seconds(30));
+        assert!(breaker.place_hold(card_number, 10_00).await.is_err());
+        assert_eq!(hold_calls.load(Ordering::SeqCst), 3);
+        assert!(breaker.place_hold(card_number, 10_00).await.is_err());
+        assert_eq!(hold_calls.load(Ordering::SeqCst), 3);
+
+        clock.advance(Duration::seconds(30));
+        assert!(breaker.place_hold(card_number, 10_00).await.is_ok());
+        assert_eq!(breaker.state(), CircuitState::Closed { failures: 0 });
+        assert!(breaker.place_hold(card_number, 10_00).await.is_ok());
+        assert_eq!(hold_calls.load(Ordering::SeqCst), 5);
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "5/30s".parse(),
+            Ok(CircuitBreakerConfig::new(5, Duration::seconds(30)))
+        );
+        assert_eq!(
+            "5".parse::<CircuitBreakerConfig>(),
+            Err(InvalidCircuitBreakerConfig)
+        );
+        assert_eq!(
+            "0/30s".parse::<CircuitBreakerConfig>(),
+            Err(InvalidCircuitBreakerConfig)
+        );
+        assert_eq!(
+            "5/0s".parse::<CircuitBreakerConfig>(),
+            Err(InvalidCircuitBreakerConfig)
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use time::{OffsetDateTime, PrimitiveDateTime, UtcOffset};
+
+/// Source of the current time.
+///
+/// Every timestamp written to the database goes through a `Clock` rather than
+/// `CURRENT_TIMESTAMP`, so that time-dependent behavior can be tested by
+/// moving a mock clock forward instead of sleeping.
+pub trait Clock: Send + Sync + 'static {
+    fn now(&self) -> OffsetDateTime;
+
+    /// Returns the current time as stored in `timestamp without time zone` columns.
+    fn now_utc(&self) -> PrimitiveDateTime {
+        to_primitive_utc(self.now())
+    }
+}
+
+/// Converts `datetime` to the UTC representation used by the database columns.
+///
+/// The columns have a precision of one second: `datetime` is truncated rather than
+/// left for postgres to round, which could otherwise move it into the future.
+pub fn to_primitive_utc(datetime: OffsetDateTime) -> PrimitiveDateTime {
+    let datetime = datetime.to_offset(UtcOffset::UTC);
+    let time = datetime
+        .time()
+        .replace_nanosecond(0)
+        .expect("0 is a valid nanosecond");
+    PrimitiveDateTime::new(datetime.date(), time)
+}
+
+/// Clock reading the system time.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> OffsetDateTime {
+        OffsetDateTime::now_utc()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    /// Clock that only moves when told to.
+    #[derive(Debug, Clone)]
+    pub struct MockClock(Arc<Mutex<OffsetDateTime>>);
+
+    impl MockClock {
+        pub fn new(now: OffsetDateTime) -> Self {
+            Self(Arc::new(Mutex::new(now)))
+        }
+
+        pub fn advance(&self, duration: time::Duration) {
+            *self.0.lock().unwrap() += duration;
+        }
+    }
+
+    impl Clock for MockClock {
+        fn now(&self) -> OffsetDateTime {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    #[test]
+    fn test_mock_clock() {
+        let start = OffsetDateTime::from_unix_timestamp(1_672_531_200).unwrap();
+        let clock = MockClock::new(start);
+
+        assert_eq!(clock.now(), start);
+
+        clock.advance(time::Duration::hours(1));
+        assert_eq!(clock.now(), start + time::Duration::hours(1));
+    }
+}
@@ -0,0 +1,170 @@
+use std::{
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+use crate::bank::{
+    accounts::{AccountService, HoldRef},
+    payments::AccountServiceError,
+};
+
+/// How many holds are placed at once, and how many more may wait for their turn.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConcurrencyLimit {
+    pub permits: usize,
+    /// Holds waiting for a permit beyond which the next ones fail fast.
+    pub max_waiting: usize,
+}
+
+impl ConcurrencyLimit {
+    pub fn new(permits: usize, max_waiting: usize) -> Self {
+        Self {
+            permits,
+            max_waiting,
+        }
+    }
+}
+
+/// Account service running at most `ConcurrencyLimit::permits` of its calls at once, so
+/// that a flood of payments can't open unbounded connections to the wrapped one.
+///
+/// Holds wait for their turn, unless `ConcurrencyLimit::max_waiting` already are: they then
+/// fail fast with `service_unavailable`. Releases share the permits of holds, but always
+/// wait, since they settle holds already placed.
+pub struct ConcurrencyLimiter<S> {
+    service: S,
+    permits: Semaphore,
+    max_waiting: usize,
+    waiting: AtomicUsize,
+}
+
+/// Counts a call waiting for a permit, until it's dropped (e.g. along with its request).
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl<S: AccountService> ConcurrencyLimiter<S> {
+    pub fn new(service: S, limit: ConcurrencyLimit) -> Self {
+        Self {
+            service,
+            permits: Semaphore::new(limit.permits),
+            max_waiting: limit.max_waiting,
+            waiting: AtomicUsize::new(0),
+        }
+    }
+
+    /// Waits for a permit, unless the wait queue is full.
+    async fn acquire_bounded(&self) -> Option<SemaphorePermit<'_>> {
+        if let Ok(permit) = self.permits.try_acquire() {
+            return Some(permit);
+        }
+        self.waiting
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |waiting| {
+                (waiting < self.max_waiting).then_some(waiting + 1)
+            })
+            .ok()?;
+        let _waiting = Waiting(&self.waiting);
+        Some(self.acquire().await)
+    }
+
+    async fn acquire(&self) -> SemaphorePermit<'_> {
+        self.permits
+            .acquire()
+            .await
+            .expect("the semaphore is never closed")
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: AccountService> AccountService for ConcurrencyLimiter<S> {
+    async fn ping(&self) -> Result<(), String> {
+        self.service.ping().await
+    }
+
+    async fn query_balance(&self, account_number: &str) -> Option<i32> {
+        self.service.query_balance(account_number).await
+    }
+
+    async fn place_hold(&self, account_number: &str, amount: i32) -> Result<HoldRef, String> {
+        let Some(_permit) = self.acquire_bounded().await else {
+            tracing::warn!("too many holds waiting on the account service, failing fast");
+            return Err(AccountServiceError::ServiceUnavailable.to_string());
+        };
+        self.service.place_hold(account_number, amount).await
+    }
+
+    async fn release_hold(&self, hold_ref: HoldRef) -> Result<(), String> {
+        let _permit = self.acquire().await;
+        self.service.release_hold(hold_ref).await
+    }
+
+    async fn release_hold_by_card(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        let _permit = self.acquire().await;
+        self.service.release_hold_by_card(card_number, amount).await
+    }
+
+    async fn capture_hold(&self, hold_ref: HoldRef, amount: i32) -> Result<(), String> {
+        self.service.capture_hold(hold_ref, amount).await
+    }
+
+    async fn credit(&self, card_number: &str, amount: i32) -> Result<(), String> {
+        self.service.credit(card_number, amount).await
+    }
+
+    fn verify_callback(&self, body: &[u8], signature: &str) -> bool {
+        self.service.verify_callback(body, signature)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidConcurrencyLimit;
+
+/// Parses a limit such as `8/100`: the permits, then how many holds may wait for one.
+impl FromStr for ConcurrencyLimit {
+    type Err = InvalidConcurrencyLimit;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (permits, max_waiting) = s.split_once('/').ok_or(InvalidConcurrencyLimit)?;
+        let permits = permits
+            .trim()
+            .parse()
+            .map_err(|_| InvalidConcurrencyLimit)?;
+        let max_waiting = max_waiting
+            .trim()
+            .parse()
+            .map_err(|_| InvalidConcurrencyLimit)?;
+        if permits == 0 {
+            return Err(InvalidConcurrencyLimit);
+        }
+        Ok(Self::new(permits, max_waiting))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!("8/100".parse(), Ok(ConcurrencyLimit::new(8, 100)));
+        assert_eq!("1/0".parse(), Ok(ConcurrencyLimit::new(1, 0)));
+        assert_eq!(
+            "8".parse::<ConcurrencyLimit>(),
+            Err(InvalidConcurrencyLimit)
+        );
+        assert_eq!(
+            "0/100".parse::<ConcurrencyLimit>(),
+            Err(InvalidConcurrencyLimit)
+        );
+        assert_eq!(
+            "8/-1".parse::<ConcurrencyLimit>(),
+            Err(InvalidConcurrencyLimit)
+        );
+    }
+}
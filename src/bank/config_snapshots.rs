@@ -0,0 +1,60 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    amount_bounds::AmountBounds, fees::FeeConfig, money::Rounding, velocity::VelocityLimit,
+};
+
+/// Limits on the payments made on a card, checked before they're created.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CardLimits {
+    pub velocity_limit: Option<VelocityLimit>,
+    /// Maximum number of payments on a card each day.
+    pub daily_payments: Option<u32>,
+}
+
+/// The configuration a payment was created under, stored with it for disputes as the
+/// configuration changes over time.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConfigSnapshot {
+    pub fee_flat: i32,
+    pub fee_basis_points: i32,
+    #[serde(default = "legacy_fee_rounding")]
+    pub fee_rounding: Rounding,
+    /// The payment's currency, if given or defaulted.
+    pub currency: Option<String>,
+    /// Bounds of the amounts in the payment's currency, if configured.
+    pub min_amount: Option<i32>,
+    pub max_amount: Option<i32>,
+    pub velocity_max_amount: Option<i64>,
+    pub velocity_window_hours: Option<i64>,
+    pub daily_card_payments: Option<u32>,
+}
+
+/// The rounding of the fees of snapshots taken before it was configurable, which rounded
+/// them down.
+fn legacy_fee_rounding() -> Rounding {
+    Rounding::Floor
+}
+
+impl ConfigSnapshot {
+    pub fn new(
+        fees: &FeeConfig,
+        currency: Option<&str>,
+        bounds: Option<&AmountBounds>,
+        card_limits: &CardLimits,
+    ) -> Self {
+        Self {
+            fee_flat: fees.flat,
+            fee_basis_points: fees.basis_points,
+            fee_rounding: fees.rounding,
+            currency: currency.map(str::to_owned),
+            min_amount: bounds.map(|bounds| bounds.min),
+            max_amount: bounds.map(|bounds| bounds.max),
+            velocity_max_amount: card_limits.velocity_limit.map(|limit| limit.max_amount),
+            velocity_window_hours: card_limits
+                .velocity_limit
+                .map(|limit| limit.window.whole_hours()),
+            daily_card_payments: card_limits.daily_payments,
+        }
+    }
+}
@@ -0,0 +1,97 @@
+/// Returns the number of decimals of the currency's minor unit, per ISO 4217.
+///
+/// Defaults to 2, the most common, for unknown currencies and payments without one.
+pub fn exponent(currency: Option<&str>) -> u32 {
+    match currency {
+        Some(
+            "BIF" | "CLP" | "DJF" | "GNF" | "ISK" | "JPY" | "KMF" | "KRW" | "PYG" | "RWF" | "UGX"
+            | "UYI" | "VND" | "VUV" | "XAF" | "XOF" | "XPF",
+        ) => 0,
+        Some("BHD" | "IQD" | "JOD" | "KWD" | "LYD" | "OMR" | "TND") => 3,
+        _ => 2,
+    }
+}
+
+/// Formats an amount in minor units as a decimal string, e.g. `1205` cents as `"12.05"`.
+pub fn format_amount(amount: i32, currency: Option<&str>) -> String {
+    let exponent = exponent(currency);
+    if exponent == 0 {
+        return amount.to_string();
+    }
+    let divisor = 10_u32.pow(exponent);
+    let sign = if amount < 0 { "-" } else { "" };
+    let amount = amount.unsigned_abs();
+    format!(
+        "{sign}{}.{:0width$}",
+        amount / divisor,
+        amount % divisor,
+        width = exponent as usize
+    )
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParseAmountError {
+    Invalid,
+    /// The amount has more decimals than the currency's minor unit, e.g. `"12.055"` EUR.
+    TooManyDecimals,
+}
+
+/// Parses a decimal amount such as `"12.05"` into minor units, e.g. `1205` cents.
+///
+/// Amounts with more decimals than the currency's minor unit are rejected rather than
+/// truncated.
+pub fn parse_amount(amount: &str, currency: Option<&str>) -> Result<i64, ParseAmountError> {
+    let exponent = exponent(currency) as usize;
+    let (units, decimals) = amount.split_once('.').unwrap_or((amount, ""));
+    let digits = units.strip_prefix('-').unwrap_or(units);
+    let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+    if digits.is_empty() || !is_digits(digits) || !is_digits(decimals) {
+        return Err(ParseAmountError::Invalid);
+    }
+    if decimals.len() > exponent {
+        return Err(ParseAmountError::TooManyDecimals);
+    }
+    format!("{units}{decimals:0<exponent$}")
+        .parse()
+        .map_err(|_| ParseAmountError::Invalid)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_amount() {
+        assert_eq!(format_amount(12_05, Some("EUR")), "12.05");
+        assert_eq!(format_amount(12_05, None), "12.05");
+        assert_eq!(format_amount(5, None), "0.05");
+        assert_eq!(format_amount(-12_05, None), "-12.05");
+        assert_eq!(format_amount(1205, Some("JPY")), "1205");
+        assert_eq!(format_amount(1_205, Some("KWD")), "1.205");
+    }
+
+    #[test]
+    fn test_parse_amount() {
+        assert_eq!(parse_amount("12.05", Some("EUR")), Ok(12_05));
+        assert_eq!(parse_amount("12.5", Some("EUR")), Ok(12_50));
+        assert_eq!(parse_amount("12", Some("EUR")), Ok(12_00));
+        assert_eq!(parse_amount("-12.05", None), Ok(-12_05));
+        assert_eq!(parse_amount("1.205", Some("KWD")), Ok(1_205));
+        assert_eq!(parse_amount("1205", Some("JPY")), Ok(1205));
+        assert_eq!(
+            parse_amount("12.055", Some("EUR")),
+            Err(ParseAmountError::TooManyDecimals)
+        );
+        assert_eq!(
+            parse_amount("12.5", Some("JPY")),
+            Err(ParseAmountError::TooManyDecimals)
+        );
+        for invalid in ["", ".5", "12,05", "1.2.3", "+12.05", "12.-5"] {
+            assert_eq!(
+                parse_amount(invalid, None),
+                Err(ParseAmountError::Invalid),
+                "{invalid:?}"
+            );
+        }
+    }
+}
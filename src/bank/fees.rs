@@ -0,0 +1,75 @@
+use crate::bank::money::{Money, Rounding};
+
+/// Fee charged by the bank on each approved payment.
+///
+/// The fee is made of a flat part (in cents) and a percentage of the payment
+/// amount, expressed in basis points (1/100th of a percent) so that the
+/// computation stays in integer arithmetic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FeeConfig {
+    pub flat: i32,
+    pub basis_points: i32,
+    /// How the percentage part is rounded to a whole cent.
+    pub rounding: Rounding,
+}
+
+impl FeeConfig {
+    pub fn new(flat: i32, basis_points: i32) -> Self {
+        Self {
+            flat,
+            basis_points,
+            rounding: Rounding::default(),
+        }
+    }
+
+    pub fn with_rounding(self, rounding: Rounding) -> Self {
+        Self { rounding, ..self }
+    }
+
+    /// Returns the fee for a payment of `amount`.
+    ///
+    /// The percentage part is rounded as configured, and the fee never exceeds the
+    /// payment amount. A fee overflowing `Money` is clamped to the amount too, though
+    /// `i32` operands can't overflow it.
+    pub fn fee_for(&self, amount: i32) -> i32 {
+        let amount = Money::from(amount);
+        let fee = amount
+            .checked_basis_points(self.basis_points, self.rounding)
+            .and_then(|percentage| Money::from(self.flat).checked_add(percentage))
+            .unwrap_or(amount);
+        i32::try_from(fee.clamp(Money::ZERO, amount)).expect("the fee is clamped to an i32")
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_for() {
+        let fees = FeeConfig::new(30, 250);
+
+        assert_eq!(fees.fee_for(10_00), 55);
+        assert_eq!(fees.fee_for(10), 10);
+        assert_eq!(FeeConfig::default().fee_for(10_00), 0);
+        assert_eq!(
+            FeeConfig::new(i32::MAX, i32::MAX).fee_for(i32::MAX),
+            i32::MAX
+        );
+    }
+
+    #[test]
+    fn test_fee_for_rounds_as_configured() {
+        // 2.5% of 1.00 is 2.5 cents
+        let fees = FeeConfig::new(30, 250);
+
+        assert_eq!(fees.fee_for(1_00), 32);
+        assert_eq!(fees.with_rounding(Rounding::HalfEven).fee_for(1_00), 32);
+        assert_eq!(fees.with_rounding(Rounding::HalfUp).fee_for(1_00), 33);
+        assert_eq!(fees.with_rounding(Rounding::Floor).fee_for(1_00), 32);
+        // 2.5% of 1.40 is 3.5 cents
+        assert_eq!(fees.with_rounding(Rounding::HalfEven).fee_for(1_40), 34);
+        assert_eq!(fees.with_rounding(Rounding::HalfUp).fee_for(1_40), 34);
+        assert_eq!(fees.with_rounding(Rounding::Floor).fee_for(1_40), 33);
+    }
+}
@@ -0,0 +1,154 @@
+//! Fingerprints of card numbers, which identify the payments made with the same card
+//! without storing its number.
+
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// Secret key of the HMAC fingerprinting card numbers.
+///
+/// It must stay the same across restarts: payments made with the same card would get
+/// different fingerprints otherwise, and no longer be detected as duplicates.
+#[derive(Clone)]
+pub struct FingerprintKey(Arc<[u8]>);
+
+impl FingerprintKey {
+    pub fn new(key: impl AsRef<[u8]>) -> Self {
+        Self(key.as_ref().into())
+    }
+
+    /// Returns the hex-encoded HMAC-SHA256 of the card number, which must be normalized so
+    /// that the same card always gets the same fingerprint.
+    pub fn fingerprint(&self, card_number: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length");
+        mac.update(card_number.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+}
+
+/// Keeps the key out of logs.
+impl fmt::Debug for FingerprintKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FingerprintKey(..)")
+    }
+}
+
+/// The fingerprint keys in use, shared by the handlers so that the key can be rotated
+/// while serving.
+///
+/// While a rotation is under way, stored fingerprints are made with either the previous or
+/// the current key: both have to be looked up.
+#[derive(Debug, Clone)]
+pub struct FingerprintKeys(Arc<RwLock<Keys>>);
+
+#[derive(Debug)]
+struct Keys {
+    current: FingerprintKey,
+    previous: Option<FingerprintKey>,
+}
+
+impl FingerprintKeys {
+    pub fn new(current: FingerprintKey) -> Self {
+        Self(Arc::new(RwLock::new(Keys {
+            current,
+            previous: None,
+        })))
+    }
+
+    /// Returns the key new fingerprints are made with.
+    pub fn current(&self) -> FingerprintKey {
+        self.0.read().unwrap().current.clone()
+    }
+
+    /// Returns the fingerprint of the card number made with the current key, then the one
+    /// made with the previous key during a rotation.
+    pub fn fingerprints(&self, card_number: &str) -> Vec<String> {
+        let keys = self.0.read().unwrap();
+        std::iter::once(&keys.current)
+            .chain(&keys.previous)
+            .map(|key| key.fingerprint(card_number))
+            .collect()
+    }
+
+    /// Makes `key` the current key, keeping the current one as the previous until
+    /// `end_rotation`. Returns false, changing nothing, if a rotation is already under way.
+    pub fn begin_rotation(&self, key: FingerprintKey) -> bool {
+        let mut keys = self.0.write().unwrap();
+        if keys.previous.is_some() {
+            return false;
+        }
+        let previous = std::mem::replace(&mut keys.current, key);
+        keys.previous = Some(previous);
+        true
+    }
+
+    /// Forgets the previous key, once every stored fingerprint is made with the current one.
+    pub fn end_rotation(&self) {
+        self.0.write().unwrap().previous = None;
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    impl FingerprintKey {
+        pub fn new_test() -> Self {
+            Self::new("test")
+        }
+    }
+
+    #[test]
+    fn test_fingerprint() {
+        let key = FingerprintKey::new_test();
+
+        let fingerprint = key.fingerprint("425123451234512");
+        assert_eq!(fingerprint.len(), 64);
+        assert_eq!(key.fingerprint("425123451234512"), fingerprint);
+        assert_eq!(
+            FingerprintKey::new_test().fingerprint("425123451234512"),
+            fingerprint
+        );
+        assert_ne!(key.fingerprint("425123451234513"), fingerprint);
+        assert_ne!(
+            FingerprintKey::new("other").fingerprint("425123451234512"),
+            fingerprint
+        );
+        assert_eq!(format!("{key:?}"), "FingerprintKey(..)");
+    }
+
+    #[test]
+    fn test_fingerprint_keys_rotation() {
+        let old = FingerprintKey::new("old");
+        let new = FingerprintKey::new("new");
+        let keys = FingerprintKeys::new(old.clone());
+        let card_number = "425123451234512";
+
+        assert_eq!(
+            keys.fingerprints(card_number),
+            [old.fingerprint(card_number)]
+        );
+
+        assert!(keys.begin_rotation(new.clone()));
+        assert!(!keys.begin_rotation(FingerprintKey::new("newer")));
+        assert_eq!(
+            keys.current().fingerprint(card_number),
+            new.fingerprint(card_number)
+        );
+        assert_eq!(
+            keys.fingerprints(card_number),
+            [new.fingerprint(card_number), old.fingerprint(card_number)]
+        );
+
+        keys.end_rotation();
+        assert_eq!(
+            keys.fingerprints(card_number),
+            [new.fingerprint(card_number)]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use std::collections::{HashMap, HashSet};
+
+use sqlx::PgPool;
+use time::{Duration, PrimitiveDateTime};
+use uuid::Uuid;
+
+use crate::bank::clock::Clock;
+
+/// Where a merchant's webhooks are sent, and the secret they're signed with.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct WebhookConfig {
+    pub merchant_id: Uuid,
+    pub webhook_url: String,
+    pub webhook_secret: String,
+    pub inserted_at: PrimitiveDateTime,
+    pub updated_at: PrimitiveDateTime,
+}
+
+/// Merchants opted in to implicit idempotency, for their clients not sending idempotency
+/// keys: payments repeated within `window` with the same card, amount and merchant
+/// reference return the payment already made instead of making another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImplicitIdempotency {
+    pub merchant_ids: HashSet<Uuid>,
+    pub window: Duration,
+}
+
+impl ImplicitIdempotency {
+    pub fn new(merchant_ids: HashSet<Uuid>, window: Duration) -> Self {
+        Self {
+            merchant_ids,
+            window,
+        }
+    }
+
+    /// Returns whether the merchant opted in.
+    pub fn applies_to(&self, merchant_id: Uuid) -> bool {
+        self.merchant_ids.contains(&merchant_id)
+    }
+}
+
+/// Currencies each merchant is enabled for, by merchant id, as merchants are onboarded for
+/// different currencies.
+///
+/// Merchants without an entry, and payments without a merchant, may use any currency.
+pub type MerchantCurrencies = HashMap<Uuid, HashSet<String>>;
+
+/// Sets the merchant's webhook URL and secret, replacing the previous ones.
+pub async fn put_webhook_config(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    merchant_id: Uuid,
+    webhook_url: &str,
+    webhook_secret: &str,
+) -> Result<WebhookConfig, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookConfig,
+        r#"
+               INSERT INTO merchant_configs ( merchant_id, webhook_url, webhook_secret, inserted_at, updated_at )
+               VALUES ( $1, $2, $3, $4, $4 )
+          ON CONFLICT (merchant_id) DO UPDATE
+                  SET webhook_url = EXCLUDED.webhook_url,
+                      webhook_secret = EXCLUDED.webhook_secret,
+                      updated_at = EXCLUDED.updated_at
+            RETURNING merchant_id, webhook_url, webhook_secret, inserted_at, updated_at
+        "#,
+        merchant_id,
+        webhook_url,
+        webhook_secret,
+        clock.now_utc()
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns the merchant's webhook configuration, if it has one.
+pub async fn get_webhook_config(
+    pool: &PgPool,
+    merchant_id: Uuid,
+) -> Result<Option<WebhookConfig>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookConfig,
+        r#"
+            SELECT merchant_id, webhook_url, webhook_secret, inserted_at, updated_at
+              FROM merchant_configs
+             WHERE merchant_id = $1
+        "#,
+        merchant_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Removes the merchant's webhook configuration, returning whether it had one.
+pub async fn delete_webhook_config(pool: &PgPool, merchant_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "DELETE FROM merchant_configs WHERE merchant_id = $1",
+        merchant_id
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::bank::clock::SystemClock;
+
+    #[test]
+    fn test_implicit_idempotency_applies_to() {
+        let merchant_id = Uuid::new_v4();
+        let idempotency =
+            ImplicitIdempotency::new(HashSet::from([merchant_id]), Duration::minutes(10));
+
+        assert!(idempotency.applies_to(merchant_id));
+        assert!(!idempotency.applies_to(Uuid::new_v4()));
+    }
+
+    #[tokio::test]
+    async fn test_put_webhook_config() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let merchant_id = Uuid::new_v4();
+        assert_eq!(get_webhook_config(&pool, merchant_id).await.unwrap(), None);
+
+        put_webhook_config(&pool, &SystemClock, merchant_id, "http://a.test", "a")
+            .await
+            .unwrap();
+        let config = put_webhook_config(&pool, &SystemClock, merchant_id, "http://b.test", "b")
+            .await
+            .unwrap();
+        assert_eq!(config.webhook_url, "http://b.test");
+        assert_eq!(config.webhook_secret, "b");
+        assert_eq!(
+            get_webhook_config(&pool, merchant_id).await.unwrap(),
+            Some(config)
+        );
+
+        assert!(delete_webhook_config(&pool, merchant_id).await.unwrap());
+        assert!(!delete_webhook_config(&pool, merchant_id).await.unwrap());
+        assert_eq!(get_webhook_config(&pool, merchant_id).await.unwrap(), None);
+    }
+}
@@ -0,0 +1,183 @@
+//! Monetary amounts, in cents, with arithmetic that can't silently wrap.
+//!
+//! Amounts are stored and serialized as `i32`, but computed as `Money`: the conversions
+//! are explicit, and fail rather than truncate when the result doesn't fit in an `i32`.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use strum_macros::EnumString;
+
+/// An amount of cents, possibly negative (e.g. the difference between two amounts).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Money(i64);
+
+/// An operation on amounts overflowed, which is a bug or a corrupted row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoneyOverflow;
+
+/// How fractions of a cent are rounded to whole cents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString, Deserialize, Serialize)]
+#[strum(serialize_all = "snake_case")]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    /// To the nearest cent, and halves to the even one (banker's rounding), so that
+    /// halves don't add up to a bias over many amounts.
+    #[default]
+    HalfEven,
+    /// To the nearest cent, and halves up.
+    HalfUp,
+    /// Down to the cent below.
+    Floor,
+}
+
+impl fmt::Display for MoneyOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("monetary amount overflow")
+    }
+}
+
+impl Money {
+    pub const ZERO: Self = Self(0);
+
+    pub const fn from_cents(cents: i64) -> Self {
+        Self(cents)
+    }
+
+    pub const fn cents(self) -> i64 {
+        self.0
+    }
+
+    pub fn checked_add(self, other: Self) -> Result<Self, MoneyOverflow> {
+        self.0.checked_add(other.0).map(Self).ok_or(MoneyOverflow)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Result<Self, MoneyOverflow> {
+        self.0.checked_sub(other.0).map(Self).ok_or(MoneyOverflow)
+    }
+
+    /// Returns `basis_points` hundredths of a percent of the amount, rounded as `rounding`.
+    pub fn checked_basis_points(
+        self,
+        basis_points: i32,
+        rounding: Rounding,
+    ) -> Result<Self, MoneyOverflow> {
+        let product = self
+            .0
+            .checked_mul(i64::from(basis_points))
+            .ok_or(MoneyOverflow)?;
+        let (cents, remainder) = (product.div_euclid(10_000), product.rem_euclid(10_000));
+        let round_up = match rounding {
+            Rounding::HalfEven => remainder > 5_000 || (remainder == 5_000 && cents % 2 != 0),
+            Rounding::HalfUp => remainder >= 5_000,
+            Rounding::Floor => false,
+        };
+        Ok(Self(cents + i64::from(round_up)))
+    }
+}
+
+/// Amounts as read from the database or a request.
+impl From<i32> for Money {
+    fn from(cents: i32) -> Self {
+        Self(i64::from(cents))
+    }
+}
+
+/// Amounts as written to the database or a response.
+impl TryFrom<Money> for i32 {
+    type Error = MoneyOverflow;
+
+    fn try_from(money: Money) -> Result<Self, Self::Error> {
+        i32::try_from(money.0).map_err(|_| MoneyOverflow)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[test]
+    fn test_checked_add() {
+        assert_eq!(
+            Money::from(1_00).checked_add(Money::from(2_50)),
+            Ok(Money::from(3_50))
+        );
+        assert_eq!(
+            Money::from_cents(i64::MAX - 1).checked_add(Money::from(1)),
+            Ok(Money::from_cents(i64::MAX))
+        );
+        assert_eq!(
+            Money::from_cents(i64::MAX).checked_add(Money::from(1)),
+            Err(MoneyOverflow)
+        );
+    }
+
+    #[test]
+    fn test_checked_sub() {
+        assert_eq!(
+            Money::from(1_00).checked_sub(Money::from(2_50)),
+            Ok(Money::from(-1_50))
+        );
+        assert_eq!(
+            Money::from_cents(i64::MIN).checked_sub(Money::from(1)),
+            Err(MoneyOverflow)
+        );
+    }
+
+    #[test]
+    fn test_checked_basis_points() {
+        assert_eq!(
+            Money::from(10_00).checked_basis_points(250, Rounding::HalfEven),
+            Ok(Money::from(25))
+        );
+        assert_eq!(
+            Money::from(i32::MAX).checked_basis_points(i32::MAX, Rounding::Floor),
+            Ok(Money::from_cents(
+                i64::from(i32::MAX) * i64::from(i32::MAX) / 10_000
+            ))
+        );
+        assert_eq!(
+            Money::from_cents(i64::MAX).checked_basis_points(2, Rounding::Floor),
+            Err(MoneyOverflow)
+        );
+    }
+
+    #[rstest]
+    // 2.5 cents
+    #[case(1_00, Rounding::HalfEven, 2)]
+    #[case(1_00, Rounding::HalfUp, 3)]
+    #[case(1_00, Rounding::Floor, 2)]
+    // 3.5 cents
+    #[case(1_40, Rounding::HalfEven, 4)]
+    #[case(1_40, Rounding::HalfUp, 4)]
+    #[case(1_40, Rounding::Floor, 3)]
+    // 3.75 cents
+    #[case(1_50, Rounding::HalfEven, 4)]
+    #[case(1_50, Rounding::HalfUp, 4)]
+    #[case(1_50, Rounding::Floor, 3)]
+    // -2.5 cents
+    #[case(-1_00, Rounding::HalfEven, -2)]
+    #[case(-1_00, Rounding::HalfUp, -2)]
+    #[case(-1_00, Rounding::Floor, -3)]
+    fn test_checked_basis_points_rounding(
+        #[case] cents: i32,
+        #[case] rounding: Rounding,
+        #[case] expected_cents: i32,
+    ) {
+        assert_eq!(
+            Money::from(cents).checked_basis_points(250, rounding),
+            Ok(Money::from(expected_cents))
+        );
+    }
+
+    #[test]
+    fn test_try_into_i32() {
+        assert_eq!(i32::try_from(Money::from(i32::MAX)), Ok(i32::MAX));
+        assert_eq!(i32::try_from(Money::from(i32::MIN)), Ok(i32::MIN));
+        assert_eq!(
+            i32::try_from(Money::from(i32::MAX).checked_add(Money::from(1)).unwrap()),
+            Err(MoneyOverflow)
+        );
+    }
+}
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use time::PrimitiveDateTime;
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+use crate::bank::payments::Status;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+pub enum PaymentEventKind {
+    /// The payment was persisted, in its initial status.
+    Created,
+    /// The payment moved to another status (e.g. an authorization was captured).
+    StatusChanged,
+    /// Part of the payment was refunded: the event's amount is the refunded amount.
+    Refunded,
+    /// The payment was archived, its card number being masked.
+    Archived,
+}
+
+/// A change in a payment's state, for audit purposes.
+///
+/// Events are recorded by a database trigger on every write to the payments table,
+/// stamped with the payment's timestamps.
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct PaymentEvent {
+    pub id: i64,
+    pub payment_id: Uuid,
+    pub kind: PaymentEventKind,
+    /// The payment's status after the event.
+    pub status: Status,
+    pub amount: i32,
+    /// Why the payment wasn't approved, if it wasn't.
+    pub reason: Option<String>,
+    pub inserted_at: PrimitiveDateTime,
+}
+
+/// Lists the payment's events, oldest first.
+pub async fn list_for_payment(
+    pool: &PgPool,
+    payment_id: Uuid,
+) -> Result<Vec<PaymentEvent>, sqlx::Error> {
+    sqlx::query_as!(
+        PaymentEvent,
+        r#"
+            SELECT id, payment_id, kind as "kind: _", status as "status: _", amount, reason, inserted_at
+              FROM payment_events
+             WHERE payment_id = $1
+          ORDER BY id
+        "#,
+        payment_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// How many status changes are buffered for slow subscribers, which miss older ones.
+const STATUS_CHANGES_CAPACITY: usize = 1024;
+
+/// A payment's new status, as broadcast by `StatusChanges`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatusChange {
+    pub payment_id: Uuid,
+    pub status: Status,
+}
+
+/// Broadcasts the status changes of payments made by this process, for real-time updates.
+///
+/// Unlike payment events, changes aren't persisted: subscribers only receive those made
+/// after they subscribed.
+#[derive(Debug, Clone)]
+pub struct StatusChanges(broadcast::Sender<StatusChange>);
+
+impl Default for StatusChanges {
+    fn default() -> Self {
+        Self(broadcast::channel(STATUS_CHANGES_CAPACITY).0)
+    }
+}
+
+impl StatusChanges {
+    pub fn publish(&self, payment_id: Uuid, status: Status) {
+        // fails when nobody is subscribed, which is fine
+        let _ = self.0.send(StatusChange { payment_id, status });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<StatusChange> {
+        self.0.subscribe()
+    }
+}
@@ -0,0 +1,316 @@
+//! A durable local queue of payments held on customers' accounts while the database was
+//! unavailable, which are persisted once it recovers.
+//!
+//! Queueing is opt-in, as it trades consistency for availability: until a queued payment
+//! is persisted, it can't be looked up or refunded, and its card number isn't checked for
+//! duplicates. Payments that turn out not to be persistable are dropped, and their holds
+//! released.
+
+use std::{io, net::IpAddr, path::PathBuf, sync::Arc};
+
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgPool};
+use time::PrimitiveDateTime;
+use tokio::{fs, io::AsyncWriteExt, sync::Mutex};
+use uuid::Uuid;
+
+use crate::bank::accounts::{AccountService, HoldRef};
+use crate::bank::config_snapshots::ConfigSnapshot;
+use crate::bank::payments::{self, Channel, Metadata, Payment, Status};
+
+/// A payment approved by the account service, awaiting its insertion.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QueuedPayment {
+    pub id: Uuid,
+    pub amount: i32,
+    pub fee_amount: i32,
+    pub card_number: String,
+    /// Missing from the payments queued before fingerprints were, which are fingerprinted
+    /// on startup once persisted.
+    #[serde(default)]
+    pub card_fingerprint: Option<String>,
+    /// The hold placed for the payment, released if it can't be persisted.
+    pub hold_id: Option<Uuid>,
+    pub currency: Option<String>,
+    pub metadata: Metadata,
+    pub client_ip: Option<IpAddr>,
+    #[serde(default)]
+    pub customer_id: Option<String>,
+    #[serde(default)]
+    pub merchant_id: Option<Uuid>,
+    #[serde(default)]
+    pub merchant_reference: Option<String>,
+    #[serde(default)]
+    pub channel: Option<Channel>,
+    #[serde(default)]
+    pub config_snapshot: Option<ConfigSnapshot>,
+    /// Payments queued before test payments were told apart were live.
+    #[serde(default = "default_livemode")]
+    pub livemode: bool,
+    pub queued_at: PrimitiveDateTime,
+}
+
+fn default_livemode() -> bool {
+    true
+}
+
+impl QueuedPayment {
+    /// Returns the payment as reported to the client until it's persisted: processing.
+    pub fn to_payment(&self) -> Payment {
+        Payment {
+            id: self.id,
+            amount: self.amount,
+            hold_amount: self.amount,
+            hold_id: None,
+            refunded_amount: 0,
+            fee_amount: self.fee_amount,
+            net_amount: self.amount - self.fee_amount,
+            card_number: self.card_number.clone(),
+            status: Status::Processing,
+            decline_reason: None,
+            currency: self.currency.clone(),
+            metadata: Json(self.metadata.clone()),
+            client_ip: self.client_ip.map(|ip| ip.to_string()),
+            customer_id: self.customer_id.clone(),
+            merchant_id: self.merchant_id,
+            merchant_reference: self.merchant_reference.clone(),
+            channel: self.channel,
+            authorized_expires_at: None,
+            livemode: self.livemode,
+            archived_at: None,
+            settled_at: None,
+            inserted_at: self.queued_at,
+            updated_at: self.queued_at,
+        }
+    }
+}
+
+/// Queue of payments stored as JSON lines in a local file.
+#[derive(Debug, Clone)]
+pub struct PaymentQueue {
+    path: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl PaymentQueue {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            lock: Arc::default(),
+        }
+    }
+
+    /// Appends the payment to the queue, returning once it's written to disk.
+    pub async fn enqueue(&self, payment: &QueuedPayment) -> io::Result<()> {
+        let mut line = serde_json::to_vec(payment).expect("failed to serialize queued payment");
+        line.push(b'\n');
+
+        let _guard = self.lock.lock().await;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        file.sync_data().await
+    }
+
+    /// Persists the queued payments, oldest first, returning how many were.
+    ///
+    /// Stops as soon as the database is unavailable again, keeping the payments left.
+    pub async fn drain(
+        &self,
+        pool: &PgPool,
+        account_service: &impl AccountService,
+    ) -> io::Result<usize> {
+        let _guard = self.lock.lock().await;
+        let contents = match fs::read(&self.path).await {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+
+        let mut persisted = 0;
+        let mut remaining = Vec::new();
+        for line in contents.split(|byte| *byte == b'\n') {
+            if line.is_empty() {
+                continue;
+            }
+            if !remaining.is_empty() {
+                remaining.push(line);
+                continue;
+            }
+            let payment: QueuedPayment = match serde_json::from_slice(line) {
+                Ok(payment) => payment,
+                // e.g. a line partially written before a crash
+                Err(err) => {
+                    tracing::error!(error = ?err, "dropping unreadable queued payment");
+                    continue;
+                }
+            };
+            match persist(pool, &payment).await {
+                Ok(()) => persisted += 1,
+                Err(e) if payments::is_database_unavailable(&e) => remaining.push(line),
+                Err(e) => {
+                    tracing::error!(payment_id = %payment.id, error = ?e, "dropping queued payment");
+                    if let Some(hold_id) = payment.hold_id {
+                        if let Err(err) = account_service.release_hold(HoldRef::from(hold_id)).await
+                        {
+                            tracing::error!(payment_id = %payment.id, error = %err, "failed to release hold");
+                        }
+                    }
+                }
+            }
+        }
+
+        if remaining.is_empty() {
+            fs::remove_file(&self.path).await?;
+        } else {
+            // written aside then renamed, so that a crash can't lose the remaining payments
+            let path = self.path.with_extension("tmp");
+            let mut contents = remaining.join(&b'\n');
+            contents.push(b'\n');
+            fs::write(&path, contents).await?;
+            fs::rename(&path, &self.path).await?;
+        }
+
+        Ok(persisted)
+    }
+}
+
+/// Inserts the payment as approved, unless it was already inserted by a previous drain.
+async fn persist(pool: &PgPool, payment: &QueuedPayment) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+               INSERT INTO payments ( id, amount, hold_amount, fee_amount, net_amount, card_number, card_fingerprint, status, currency, metadata, client_ip, customer_id, merchant_id, merchant_reference, channel, config_snapshot, livemode, inserted_at, updated_at )
+               VALUES ( $1, $2, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $17 )
+          ON CONFLICT (id) DO NOTHING
+        "#,
+        payment.id,
+        payment.amount,
+        payment.fee_amount,
+        payment.amount - payment.fee_amount,
+        payment.card_number,
+        payment.card_fingerprint,
+        Status::Approved as Status,
+        payment.currency,
+        serde_json::to_value(&payment.metadata).expect("failed to serialize metadata"),
+        payment.client_ip.map(|ip| ip.to_string()),
+        payment.customer_id,
+        payment.merchant_id,
+        payment.merchant_reference,
+        payment.channel as Option<Channel>,
+        payment
+            .config_snapshot
+            .as_ref()
+            .map(|snapshot| serde_json::to_value(snapshot).expect("failed to serialize snapshot")),
+        payment.livemode,
+        payment.queued_at,
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+}
+
+/// Periodically persists the queued payments.
+pub fn spawn_worker(
+    pool: PgPool,
+    account_service: Arc<dyn AccountService>,
+    queue: PaymentQueue,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match queue.drain(&pool, &account_service).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "persisted queued payments"),
+                Err(err) => tracing::error!(error = ?err, "failed to drain payment queue"),
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::bank::{
+        accounts::DummyService, amount_bounds::AmountBoundsTable, clock::SystemClock,
+        config_snapshots::CardLimits, fees::FeeConfig, fingerprints::FingerprintKey,
+        merchants::MerchantCurrencies, payment_instruments::Card, payments::CreateError,
+        retry_budget::RetryBudget, vault::NoVault,
+    };
+
+    #[tokio::test]
+    async fn test_queue_payments_while_database_is_down() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let unavailable_pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        unavailable_pool.close().await;
+        let queue = PaymentQueue::new(
+            std::env::temp_dir().join(format!("payment-queue-{}.jsonl", Uuid::new_v4())),
+        );
+        let account_service = DummyService::default();
+        let card_number: String = Card::new_test().into();
+
+        let result = payments::create(
+            &unavailable_pool,
+            &account_service,
+            &SystemClock,
+            &FingerprintKey::new_test(),
+            &NoVault,
+            &FeeConfig::default(),
+            false,
+            &AmountBoundsTable::new(),
+            &MerchantCurrencies::new(),
+            &CardLimits::default(),
+            None,
+            Some(&queue),
+            &mut RetryBudget::default(),
+            true,
+            false,
+            12_05,
+            None,
+            &card_number,
+            Status::Approved,
+            Metadata::new(),
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        let Err(CreateError::Queued(queued)) = result else {
+            panic!("payment wasn't queued: {result:?}");
+        };
+        assert_eq!(queued.status, Status::Processing);
+        assert!(matches!(
+            payments::get(&pool, queued.id).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
+
+        // still down
+        assert_eq!(
+            queue
+                .drain(&unavailable_pool, &account_service)
+                .await
+                .unwrap(),
+            0
+        );
+        // recovered
+        assert_eq!(queue.drain(&pool, &account_service).await.unwrap(), 1);
+        assert_eq!(queue.drain(&pool, &account_service).await.unwrap(), 0);
+
+        let payment = payments::get(&pool, queued.id)
+            .await
+            .expect("queued payment wasn't persisted");
+        assert_eq!(payment.status, Status::Approved);
+        assert_eq!(payment.amount, 12_05);
+        assert_eq!(payment.card_number, card_number);
+    }
+}
@@ -1,18 +1,65 @@
 use crate::bank::accounts::{AccountService, HoldRef};
+use crate::bank::amount_bounds::{AmountBounds, AmountBoundsTable};
+use crate::bank::clock::{to_primitive_utc, Clock};
+use crate::bank::config_snapshots::{CardLimits, ConfigSnapshot};
+use crate::bank::fees::FeeConfig;
+use crate::bank::fingerprints::{FingerprintKey, FingerprintKeys};
+use crate::bank::merchants::MerchantCurrencies;
+use crate::bank::payment_events::{self, PaymentEvent, PaymentEventKind, StatusChanges};
+use crate::bank::payment_queue::{PaymentQueue, QueuedPayment};
+use crate::bank::retry_budget::RetryBudget;
+use crate::bank::sandbox::MagicAmounts;
+use crate::bank::vault::CardVault;
+use futures::stream::BoxStream;
 use lazy_static::lazy_static;
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use sqlx::PgPool;
+use serde_json::json;
+use sqlx::{types::Json, PgExecutor, PgPool};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
 use std::str::FromStr;
-use strum_macros::EnumString;
-use time::PrimitiveDateTime;
+use std::sync::Arc;
+use strum::IntoEnumIterator;
+use strum_macros::{Display, EnumIter, EnumString};
+use time::{Date, PrimitiveDateTime};
 use uuid::Uuid;
 
+/// Maximum length, in bytes, of a card number before normalization: generous enough for
+/// 15 digits grouped with spaces or dashes, and cheap to reject anything longer.
+const MAX_CARD_NUMBER_INPUT_LENGTH: usize = 40;
+
+/// Number of trailing card number digits kept when a payment is archived.
+pub const UNMASKED_CARD_DIGITS: i32 = 4;
+
+/// Maximum number of metadata keys on a payment.
+pub const MAX_METADATA_KEYS: usize = 50;
+/// Maximum length of a metadata key, in characters.
+pub const MAX_METADATA_KEY_LENGTH: usize = 40;
+/// Maximum length of a metadata value, in characters.
+pub const MAX_METADATA_VALUE_LENGTH: usize = 500;
+
+/// Maximum length of a customer id, as stored.
+pub const MAX_CUSTOMER_ID_LENGTH: usize = 255;
+/// Maximum length of a merchant reference, as stored.
+pub const MAX_MERCHANT_REFERENCE_LENGTH: usize = 255;
+/// How long issuers keep the funds of an authorization held, after which it can no longer
+/// be captured.
+pub const AUTHORIZATION_LIFETIME: time::Duration = time::Duration::days(7);
+
+/// Decline reason of the processing payments failed by `release_dangling_holds`.
+const DANGLING_HOLD_DECLINE_REASON: &str = "dangling_hold";
+
+/// Arbitrary key-value pairs attached to a payment by the merchant (e.g. order id, cart id).
+pub type Metadata = HashMap<String, String>;
+
 lazy_static! {
     static ref CARD_NUMBER_REGEX: Regex = Regex::new(r"^\d{15}$").unwrap();
+    /// Runs of digits long enough to be card numbers, within some text.
+    static ref CARD_NUMBER_LIKE_REGEX: Regex = Regex::new(r"\d{12,}").unwrap();
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize, sqlx::Type)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Copy, Serialize, Deserialize, sqlx::Type, EnumIter)]
 #[serde(rename_all = "snake_case")]
 pub enum Status {
     /// The payment is being processed, and it's state is unknown.
@@ -23,28 +70,230 @@ pub enum Status {
     Declined,
     /// The payment was unable to complete (e.g. banking system crashed).
     Failed,
+    /// The funds are held on the customer's account, awaiting capture.
+    Authorized,
+    /// The payment is being captured: the account service may have withdrawn the funds.
+    Capturing,
 }
 
-#[derive(Debug)]
+/// Where a payment was made from, as told by the merchant.
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Web,
+    Mobile,
+    /// A point of sale terminal.
+    Pos,
+    /// The merchant's own systems, e.g. recurring billing.
+    Api,
+}
+
+impl Status {
+    /// Returns whether the payment's status can't change anymore.
+    pub fn is_terminal(self) -> bool {
+        matches!(self, Self::Approved | Self::Declined | Self::Failed)
+    }
+}
+
+#[derive(Debug, Display)]
+#[strum(serialize_all = "snake_case")]
 pub enum InvalidArgumentError {
     NegativeAmount,
     ZeroAmount,
+    /// The card number is empty or blank, as opposed to malformed.
+    MissingCardNumber,
     InvalidCardFormat,
+    HoldAmountBelowAmount,
+    MetadataTooLarge,
+    InvalidCurrency,
+    /// The currency is valid, but the payment's merchant isn't enabled for it.
+    UnsupportedCurrency,
+    /// The amount is below the minimum configured for the payment's currency.
+    AmountTooSmall,
+    /// The amount is above the maximum configured for the payment's currency.
+    AmountTooLarge,
+    /// The customer id is empty or longer than `MAX_CUSTOMER_ID_LENGTH`.
+    InvalidCustomerId,
+    /// The merchant reference is empty or longer than `MAX_MERCHANT_REFERENCE_LENGTH`.
+    InvalidMerchantReference,
 }
 
-#[derive(Debug, Eq, PartialEq, EnumString)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, EnumString, Display)]
 #[strum(serialize_all = "snake_case")]
 pub enum AccountServiceError {
     InsufficientFunds,
     InvalidAccountNumber,
     ServiceUnavailable,
     InternalError,
+    /// The issuer declined without telling why.
+    DoNotHonor,
+    ExpiredCard,
+    LostCard,
+    StolenCard,
+    SuspectedFraud,
+}
+
+/// Whose fault an error is, for telling client errors apart from server ones on dashboards
+/// and in logs.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorClass {
+    /// The request can't succeed as sent, e.g. it's invalid or the card was declined.
+    ClientError,
+    /// The bank or one of its services failed, so the request may succeed when retried.
+    ServerError,
+}
+
+impl InvalidArgumentError {
+    /// Invalid arguments are always the client's fault.
+    pub fn class(&self) -> ErrorClass {
+        ErrorClass::ClientError
+    }
+
+    /// Returns the field of the payment request that is invalid.
+    pub fn field(&self) -> &'static str {
+        match self {
+            Self::NegativeAmount
+            | Self::ZeroAmount
+            | Self::AmountTooSmall
+            | Self::AmountTooLarge => "amount",
+            Self::MissingCardNumber | Self::InvalidCardFormat => "card_number",
+            Self::HoldAmountBelowAmount => "hold_amount",
+            Self::MetadataTooLarge => "metadata",
+            Self::InvalidCurrency | Self::UnsupportedCurrency => "currency",
+            Self::InvalidCustomerId => "customer_id",
+            Self::InvalidMerchantReference => "merchant_reference",
+        }
+    }
+}
+
+impl AccountServiceError {
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::InsufficientFunds
+            | Self::InvalidAccountNumber
+            | Self::DoNotHonor
+            | Self::ExpiredCard
+            | Self::LostCard
+            | Self::StolenCard
+            | Self::SuspectedFraud => ErrorClass::ClientError,
+            Self::ServiceUnavailable | Self::InternalError => ErrorClass::ServerError,
+        }
+    }
+
+    /// Returns the status of a payment rejected by the account service with this error.
+    pub fn payment_status(&self) -> Status {
+        match self {
+            Self::InsufficientFunds
+            | Self::InvalidAccountNumber
+            | Self::DoNotHonor
+            | Self::ExpiredCard
+            | Self::LostCard
+            | Self::StolenCard
+            | Self::SuspectedFraud => Status::Declined,
+            Self::ServiceUnavailable | Self::InternalError => Status::Failed,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum CreateError {
     DuplicatedCardNumber,
+    /// The merchant already made an unarchived payment with the merchant reference.
+    DuplicateMerchantReference,
+    /// The payment was made outside the merchant's accepting window.
+    OutsideAcceptingWindow,
+    /// The card is a test card, which is only accepted in sandbox mode.
+    CardNotAccepted,
+    /// The payment would push the amount approved for the card over the velocity limit.
+    VelocityLimitExceeded,
+    /// The card already made the maximum number of payments of the day.
+    DailyCardLimitExceeded,
     InvalidArgument(InvalidArgumentError),
+    /// The account service rejected the payment, which was persisted as declined or failed.
+    AccountService(AccountServiceError, Box<Payment>),
+    /// The database was unavailable, so the held payment was queued to be persisted once
+    /// it recovers. It's processing until then.
+    Queued(Box<Payment>),
+    /// The card vault failed to store the card number.
+    Vault(String),
+    /// An invariant was broken, which is a bug.
+    Internal(String),
+    Database(sqlx::Error),
+}
+
+impl CreateError {
+    /// Returns why the payment wasn't approved, as recorded in `Payment::decline_reason`.
+    pub fn decline_reason(&self) -> Option<String> {
+        match self {
+            Self::DuplicatedCardNumber => Some("duplicated_card_number".into()),
+            Self::DuplicateMerchantReference => Some("duplicate_merchant_reference".into()),
+            Self::OutsideAcceptingWindow => Some("outside_accepting_window".into()),
+            Self::CardNotAccepted => Some("card_not_accepted".into()),
+            Self::VelocityLimitExceeded => Some("velocity_limit_exceeded".into()),
+            Self::DailyCardLimitExceeded => Some("daily_card_limit_exceeded".into()),
+            Self::InvalidArgument(err) => Some(err.to_string()),
+            Self::AccountService(err, _) => Some(err.to_string()),
+            Self::Queued(_) | Self::Vault(_) | Self::Internal(_) | Self::Database(_) => None,
+        }
+    }
+
+    /// Queued payments count as server errors, as the database was unavailable.
+    pub fn class(&self) -> ErrorClass {
+        match self {
+            Self::DuplicatedCardNumber
+            | Self::DuplicateMerchantReference
+            | Self::OutsideAcceptingWindow
+            | Self::CardNotAccepted
+            | Self::VelocityLimitExceeded
+            | Self::DailyCardLimitExceeded => ErrorClass::ClientError,
+            Self::InvalidArgument(err) => err.class(),
+            Self::AccountService(err, _) => err.class(),
+            Self::Queued(_) | Self::Vault(_) | Self::Internal(_) | Self::Database(_) => {
+                ErrorClass::ServerError
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum CaptureError {
+    PaymentNotFound,
+    /// The payment isn't an authorization awaiting capture, or is being captured for
+    /// another amount.
+    NotAuthorized,
+    /// The captured amount exceeds the held amount.
+    ExcessiveAmount,
+    /// The payment was already captured with the idempotency key, for another amount.
+    IdempotencyKeyReused,
+    InvalidArgument(InvalidArgumentError),
+    AccountService(AccountServiceError),
+    /// An invariant was broken, which is a bug.
+    Internal(String),
+    Database(sqlx::Error),
+}
+
+#[derive(Debug)]
+pub enum UpdateMetadataError {
+    PaymentNotFound,
+    InvalidArgument(InvalidArgumentError),
+    Database(sqlx::Error),
+}
+
+#[derive(Debug)]
+pub enum ConfirmHoldError {
+    /// No payment was made with the hold.
+    PaymentNotFound,
+    /// The payment made with the hold isn't processing (e.g. it was confirmed already).
+    NotProcessing,
+    Database(sqlx::Error),
+}
+
+#[derive(Debug)]
+pub enum ForceFailError {
+    PaymentNotFound,
+    /// The payment is already approved, declined or failed.
+    Terminal,
     AccountService(AccountServiceError),
     Database(sqlx::Error),
 }
@@ -57,131 +306,2973 @@ pub enum CreateError {
 pub struct Payment {
     pub id: Uuid,
     pub amount: i32,
+    /// The amount held on the customer's account, which authorizations may set above `amount`.
+    pub hold_amount: i32,
+    /// The hold to capture, for authorized payments.
+    pub hold_id: Option<Uuid>,
     pub refunded_amount: i32,
+    pub fee_amount: i32,
+    pub net_amount: i32,
     pub card_number: String,
     pub status: Status,
+    /// Why the payment wasn't approved, for declined and failed payments.
+    pub decline_reason: Option<String>,
+    /// ISO 4217 code of the payment's currency, if given.
+    pub currency: Option<String>,
+    pub metadata: Json<Metadata>,
+    /// IP address of the client who made the payment, if known.
+    pub client_ip: Option<String>,
+    /// The merchant's identifier of the customer who made the payment, if given.
+    pub customer_id: Option<String>,
+    /// The merchant the payment was made to, if given.
+    pub merchant_id: Option<Uuid>,
+    /// The merchant's own reference of the payment (e.g. an order number), if given.
+    pub merchant_reference: Option<String>,
+    /// Where the payment was made from, if given.
+    pub channel: Option<Channel>,
+    /// When the hold of an authorization lapses, unless it's captured or voided first.
+    pub authorized_expires_at: Option<PrimitiveDateTime>,
+    /// Whether the payment was made live, as opposed to a test payment made in sandbox mode.
+    pub livemode: bool,
+    /// When the payment was archived, its card number being masked.
+    pub archived_at: Option<PrimitiveDateTime>,
+    /// When the payment was settled, if it was created processing to be settled later.
+    pub settled_at: Option<PrimitiveDateTime>,
     pub inserted_at: PrimitiveDateTime,
     pub updated_at: PrimitiveDateTime,
 }
 
-async fn insert(
-    pool: &PgPool,
+/// Attributes of a payment to insert.
+#[derive(Clone)]
+struct NewPayment<'a> {
     amount: i32,
-    card_number: &str,
+    hold_amount: i32,
+    card_number: &'a str,
+    /// Left out of payments whose duplicated card was demoted, for the unique index to let
+    /// them through.
+    card_fingerprint: Option<String>,
     status: Status,
+    fee_amount: i32,
+    decline_reason: Option<String>,
+    hold_id: Option<Uuid>,
+    currency: Option<&'a str>,
+    metadata: Metadata,
+    client_ip: Option<IpAddr>,
+    customer_id: Option<&'a str>,
+    merchant_id: Option<Uuid>,
+    merchant_reference: Option<&'a str>,
+    channel: Option<Channel>,
+    config_snapshot: Option<ConfigSnapshot>,
+    authorized_expires_at: Option<PrimitiveDateTime>,
+    livemode: bool,
+}
+
+impl<'a> NewPayment<'a> {
+    fn new(
+        fingerprint_key: &FingerprintKey,
+        amount: i32,
+        card_number: &'a str,
+        status: Status,
+    ) -> Self {
+        Self {
+            amount,
+            hold_amount: amount,
+            card_number,
+            card_fingerprint: Some(fingerprint_key.fingerprint(card_number)),
+            status,
+            fee_amount: 0,
+            decline_reason: None,
+            hold_id: None,
+            currency: None,
+            metadata: Metadata::new(),
+            client_ip: None,
+            customer_id: None,
+            merchant_id: None,
+            merchant_reference: None,
+            channel: None,
+            config_snapshot: None,
+            authorized_expires_at: None,
+            livemode: true,
+        }
+    }
+}
+
+async fn insert(
+    executor: impl PgExecutor<'_>,
+    payment: NewPayment<'_>,
+    now: PrimitiveDateTime,
 ) -> Result<Payment, sqlx::Error> {
     sqlx::query_as!(
         Payment,
         r#"
-               INSERT INTO payments ( id, amount, card_number, status, inserted_at, updated_at )
-               VALUES ( $1, $2, $3, $4, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP )
-            RETURNING id, amount, refunded_amount, card_number, inserted_at, updated_at, status as "status: _"
+               INSERT INTO payments ( id, amount, hold_amount, hold_id, fee_amount, net_amount, card_number, card_fingerprint, status, decline_reason, currency, metadata, client_ip, customer_id, merchant_id, merchant_reference, channel, config_snapshot, authorized_expires_at, livemode, inserted_at, updated_at )
+               VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $21 )
+            RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
         "#,
         Uuid::new_v4(),
-        amount,
-        card_number.to_string(),
-        status as Status
+        payment.amount,
+        payment.hold_amount,
+        payment.hold_id,
+        payment.fee_amount,
+        payment.amount - payment.fee_amount,
+        payment.card_number,
+        payment.card_fingerprint,
+        payment.status as Status,
+        payment.decline_reason,
+        payment.currency,
+        serde_json::to_value(&payment.metadata).expect("failed to serialize metadata"),
+        payment.client_ip.map(|ip| ip.to_string()),
+        payment.customer_id,
+        payment.merchant_id,
+        payment.merchant_reference,
+        payment.channel as Option<Channel>,
+        payment
+            .config_snapshot
+            .map(|snapshot| serde_json::to_value(snapshot).expect("failed to serialize snapshot")),
+        payment.authorized_expires_at,
+        payment.livemode,
+        now
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await
 }
 
+/// Persists a payment rejected by the account service, along with the reason for dispute handling.
+async fn insert_declined(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    payment: NewPayment<'_>,
+    err: AccountServiceError,
+) -> CreateError {
+    let amount = payment.amount;
+    let payment = NewPayment {
+        status: err.payment_status(),
+        decline_reason: Some(err.to_string()),
+        ..payment
+    };
+    match insert(pool, payment, clock.now_utc()).await {
+        Ok(payment) => CreateError::AccountService(err, Box::new(payment)),
+        Err(e) => create_error_from_database(e, amount),
+    }
+}
+
+/// Leaves the payment unfingerprinted if an unarchived payment was already made with its
+/// card, so that the unique index lets it through rather than rejecting it as duplicated.
+///
+/// Duplicates made concurrently may still both be fingerprinted, so that one is rejected.
+async fn demote_duplicated_card<'a>(
+    pool: &PgPool,
+    payment: NewPayment<'a>,
+) -> Result<NewPayment<'a>, CreateError> {
+    let Some(fingerprint) = &payment.card_fingerprint else {
+        return Ok(payment);
+    };
+    if !is_card_fingerprint_in_use(pool, std::slice::from_ref(fingerprint))
+        .await
+        .map_err(CreateError::Database)?
+    {
+        return Ok(payment);
+    }
+    tracing::warn!(
+        "making a payment with a duplicated card, as duplicated cards are demoted: this is \
+         deprecated, and will be removed along with the unique index"
+    );
+    Ok(NewPayment {
+        card_fingerprint: None,
+        ..payment
+    })
+}
+
+/// Inserts a payment for which `held_amount` was held on the customer's account.
+///
+/// Both amounts come from the same inputs, so a mismatch can only be a logic bug: the
+/// payment is then rolled back rather than persisted with a hold it doesn't reflect.
+async fn insert_held(
+    pool: &PgPool,
+    payment: NewPayment<'_>,
+    now: PrimitiveDateTime,
+    held_amount: i32,
+) -> Result<Payment, CreateError> {
+    let amount = payment.amount;
+    let mut transaction = pool.begin().await.map_err(CreateError::Database)?;
+    let payment = insert(&mut transaction, payment, now)
+        .await
+        .map_err(|e| create_error_from_database(e, amount))?;
+    check_held_amount(held_amount, payment.hold_amount).map_err(CreateError::Internal)?;
+    transaction.commit().await.map_err(CreateError::Database)?;
+
+    Ok(payment)
+}
+
+/// How many times a payment's insertion is attempted when it fails to serialize.
+const MAX_INSERT_ATTEMPTS: u32 = 3;
+/// Delay before retrying an insertion the first time, doubled on each next retry.
+const INSERT_RETRY_BACKOFF: std::time::Duration = std::time::Duration::from_millis(10);
+
+/// Runs the `insert`, retrying with backoff while it fails to serialize or deadlocks with
+/// concurrent transactions, up to `MAX_INSERT_ATTEMPTS` times. Other errors aren't retried.
+async fn retry_serialization_failures<F, Fut>(mut insert: F) -> Result<Payment, CreateError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<Payment, CreateError>>,
+{
+    let mut attempt = 1;
+    loop {
+        match insert().await {
+            Err(CreateError::Database(e))
+                if is_serialization_failure(&e) && attempt < MAX_INSERT_ATTEMPTS =>
+            {
+                tracing::warn!(attempt, error = ?e, "retrying payment insertion after serialization failure");
+                tokio::time::sleep(INSERT_RETRY_BACKOFF * 2u32.pow(attempt - 1)).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Checks that the amount sent to the account service is the one persisted.
+fn check_held_amount(held_amount: i32, persisted_amount: i32) -> Result<(), String> {
+    if held_amount == persisted_amount {
+        Ok(())
+    } else {
+        Err(format!(
+            "held amount {held_amount} differs from persisted amount {persisted_amount}"
+        ))
+    }
+}
+
+/// Queues a held payment whose insertion failed because the database is unavailable,
+/// returning the insertion's error if it can't be queued either.
+async fn enqueue_held(
+    queue: &PaymentQueue,
+    payment: NewPayment<'_>,
+    hold: HoldRef,
+    now: PrimitiveDateTime,
+    e: sqlx::Error,
+) -> CreateError {
+    let queued = QueuedPayment {
+        id: Uuid::new_v4(),
+        amount: payment.amount,
+        fee_amount: payment.fee_amount,
+        card_number: payment.card_number.to_owned(),
+        card_fingerprint: payment.card_fingerprint,
+        hold_id: Some(hold.id()),
+        currency: payment.currency.map(str::to_owned),
+        metadata: payment.metadata,
+        client_ip: payment.client_ip,
+        customer_id: payment.customer_id.map(str::to_owned),
+        merchant_id: payment.merchant_id,
+        merchant_reference: payment.merchant_reference.map(str::to_owned),
+        channel: payment.channel,
+        config_snapshot: payment.config_snapshot,
+        livemode: payment.livemode,
+        queued_at: now,
+    };
+    match queue.enqueue(&queued).await {
+        Ok(()) => {
+            tracing::warn!(payment_id = %queued.id, error = ?e, "queued payment while the database is unavailable");
+            CreateError::Queued(Box::new(queued.to_payment()))
+        }
+        Err(err) => {
+            tracing::error!(error = ?err, "failed to queue payment");
+            CreateError::Database(e)
+        }
+    }
+}
+
+/// Whether the database couldn't be reached, as opposed to rejecting the query.
+pub fn is_database_unavailable(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Io(_)
+        | sqlx::Error::PoolTimedOut
+        | sqlx::Error::PoolClosed
+        | sqlx::Error::WorkerCrashed => true,
+        // 08 = connection_exception, 57P01 = admin_shutdown, 57P03 = cannot_connect_now
+        sqlx::Error::Database(err) => err
+            .code()
+            .is_some_and(|code| code.starts_with("08") || code == "57P01" || code == "57P03"),
+        _ => false,
+    }
+}
+
+/// Whether postgres rejected the connection as it had too many already: a transient lack of
+/// capacity rather than a problem with the request.
+pub fn is_too_many_connections(e: &sqlx::Error) -> bool {
+    // 53300 = too_many_connections
+    e.as_database_error()
+        .and_then(|err| err.code())
+        .is_some_and(|code| code == "53300")
+}
+
+/// Whether the transaction failed because of concurrent ones, and may succeed if retried.
+pub fn is_serialization_failure(e: &sqlx::Error) -> bool {
+    // 40001 = serialization_failure, 40P01 = deadlock_detected
+    e.as_database_error()
+        .and_then(|err| err.code())
+        .is_some_and(|code| code == "40001" || code == "40P01")
+}
+
+/// Maps an error returned by the account service to an `AccountServiceError`.
+///
+/// Messages that aren't a known error are logged and mapped to `InternalError`: a
+/// misbehaving service fails the payment rather than the request handler.
+pub fn account_service_error(msg: String) -> AccountServiceError {
+    AccountServiceError::from_str(msg.as_str()).unwrap_or_else(|_| {
+        tracing::error!(message = %msg, "unrecognized account service error");
+        AccountServiceError::InternalError
+    })
+}
+
+/// Places a hold of `amount` on the account, retrying while the account service is
+/// unavailable and the `retry_budget` allows it.
+///
+/// With `balance_precheck`, the balance is queried first and a hold it can't cover isn't
+/// attempted: this costs an extra request per payment, so it is opt-in. A hold declined for
+/// insufficient funds although the balance sufficed is then retried once, if the balance
+/// still suffices: the contradiction is usually a concurrent change to the account, so the
+/// service's answer to the retry is final.
 async fn hold_account(
     account_service: &impl AccountService,
     card_number: &str,
     amount: i32,
+    balance_precheck: bool,
+    retry_budget: &mut RetryBudget,
 ) -> Result<HoldRef, AccountServiceError> {
-    account_service
-        .place_hold(card_number, amount)
-        .await
-        .map_err(|msg| AccountServiceError::from_str(msg.as_str()).unwrap())
+    // spare the account service a hold attempt when the balance is known to be insufficient
+    let mut balance_sufficed = if balance_precheck {
+        match account_service.query_balance(card_number).await {
+            Some(balance) if balance < amount => {
+                return Err(AccountServiceError::InsufficientFunds)
+            }
+            Some(_) => true,
+            None => false,
+        }
+    } else {
+        false
+    };
+
+    loop {
+        match account_service
+            .place_hold(card_number, amount)
+            .await
+            .map_err(account_service_error)
+        {
+            Err(AccountServiceError::ServiceUnavailable) if retry_budget.try_retry() => {}
+            Err(AccountServiceError::InsufficientFunds) if balance_sufficed => {
+                balance_sufficed = false;
+                let balance = account_service.query_balance(card_number).await;
+                if balance.is_none_or(|balance| balance < amount) {
+                    return Err(AccountServiceError::InsufficientFunds);
+                }
+                tracing::warn!(
+                    "hold declined for insufficient funds despite the balance, retrying"
+                );
+            }
+            result => return result,
+        }
+    }
 }
 
-async fn validate_payment_inputs(
+/// Strips the spaces and dashes users commonly group card digits with.
+fn normalize_card_number(card_number: &str) -> String {
+    card_number
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect()
+}
+
+/// Validates the card number, returning it normalized.
+pub fn validate_card_number(card_number: &str) -> Result<String, InvalidArgumentError> {
+    if card_number.trim().is_empty() {
+        return Err(InvalidArgumentError::MissingCardNumber);
+    }
+    // checked before normalizing and matching, which are linear in the input's length
+    if card_number.len() > MAX_CARD_NUMBER_INPUT_LENGTH {
+        return Err(InvalidArgumentError::InvalidCardFormat);
+    }
+    let card_number = normalize_card_number(card_number);
+    if CARD_NUMBER_REGEX.is_match(&card_number) {
+        Ok(card_number)
+    } else {
+        Err(InvalidArgumentError::InvalidCardFormat)
+    }
+}
+
+/// Validates the metadata against the `MAX_METADATA_*` limits.
+pub fn validate_metadata(metadata: &Metadata) -> Result<(), InvalidArgumentError> {
+    let oversized = metadata.len() > MAX_METADATA_KEYS
+        || metadata.iter().any(|(key, value)| {
+            key.chars().count() > MAX_METADATA_KEY_LENGTH
+                || value.chars().count() > MAX_METADATA_VALUE_LENGTH
+        });
+    if oversized {
+        Err(InvalidArgumentError::MetadataTooLarge)
+    } else {
+        Ok(())
+    }
+}
+
+pub fn validate_customer_id(customer_id: &str) -> Result<(), InvalidArgumentError> {
+    if (1..=MAX_CUSTOMER_ID_LENGTH).contains(&customer_id.len()) {
+        Ok(())
+    } else {
+        Err(InvalidArgumentError::InvalidCustomerId)
+    }
+}
+
+pub fn validate_merchant_reference(merchant_reference: &str) -> Result<(), InvalidArgumentError> {
+    if (1..=MAX_MERCHANT_REFERENCE_LENGTH).contains(&merchant_reference.len()) {
+        Ok(())
+    } else {
+        Err(InvalidArgumentError::InvalidMerchantReference)
+    }
+}
+
+/// Runs every validation of the inputs of `create`, returning all their failures rather
+/// than the first one, e.g. for forms to show them at once.
+///
+/// The amount bounds are only checked once the currency and the amount's sign are valid.
+#[allow(clippy::too_many_arguments)]
+pub fn validate_all(
     amount: i32,
+    currency: Option<&str>,
+    merchant_id: Option<Uuid>,
+    merchant_currencies: &MerchantCurrencies,
+    amount_bounds: &AmountBoundsTable,
     card_number: &str,
+    metadata: &Metadata,
+    customer_id: Option<&str>,
+    merchant_reference: Option<&str>,
+) -> Vec<InvalidArgumentError> {
+    let mut errors = Vec::new();
+    let currency = match currency.map(validate_currency).transpose() {
+        Ok(currency) => currency,
+        Err(err) => {
+            errors.push(err);
+            None
+        }
+    };
+    let allowed_currencies =
+        merchant_id.and_then(|merchant_id| merchant_currencies.get(&merchant_id));
+    if let Some(Err(err)) = currency
+        .as_deref()
+        .map(|currency| check_merchant_currency(currency, allowed_currencies))
+    {
+        errors.push(err);
+    }
+    if amount < 0 {
+        errors.push(InvalidArgumentError::NegativeAmount);
+    } else if amount == 0 {
+        errors.push(InvalidArgumentError::ZeroAmount);
+    } else if let Some(Err(err)) = currency
+        .and_then(|currency| amount_bounds.get(&currency))
+        .map(|bounds| bounds.check(amount))
+    {
+        errors.push(err);
+    }
+    let results = [
+        validate_card_number(card_number).map(|_| ()),
+        validate_metadata(metadata),
+        customer_id.map_or(Ok(()), validate_customer_id),
+        merchant_reference.map_or(Ok(()), validate_merchant_reference),
+    ];
+    errors.extend(results.into_iter().filter_map(Result::err));
+    errors
+}
+
+/// Checks that the merchant is enabled for the (validated) currency, when it's only enabled
+/// for the `allowed_currencies`.
+fn check_merchant_currency(
+    currency: &str,
+    allowed_currencies: Option<&HashSet<String>>,
 ) -> Result<(), InvalidArgumentError> {
+    match allowed_currencies {
+        Some(allowed_currencies) if !allowed_currencies.contains(currency) => {
+            Err(InvalidArgumentError::UnsupportedCurrency)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Validates the payment inputs, returning the normalized card number.
+///
+/// The amount must be positive, and within the `bounds` of the payment's currency if any.
+/// The (validated) currency must be one of the `allowed_currencies` of the payment's merchant
+/// when it's only enabled for some.
+async fn validate_payment_inputs(
+    amount: i32,
+    currency: Option<&str>,
+    allowed_currencies: Option<&HashSet<String>>,
+    bounds: Option<&AmountBounds>,
+    card_number: &str,
+) -> Result<String, InvalidArgumentError> {
     if amount < 0 {
         Err(InvalidArgumentError::NegativeAmount)
     } else if amount == 0 {
         Err(InvalidArgumentError::ZeroAmount)
-    } else if !CARD_NUMBER_REGEX.is_match(card_number) {
-        Err(InvalidArgumentError::InvalidCardFormat)
     } else {
-        Ok(())
+        if let Some(currency) = currency {
+            check_merchant_currency(currency, allowed_currencies)?;
+        }
+        if let Some(bounds) = bounds {
+            bounds.check(amount)?;
+        }
+        validate_card_number(card_number)
+    }
+}
+
+/// Validates an ISO 4217 currency code, returning it uppercased.
+pub fn validate_currency(currency: &str) -> Result<String, InvalidArgumentError> {
+    if currency.len() == 3 && currency.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(currency.to_ascii_uppercase())
+    } else {
+        Err(InvalidArgumentError::InvalidCurrency)
     }
 }
 
+/// Creates a payment, charging the `fees` on it if it is approved.
+///
+/// Its amount must be within the `amount_bounds` of its currency, when it has one, and its
+/// merchant must be enabled for the currency when `merchant_currencies` restricts it.
+///
+/// The fees, currency, bounds and `card_limits` are stored with the payment as its
+/// configuration snapshot.
+///
+/// When `magic_amounts` are given (in sandbox mode only), the payments whose amounts they
+/// map to an error are declined or failed without reaching the account service.
+///
+/// When a `queue` is given, held payments are queued if the database is unavailable.
+///
+/// Holds are retried while the account service is unavailable, as long as the
+/// `retry_budget` allows it.
+///
+/// `balance_precheck` rejects payments exceeding the account's balance, when the account
+/// service exposes it, before placing a hold.
+///
+/// The payment stores the token of its card number in the `card_vault`, which is the card
+/// number itself with `NoVault`. The card is still fingerprinted, and held on, by its number.
+///
+/// Payments made with a card already in use are rejected as duplicated, unless
+/// `demote_duplicate_cards`: they're then made, but left unfingerprinted. This is a
+/// deprecated compatibility toggle, for merchants reusing cards while the unique index is
+/// being dropped.
+#[allow(clippy::too_many_arguments)]
 pub async fn create(
     pool: &PgPool,
     account_service: &impl AccountService,
+    clock: &dyn Clock,
+    fingerprint_key: &FingerprintKey,
+    card_vault: &dyn CardVault,
+    fees: &FeeConfig,
+    balance_precheck: bool,
+    amount_bounds: &AmountBoundsTable,
+    merchant_currencies: &MerchantCurrencies,
+    card_limits: &CardLimits,
+    magic_amounts: Option<&MagicAmounts>,
+    queue: Option<&PaymentQueue>,
+    retry_budget: &mut RetryBudget,
+    livemode: bool,
+    demote_duplicate_cards: bool,
     amount: i32,
+    currency: Option<&str>,
     card_number: &str,
     status: Status,
+    metadata: Metadata,
+    client_ip: Option<IpAddr>,
+    customer_id: Option<&str>,
+    merchant_id: Option<Uuid>,
+    merchant_reference: Option<&str>,
+    channel: Option<Channel>,
 ) -> Result<Payment, CreateError> {
-    validate_payment_inputs(amount, card_number)
-        .await
+    let currency = currency
+        .map(validate_currency)
+        .transpose()
+        .map_err(CreateError::InvalidArgument)?;
+    let bounds = currency
+        .as_ref()
+        .and_then(|currency| amount_bounds.get(currency));
+    let allowed_currencies =
+        merchant_id.and_then(|merchant_id| merchant_currencies.get(&merchant_id));
+    let card_number = validate_payment_inputs(
+        amount,
+        currency.as_deref(),
+        allowed_currencies,
+        bounds,
+        card_number,
+    )
+    .await
+    .map_err(CreateError::InvalidArgument)?;
+    validate_metadata(&metadata).map_err(CreateError::InvalidArgument)?;
+    customer_id
+        .map(validate_customer_id)
+        .transpose()
         .map_err(CreateError::InvalidArgument)?;
-    let _ = hold_account(account_service, card_number, amount)
+    merchant_reference
+        .map(validate_merchant_reference)
+        .transpose()
+        .map_err(CreateError::InvalidArgument)?;
+    let card_number = card_number.as_str();
+    let stored_card_number = card_vault
+        .store(card_number)
         .await
-        .map_err(CreateError::AccountService)?;
-    insert(pool, amount, card_number, status)
+        .map_err(CreateError::Vault)?;
+    let payment = NewPayment {
+        card_number: &stored_card_number,
+        currency: currency.as_deref(),
+        metadata,
+        client_ip,
+        customer_id,
+        merchant_id,
+        merchant_reference,
+        channel,
+        config_snapshot: Some(ConfigSnapshot::new(
+            fees,
+            currency.as_deref(),
+            bounds,
+            card_limits,
+        )),
+        livemode,
+        ..NewPayment::new(fingerprint_key, amount, card_number, status)
+    };
+    let payment = if demote_duplicate_cards {
+        demote_duplicated_card(pool, payment).await?
+    } else {
+        payment
+    };
+    if let Some(err) = magic_amounts.and_then(|magic_amounts| magic_amounts.outcome(amount)) {
+        return Err(insert_declined(pool, clock, payment, err).await);
+    }
+    let hold = match hold_account(
+        account_service,
+        card_number,
+        amount,
+        balance_precheck,
+        retry_budget,
+    )
+    .await
+    {
+        Ok(hold) => hold,
+        Err(err) => return Err(insert_declined(pool, clock, payment, err).await),
+    };
+    let fee_amount = if status == Status::Approved {
+        fees.fee_for(amount)
+    } else {
+        0
+    };
+    // processing payments keep their hold id, for it to be released if they are cancelled
+    // or confirmed asynchronously by the account service
+    let payment = NewPayment {
+        fee_amount,
+        hold_id: (status == Status::Processing).then(|| hold.id()),
+        ..payment
+    };
+    let now = clock.now_utc();
+    let result =
+        retry_serialization_failures(|| insert_held(pool, payment.clone(), now, amount)).await;
+    let err = match (result, queue) {
+        (Ok(payment), _) => return Ok(payment),
+        (Err(CreateError::Database(e)), Some(queue)) if is_database_unavailable(&e) => {
+            enqueue_held(queue, payment, hold, now, e).await
+        }
+        (Err(err), _) => err,
+    };
+    // queued payments keep their hold, for the payment to be inserted with it later
+    if !matches!(err, CreateError::Queued(_)) {
+        release_unpersisted_hold(account_service, hold).await;
+    }
+    Err(err)
+}
+
+/// Releases the hold of a payment that failed to be persisted, which nothing would release
+/// later. Failing to release it is only logged, the payment's own error being the one
+/// reported.
+async fn release_unpersisted_hold(account_service: &impl AccountService, hold: HoldRef) {
+    if let Err(msg) = account_service.release_hold(hold).await {
+        tracing::error!(hold_id = %hold.id(), error = %msg, "failed to release hold of unpersisted payment");
+    }
+}
+
+/// Authorizes a payment: funds are held on the customer's account until the payment is captured.
+///
+/// `hold_amount` defaults to `amount`, and may be set above it when the eventual captured
+/// amount isn't known yet (e.g. gas stations, hotels).
+#[allow(clippy::too_many_arguments)]
+pub async fn authorize(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    balance_precheck: bool,
+    fingerprint_key: &FingerprintKey,
+    amount: i32,
+    hold_amount: Option<i32>,
+    card_number: &str,
+    client_ip: Option<IpAddr>,
+    livemode: bool,
+) -> Result<Payment, CreateError> {
+    let card_number = validate_payment_inputs(amount, None, None, None, card_number)
         .await
-        // TODO: call account_service.release_hold(hold_ref)
-        .map_err(|e| {
-            let err = e.as_database_error().unwrap();
-            if err.code().unwrap() == "23505"
-                && err.constraint() == Some("payments_card_number_index")
-            {
-                CreateError::DuplicatedCardNumber
-            } else {
-                CreateError::Database(e)
-            }
-        })
+        .map_err(CreateError::InvalidArgument)?;
+    let card_number = card_number.as_str();
+    let hold_amount = hold_amount.unwrap_or(amount);
+    if hold_amount < amount {
+        return Err(CreateError::InvalidArgument(
+            InvalidArgumentError::HoldAmountBelowAmount,
+        ));
+    }
+    let payment = NewPayment {
+        hold_amount,
+        client_ip,
+        livemode,
+        ..NewPayment::new(fingerprint_key, amount, card_number, Status::Authorized)
+    };
+    let hold_ref = match hold_account(
+        account_service,
+        card_number,
+        hold_amount,
+        balance_precheck,
+        &mut RetryBudget::default(),
+    )
+    .await
+    {
+        Ok(hold_ref) => hold_ref,
+        Err(err) => return Err(insert_declined(pool, clock, payment, err).await),
+    };
+    let now = clock.now_utc();
+    let payment = NewPayment {
+        hold_id: Some(hold_ref.id()),
+        authorized_expires_at: Some(now + AUTHORIZATION_LIFETIME),
+        ..payment
+    };
+    let result =
+        retry_serialization_failures(|| insert_held(pool, payment.clone(), now, hold_amount)).await;
+    if result.is_err() {
+        release_unpersisted_hold(account_service, hold_ref).await;
+    }
+    result
 }
 
-pub async fn get(pool: &PgPool, id: Uuid) -> Result<Payment, sqlx::Error> {
-    sqlx::query_as!(
+/// A captured payment.
+#[derive(Debug)]
+pub struct Capture {
+    pub payment: Payment,
+    /// Whether the payment was already captured by a previous attempt of the capture.
+    pub retried: bool,
+}
+
+/// Captures an authorized payment, settling `amount` (defaulting to the authorized amount)
+/// and releasing the rest of the hold.
+///
+/// A capture is final: `AccountService::capture_hold` releases whatever it doesn't withdraw,
+/// so no part of the hold is left to capture later. Capturing up to the whole hold is done
+/// in one go, with an `amount` of up to `hold_amount`.
+///
+/// The `fees` are charged on the captured amount, which becomes the payment's amount.
+///
+/// The capture is committed to, leaving the payment `Capturing`, before the account service
+/// is asked to capture the hold, and only recorded as approved once it did. Should that fail
+/// in between, capturing the payment again resumes the capture, for the same amount.
+///
+/// Retrying a capture with the same `idempotency_key` returns the captured payment without
+/// settling it again, or fails with `IdempotencyKeyReused` if another amount was captured.
+pub async fn capture(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    fees: &FeeConfig,
+    id: Uuid,
+    amount: Option<i32>,
+    idempotency_key: Option<&str>,
+) -> Result<Capture, CaptureError> {
+    // the payment stays locked until the capture is committed to, so that it can't be
+    // captured twice
+    let mut transaction = pool.begin().await.map_err(CaptureError::Database)?;
+    let payment = sqlx::query_as!(
         Payment,
         r#"
-            SELECT id, amount, refunded_amount, card_number, inserted_at, updated_at, status as "status: _"
+            SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
               FROM payments
              WHERE id = $1
+               FOR UPDATE
         "#,
         id
     )
-    .fetch_one(pool)
+    .fetch_optional(&mut transaction)
     .await
-}
+    .map_err(CaptureError::Database)?
+    .ok_or(CaptureError::PaymentNotFound)?;
 
-#[cfg(test)]
-pub mod tests {
+    if let (Status::Approved, Some(idempotency_key)) = (payment.status, idempotency_key) {
+        let captured_with = sqlx::query_scalar!(
+            "SELECT capture_idempotency_key FROM payments WHERE id = $1",
+            id
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .map_err(CaptureError::Database)?;
+        if captured_with.as_deref() == Some(idempotency_key) {
+            return if amount.is_none_or(|amount| amount == payment.amount) {
+                Ok(Capture {
+                    payment,
+                    retried: true,
+                })
+            } else {
+                Err(CaptureError::IdempotencyKeyReused)
+            };
+        }
+    }
+    let hold_id = match (payment.status, payment.hold_id) {
+        (Status::Authorized, Some(hold_id)) => hold_id,
+        (Status::Capturing, Some(hold_id))
+            if amount.is_none_or(|amount| amount == payment.amount) =>
+        {
+            hold_id
+        }
+        _ => return Err(CaptureError::NotAuthorized),
+    };
+    let amount = amount.unwrap_or(payment.amount);
+    if amount < 0 {
+        return Err(CaptureError::InvalidArgument(
+            InvalidArgumentError::NegativeAmount,
+        ));
+    } else if amount == 0 {
+        return Err(CaptureError::InvalidArgument(
+            InvalidArgumentError::ZeroAmount,
+        ));
+    } else if amount > payment.hold_amount {
+        return Err(CaptureError::ExcessiveAmount);
+    }
 
-    use super::*;
-    use crate::bank::payment_instruments::Card;
+    sqlx::query!(
+        r#"
+            UPDATE payments
+               SET status = 'Capturing',
+                   amount = $2,
+                   updated_at = $3
+             WHERE id = $1
+        "#,
+        id,
+        amount,
+        clock.now_utc()
+    )
+    .execute(&mut transaction)
+    .await
+    .map_err(CaptureError::Database)?;
+    transaction.commit().await.map_err(CaptureError::Database)?;
 
-    pub const PAYMENT_AMOUNT: i32 = 1_23;
-    pub const PAYMENT_STATUS: Status = Status::Approved;
+    account_service
+        .capture_hold(HoldRef::from(hold_id), amount)
+        .await
+        .map_err(|msg| CaptureError::AccountService(account_service_error(msg)))?;
 
-    impl Payment {
-        pub async fn new_test(pool: &PgPool) -> Result<Payment, sqlx::Error> {
-            let card_number: String = Card::new_test().into();
+    let mut transaction = pool.begin().await.map_err(CaptureError::Database)?;
+    let fee_amount = fees.fee_for(amount);
+    // a concurrent attempt may have resumed and recorded the capture meanwhile
+    let payment = sqlx::query_as!(
+        Payment,
+        r#"
+               UPDATE payments
+                  SET status = 'Approved',
+                      amount = $2,
+                      fee_amount = $3,
+                      net_amount = $4,
+                      hold_id = NULL,
+                      captured_at = $5,
+                      capture_idempotency_key = $6,
+                      updated_at = $5
+                WHERE id = $1 AND status = 'Capturing'
+            RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+        "#,
+        id,
+        amount,
+        fee_amount,
+        amount - fee_amount,
+        clock.now_utc(),
+        idempotency_key
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(CaptureError::Database)?;
+    let Some(payment) = payment else {
+        let payment = get(pool, id).await.map_err(CaptureError::Database)?;
+        return Ok(Capture {
+            payment,
+            retried: true,
+        });
+    };
+    check_held_amount(amount, payment.amount).map_err(CaptureError::Internal)?;
 
-            insert(pool, PAYMENT_AMOUNT, card_number.as_str(), PAYMENT_STATUS).await
-        }
-    }
+    transaction.commit().await.map_err(CaptureError::Database)?;
 
-    #[tokio::test]
-    async fn test_payment() {
-        let pool = crate::pg_pool()
-            .await
-            .expect("failed to connect to postgres");
+    Ok(Capture {
+        payment,
+        retried: false,
+    })
+}
 
-        let payment = Payment::new_test(&pool)
-            .await
-            .expect("failed to create payment");
+/// Name of the unique index on card fingerprints of unarchived payments, as created by the
+/// `add_payment_card_fingerprint` migration.
+///
+/// Renaming the index would otherwise silently turn duplicates into `CreateError::Database`.
+pub const CARD_FINGERPRINT_INDEX: &str = "payments_card_fingerprint_index";
+/// Name of the unique index on the merchant references of unarchived payments, as created
+/// by the `add_payment_merchant_reference_unique` migration.
+pub const MERCHANT_REFERENCE_INDEX: &str = "payments_merchant_reference_index";
+/// Name of the CHECK constraint on amounts, as created by the `add_payment_amount_checks`
+/// migration.
+pub const AMOUNT_CHECK: &str = "payments_amount_check";
+/// Name of the CHECK constraint keeping refunds within the amount, as created by the
+/// `add_payment_amount_checks` migration.
+pub const REFUNDED_AMOUNT_CHECK: &str = "payments_refunded_amount_check";
 
-        assert_eq!(payment.amount, PAYMENT_AMOUNT);
-        assert_eq!(payment.status, PAYMENT_STATUS);
+/// Maps the constraint violations raised when inserting a payment to the
+/// matching `CreateError`.
+///
+/// The CHECK constraints on the payments table mirror the validation done in
+/// `validate_payment_inputs`, so writes bypassing it are rejected the same way.
+fn create_error_from_database(e: sqlx::Error, amount: i32) -> CreateError {
+    let Some(err) = e.as_database_error() else {
+        return CreateError::Database(e);
+    };
+    // postgresql error codes: https://www.postgresql.org/docs/current/errcodes-appendix.html
+    // 23505 = unique_violation, 23514 = check_violation
+    match (err.code().as_deref(), err.constraint()) {
+        (Some("23505"), Some(CARD_FINGERPRINT_INDEX)) => CreateError::DuplicatedCardNumber,
+        (Some("23505"), Some(MERCHANT_REFERENCE_INDEX)) => CreateError::DuplicateMerchantReference,
+        (Some("23514"), Some(AMOUNT_CHECK)) if amount == 0 => {
+            CreateError::InvalidArgument(InvalidArgumentError::ZeroAmount)
+        }
+        (Some("23514"), Some(AMOUNT_CHECK)) => {
+            CreateError::InvalidArgument(InvalidArgumentError::NegativeAmount)
+        }
+        _ => CreateError::Database(e),
+    }
+}
+
+pub async fn get(pool: &PgPool, id: Uuid) -> Result<Payment, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+            SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+              FROM payments
+             WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns the merchant's unarchived payment with the `merchant_reference`, if any.
+///
+/// There's at most one, as the unique index on merchant references guarantees. Payments of
+/// other merchants are never returned, even when they use the same reference.
+pub async fn get_by_reference(
+    pool: &PgPool,
+    merchant_id: Uuid,
+    merchant_reference: &str,
+) -> Result<Option<Payment>, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+            SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+              FROM payments
+             WHERE merchant_id = $1
+               AND merchant_reference = $2
+               AND archived_at IS NULL
+        "#,
+        merchant_id,
+        merchant_reference
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Returns the configuration the payment was created under, or `None` for payments created
+/// without one (e.g. authorizations, or payments created before snapshots were stored).
+pub async fn get_config_snapshot(
+    pool: &PgPool,
+    id: Uuid,
+) -> Result<Option<ConfigSnapshot>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            SELECT config_snapshot as "config_snapshot: Json<ConfigSnapshot>"
+              FROM payments
+             WHERE id = $1
+        "#,
+        id
+    )
+    .fetch_one(pool)
+    .await
+    .map(|snapshot| snapshot.map(|Json(snapshot)| snapshot))
+}
+
+/// Fingerprints the card numbers of the unarchived payments inserted before fingerprints
+/// were, returning how many were.
+///
+/// Their card numbers were unique, so their fingerprints are too. Payments whose duplicated
+/// card was demoted are left unfingerprinted, as their card is fingerprinted already.
+pub async fn backfill_card_fingerprints(
+    pool: &PgPool,
+    fingerprint_key: &FingerprintKey,
+) -> Result<u64, sqlx::Error> {
+    let payments = sqlx::query!(
+        r#"
+            SELECT id, card_number
+              FROM payments
+             WHERE card_fingerprint IS NULL
+               AND archived_at IS NULL
+        "#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut count = 0;
+    let mut transaction = pool.begin().await?;
+    for payment in &payments {
+        let result = sqlx::query!(
+            r#"
+                UPDATE payments
+                   SET card_fingerprint = $2
+                 WHERE id = $1
+                   AND NOT EXISTS (
+                       SELECT 1
+                         FROM payments
+                        WHERE card_fingerprint = $2
+                          AND archived_at IS NULL
+                   )
+            "#,
+            payment.id,
+            fingerprint_key.fingerprint(&payment.card_number)
+        )
+        .execute(&mut transaction)
+        .await?;
+        count += result.rows_affected();
+    }
+    transaction.commit().await?;
+
+    Ok(count)
+}
+
+/// Returns whether an unarchived payment was made with the card of one of the
+/// `fingerprints`, e.g. those made with the previous key during a rotation.
+///
+/// Like the unique index on fingerprints, declined and failed payments don't use up their
+/// card.
+pub async fn is_card_fingerprint_in_use(
+    pool: &PgPool,
+    fingerprints: &[String],
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            SELECT EXISTS (
+                SELECT 1
+                  FROM payments
+                 WHERE card_fingerprint = ANY($1)
+                   AND archived_at IS NULL
+                   AND status NOT IN ('Declined', 'Failed')
+            ) as "exists!"
+        "#,
+        fingerprints
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Fingerprints the card numbers of every unarchived payment again with `fingerprint_key`,
+/// `batch_size` payments per transaction, returning how many were.
+///
+/// Archived payments are left as they are: their card numbers are masked, so their
+/// fingerprints can't be made again. So are those whose duplicated card was demoted.
+pub async fn refingerprint_cards(
+    pool: &PgPool,
+    fingerprint_key: &FingerprintKey,
+    batch_size: i64,
+) -> Result<u64, sqlx::Error> {
+    let mut count = 0;
+    let mut after_id = Uuid::nil();
+    loop {
+        let mut transaction = pool.begin().await?;
+        let payments = sqlx::query!(
+            r#"
+                SELECT id, card_number
+                  FROM payments
+                 WHERE id > $1
+                   AND archived_at IS NULL
+                   AND card_fingerprint IS NOT NULL
+              ORDER BY id
+                 LIMIT $2
+                   FOR UPDATE
+            "#,
+            after_id,
+            batch_size
+        )
+        .fetch_all(&mut transaction)
+        .await?;
+        let Some(last) = payments.last() else {
+            return Ok(count);
+        };
+        after_id = last.id;
+
+        let ids: Vec<_> = payments.iter().map(|payment| payment.id).collect();
+        let fingerprints: Vec<_> = payments
+            .iter()
+            .map(|payment| fingerprint_key.fingerprint(&payment.card_number))
+            .collect();
+        sqlx::query!(
+            r#"
+                UPDATE payments
+                   SET card_fingerprint = fingerprints.card_fingerprint
+                  FROM unnest($1::uuid[], $2::text[]) AS fingerprints(id, card_fingerprint)
+                 WHERE payments.id = fingerprints.id
+            "#,
+            &ids,
+            &fingerprints
+        )
+        .execute(&mut transaction)
+        .await?;
+        transaction.commit().await?;
+        count += payments.len() as u64;
+    }
+}
+
+/// Counts the payments in each status, including those no payment is in. Test payments
+/// are left out unless `include_test`.
+pub async fn count_by_status(
+    pool: &PgPool,
+    include_test: bool,
+) -> Result<HashMap<Status, i64>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT status as "status!: Status", COUNT(*) as "count!"
+              FROM payments
+             WHERE livemode OR $1
+          GROUP BY status
+        "#,
+        include_test
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut counts: HashMap<Status, i64> = Status::iter().map(|status| (status, 0)).collect();
+    counts.extend(rows.into_iter().map(|row| (row.status, row.count)));
+
+    Ok(counts)
+}
+
+/// The number of approved payments with an amount from `bucket_start` included to
+/// `bucket_end` excluded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistogramBucket {
+    pub bucket_start: i64,
+    pub bucket_end: i64,
+    pub count: i64,
+}
+
+/// Counts the approved payments per bucket of `bucket_size` cents of their amount, in
+/// increasing order and omitting empty buckets.
+///
+/// `bucket_size` must be positive. Test payments are left out unless `include_test`.
+pub async fn amount_histogram(
+    pool: &PgPool,
+    bucket_size: i32,
+    include_test: bool,
+) -> Result<Vec<HistogramBucket>, sqlx::Error> {
+    assert!(bucket_size > 0, "bucket size must be positive");
+    sqlx::query_as!(
+        HistogramBucket,
+        r#"
+              SELECT (amount / $2)::bigint * $2 as "bucket_start!",
+                     (amount / $2)::bigint * $2 + $2 as "bucket_end!",
+                     COUNT(*) as "count!"
+                FROM payments
+               WHERE status = $1
+                 AND (livemode OR $3)
+            GROUP BY 1
+            ORDER BY 1
+        "#,
+        Status::Approved as Status,
+        bucket_size,
+        include_test
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The 50th, 90th and 99th percentiles of the amounts of approved payments, interpolated
+/// between amounts. They're all null when no payment was approved.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AmountPercentiles {
+    pub p50: Option<f64>,
+    pub p90: Option<f64>,
+    pub p99: Option<f64>,
+}
+
+/// Computes the percentiles of the amounts of the payments approved from `from` to `to`
+/// included (in UTC).
+///
+/// Test payments are left out unless `include_test`.
+pub async fn amount_percentiles(
+    pool: &PgPool,
+    from: Date,
+    to: Date,
+    include_test: bool,
+) -> Result<AmountPercentiles, sqlx::Error> {
+    sqlx::query_as!(
+        AmountPercentiles,
+        r#"
+            SELECT percentile_cont(0.5) WITHIN GROUP (ORDER BY amount) as p50,
+                   percentile_cont(0.9) WITHIN GROUP (ORDER BY amount) as p90,
+                   percentile_cont(0.99) WITHIN GROUP (ORDER BY amount) as p99
+              FROM payments
+             WHERE status = $1
+               AND inserted_at >= $2::date
+               AND inserted_at < $3::date + 1
+               AND (livemode OR $4)
+        "#,
+        Status::Approved as Status,
+        from,
+        to,
+        include_test
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns the payments approved on `date` (in UTC), oldest first. Test payments are left
+/// out unless `include_test`.
+pub async fn approved_on(
+    pool: &PgPool,
+    date: Date,
+    include_test: bool,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+              SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                FROM payments
+               WHERE status = $1
+                 AND inserted_at >= $2::date
+                 AND inserted_at < $2::date + 1
+                 AND (livemode OR $3)
+            ORDER BY inserted_at, id
+        "#,
+        Status::Approved as Status,
+        date,
+        include_test
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// The amounts of the authorizations made on a day: still held, or captured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HoldsSummary {
+    /// Held by the authorizations not captured nor voided yet.
+    pub total_held: i64,
+    /// Captured, before refunds.
+    pub total_settled: i64,
+    pub count_open_authorizations: i64,
+}
+
+/// Sums the amounts still held and captured of the authorizations made on `date`, or
+/// ever if `None`. Test authorizations are left out unless `include_test`.
+pub async fn holds_vs_settled(
+    pool: &PgPool,
+    date: Option<Date>,
+    include_test: bool,
+) -> Result<HoldsSummary, sqlx::Error> {
+    sqlx::query_as!(
+        HoldsSummary,
+        r#"
+            SELECT COALESCE(SUM(hold_amount) FILTER (WHERE status = $1 AND hold_id IS NOT NULL), 0) as "total_held!",
+                   COALESCE(SUM(amount) FILTER (WHERE captured_at IS NOT NULL), 0) as "total_settled!",
+                   COUNT(*) FILTER (WHERE status = $1 AND hold_id IS NOT NULL) as "count_open_authorizations!"
+              FROM payments
+             WHERE ($2::date IS NULL
+                    OR (inserted_at >= $2::date AND inserted_at < $2::date + 1))
+               AND (livemode OR $3)
+        "#,
+        Status::Authorized as Status,
+        date as Option<Date>,
+        include_test
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// What a merchant is owed for a period: its approved payments less their fees and the
+/// refunds issued.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetSettlement {
+    /// Approved amounts, before refunds and fees.
+    pub gross: i64,
+    pub refunds: i64,
+    pub fees: i64,
+    pub net: i64,
+}
+
+/// Computes the net settlement of the merchant's payments approved from `from` to `to`
+/// included (in UTC), less the approved refunds issued in that period.
+///
+/// Test payments and their refunds are left out unless `include_test`.
+pub async fn net_settlement(
+    pool: &PgPool,
+    merchant_id: Uuid,
+    from: Date,
+    to: Date,
+    include_test: bool,
+) -> Result<NetSettlement, sqlx::Error> {
+    sqlx::query_as!(
+        NetSettlement,
+        r#"
+            WITH approved AS (
+                SELECT COALESCE(SUM(amount), 0) as gross, COALESCE(SUM(fee_amount), 0) as fees
+                  FROM payments
+                 WHERE merchant_id = $1
+                   AND status = 'Approved'
+                   AND inserted_at >= $2::date
+                   AND inserted_at < $3::date + 1
+                   AND (livemode OR $4)
+            ), refunded AS (
+                SELECT COALESCE(SUM(refunds.amount), 0) as refunds
+                  FROM refunds
+                  JOIN payments ON payments.id = refunds.payment_id
+                 WHERE payments.merchant_id = $1
+                   AND refunds.status = 'Approved'
+                   AND refunds.inserted_at >= $2::date
+                   AND refunds.inserted_at < $3::date + 1
+                   AND (payments.livemode OR $4)
+            )
+            SELECT gross as "gross!", refunds as "refunds!", fees as "fees!", gross - refunds - fees as "net!"
+              FROM approved, refunded
+        "#,
+        merchant_id,
+        from,
+        to,
+        include_test
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns up to `limit` of the unarchived payments made by the merchant's customer,
+/// oldest first, only those made from `channel` if given.
+pub async fn list_by_customer(
+    pool: &PgPool,
+    customer_id: &str,
+    channel: Option<Channel>,
+    limit: i64,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+              SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                FROM payments
+               WHERE customer_id = $1
+                 AND ($2::channel IS NULL OR channel = $2)
+                 AND archived_at IS NULL
+            ORDER BY inserted_at, id
+               LIMIT $3
+        "#,
+        customer_id,
+        channel as Option<Channel>,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Lists the payments inserted from `since` up to `until`, newest first, omitting archived
+/// ones.
+pub async fn list_inserted_between(
+    pool: &PgPool,
+    since: PrimitiveDateTime,
+    until: PrimitiveDateTime,
+    limit: i64,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+              SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                FROM payments
+               WHERE inserted_at BETWEEN $1 AND $2
+                 AND archived_at IS NULL
+            ORDER BY inserted_at DESC, id DESC
+               LIMIT $3
+        "#,
+        since,
+        until,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Lists the authorizations awaiting capture whose holds lapse within `within` from now,
+/// soonest first. Those which already lapsed are omitted.
+pub async fn list_expiring_authorizations(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    within: time::Duration,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let now = clock.now_utc();
+    sqlx::query_as!(
+        Payment,
+        r#"
+              SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                FROM payments
+               WHERE status = $1
+                 AND authorized_expires_at > $2
+                 AND authorized_expires_at <= $3
+            ORDER BY authorized_expires_at, id
+        "#,
+        Status::Authorized as Status,
+        now,
+        now + within
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Lists the payments still processing or awaiting capture with a hold placed more than
+/// `older_than` ago, oldest first, e.g. as a callback confirming the hold was lost.
+pub async fn list_stuck_holds(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    older_than: time::Duration,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+              SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                FROM payments
+               WHERE status IN ($1, $2)
+                 AND hold_id IS NOT NULL
+                 AND inserted_at <= $3
+            ORDER BY inserted_at, id
+        "#,
+        Status::Processing as Status,
+        Status::Authorized as Status,
+        clock.now_utc() - older_than
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Summary of the payments made with a card.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CardHistory {
+    pub count: i64,
+    /// When the first payment was made, `None` if there is none.
+    pub first_seen: Option<PrimitiveDateTime>,
+    /// When the last payment was made, `None` if there is none.
+    pub last_seen: Option<PrimitiveDateTime>,
+}
+
+/// Summarizes the payments made with the (normalized) card number.
+///
+/// Payments are matched by the card's `fingerprints`, as their card number may be a vault
+/// token or masked by archival, as well as by the card number itself for those whose
+/// duplicated card was demoted, which are left unfingerprinted.
+pub async fn card_history(
+    pool: &PgPool,
+    fingerprints: &[String],
+    card_number: &str,
+) -> Result<CardHistory, sqlx::Error> {
+    sqlx::query_as!(
+        CardHistory,
+        r#"
+            SELECT COUNT(*) as "count!", MIN(inserted_at) as first_seen, MAX(inserted_at) as last_seen
+              FROM payments
+             WHERE card_fingerprint = ANY($1) OR card_number = $2
+        "#,
+        fingerprints,
+        card_number
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns the total amount approved for the card since `since`, matching its payments as
+/// `card_history` does.
+pub async fn approved_amount(
+    pool: &PgPool,
+    fingerprints: &[String],
+    card_number: &str,
+    since: PrimitiveDateTime,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            SELECT COALESCE(SUM(amount), 0) as "amount!"
+              FROM payments
+             WHERE (card_fingerprint = ANY($1) OR card_number = $2)
+               AND status = $3 AND inserted_at >= $4
+        "#,
+        fingerprints,
+        card_number,
+        Status::Approved as Status,
+        since
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns the number of payments held on the card since `since`, whether approved,
+/// processing or awaiting capture, matching its payments as `card_history` does.
+pub async fn count_held(
+    pool: &PgPool,
+    fingerprints: &[String],
+    card_number: &str,
+    since: PrimitiveDateTime,
+) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            SELECT COUNT(*) as "count!"
+              FROM payments
+             WHERE (card_fingerprint = ANY($1) OR card_number = $2)
+               AND status IN ($3, $4, $5) AND inserted_at >= $6
+        "#,
+        fingerprints,
+        card_number,
+        Status::Approved as Status,
+        Status::Processing as Status,
+        Status::Authorized as Status,
+        since
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Returns the latest approved or processing payment of `amount` the merchant made since
+/// `since` with the card of one of the `fingerprints` and `merchant_reference`, if any.
+///
+/// Payments of other merchants are never returned, even when they use the same reference.
+pub async fn find_repeated(
+    pool: &PgPool,
+    fingerprints: &[String],
+    amount: i32,
+    merchant_id: Uuid,
+    merchant_reference: &str,
+    since: PrimitiveDateTime,
+) -> Result<Option<Payment>, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+              SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                FROM payments
+               WHERE merchant_id = $1
+                 AND merchant_reference = $2
+                 AND card_fingerprint = ANY($3)
+                 AND amount = $4
+                 AND status IN ($5, $6)
+                 AND inserted_at >= $7
+            ORDER BY inserted_at DESC
+               LIMIT 1
+        "#,
+        merchant_id,
+        merchant_reference,
+        fingerprints,
+        amount,
+        Status::Approved as Status,
+        Status::Processing as Status,
+        since
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// How masked card numbers are displayed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, EnumString)]
+#[strum(serialize_all = "snake_case")]
+pub enum CardNumberDisplay {
+    /// As stored, e.g. `************1234`.
+    #[default]
+    Contiguous,
+    /// In groups of `CARD_NUMBER_GROUP_SIZE` characters separated by spaces, e.g.
+    /// `**** **** **** 1234`.
+    Grouped,
+}
+
+/// Number of characters of each group of a grouped card number.
+pub const CARD_NUMBER_GROUP_SIZE: usize = 4;
+
+/// Masks the card number down to its last `UNMASKED_CARD_DIGITS`, as archiving does,
+/// displaying it as `display` tells.
+pub fn mask_card_number(card_number: &str, display: CardNumberDisplay) -> String {
+    let masked = card_number
+        .chars()
+        .count()
+        .saturating_sub(UNMASKED_CARD_DIGITS as usize);
+    let mut display_number = String::with_capacity(card_number.len() * 5 / 4);
+    for (i, c) in card_number.chars().enumerate() {
+        if display == CardNumberDisplay::Grouped && i > 0 && i % CARD_NUMBER_GROUP_SIZE == 0 {
+            display_number.push(' ');
+        }
+        display_number.push(if i < masked { '*' } else { c });
+    }
+    display_number
+}
+
+/// Masks the runs of digits of `text` long enough to be card numbers, e.g. in the database
+/// errors quoting the conflicting row.
+pub fn mask_card_numbers(text: &str) -> String {
+    CARD_NUMBER_LIKE_REGEX
+        .replace_all(text, |captures: &regex::Captures| {
+            mask_card_number(&captures[0], CardNumberDisplay::Contiguous)
+        })
+        .into_owned()
+}
+
+/// A field of a payment whose stored value differs from the one its events imply.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Discrepancy {
+    pub field: String,
+    /// The value implied by the payment's events, `null` if they don't imply any.
+    pub derived: serde_json::Value,
+    pub stored: serde_json::Value,
+}
+
+/// The state of a payment, as derived from its audit events.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct DerivedPayment {
+    status: Option<Status>,
+    amount: Option<i32>,
+    refunded_amount: i32,
+    decline_reason: Option<String>,
+    archived: bool,
+}
+
+impl DerivedPayment {
+    /// Folds the events, oldest first, into the payment's state after the last one.
+    fn from_events(events: &[PaymentEvent]) -> Self {
+        events.iter().fold(Self::default(), |mut payment, event| {
+            payment.status = Some(event.status);
+            match event.kind {
+                PaymentEventKind::Created | PaymentEventKind::StatusChanged => {
+                    payment.amount = Some(event.amount);
+                    payment.decline_reason = event.reason.clone();
+                }
+                // the event's amount is the refunded one
+                PaymentEventKind::Refunded => payment.refunded_amount += event.amount,
+                PaymentEventKind::Archived => payment.archived = true,
+            }
+            payment
+        })
+    }
+
+    fn discrepancies(&self, stored: &Payment) -> Vec<Discrepancy> {
+        let fields = [
+            ("status", json!(self.status), json!(stored.status)),
+            ("amount", json!(self.amount), json!(stored.amount)),
+            (
+                "refunded_amount",
+                json!(self.refunded_amount),
+                json!(stored.refunded_amount),
+            ),
+            (
+                "decline_reason",
+                json!(self.decline_reason),
+                json!(stored.decline_reason),
+            ),
+            (
+                "archived",
+                json!(self.archived),
+                json!(stored.archived_at.is_some()),
+            ),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, derived, stored)| derived != stored)
+            .map(|(field, derived, stored)| Discrepancy {
+                field: field.to_owned(),
+                derived,
+                stored,
+            })
+            .collect()
+    }
+}
+
+/// Replays the payment's audit events and compares the state they imply with the stored
+/// payment, returning the fields that differ: none if the payment is consistent.
+///
+/// Fails with `RowNotFound` if the payment doesn't exist.
+pub async fn rebuild_from_events(pool: &PgPool, id: Uuid) -> Result<Vec<Discrepancy>, sqlx::Error> {
+    let payment = get(pool, id).await?;
+    let events = payment_events::list_for_payment(pool, id).await?;
+
+    Ok(DerivedPayment::from_events(&events).discrepancies(&payment))
+}
+
+/// Reason recorded on payments failed with `force_fail`.
+pub const FORCED_FAILURE_REASON: &str = "forced_failure";
+
+/// Fails a payment that is still processing or awaiting capture, releasing its hold.
+///
+/// This is meant for disaster-recovery drills, to exercise the handling of failed payments.
+pub async fn force_fail(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    id: Uuid,
+) -> Result<Payment, ForceFailError> {
+    fail(pool, account_service, clock, id, FORCED_FAILURE_REASON).await
+}
+
+/// Reason recorded on the authorizations failed with `void_all_authorizations_for_card`.
+pub const VOIDED_REASON: &str = "voided";
+
+/// Voids every authorization awaiting capture on the card, releasing their holds, and
+/// returns how many were.
+///
+/// Authorizations are found by the card's fingerprints, so that archived ones are voided
+/// too. Each is voided on its own: those voided before an error stay voided.
+pub async fn void_all_authorizations_for_card(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    fingerprint_keys: &FingerprintKeys,
+    card_number: &str,
+) -> Result<u64, ForceFailError> {
+    let ids = sqlx::query_scalar!(
+        r#"
+            SELECT id
+              FROM payments
+             WHERE card_fingerprint = ANY($1)
+               AND status = 'Authorized'
+        "#,
+        &fingerprint_keys.fingerprints(card_number)
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(ForceFailError::Database)?;
+
+    let mut voided = 0;
+    for id in ids {
+        match fail(pool, account_service, clock, id, VOIDED_REASON).await {
+            Ok(_) => voided += 1,
+            // captured since
+            Err(ForceFailError::Terminal) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(voided)
+}
+
+/// Fails a payment that is still processing or awaiting capture with `reason`, releasing
+/// its hold.
+async fn fail(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    id: Uuid,
+    reason: &str,
+) -> Result<Payment, ForceFailError> {
+    let mut transaction = pool.begin().await.map_err(ForceFailError::Database)?;
+    let payment = sqlx::query_as!(
+        Payment,
+        r#"
+            SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+              FROM payments
+             WHERE id = $1
+               FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(ForceFailError::Database)?
+    .ok_or(ForceFailError::PaymentNotFound)?;
+
+    if !matches!(payment.status, Status::Processing | Status::Authorized) {
+        return Err(ForceFailError::Terminal);
+    }
+    if let Some(hold_id) = payment.hold_id {
+        account_service
+            .release_hold(HoldRef::from(hold_id))
+            .await
+            .map_err(|msg| ForceFailError::AccountService(account_service_error(msg)))?;
+    }
+
+    let payment = sqlx::query_as!(
+        Payment,
+        r#"
+               UPDATE payments
+                  SET status = 'Failed',
+                      decline_reason = $2,
+                      hold_id = NULL,
+                      updated_at = $3
+                WHERE id = $1
+            RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+        "#,
+        id,
+        reason,
+        clock.now_utc()
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .map_err(ForceFailError::Database)?;
+    transaction
+        .commit()
+        .await
+        .map_err(ForceFailError::Database)?;
+
+    Ok(payment)
+}
+
+/// Merges `metadata` into the payment's metadata, overwriting the keys it already has.
+///
+/// The merged metadata is subject to the same limits as on creation. Archived payments
+/// are treated as not found.
+pub async fn merge_metadata(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    id: Uuid,
+    metadata: Metadata,
+) -> Result<Payment, UpdateMetadataError> {
+    // the payment stays locked until updated, so that concurrent merges don't lose keys
+    let mut transaction = pool.begin().await.map_err(UpdateMetadataError::Database)?;
+    let mut merged = sqlx::query!(
+        r#"
+            SELECT metadata as "metadata: Json<Metadata>"
+              FROM payments
+             WHERE id = $1
+               AND archived_at IS NULL
+               FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(UpdateMetadataError::Database)?
+    .ok_or(UpdateMetadataError::PaymentNotFound)?
+    .metadata
+    .0;
+    merged.extend(metadata);
+    validate_metadata(&merged).map_err(UpdateMetadataError::InvalidArgument)?;
+
+    let payment = sqlx::query_as!(
+        Payment,
+        r#"
+               UPDATE payments
+                  SET metadata = $2,
+                      updated_at = $3
+                WHERE id = $1
+            RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+        "#,
+        id,
+        serde_json::to_value(&merged).expect("failed to serialize metadata"),
+        clock.now_utc()
+    )
+    .fetch_one(&mut transaction)
+    .await
+    .map_err(UpdateMetadataError::Database)?;
+    transaction
+        .commit()
+        .await
+        .map_err(UpdateMetadataError::Database)?;
+
+    Ok(payment)
+}
+
+/// Archives the payment, masking its card number down to the last `UNMASKED_CARD_DIGITS`.
+///
+/// Returns `sqlx::Error::RowNotFound` if the payment doesn't exist or is already archived.
+pub async fn archive(pool: &PgPool, clock: &dyn Clock, id: Uuid) -> Result<Payment, sqlx::Error> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+               UPDATE payments
+                  SET card_number = repeat('*', length(card_number) - $3) || right(card_number, $3),
+                      archived_at = $2,
+                      updated_at = $2
+                WHERE id = $1
+                  AND archived_at IS NULL
+            RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+        "#,
+        id,
+        clock.now_utc(),
+        UNMASKED_CARD_DIGITS
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Archives the payments inserted more than `retention` ago, returning how many were.
+pub async fn archive_expired(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    retention: time::Duration,
+) -> Result<u64, sqlx::Error> {
+    let now = clock.now();
+    let result = sqlx::query!(
+        r#"
+            UPDATE payments
+               SET card_number = repeat('*', length(card_number) - $3) || right(card_number, $3),
+                   archived_at = $2,
+                   updated_at = $2
+             WHERE inserted_at < $1
+               AND archived_at IS NULL
+        "#,
+        to_primitive_utc(now - retention),
+        to_primitive_utc(now),
+        UNMASKED_CARD_DIGITS
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Spawns a background task running `archive_expired` every `interval`.
+pub fn spawn_archiver(
+    pool: PgPool,
+    clock: Arc<dyn Clock>,
+    retention: time::Duration,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match archive_expired(&pool, clock.as_ref(), retention).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "archived expired payments"),
+                Err(err) => tracing::error!(error = ?err, "failed to archive payments"),
+            }
+        }
+    })
+}
+
+/// Releases the holds of the processing payments inserted more than `timeout` ago without
+/// a hold id, i.e. persisted before their hold was: nothing else would release them.
+///
+/// The holds are released by card and amount, which is best-effort. Each payment is failed
+/// before its hold is released, and put back to processing if releasing it fails, for the
+/// next call to try again. Returns how many holds were released.
+pub async fn release_dangling_holds(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    timeout: time::Duration,
+) -> Result<u64, sqlx::Error> {
+    let now = clock.now_utc();
+    let dangling = sqlx::query!(
+        r#"
+            SELECT id, card_number, hold_amount
+              FROM payments
+             WHERE status = 'Processing'
+               AND hold_id IS NULL
+               AND archived_at IS NULL
+               AND inserted_at < $1
+        "#,
+        now - timeout
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut released = 0;
+    for payment in dangling {
+        let failed = sqlx::query!(
+            r#"
+                UPDATE payments
+                   SET status = 'Failed',
+                       decline_reason = $2,
+                       updated_at = $3
+                 WHERE id = $1
+                   AND status = 'Processing'
+            "#,
+            payment.id,
+            DANGLING_HOLD_DECLINE_REASON,
+            now
+        )
+        .execute(pool)
+        .await?;
+        // settled or failed since
+        if failed.rows_affected() == 0 {
+            continue;
+        }
+
+        match account_service
+            .release_hold_by_card(&payment.card_number, payment.hold_amount)
+            .await
+        {
+            Ok(()) => released += 1,
+            Err(msg) => {
+                tracing::warn!(payment_id = %payment.id, error = %msg, "failed to release dangling hold");
+                sqlx::query!(
+                    r#"
+                        UPDATE payments
+                           SET status = 'Processing',
+                               decline_reason = NULL,
+                               updated_at = $2
+                         WHERE id = $1
+                    "#,
+                    payment.id,
+                    now
+                )
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+    Ok(released)
+}
+
+/// Spawns a background task running `release_dangling_holds` every `interval`.
+pub fn spawn_dangling_hold_sweeper(
+    pool: PgPool,
+    account_service: impl AccountService,
+    clock: Arc<dyn Clock>,
+    timeout: time::Duration,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match release_dangling_holds(&pool, &account_service, clock.as_ref(), timeout).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "released dangling holds"),
+                Err(err) => tracing::error!(error = ?err, "failed to release dangling holds"),
+            }
+        }
+    })
+}
+
+/// Approves the processing payments inserted at least `delay` ago, charging their fees,
+/// and returns them.
+///
+/// This simulates the settlement of the payments made in sandbox mode with a settlement
+/// delay, which are created processing rather than approved.
+pub async fn settle_due(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    fees: &FeeConfig,
+    delay: time::Duration,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let now = clock.now();
+    let mut transaction = pool.begin().await?;
+    let due = sqlx::query!(
+        r#"
+            SELECT id, amount
+              FROM payments
+             WHERE status = 'Processing'
+               AND inserted_at <= $1
+               AND archived_at IS NULL
+               FOR UPDATE SKIP LOCKED
+        "#,
+        to_primitive_utc(now - delay)
+    )
+    .fetch_all(&mut transaction)
+    .await?;
+
+    let mut settled = Vec::with_capacity(due.len());
+    for payment in due {
+        let fee_amount = fees.fee_for(payment.amount);
+        let payment = sqlx::query_as!(
+            Payment,
+            r#"
+                   UPDATE payments
+                      SET status = 'Approved',
+                          fee_amount = $2,
+                          net_amount = $3,
+                          settled_at = $4,
+                          updated_at = $4
+                    WHERE id = $1
+                RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+            "#,
+            payment.id,
+            fee_amount,
+            payment.amount - fee_amount,
+            to_primitive_utc(now)
+        )
+        .fetch_one(&mut transaction)
+        .await?;
+        settled.push(payment);
+    }
+    transaction.commit().await?;
+
+    Ok(settled)
+}
+
+/// Outcome of a hold the account service confirms asynchronously.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HoldOutcome {
+    Approved,
+    Declined,
+}
+
+/// Reason recorded on the payments declined by `confirm_hold`.
+pub const HOLD_DECLINED_REASON: &str = "hold_declined";
+
+/// Applies the account service's asynchronous confirmation of the hold to the processing
+/// payment it was placed for: approving the payment and charging its fee, or declining it.
+///
+/// The hold of a declined payment was never placed, so there's nothing to release. Its id is
+/// kept on the payment, so a repeated confirmation finds it and is rejected as a conflict.
+pub async fn confirm_hold(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    fees: &FeeConfig,
+    hold_ref: HoldRef,
+    outcome: HoldOutcome,
+) -> Result<Payment, ConfirmHoldError> {
+    let mut transaction = pool.begin().await.map_err(ConfirmHoldError::Database)?;
+    let payment = sqlx::query!(
+        r#"
+            SELECT id, amount, status as "status: Status"
+              FROM payments
+             WHERE hold_id = $1
+               FOR UPDATE
+        "#,
+        hold_ref.id()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .map_err(ConfirmHoldError::Database)?
+    .ok_or(ConfirmHoldError::PaymentNotFound)?;
+    if payment.status != Status::Processing {
+        return Err(ConfirmHoldError::NotProcessing);
+    }
+
+    let now = clock.now_utc();
+    let payment = match outcome {
+        HoldOutcome::Approved => {
+            let fee_amount = fees.fee_for(payment.amount);
+            sqlx::query_as!(
+                Payment,
+                r#"
+                       UPDATE payments
+                          SET status = 'Approved',
+                              fee_amount = $2,
+                              net_amount = $3,
+                              settled_at = $4,
+                              updated_at = $4
+                        WHERE id = $1
+                    RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                "#,
+                payment.id,
+                fee_amount,
+                payment.amount - fee_amount,
+                now
+            )
+            .fetch_one(&mut transaction)
+            .await
+        }
+        HoldOutcome::Declined => {
+            sqlx::query_as!(
+                Payment,
+                r#"
+                       UPDATE payments
+                          SET status = 'Declined',
+                              decline_reason = $2,
+                              updated_at = $3
+                        WHERE id = $1
+                    RETURNING id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+                "#,
+                payment.id,
+                HOLD_DECLINED_REASON,
+                now
+            )
+            .fetch_one(&mut transaction)
+            .await
+        }
+    }
+    .map_err(ConfirmHoldError::Database)?;
+    transaction
+        .commit()
+        .await
+        .map_err(ConfirmHoldError::Database)?;
+
+    Ok(payment)
+}
+
+/// Spawns a background task running `settle_due` every `interval`, publishing the status
+/// of the settled payments to `status_changes`.
+pub fn spawn_settler(
+    pool: PgPool,
+    clock: Arc<dyn Clock>,
+    fees: FeeConfig,
+    delay: time::Duration,
+    status_changes: StatusChanges,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match settle_due(&pool, clock.as_ref(), &fees, delay).await {
+                Ok(payments) => {
+                    for payment in &payments {
+                        status_changes.publish(payment.id, payment.status);
+                    }
+                    if !payments.is_empty() {
+                        tracing::info!(count = payments.len(), "settled delayed payments");
+                    }
+                }
+                Err(err) => tracing::error!(error = ?err, "failed to settle payments"),
+            }
+        }
+    })
+}
+
+/// Captures the full amount of the authorizations made at least `delay` ago and still
+/// awaiting capture, and returns them.
+///
+/// Authorizations voided or captured in the meantime are skipped, as are those failing to
+/// be captured, which are tried again by the next call.
+pub async fn capture_due(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    fees: &FeeConfig,
+    delay: time::Duration,
+) -> Result<Vec<Payment>, sqlx::Error> {
+    let ids = sqlx::query_scalar!(
+        r#"
+            SELECT id
+              FROM payments
+             WHERE status = 'Authorized'
+               AND hold_id IS NOT NULL
+               AND inserted_at <= $1
+               AND archived_at IS NULL
+        "#,
+        to_primitive_utc(clock.now() - delay)
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut captured = Vec::with_capacity(ids.len());
+    for id in ids {
+        match capture(pool, account_service, clock, fees, id, None, None).await {
+            Ok(capture) => captured.push(capture.payment),
+            // voided or captured since
+            Err(CaptureError::NotAuthorized) => {}
+            Err(CaptureError::Database(err)) => return Err(err),
+            Err(err) => tracing::warn!(payment_id = %id, error = ?err, "failed to auto-capture"),
+        }
+    }
+    Ok(captured)
+}
+
+/// Spawns a background task running `capture_due` every `interval`, publishing the status
+/// of the captured payments to `status_changes`.
+pub fn spawn_auto_capturer(
+    pool: PgPool,
+    account_service: impl AccountService,
+    clock: Arc<dyn Clock>,
+    fees: FeeConfig,
+    delay: time::Duration,
+    status_changes: StatusChanges,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match capture_due(&pool, &account_service, clock.as_ref(), &fees, delay).await {
+                Ok(payments) => {
+                    for payment in &payments {
+                        status_changes.publish(payment.id, payment.status);
+                    }
+                    if !payments.is_empty() {
+                        tracing::info!(count = payments.len(), "auto-captured authorizations");
+                    }
+                }
+                Err(err) => tracing::error!(error = ?err, "failed to auto-capture authorizations"),
+            }
+        }
+    })
+}
+
+/// Returns the status of each of the `ids` payments, omitting unknown ids.
+pub async fn statuses(pool: &PgPool, ids: &[Uuid]) -> Result<HashMap<Uuid, Status>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT id, status as "status: Status"
+              FROM payments
+             WHERE id = ANY($1)
+        "#,
+        ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows.into_iter().map(|row| (row.id, row.status)).collect())
+}
+
+/// Returns the refunded amount of each of the `ids` payments, omitting unknown ids.
+pub async fn refunded_amounts(
+    pool: &PgPool,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, i32>, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT id, refunded_amount
+              FROM payments
+             WHERE id = ANY($1)
+        "#,
+        ids
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.id, row.refunded_amount))
+        .collect())
+}
+
+/// A payment's refunded amount before and after `recompute_refunded_amount`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RefundedAmountCorrection {
+    pub before: i32,
+    pub after: i32,
+}
+
+/// Recomputes the payment's refunded amount from its approved refunds, correcting it if it
+/// drifted (e.g. after a manual edit of the database).
+///
+/// The payment's `overrefunded_amount` is trusted: its part of the refunds isn't counted.
+/// The payment stays locked meanwhile, so that concurrent refunds are accounted for.
+/// Returns `sqlx::Error::RowNotFound` if the payment doesn't exist.
+pub async fn recompute_refunded_amount(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    id: Uuid,
+) -> Result<RefundedAmountCorrection, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let payment = sqlx::query!(
+        r#"
+            SELECT refunded_amount, overrefunded_amount
+              FROM payments
+             WHERE id = $1
+               FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .ok_or(sqlx::Error::RowNotFound)?;
+    let refunds = sqlx::query_scalar!(
+        r#"
+            SELECT coalesce(sum(amount), 0) as "amount!"
+              FROM refunds
+             WHERE payment_id = $1
+               AND status = 'Approved'
+        "#,
+        id
+    )
+    .fetch_one(&mut transaction)
+    .await?;
+    let after = i32::try_from(refunds - i64::from(payment.overrefunded_amount))
+        .map_err(|e| sqlx::Error::Decode(Box::new(e)))?;
+
+    if after != payment.refunded_amount {
+        sqlx::query!(
+            r#"
+                UPDATE payments
+                   SET refunded_amount = $2,
+                       updated_at = $3
+                 WHERE id = $1
+            "#,
+            id,
+            after,
+            clock.now_utc()
+        )
+        .execute(&mut transaction)
+        .await?;
+        tracing::warn!(
+            payment_id = %id,
+            before = payment.refunded_amount,
+            after,
+            "corrected refunded amount"
+        );
+    }
+    transaction.commit().await?;
+
+    Ok(RefundedAmountCorrection {
+        before: payment.refunded_amount,
+        after,
+    })
+}
+
+/// Streams the payments inserted within `[from, to)`, oldest first.
+///
+/// Rows are fetched lazily from a database cursor, so this can be used to walk
+/// through the whole table without loading it in memory.
+///
+/// Payments are ordered by `(inserted_at, id)`. To resume an interrupted walk,
+/// pass the last payment seen as `after_id` (and optionally its `after_inserted_at`):
+/// the stream then starts strictly after it. When only `after_inserted_at` is
+/// given, the stream starts with the first payment inserted after that time.
+pub fn stream(
+    pool: &PgPool,
+    from: Option<PrimitiveDateTime>,
+    to: Option<PrimitiveDateTime>,
+    after_inserted_at: Option<PrimitiveDateTime>,
+    after_id: Option<Uuid>,
+) -> BoxStream<'_, Result<Payment, sqlx::Error>> {
+    sqlx::query_as!(
+        Payment,
+        r#"
+            SELECT id, amount, hold_amount, hold_id, refunded_amount, fee_amount, net_amount, card_number, decline_reason, currency, metadata as "metadata: Json<Metadata>", client_ip, customer_id, merchant_id, merchant_reference, channel as "channel: _", authorized_expires_at, livemode, archived_at, settled_at, inserted_at, updated_at, status as "status: _"
+              FROM payments
+             WHERE ($1::timestamp IS NULL OR inserted_at >= $1)
+               AND ($2::timestamp IS NULL OR inserted_at < $2)
+               AND ($3::timestamp IS NULL OR $4::uuid IS NOT NULL OR inserted_at > $3)
+               AND ($4::uuid IS NULL OR (inserted_at, id) > (
+                       COALESCE($3, (SELECT inserted_at FROM payments WHERE id = $4)),
+                       $4
+                   ))
+          ORDER BY inserted_at, id
+        "#,
+        from,
+        to,
+        after_inserted_at,
+        after_id
+    )
+    .fetch(pool)
+}
+
+#[cfg(test)]
+pub mod tests {
+
+    use super::*;
+    use crate::bank::{
+        accounts::DummyService,
+        clock::{tests::MockClock, SystemClock},
+        payment_instruments::Card,
+    };
+
+    pub const PAYMENT_AMOUNT: i32 = 1_23;
+    pub const PAYMENT_STATUS: Status = Status::Approved;
+
+    /// Error returned by postgres with the `code` SQLSTATE.
+    #[derive(Debug)]
+    struct MockDatabaseError {
+        code: &'static str,
+    }
+
+    impl std::fmt::Display for MockDatabaseError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "mock database error {}", self.code)
+        }
+    }
+
+    impl std::error::Error for MockDatabaseError {}
+
+    impl sqlx::error::DatabaseError for MockDatabaseError {
+        fn message(&self) -> &str {
+            "mock database error"
+        }
+
+        fn code(&self) -> Option<std::borrow::Cow<'_, str>> {
+            Some(self.code.into())
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+    }
+
+    /// Returns the error of a query rejected by postgres with the `code` SQLSTATE.
+    pub fn database_error(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(MockDatabaseError { code }))
+    }
+
+    #[rstest::rstest]
+    #[case(CardNumberDisplay::Contiguous, "************1234")]
+    #[case(CardNumberDisplay::Grouped, "**** **** **** 1234")]
+    fn test_mask_card_number(#[case] display: CardNumberDisplay, #[case] expected: &str) {
+        assert_eq!(mask_card_number("4242424242421234", display), expected);
+    }
+
+    #[test]
+    fn test_mask_card_numbers() {
+        assert_eq!(
+            mask_card_numbers("Key (card_number)=(424242424242424) already exists, order 1205."),
+            "Key (card_number)=(***********2424) already exists, order 1205."
+        );
+    }
+
+    #[test]
+    fn test_is_too_many_connections() {
+        assert!(is_too_many_connections(&database_error("53300")));
+        assert!(!is_too_many_connections(&database_error("23505")));
+        assert!(!is_too_many_connections(&sqlx::Error::RowNotFound));
+        // it doesn't mean the database is down, so payments aren't queued
+        assert!(!is_database_unavailable(&database_error("53300")));
+    }
+
+    #[rstest::rstest]
+    #[case(AccountServiceError::InsufficientFunds, ErrorClass::ClientError)]
+    #[case(AccountServiceError::InvalidAccountNumber, ErrorClass::ClientError)]
+    #[case(AccountServiceError::ServiceUnavailable, ErrorClass::ServerError)]
+    #[case(AccountServiceError::InternalError, ErrorClass::ServerError)]
+    #[case(AccountServiceError::DoNotHonor, ErrorClass::ClientError)]
+    #[case(AccountServiceError::ExpiredCard, ErrorClass::ClientError)]
+    #[case(AccountServiceError::LostCard, ErrorClass::ClientError)]
+    #[case(AccountServiceError::StolenCard, ErrorClass::ClientError)]
+    #[case(AccountServiceError::SuspectedFraud, ErrorClass::ClientError)]
+    fn test_account_service_error_class(
+        #[case] error: AccountServiceError,
+        #[case] class: ErrorClass,
+    ) {
+        assert_eq!(error.class(), class);
+    }
+
+    #[rstest::rstest]
+    #[case(CreateError::DuplicatedCardNumber, ErrorClass::ClientError)]
+    #[case(CreateError::DuplicateMerchantReference, ErrorClass::ClientError)]
+    #[case(CreateError::OutsideAcceptingWindow, ErrorClass::ClientError)]
+    #[case(CreateError::CardNotAccepted, ErrorClass::ClientError)]
+    #[case(CreateError::VelocityLimitExceeded, ErrorClass::ClientError)]
+    #[case(CreateError::DailyCardLimitExceeded, ErrorClass::ClientError)]
+    #[case(
+        CreateError::InvalidArgument(InvalidArgumentError::NegativeAmount),
+        ErrorClass::ClientError
+    )]
+    #[case(
+        CreateError::InvalidArgument(InvalidArgumentError::InvalidCardFormat),
+        ErrorClass::ClientError
+    )]
+    #[case(CreateError::Vault("vault is down".into()), ErrorClass::ServerError)]
+    #[case(CreateError::Internal("overflow".into()), ErrorClass::ServerError)]
+    #[case(
+        CreateError::Database(sqlx::Error::PoolTimedOut),
+        ErrorClass::ServerError
+    )]
+    fn test_create_error_class(#[case] error: CreateError, #[case] class: ErrorClass) {
+        assert_eq!(error.class(), class);
+    }
+
+    #[tokio::test]
+    async fn test_create_error_class_of_persisted_payment() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Box::new(Payment::new_test(&pool).await.unwrap());
+
+        let declined =
+            CreateError::AccountService(AccountServiceError::InsufficientFunds, payment.clone());
+        assert_eq!(declined.class(), ErrorClass::ClientError);
+        let failed =
+            CreateError::AccountService(AccountServiceError::InternalError, payment.clone());
+        assert_eq!(failed.class(), ErrorClass::ServerError);
+        assert_eq!(
+            CreateError::Queued(payment).class(),
+            ErrorClass::ServerError
+        );
+    }
+
+    impl Payment {
+        pub async fn new_test(pool: &PgPool) -> Result<Payment, sqlx::Error> {
+            Self::new_test_with_status(pool, PAYMENT_STATUS).await
+        }
+
+        pub async fn new_test_with_status(
+            pool: &PgPool,
+            status: Status,
+        ) -> Result<Payment, sqlx::Error> {
+            let card_number: String = Card::new_test().into();
+
+            insert(
+                pool,
+                NewPayment::new(
+                    &FingerprintKey::new_test(),
+                    PAYMENT_AMOUNT,
+                    card_number.as_str(),
+                    status,
+                ),
+                SystemClock.now_utc(),
+            )
+            .await
+        }
+    }
+
+    /// Sets the merchant and merchant reference of an existing payment.
+    pub async fn set_merchant_reference(
+        pool: &PgPool,
+        id: Uuid,
+        merchant_id: Uuid,
+        merchant_reference: &str,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+                UPDATE payments
+                   SET merchant_id = $2,
+                       merchant_reference = $3
+                 WHERE id = $1
+            "#,
+            id,
+            merchant_id,
+            merchant_reference
+        )
+        .execute(pool)
+        .await
+        .map(|_| ())
+    }
+
+    #[tokio::test]
+    async fn test_payment() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+
+        assert_eq!(payment.amount, PAYMENT_AMOUNT);
+        assert_eq!(payment.status, PAYMENT_STATUS);
+    }
+
+    #[tokio::test]
+    async fn test_insert_violating_amount_check() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let card_number: String = Card::new_test().into();
+
+        let err = insert(
+            &pool,
+            NewPayment::new(
+                &FingerprintKey::new_test(),
+                -1_00,
+                card_number.as_str(),
+                PAYMENT_STATUS,
+            ),
+            SystemClock.now_utc(),
+        )
+        .await
+        .expect_err("inserted a payment with a negative amount");
+
+        assert!(matches!(
+            create_error_from_database(err, -1_00),
+            CreateError::InvalidArgument(InvalidArgumentError::NegativeAmount)
+        ));
+    }
+
+    #[test]
+    fn test_validate_card_number_rejects_overlong_input() {
+        let card_number = "1".repeat(10 * 1024 * 1024);
+
+        assert!(matches!(
+            validate_card_number(&card_number),
+            Err(InvalidArgumentError::InvalidCardFormat)
+        ));
+        // still accepted when grouped
+        assert!(validate_card_number("1234 5123 4512 345").is_ok());
+        assert!(validate_card_number(&"1".repeat(MAX_CARD_NUMBER_INPUT_LENGTH + 1)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_insert_duplicated_card_number() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+
+        let err = insert(
+            &pool,
+            NewPayment::new(
+                &FingerprintKey::new_test(),
+                PAYMENT_AMOUNT,
+                payment.card_number.as_str(),
+                PAYMENT_STATUS,
+            ),
+            SystemClock.now_utc(),
+        )
+        .await
+        .expect_err("inserted a duplicated card number");
+
+        assert!(matches!(
+            create_error_from_database(err, PAYMENT_AMOUNT),
+            CreateError::DuplicatedCardNumber
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_get_by_reference() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let merchant_id = Uuid::new_v4();
+        let other_merchant_id = Uuid::new_v4();
+        let merchant_reference = format!("order-{}", Uuid::new_v4().simple());
+        let [payment, other_payment, other_merchant_payment] = [
+            Payment::new_test(&pool).await,
+            Payment::new_test(&pool).await,
+            Payment::new_test(&pool).await,
+        ]
+        .map(|payment| payment.expect("failed to create payment"));
+
+        set_merchant_reference(&pool, payment.id, merchant_id, &merchant_reference)
+            .await
+            .expect("failed to set merchant reference");
+        set_merchant_reference(&pool, other_payment.id, merchant_id, &merchant_reference)
+            .await
+            .expect_err("set a merchant reference twice");
+        set_merchant_reference(
+            &pool,
+            other_merchant_payment.id,
+            other_merchant_id,
+            &merchant_reference,
+        )
+        .await
+        .expect("failed to set another merchant's reference");
+
+        let found = get_by_reference(&pool, merchant_id, &merchant_reference)
+            .await
+            .unwrap();
+        assert_eq!(found.map(|payment| payment.id), Some(payment.id));
+        let found = get_by_reference(&pool, other_merchant_id, &merchant_reference)
+            .await
+            .unwrap();
+        assert_eq!(
+            found.map(|payment| payment.id),
+            Some(other_merchant_payment.id)
+        );
+
+        archive(&pool, &SystemClock, payment.id)
+            .await
+            .expect("failed to archive payment");
+        let found = get_by_reference(&pool, merchant_id, &merchant_reference)
+            .await
+            .unwrap();
+        assert!(found.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_release_dangling_holds() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let account_service = DummyService::default();
+        let payment = Payment::new_test_with_status(&pool, Status::Processing)
+            .await
+            .expect("failed to create payment");
+        account_service
+            .place_hold(&payment.card_number, payment.hold_amount)
+            .await
+            .expect("failed to place hold");
+        let clock = MockClock::new(payment.inserted_at.assume_utc());
+        let timeout = time::Duration::hours(1);
+
+        release_dangling_holds(&pool, &account_service, &clock, timeout)
+            .await
+            .expect("failed to release dangling holds");
+        assert_eq!(
+            get(&pool, payment.id).await.unwrap().status,
+            Status::Processing
+        );
+
+        clock.advance(timeout + time::Duration::seconds(1));
+        release_dangling_holds(&pool, &account_service, &clock, timeout)
+            .await
+            .expect("failed to release dangling holds");
+        let payment = get(&pool, payment.id).await.unwrap();
+        assert_eq!(payment.status, Status::Failed);
+        assert_eq!(
+            payment.decline_reason.as_deref(),
+            Some(DANGLING_HOLD_DECLINE_REASON)
+        );
+        assert!(account_service.holds_by_card.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_keep_processing_payments_whose_hold_fails_to_be_released() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        // no hold was placed on the card
+        let account_service = DummyService::default();
+        let payment = Payment::new_test_with_status(&pool, Status::Processing)
+            .await
+            .expect("failed to create payment");
+        let clock = MockClock::new(payment.inserted_at.assume_utc() + time::Duration::days(1));
+
+        release_dangling_holds(&pool, &account_service, &clock, time::Duration::hours(1))
+            .await
+            .expect("failed to release dangling holds");
+        let payment = get(&pool, payment.id).await.unwrap();
+        assert_eq!(payment.status, Status::Processing);
+        assert_eq!(payment.decline_reason, None);
+    }
+
+    #[tokio::test]
+    async fn test_insert_fingerprints_card_number() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+        let other_payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+
+        archive(&pool, &SystemClock, payment.id)
+            .await
+            .expect("failed to archive payment");
+        let repeated_payment = insert(
+            &pool,
+            NewPayment::new(
+                &FingerprintKey::new_test(),
+                PAYMENT_AMOUNT,
+                payment.card_number.as_str(),
+                PAYMENT_STATUS,
+            ),
+            SystemClock.now_utc(),
+        )
+        .await
+        .expect("failed to insert the archived payment's card number");
+
+        let fingerprint = |id: Uuid| {
+            sqlx::query_scalar!("SELECT card_fingerprint FROM payments WHERE id = $1", id)
+                .fetch_one(&pool)
+        };
+        let expected = FingerprintKey::new_test().fingerprint(&payment.card_number);
+        assert_eq!(
+            fingerprint(payment.id).await.unwrap(),
+            Some(expected.clone())
+        );
+        assert_eq!(
+            fingerprint(repeated_payment.id).await.unwrap(),
+            Some(expected.clone())
+        );
+        assert_ne!(fingerprint(other_payment.id).await.unwrap(), Some(expected));
+    }
+
+    #[rstest::rstest]
+    #[case("40001", 1, true, 2)]
+    #[case("40P01", 1, true, 2)]
+    #[case("40001", 3, false, MAX_INSERT_ATTEMPTS as usize)]
+    #[case("57014", 1, false, 1)]
+    #[tokio::test]
+    async fn test_insert_retries_serialization_failures(
+        #[case] code: &'static str,
+        #[case] failures: usize,
+        #[case] expected_inserted: bool,
+        #[case] expected_attempts: usize,
+    ) {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let card_number: String = Card::new_test().into();
+        let payment = NewPayment::new(
+            &FingerprintKey::new_test(),
+            PAYMENT_AMOUNT,
+            card_number.as_str(),
+            PAYMENT_STATUS,
+        );
+
+        let mut attempts = 0;
+        let result = retry_serialization_failures(|| {
+            attempts += 1;
+            let fails = attempts <= failures;
+            let (pool, payment) = (&pool, payment.clone());
+            async move {
+                if fails {
+                    return Err(CreateError::Database(database_error(code)));
+                }
+                insert_held(pool, payment, SystemClock.now_utc(), PAYMENT_AMOUNT).await
+            }
+        })
+        .await;
+        assert_eq!(attempts, expected_attempts);
+        match result {
+            Ok(inserted) => {
+                assert!(expected_inserted);
+                assert_eq!(
+                    get(&pool, inserted.id).await.unwrap().card_number,
+                    card_number
+                );
+            }
+            Err(CreateError::Database(e)) => {
+                assert!(!expected_inserted);
+                assert_eq!(e.as_database_error().unwrap().code().as_deref(), Some(code));
+            }
+            Err(e) => panic!("unexpected error {e:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_insert_held_rolls_back_amount_mismatch() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let card_number: String = Card::new_test().into();
+
+        // as if a logic bug held another amount than the one persisted
+        let err = insert_held(
+            &pool,
+            NewPayment::new(
+                &FingerprintKey::new_test(),
+                PAYMENT_AMOUNT,
+                card_number.as_str(),
+                PAYMENT_STATUS,
+            ),
+            SystemClock.now_utc(),
+            PAYMENT_AMOUNT + 1,
+        )
+        .await
+        .expect_err("inserted a payment with a mismatched hold");
+        assert!(matches!(err, CreateError::Internal(_)));
+
+        let count = sqlx::query_scalar!(
+            r#"SELECT count(*) as "count!" FROM payments WHERE card_number = $1"#,
+            card_number
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_rebuild_from_events_detects_corrupted_row() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+        assert_eq!(rebuild_from_events(&pool, payment.id).await.unwrap(), []);
+
+        // amounts aren't audited unless the status changes along
+        sqlx::query!(
+            "UPDATE payments SET amount = amount - 1, net_amount = net_amount - 1 WHERE id = $1",
+            payment.id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        assert_eq!(
+            rebuild_from_events(&pool, payment.id).await.unwrap(),
+            [Discrepancy {
+                field: "amount".into(),
+                derived: json!(PAYMENT_AMOUNT),
+                stored: json!(PAYMENT_AMOUNT - 1),
+            }]
+        );
+        assert!(matches!(
+            rebuild_from_events(&pool, Uuid::new_v4()).await,
+            Err(sqlx::Error::RowNotFound)
+        ));
     }
 }
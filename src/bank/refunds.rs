@@ -1,5 +1,11 @@
-use sqlx::PgPool;
-use time::PrimitiveDateTime;
+use crate::bank::accounts::AccountService;
+use crate::bank::clock::Clock;
+use crate::bank::money::{Money, MoneyOverflow};
+use crate::bank::payments::{self, AccountServiceError, Status};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use std::sync::Arc;
+use time::{Date, Duration, PrimitiveDateTime};
 use uuid::Uuid;
 
 /// Module and schema representing a refund.
@@ -10,79 +16,1059 @@ use uuid::Uuid;
 /// payment record, the but sum of all refunded amounts for a given payment can
 /// never surpass the original payment amount.
 ///
-/// If a refund is persisted in the database, it is considered effective: the
-/// bank's client will have the money credited to their account.
+/// Once a refund is persisted as `Approved`, it is considered effective: the
+/// bank's client will have the money credited to their account. Refunds above the
+/// approval threshold are persisted as `Pending` instead, and only take effect once
+/// approved by a second person. Refunds scheduled for later are persisted as `Scheduled`,
+/// and only take effect once executed at their `execute_at`.
 #[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Refund {
     pub id: Uuid,
     pub payment_id: Uuid,
     pub amount: i32,
+    pub status: RefundStatus,
+    /// The card credited instead of the payment's, if any.
+    pub destination_card_number: Option<String>,
+    /// The currency of the refunded payment, if it has one.
+    pub currency: Option<String>,
+    /// Whether the refund may exceed what remains refundable of the payment, as a
+    /// goodwill credit.
+    pub allow_overrefund: bool,
+    /// The merchant's reference of the credit note issued for the refund, if given.
+    pub credit_note_reference: Option<String>,
+    /// When the refund is due, for refunds scheduled for later.
+    pub execute_at: Option<PrimitiveDateTime>,
     pub inserted_at: PrimitiveDateTime,
     pub updated_at: PrimitiveDateTime,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+pub enum RefundStatus {
+    /// The refund awaits approval: the money hasn't been credited yet.
+    Pending,
+    /// The money was credited to the customer.
+    Approved,
+    /// The refund was turned down during its review.
+    Rejected,
+    /// The refund awaits its `execute_at`: the money hasn't been credited yet.
+    Scheduled,
+}
+
 #[derive(Debug)]
 pub enum CreateError {
     PaymentNotFound,
+    /// The payment isn't settled yet (e.g. still processing, or only authorized).
+    PaymentNotSettled,
+    /// The payment is a voided authorization, whose money was never captured.
+    CannotRefundVoided,
     ExcessiveAmount,
+    /// The payment is already fully refunded.
+    NothingToRefund,
+    InvalidDestinationCard,
+    /// The credit note reference is empty or longer than `MAX_CREDIT_NOTE_REFERENCE_LENGTH`.
+    InvalidCreditNoteReference,
+    /// A line item's SKU is empty or longer than `MAX_SKU_LENGTH`, or its amount isn't
+    /// positive.
+    InvalidLineItem,
+    /// The line items don't sum to the refund's amount.
+    LineItemsMismatch,
+    RefundNotFound,
+    /// The refund was already approved or rejected, or already executed if scheduled.
+    RefundNotPending,
+    /// A refund of another amount was already made with the idempotency key.
+    IdempotencyKeyReused,
+    /// The refund's currency isn't the payment's.
+    CurrencyMismatch,
+    /// The payment was settled longer ago than the refund window.
+    RefundWindowExpired,
+    /// The refund is scheduled further ahead than `MAX_SCHEDULE_AHEAD`.
+    ExecuteAtTooFar,
+    AccountService(AccountServiceError),
+    /// An invariant was broken (e.g. amounts overflowed), which is a bug.
+    Internal(String),
     Database(sqlx::Error),
 }
 
-pub async fn create(pool: &PgPool, payment_id: Uuid, amount: i32) -> Result<Refund, CreateError> {
+impl CreateError {
+    /// Returns why the refund was rejected, for the errors sharing their status code with
+    /// others (e.g. the conflicts).
+    pub fn reason(&self) -> Option<&'static str> {
+        match self {
+            Self::PaymentNotSettled => Some("payment_not_settled"),
+            Self::CannotRefundVoided => Some("cannot_refund_voided"),
+            Self::NothingToRefund => Some("nothing_to_refund"),
+            Self::RefundNotPending => Some("refund_not_pending"),
+            _ => None,
+        }
+    }
+}
+
+/// An item of an itemized order, refunded as part of a refund.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LineItem {
+    /// The merchant's identifier of the item's product.
+    pub sku: String,
+    pub amount: i32,
+}
+
+/// How the amount of a refund made in another currency than its source's was derived,
+/// kept as an audit trail for finance.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    /// Units of the refund's currency per unit of the source currency, as an exact decimal
+    /// (e.g. `1.0825`).
+    pub exchange_rate: String,
+    /// The amount before conversion, in minor units of `source_currency`.
+    pub source_amount: i32,
+    pub source_currency: String,
+    pub converted_at: PrimitiveDateTime,
+}
+
+/// Longest SKU accepted, as stored.
+pub const MAX_SKU_LENGTH: usize = 255;
+
+/// Checks that the line items are valid and sum to the refund's `amount`.
+fn validate_line_items(line_items: &[LineItem], amount: i32) -> Result<(), CreateError> {
+    if line_items
+        .iter()
+        .any(|item| !(1..=MAX_SKU_LENGTH).contains(&item.sku.len()) || item.amount <= 0)
+    {
+        return Err(CreateError::InvalidLineItem);
+    }
+    let total: i64 = line_items.iter().map(|item| i64::from(item.amount)).sum();
+    if total != i64::from(amount) {
+        return Err(CreateError::LineItemsMismatch);
+    }
+    Ok(())
+}
+
+/// Reason why a refund would be rejected by `create`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IneligibilityReason {
+    PaymentNotFound,
+    PaymentNotApproved,
+    PaymentNotSettled,
+    ExcessiveAmount,
+}
+
+/// Outcome of a dry-run refund against a payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Eligibility {
+    /// Why the refund would be rejected, if it would.
+    pub reason: Option<IneligibilityReason>,
+    /// The largest amount that can currently be refunded.
+    pub max_refundable: i32,
+}
+
+/// Returns until when the payment can be refunded with the `refund_window`, or `None` if it
+/// isn't settled.
+pub fn refundable_until(
+    payment: &payments::Payment,
+    refund_window: Duration,
+) -> Option<PrimitiveDateTime> {
+    (payment.status == Status::Approved)
+        .then(|| payment.settled_at.unwrap_or(payment.inserted_at) + refund_window)
+}
+
+/// The refunded amounts of a payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PaymentBalance {
+    pub refunded_amount: i32,
+    /// What remains to refund, short of over-refunds.
+    pub refundable_amount: i32,
+}
+
+/// A refund just made, with the balance of its payment once it was applied.
+#[derive(Debug, Clone)]
+pub struct CreatedRefund {
+    pub refund: Refund,
+    /// Read along with the refund, so that it accounts for it and no later refund. For a
+    /// retried idempotent refund, it's read once it's found instead.
+    pub payment_balance: PaymentBalance,
+}
+
+/// Refunds `amount` of the payment, crediting it to the `destination_card_number`.
+///
+/// The destination defaults to the card the payment was made with, and is otherwise validated
+/// like payment card numbers. The money is credited once the refund is committed, which is
+/// reverted if the account service fails, unless `amount` exceeds the `approval_threshold`:
+/// the refund is then persisted as pending, to be applied by `approve`.
+///
+/// Refunds are made in the payment's currency: a `currency` given must be it, or the
+/// refund fails with `CurrencyMismatch`. Refunds exceeding the remaining refundable
+/// amount fail with `ExcessiveAmount`, unless `allow_overrefund` is set for a goodwill
+/// credit: the excess is then tracked in the payment's `overrefunded_amount`. Refunds made
+/// after the `refund_window` since the payment was settled fail with `RefundWindowExpired`.
+///
+/// Refunds of itemized orders can list the refunded `line_items`, which must sum to
+/// `amount`. They're stored along with the refund.
+///
+/// Refunds with an `execute_at` in the future are persisted as scheduled instead, without
+/// crediting anything: `execute_due` applies them once due. They fail with
+/// `ExecuteAtTooFar` if scheduled further ahead than `MAX_SCHEDULE_AHEAD`.
+#[allow(clippy::too_many_arguments)]
+pub async fn create(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    payment_id: Uuid,
+    amount: i32,
+    currency: Option<&str>,
+    destination_card_number: Option<&str>,
+    credit_note_reference: Option<&str>,
+    line_items: &[LineItem],
+    allow_overrefund: bool,
+    approval_threshold: Option<i32>,
+    refund_window: Option<Duration>,
+    execute_at: Option<PrimitiveDateTime>,
+) -> Result<CreatedRefund, CreateError> {
+    refund(
+        pool,
+        account_service,
+        clock,
+        payment_id,
+        Some(amount),
+        currency,
+        destination_card_number,
+        credit_note_reference,
+        line_items,
+        allow_overrefund,
+        approval_threshold,
+        refund_window,
+        execute_at,
+        None,
+    )
+    .await
+}
+
+/// Refunds `amount` of the payment like `create`, at most once per `idempotency_key`.
+///
+/// Retrying with the same key returns the refund already made, even if the attempts are
+/// concurrent: the database's unique index on the key decides which one is made. Fails
+/// with `IdempotencyKeyReused` if the key was already used for another amount.
+#[allow(clippy::too_many_arguments)]
+pub async fn create_idempotent(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    payment_id: Uuid,
+    amount: i32,
+    currency: Option<&str>,
+    destination_card_number: Option<&str>,
+    credit_note_reference: Option<&str>,
+    line_items: &[LineItem],
+    allow_overrefund: bool,
+    approval_threshold: Option<i32>,
+    refund_window: Option<Duration>,
+    execute_at: Option<PrimitiveDateTime>,
+    idempotency_key: &str,
+) -> Result<CreatedRefund, CreateError> {
+    let created = refund(
+        pool,
+        account_service,
+        clock,
+        payment_id,
+        Some(amount),
+        currency,
+        destination_card_number,
+        credit_note_reference,
+        line_items,
+        allow_overrefund,
+        approval_threshold,
+        refund_window,
+        execute_at,
+        Some(idempotency_key),
+    )
+    .await?;
+    if created.refund.amount == amount {
+        Ok(created)
+    } else {
+        Err(CreateError::IdempotencyKeyReused)
+    }
+}
+
+/// A refund of whatever remained refundable of a payment.
+#[derive(Debug, Clone)]
+pub struct RemainingRefund {
+    pub refund: Refund,
+    /// Whether the refund was already made by an earlier call, rather than by this one.
+    pub repeated: bool,
+}
+
+/// Refunds whatever remains refundable of the payment, to the card it was made with.
+///
+/// The remaining amount is computed within the refund's transaction, so that concurrent
+/// refunds can't make it excessive. Once the payment is fully refunded this way, calling
+/// this again returns the same refund, marked as `repeated`. Fails with `NothingToRefund`
+/// if the payment was fully refunded otherwise.
+pub async fn refund_remaining(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    payment_id: Uuid,
+    approval_threshold: Option<i32>,
+    refund_window: Option<Duration>,
+) -> Result<RemainingRefund, CreateError> {
+    let result = refund(
+        pool,
+        account_service,
+        clock,
+        payment_id,
+        None,
+        None,
+        None,
+        None,
+        &[],
+        false,
+        approval_threshold,
+        refund_window,
+        None,
+        None,
+    )
+    .await;
+    match result {
+        Ok(created) => Ok(RemainingRefund {
+            refund: created.refund,
+            repeated: false,
+        }),
+        Err(CreateError::NothingToRefund) => get_full_refund(pool, payment_id)
+            .await
+            .map_err(CreateError::Database)?
+            .map(|refund| RemainingRefund {
+                refund,
+                repeated: true,
+            })
+            .ok_or(CreateError::NothingToRefund),
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns the latest refund of whatever remained of the payment, unless it was rejected.
+async fn get_full_refund(pool: &PgPool, payment_id: Uuid) -> Result<Option<Refund>, sqlx::Error> {
+    sqlx::query_as!(
+        Refund,
+        r#"
+              SELECT id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at
+                FROM refunds
+               WHERE payment_id = $1
+                 AND full_refund
+                 AND status <> 'Rejected'
+            ORDER BY inserted_at DESC, id DESC
+               LIMIT 1
+        "#,
+        payment_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Longest credit note reference accepted, as stored.
+pub const MAX_CREDIT_NOTE_REFERENCE_LENGTH: usize = 255;
+
+/// Furthest ahead a refund can be scheduled.
+pub const MAX_SCHEDULE_AHEAD: Duration = Duration::days(90);
+
+/// How many times a refund's transaction is attempted when it fails to serialize.
+const MAX_REFUND_ATTEMPTS: usize = 5;
+
+/// Refunds `amount` of the payment, defaulting to the remaining refundable amount.
+///
+/// The refund runs at `REPEATABLE READ`, so a refund that locks the payment after a
+/// concurrent one committed fails to serialize instead of reading a stale amount; the
+/// whole transaction is then retried, up to `MAX_REFUND_ATTEMPTS` times.
+#[allow(clippy::too_many_arguments)]
+async fn refund(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    payment_id: Uuid,
+    amount: Option<i32>,
+    currency: Option<&str>,
+    destination_card_number: Option<&str>,
+    credit_note_reference: Option<&str>,
+    line_items: &[LineItem],
+    allow_overrefund: bool,
+    approval_threshold: Option<i32>,
+    refund_window: Option<Duration>,
+    execute_at: Option<PrimitiveDateTime>,
+    idempotency_key: Option<&str>,
+) -> Result<CreatedRefund, CreateError> {
+    let destination_card_number = destination_card_number
+        .map(payments::validate_card_number)
+        .transpose()
+        .map_err(|_| CreateError::InvalidDestinationCard)?;
+    if credit_note_reference
+        .is_some_and(|reference| !(1..=MAX_CREDIT_NOTE_REFERENCE_LENGTH).contains(&reference.len()))
+    {
+        return Err(CreateError::InvalidCreditNoteReference);
+    }
+    if let (Some(amount), false) = (amount, line_items.is_empty()) {
+        validate_line_items(line_items, amount)?;
+    }
+    if execute_at.is_some_and(|execute_at| execute_at > clock.now_utc() + MAX_SCHEDULE_AHEAD) {
+        return Err(CreateError::ExecuteAtTooFar);
+    }
+
+    let mut attempt = 1;
+    loop {
+        let result = try_refund(
+            pool,
+            account_service,
+            clock,
+            payment_id,
+            amount,
+            currency,
+            destination_card_number.as_deref(),
+            credit_note_reference,
+            line_items,
+            allow_overrefund,
+            approval_threshold,
+            refund_window,
+            execute_at,
+            idempotency_key,
+        )
+        .await;
+        match result {
+            Err(CreateError::Database(e))
+                if payments::is_serialization_failure(&e) && attempt < MAX_REFUND_ATTEMPTS =>
+            {
+                tracing::warn!(%payment_id, attempt, "retrying refund after serialization failure");
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+pub const IDEMPOTENCY_KEY_INDEX: &str = "refunds_payment_id_idempotency_key_index";
+
+fn is_idempotency_key_violation(e: &sqlx::Error) -> bool {
+    // 23505 = unique_violation
+    e.as_database_error().is_some_and(|err| {
+        err.code().as_deref() == Some("23505") && err.constraint() == Some(IDEMPOTENCY_KEY_INDEX)
+    })
+}
+
+async fn get_by_idempotency_key(
+    pool: &PgPool,
+    payment_id: Uuid,
+    idempotency_key: &str,
+) -> Result<Refund, sqlx::Error> {
+    sqlx::query_as!(
+        Refund,
+        r#"
+            SELECT id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at
+              FROM refunds
+             WHERE payment_id = $1 AND idempotency_key = $2
+        "#,
+        payment_id,
+        idempotency_key
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn try_refund(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    payment_id: Uuid,
+    amount: Option<i32>,
+    currency: Option<&str>,
+    destination_card_number: Option<&str>,
+    credit_note_reference: Option<&str>,
+    line_items: &[LineItem],
+    allow_overrefund: bool,
+    approval_threshold: Option<i32>,
+    refund_window: Option<Duration>,
+    execute_at: Option<PrimitiveDateTime>,
+    idempotency_key: Option<&str>,
+) -> Result<CreatedRefund, CreateError> {
+    let now = clock.now_utc();
     let mut transaction = pool.begin().await.map_err(CreateError::Database)?;
+    sqlx::query!("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ")
+        .execute(&mut transaction)
+        .await
+        .map_err(CreateError::Database)?;
+
+    let payment = lock_refundable_payment(&mut transaction, payment_id).await?;
+    if currency.is_some_and(|currency| {
+        !payment
+            .currency
+            .as_deref()
+            .is_some_and(|payment_currency| payment_currency.eq_ignore_ascii_case(currency))
+    }) {
+        return Err(CreateError::CurrencyMismatch);
+    }
+    if refund_window.is_some_and(|window| now > payment.settled_at + window) {
+        return Err(CreateError::RefundWindowExpired);
+    }
+    let remaining = payment.remaining()?;
+    let full_refund = amount.is_none();
+    let amount = match amount {
+        Some(amount) => amount,
+        None if remaining == Money::ZERO => return Err(CreateError::NothingToRefund),
+        None => i32::try_from(remaining).map_err(internal_error)?,
+    };
+
+    // refunds due by now are made right away
+    let execute_at = execute_at.filter(|execute_at| *execute_at > now);
+    let status = if execute_at.is_some() {
+        RefundStatus::Scheduled
+    } else if approval_threshold.is_some_and(|threshold| amount > threshold) {
+        RefundStatus::Pending
+    } else {
+        RefundStatus::Approved
+    };
     let refund = sqlx::query_as!(
         Refund,
         r#"
-               INSERT INTO refunds ( id, payment_id, amount, inserted_at, updated_at )
-               VALUES ( $1, $2, $3, CURRENT_TIMESTAMP, CURRENT_TIMESTAMP )
-            RETURNING id, payment_id, amount, inserted_at, updated_at
+               INSERT INTO refunds ( id, payment_id, amount, status, destination_card_number, idempotency_key, currency, allow_overrefund, full_refund, credit_note_reference, execute_at, inserted_at, updated_at )
+               VALUES ( $1, $2, $3, $4, $5, $6, $7, $8, $10, $11, $12, $9, $9 )
+            RETURNING id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at
         "#,
         Uuid::new_v4(),
         payment_id,
         amount,
+        status as RefundStatus,
+        destination_card_number,
+        idempotency_key,
+        payment.currency,
+        allow_overrefund,
+        now,
+        full_refund,
+        credit_note_reference,
+        execute_at,
     )
     .fetch_one(&mut transaction)
+    .await;
+    let refund = match (refund, idempotency_key) {
+        (Ok(refund), _) => refund,
+        (Err(e), Some(idempotency_key)) if is_idempotency_key_violation(&e) => {
+            // the transaction is aborted, and the existing refund committed
+            drop(transaction);
+            let refund = get_by_idempotency_key(pool, payment_id, idempotency_key)
+                .await
+                .map_err(CreateError::Database)?;
+            let payment_balance = payment_balance(pool, payment_id)
+                .await
+                .map_err(CreateError::Database)?;
+            return Ok(CreatedRefund {
+                refund,
+                payment_balance,
+            });
+        }
+        (Err(e), _) => return Err(CreateError::Database(e)),
+    };
+    // checked once the key is claimed, so that a retry returns the existing refund rather
+    // than being excessive
+    if Money::from(amount) > remaining && !allow_overrefund {
+        return Err(CreateError::ExcessiveAmount);
+    }
+    insert_line_items(&mut transaction, refund.id, line_items)
+        .await
+        .map_err(CreateError::Database)?;
+
+    let credit = if status == RefundStatus::Approved {
+        Some(apply(&mut transaction, &payment, &refund, now).await?)
+    } else {
+        None
+    };
+    let payment_balance = payment_balance(&mut transaction, payment_id)
+        .await
+        .map_err(CreateError::Database)?;
+
+    transaction.commit().await.map_err(CreateError::Database)?;
+    if let Some(credit) = credit {
+        credit_or_revert(pool, account_service, clock, &refund, credit, None).await?;
+    }
+
+    Ok(CreatedRefund {
+        refund,
+        payment_balance,
+    })
+}
+
+async fn payment_balance(
+    executor: impl PgExecutor<'_>,
+    payment_id: Uuid,
+) -> Result<PaymentBalance, sqlx::Error> {
+    let balance = sqlx::query!(
+        r#"
+            SELECT refunded_amount, amount - refunded_amount as "refundable_amount!"
+              FROM payments
+             WHERE id = $1
+        "#,
+        payment_id
+    )
+    .fetch_one(executor)
+    .await?;
+
+    Ok(PaymentBalance {
+        refunded_amount: balance.refunded_amount,
+        refundable_amount: balance.refundable_amount,
+    })
+}
+
+/// A payment being refunded.
+struct RefundablePayment {
+    id: Uuid,
+    amount: i32,
+    refunded_amount: i32,
+    card_number: String,
+    currency: Option<String>,
+    settled_at: PrimitiveDateTime,
+}
+
+impl RefundablePayment {
+    /// Returns the amount left to refund.
+    fn remaining(&self) -> Result<Money, CreateError> {
+        Money::from(self.amount)
+            .checked_sub(Money::from(self.refunded_amount))
+            .map_err(internal_error)
+    }
+}
+
+fn internal_error(e: MoneyOverflow) -> CreateError {
+    CreateError::Internal(e.to_string())
+}
+
+/// Locks the payment until the refund is committed, failing unless it can be refunded.
+///
+/// The lock prevents the payment's status from changing in between (e.g. while an
+/// asynchronous settlement is confirming it), as well as concurrent refunds.
+async fn lock_refundable_payment(
+    transaction: &mut Transaction<'_, Postgres>,
+    payment_id: Uuid,
+) -> Result<RefundablePayment, CreateError> {
+    let payment = sqlx::query!(
+        r#"
+            SELECT id, amount, refunded_amount, card_number, currency, decline_reason, settled_at, inserted_at, status as "status: Status"
+              FROM payments
+             WHERE id = $1
+               FOR UPDATE
+        "#,
+        payment_id
+    )
+    .fetch_optional(&mut *transaction)
     .await
-    .map_err(|e| {
-        let err = e.as_database_error().unwrap();
+    .map_err(CreateError::Database)?
+    .ok_or(CreateError::PaymentNotFound)?;
+
+    match payment.status {
+        Status::Approved => Ok(RefundablePayment {
+            id: payment.id,
+            amount: payment.amount,
+            refunded_amount: payment.refunded_amount,
+            card_number: payment.card_number,
+            currency: payment.currency,
+            // payments approved when created were settled then
+            settled_at: payment.settled_at.unwrap_or(payment.inserted_at),
+        }),
+        Status::Processing | Status::Authorized | Status::Capturing => {
+            Err(CreateError::PaymentNotSettled)
+        }
+        Status::Failed if payment.decline_reason.as_deref() == Some(payments::VOIDED_REASON) => {
+            Err(CreateError::CannotRefundVoided)
+        }
+        Status::Declined | Status::Failed => Err(CreateError::PaymentNotFound),
+    }
+}
+
+/// Applies the refund to the payment, returning the credit to make once it's committed.
+///
+/// The part of an allowed over-refund exceeding the remaining refundable amount is added to
+/// the payment's `overrefunded_amount` rather than its `refunded_amount`.
+async fn apply(
+    transaction: &mut Transaction<'_, Postgres>,
+    payment: &RefundablePayment,
+    refund: &Refund,
+    now: PrimitiveDateTime,
+) -> Result<PendingCredit, CreateError> {
+    let amount = Money::from(refund.amount);
+    let overrefunded_amount = if refund.allow_overrefund {
+        amount
+            .checked_sub(payment.remaining()?)
+            .map_err(internal_error)?
+            .max(Money::ZERO)
+    } else {
+        Money::ZERO
+    };
+    let refunded_amount = amount
+        .checked_sub(overrefunded_amount)
+        .and_then(i32::try_from)
+        .map_err(internal_error)?;
+    let overrefunded_amount = i32::try_from(overrefunded_amount).map_err(internal_error)?;
+    sqlx::query!(
+        r#"
+            UPDATE payments
+               SET refunded_amount = refunded_amount + $1,
+                   overrefunded_amount = overrefunded_amount + $4,
+                   updated_at = $3
+             WHERE id = $2
+        "#,
+        refunded_amount,
+        payment.id,
+        now,
+        overrefunded_amount
+    )
+    .execute(&mut *transaction)
+    .await
+    .map_err(|e| match e.as_database_error() {
         // postgresql error codes: https://www.postgresql.org/docs/current/errcodes-appendix.html
-        // 23503 = foreign_key_violation
-        if err.code().unwrap() == "23503" && err.constraint() == Some("refunds_payment_id_fkey") {
-            CreateError::PaymentNotFound
-        } else {
-            CreateError::Database(e)
+        // 23514 = check_violation
+        Some(err)
+            if err.code().as_deref() == Some("23514")
+                && err.constraint() == Some(payments::REFUNDED_AMOUNT_CHECK) =>
+        {
+            CreateError::ExcessiveAmount
         }
+        _ => CreateError::Database(e),
     })?;
 
-    let count = sqlx::query!(
+    Ok(PendingCredit {
+        card_number: refund
+            .destination_card_number
+            .clone()
+            .unwrap_or_else(|| payment.card_number.clone()),
+        refunded_amount,
+        overrefunded_amount,
+    })
+}
+
+/// The money of a refund applied to its payment, which remains to be credited.
+struct PendingCredit {
+    card_number: String,
+    refunded_amount: i32,
+    overrefunded_amount: i32,
+}
+
+/// Credits the money of the applied refund, once committed.
+///
+/// Crediting before committing could credit refunds whose commit then fails, so the refund is
+/// reverted instead if the account service fails: the payment's amounts are restored, and the
+/// refund is put back to its `previous_status`, or deleted if it was just made.
+async fn credit_or_revert(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    refund: &Refund,
+    credit: PendingCredit,
+    previous_status: Option<RefundStatus>,
+) -> Result<(), CreateError> {
+    let Err(msg) = account_service
+        .credit(&credit.card_number, refund.amount)
+        .await
+    else {
+        return Ok(());
+    };
+
+    let now = clock.now_utc();
+    let mut transaction = pool.begin().await.map_err(CreateError::Database)?;
+    sqlx::query!(
         r#"
             UPDATE payments
-               SET refunded_amount = refunded_amount + $1
-             WHERE id = $2
-               AND status = 'Approved'
-               AND refunded_amount + $1 <= amount
+               SET refunded_amount = refunded_amount - $1,
+                   overrefunded_amount = overrefunded_amount - $2,
+                   updated_at = $4
+             WHERE id = $3
         "#,
-        amount,
-        payment_id
+        credit.refunded_amount,
+        credit.overrefunded_amount,
+        refund.payment_id,
+        now
     )
     .execute(&mut transaction)
     .await
     .map_err(CreateError::Database)?;
+    match previous_status {
+        Some(status) => {
+            review(&mut transaction, refund.id, status, now).await?;
+        }
+        None => {
+            sqlx::query!("DELETE FROM refunds WHERE id = $1", refund.id)
+                .execute(&mut transaction)
+                .await
+                .map_err(CreateError::Database)?;
+        }
+    }
+    transaction.commit().await.map_err(CreateError::Database)?;
+
+    Err(CreateError::AccountService(
+        payments::account_service_error(msg),
+    ))
+}
+
+/// Locks the refund until its review is committed, failing unless it has the `status`
+/// (e.g. is pending).
+async fn lock_refund_with_status(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    status: RefundStatus,
+) -> Result<Refund, CreateError> {
+    let refund = sqlx::query_as!(
+        Refund,
+        r#"
+            SELECT id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at
+              FROM refunds
+             WHERE id = $1
+               FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(&mut *transaction)
+    .await
+    .map_err(CreateError::Database)?
+    .ok_or(CreateError::RefundNotFound)?;
+
+    if refund.status == status {
+        Ok(refund)
+    } else {
+        Err(CreateError::RefundNotPending)
+    }
+}
+
+/// Sets the status of a refund under review.
+async fn review(
+    transaction: &mut Transaction<'_, Postgres>,
+    id: Uuid,
+    status: RefundStatus,
+    now: PrimitiveDateTime,
+) -> Result<Refund, CreateError> {
+    sqlx::query_as!(
+        Refund,
+        r#"
+               UPDATE refunds
+                  SET status = $2,
+                      updated_at = $3
+                WHERE id = $1
+            RETURNING id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at
+        "#,
+        id,
+        status as RefundStatus,
+        now
+    )
+    .fetch_one(&mut *transaction)
+    .await
+    .map_err(CreateError::Database)
+}
+
+/// Approves a pending refund, applying it to the payment.
+///
+/// The refund is checked against the payment's remaining refundable amount again, as
+/// other refunds may have been made since it was requested, unless it allows over-refunds.
+pub async fn approve(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    id: Uuid,
+) -> Result<Refund, CreateError> {
+    let now = clock.now_utc();
+    let mut transaction = pool.begin().await.map_err(CreateError::Database)?;
+    let refund = lock_refund_with_status(&mut transaction, id, RefundStatus::Pending).await?;
+    let (refund, credit) = apply_locked(&mut transaction, refund, now).await?;
+
+    transaction.commit().await.map_err(CreateError::Database)?;
+    credit_or_revert(
+        pool,
+        account_service,
+        clock,
+        &refund,
+        credit,
+        Some(RefundStatus::Pending),
+    )
+    .await?;
+
+    Ok(refund)
+}
 
-    if count.rows_affected() == 0 {
+/// Applies the locked refund to its payment, and approves it, returning the credit to make
+/// once it's committed.
+///
+/// The refund is checked against the payment's remaining refundable amount again, unless it
+/// allows over-refunds.
+async fn apply_locked(
+    transaction: &mut Transaction<'_, Postgres>,
+    refund: Refund,
+    now: PrimitiveDateTime,
+) -> Result<(Refund, PendingCredit), CreateError> {
+    let payment = lock_refundable_payment(transaction, refund.payment_id).await?;
+    if Money::from(refund.amount) > payment.remaining()? && !refund.allow_overrefund {
         return Err(CreateError::ExcessiveAmount);
     }
 
+    let credit = apply(transaction, &payment, &refund, now).await?;
+    let refund = review(transaction, refund.id, RefundStatus::Approved, now).await?;
+    Ok((refund, credit))
+}
+
+/// Rejects a pending refund: the payment is left untouched.
+pub async fn reject(pool: &PgPool, clock: &dyn Clock, id: Uuid) -> Result<Refund, CreateError> {
+    let mut transaction = pool.begin().await.map_err(CreateError::Database)?;
+    lock_refund_with_status(&mut transaction, id, RefundStatus::Pending).await?;
+    let refund = review(
+        &mut transaction,
+        id,
+        RefundStatus::Rejected,
+        clock.now_utc(),
+    )
+    .await?;
+
+    transaction.commit().await.map_err(CreateError::Database)?;
+
+    Ok(refund)
+}
+
+/// Executes the scheduled refunds due by now, and returns them.
+///
+/// Refunds exceeding the `approval_threshold` then await approval, like refunds made right
+/// away, while the others are applied. Refunds failing to be applied (e.g. the account service
+/// is unavailable, or other refunds left too little to refund) are skipped, and tried again by
+/// the next call.
+pub async fn execute_due(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    approval_threshold: Option<i32>,
+) -> Result<Vec<Refund>, sqlx::Error> {
+    let ids = sqlx::query_scalar!(
+        r#"
+            SELECT id
+              FROM refunds
+             WHERE status = 'Scheduled'
+               AND execute_at <= $1
+        "#,
+        clock.now_utc()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut executed = Vec::with_capacity(ids.len());
+    for id in ids {
+        match execute(pool, account_service, clock, id, approval_threshold).await {
+            Ok(refund) => executed.push(refund),
+            // executed since
+            Err(CreateError::RefundNotPending) => {}
+            Err(CreateError::Database(err)) => return Err(err),
+            Err(err) => tracing::warn!(refund_id = %id, error = ?err, "failed to execute refund"),
+        }
+    }
+    Ok(executed)
+}
+
+/// Executes a scheduled refund, making it pending if it exceeds the `approval_threshold`
+/// and applying it otherwise.
+async fn execute(
+    pool: &PgPool,
+    account_service: &impl AccountService,
+    clock: &dyn Clock,
+    id: Uuid,
+    approval_threshold: Option<i32>,
+) -> Result<Refund, CreateError> {
+    let now = clock.now_utc();
+    let mut transaction = pool.begin().await.map_err(CreateError::Database)?;
+    let refund = lock_refund_with_status(&mut transaction, id, RefundStatus::Scheduled).await?;
+    if approval_threshold.is_some_and(|threshold| refund.amount > threshold) {
+        let refund = review(&mut transaction, id, RefundStatus::Pending, now).await?;
+        transaction.commit().await.map_err(CreateError::Database)?;
+        return Ok(refund);
+    }
+    let (refund, credit) = apply_locked(&mut transaction, refund, now).await?;
+
     transaction.commit().await.map_err(CreateError::Database)?;
+    credit_or_revert(
+        pool,
+        account_service,
+        clock,
+        &refund,
+        credit,
+        Some(RefundStatus::Scheduled),
+    )
+    .await?;
 
     Ok(refund)
 }
 
+/// Spawns a background task running `execute_due` every `interval`.
+pub fn spawn_executor(
+    pool: PgPool,
+    account_service: impl AccountService,
+    clock: Arc<dyn Clock>,
+    approval_threshold: Option<i32>,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            match execute_due(&pool, &account_service, clock.as_ref(), approval_threshold).await {
+                Ok(refunds) if !refunds.is_empty() => {
+                    tracing::info!(count = refunds.len(), "executed scheduled refunds");
+                }
+                Ok(_) => {}
+                Err(err) => tracing::error!(error = ?err, "failed to execute scheduled refunds"),
+            }
+        }
+    })
+}
+
+/// Runs the same checks as `create` for a refund of `amount`, without writing anything.
+pub async fn eligibility(
+    pool: &PgPool,
+    payment_id: Uuid,
+    amount: i32,
+) -> Result<Eligibility, sqlx::Error> {
+    let payment = sqlx::query!(
+        r#"
+            SELECT amount, refunded_amount, status as "status: Status"
+              FROM payments
+             WHERE id = $1
+        "#,
+        payment_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    let eligibility = match payment {
+        None => Eligibility {
+            reason: Some(IneligibilityReason::PaymentNotFound),
+            max_refundable: 0,
+        },
+        Some(payment)
+            if matches!(
+                payment.status,
+                Status::Processing | Status::Authorized | Status::Capturing
+            ) =>
+        {
+            Eligibility {
+                reason: Some(IneligibilityReason::PaymentNotSettled),
+                max_refundable: 0,
+            }
+        }
+        Some(payment) if payment.status != Status::Approved => Eligibility {
+            reason: Some(IneligibilityReason::PaymentNotApproved),
+            max_refundable: 0,
+        },
+        Some(payment) => {
+            let max_refundable = payment.amount - payment.refunded_amount;
+            Eligibility {
+                reason: (amount > max_refundable).then_some(IneligibilityReason::ExcessiveAmount),
+                max_refundable,
+            }
+        }
+    };
+
+    Ok(eligibility)
+}
+
 pub async fn get(pool: &PgPool, id: Uuid) -> Result<Refund, sqlx::Error> {
     sqlx::query_as!(
         Refund,
         r#"
-            SELECT id, payment_id, amount, inserted_at, updated_at FROM refunds
+            SELECT id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at FROM refunds
             WHERE id = $1
         "#,
         id
@@ -91,24 +1077,270 @@ pub async fn get(pool: &PgPool, id: Uuid) -> Result<Refund, sqlx::Error> {
     .await
 }
 
+/// Inserts the line items of the refund, in the order they were given.
+async fn insert_line_items(
+    transaction: &mut Transaction<'_, Postgres>,
+    refund_id: Uuid,
+    line_items: &[LineItem],
+) -> Result<(), sqlx::Error> {
+    let positions: Vec<_> = (0..line_items.len() as i32).collect();
+    let skus: Vec<_> = line_items.iter().map(|item| item.sku.clone()).collect();
+    let amounts: Vec<_> = line_items.iter().map(|item| item.amount).collect();
+    sqlx::query!(
+        r#"
+            INSERT INTO refund_line_items ( refund_id, position, sku, amount )
+            SELECT $1, position, sku, amount
+              FROM unnest($2::int[], $3::text[], $4::int[]) AS items(position, sku, amount)
+        "#,
+        refund_id,
+        &positions,
+        &skus,
+        &amounts
+    )
+    .execute(&mut *transaction)
+    .await
+    .map(|_| ())
+}
+
+/// Returns the line items of the refund, in the order they were given.
+pub async fn list_line_items(pool: &PgPool, refund_id: Uuid) -> Result<Vec<LineItem>, sqlx::Error> {
+    sqlx::query_as!(
+        LineItem,
+        r#"
+              SELECT sku, amount
+                FROM refund_line_items
+               WHERE refund_id = $1
+            ORDER BY position
+        "#,
+        refund_id
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns how the refund's amount was converted, or `None` if it wasn't.
+pub async fn get_conversion(
+    pool: &PgPool,
+    refund_id: Uuid,
+) -> Result<Option<Conversion>, sqlx::Error> {
+    sqlx::query_as!(
+        Conversion,
+        r#"
+            SELECT exchange_rate::text as "exchange_rate!", source_amount, source_currency, converted_at
+              FROM refund_conversions
+             WHERE refund_id = $1
+        "#,
+        refund_id
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Filters of searched refunds, all of which must match. Unset ones match any refund.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SearchFilter<'a> {
+    /// The credit note the refunds are linked to.
+    pub credit_note_reference: Option<&'a str>,
+    /// The smallest amount of the refunds, included.
+    pub min_amount: Option<i32>,
+    /// The largest amount of the refunds, included.
+    pub max_amount: Option<i32>,
+}
+
+/// Order of searched refunds, ties being broken oldest first.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SearchOrder {
+    /// Oldest first.
+    #[default]
+    #[serde(rename = "inserted_at")]
+    InsertedAt,
+    /// Smallest first.
+    #[serde(rename = "amount")]
+    Amount,
+    /// Largest first.
+    #[serde(rename = "-amount")]
+    AmountDesc,
+}
+
+impl SearchOrder {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::InsertedAt => "inserted_at",
+            Self::Amount => "amount",
+            Self::AmountDesc => "-amount",
+        }
+    }
+}
+
+/// Lists up to `limit` of the refunds matching the `filter`, in the `order`.
+pub async fn search(
+    pool: &PgPool,
+    filter: SearchFilter<'_>,
+    order: SearchOrder,
+    limit: i64,
+) -> Result<Vec<Refund>, sqlx::Error> {
+    sqlx::query_as!(
+        Refund,
+        r#"
+              SELECT id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at
+                FROM refunds
+               WHERE ($1::varchar IS NULL OR credit_note_reference = $1)
+                 AND ($2::integer IS NULL OR amount >= $2)
+                 AND ($3::integer IS NULL OR amount <= $3)
+            ORDER BY CASE WHEN $4::text = 'amount' THEN amount END,
+                     CASE WHEN $4::text = '-amount' THEN amount END DESC,
+                     inserted_at, id
+               LIMIT $5
+        "#,
+        filter.credit_note_reference,
+        filter.min_amount,
+        filter.max_amount,
+        order.as_str(),
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Lists a page of the refunds made against the payment, oldest first.
+pub async fn list_for_payment(
+    pool: &PgPool,
+    payment_id: Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<Refund>, sqlx::Error> {
+    sqlx::query_as!(
+        Refund,
+        r#"
+            SELECT id, payment_id, amount, status as "status: _", destination_card_number, currency, allow_overrefund, credit_note_reference, execute_at, inserted_at, updated_at FROM refunds
+            WHERE payment_id = $1
+            ORDER BY inserted_at, id
+            LIMIT $2 OFFSET $3
+        "#,
+        payment_id,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Returns the number of refunds made against the payment.
+pub async fn count_for_payment(pool: &PgPool, payment_id: Uuid) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"SELECT count(*) as "count!" FROM refunds WHERE payment_id = $1"#,
+        payment_id
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// The number and sum of the refunds issued on a day.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyTotal {
+    pub day: Date,
+    pub count: i64,
+    pub amount: i64,
+}
+
+/// Totals the approved refunds per day (in UTC) of their issuance, from `from` to `to`
+/// included, omitting the days without any.
+///
+/// Pending and rejected refunds are left out, as they didn't credit any money, and so are
+/// refunds of test payments unless `include_test`.
+pub async fn daily_totals(
+    pool: &PgPool,
+    from: Date,
+    to: Date,
+    include_test: bool,
+) -> Result<Vec<DailyTotal>, sqlx::Error> {
+    sqlx::query_as!(
+        DailyTotal,
+        r#"
+              SELECT inserted_at::date as "day!", COUNT(*) as "count!", SUM(amount) as "amount!"
+                FROM refunds
+               WHERE status = 'Approved'
+                 AND inserted_at >= $1::date
+                 AND inserted_at < $2::date + 1
+                 AND ($3 OR payment_id IN (SELECT id FROM payments WHERE livemode))
+            GROUP BY 1
+            ORDER BY 1
+        "#,
+        from,
+        to,
+        include_test
+    )
+    .fetch_all(pool)
+    .await
+}
+
 #[cfg(test)]
 pub mod tests {
 
     use super::*;
-    use crate::bank::payments::Payment;
+    use crate::bank::{
+        accounts::DummyService,
+        clock::{tests::MockClock, SystemClock},
+        payments::Payment,
+    };
 
     pub const REFUND_AMOUNT: i32 = 42;
 
+    /// Name of the index on refunds' payment ids and insertion times, which orders the refunds
+    /// of a payment as listed by `list_for_payment` without sorting them.
+    pub const PAYMENT_ID_INSERTED_AT_INDEX: &str = "refunds_payment_id_inserted_at_index";
+
+    /// Records how the refund's amount was converted from another currency, as cross-currency
+    /// refunds will once they exist.
+    ///
+    /// Fails if the exchange rate isn't a positive decimal, or a conversion was already
+    /// recorded for the refund.
+    pub async fn record_conversion(
+        pool: &PgPool,
+        refund_id: Uuid,
+        conversion: &Conversion,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            r#"
+                INSERT INTO refund_conversions ( refund_id, exchange_rate, source_amount, source_currency, converted_at )
+                VALUES ( $1, $2::text::numeric, $3, $4, $5 )
+            "#,
+            refund_id,
+            conversion.exchange_rate,
+            conversion.source_amount,
+            conversion.source_currency,
+            conversion.converted_at
+        )
+        .execute(pool)
+        .await
+        .map(|_| ())
+    }
+
     impl Refund {
         pub async fn new_test(pool: &PgPool) -> Result<Refund, sqlx::Error> {
             let payment = Payment::new_test(pool).await?;
 
-            let refund = create(pool, payment.id, REFUND_AMOUNT)
-                .await
-                .map_err(|e| match e {
-                    CreateError::Database(err) => err,
-                    _ => panic!("Not a database error: {:?}", e),
-                })?;
+            let refund = create(
+                pool,
+                &DummyService::default(),
+                &SystemClock,
+                payment.id,
+                REFUND_AMOUNT,
+                None,
+                None,
+                None,
+                &[],
+                false,
+                None,
+                None,
+                None,
+            )
+            .await
+            .map_err(|e| match e {
+                CreateError::Database(err) => err,
+                _ => panic!("Not a database error: {:?}", e),
+            })?
+            .refund;
 
             get(pool, refund.id).await
         }
@@ -126,4 +1358,457 @@ pub mod tests {
 
         assert_eq!(refund.amount, REFUND_AMOUNT);
     }
+
+    #[tokio::test]
+    async fn test_refund_reverted_when_credit_fails() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let account_service = DummyService {
+            response: Some("service_unavailable".into()),
+            ..Default::default()
+        };
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+
+        let result = create(
+            &pool,
+            &account_service,
+            &SystemClock,
+            payment.id,
+            REFUND_AMOUNT,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            None,
+            None,
+            None,
+        )
+        .await;
+        assert!(matches!(
+            result,
+            Err(CreateError::AccountService(
+                AccountServiceError::ServiceUnavailable
+            ))
+        ));
+
+        let payment = payments::get(&pool, payment.id)
+            .await
+            .expect("failed to get payment");
+        assert_eq!(payment.refunded_amount, 0);
+        let refunds = count_for_payment(&pool, payment.id)
+            .await
+            .expect("failed to count refunds");
+        assert_eq!(refunds, 0);
+    }
+
+    #[tokio::test]
+    async fn test_approval_reverted_when_credit_fails() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+        let refund = create(
+            &pool,
+            &DummyService::default(),
+            &SystemClock,
+            payment.id,
+            REFUND_AMOUNT,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            Some(REFUND_AMOUNT - 1),
+            None,
+            None,
+        )
+        .await
+        .expect("failed to create refund")
+        .refund;
+        assert_eq!(refund.status, RefundStatus::Pending);
+
+        let account_service = DummyService {
+            response: Some("service_unavailable".into()),
+            ..Default::default()
+        };
+        let result = approve(&pool, &account_service, &SystemClock, refund.id).await;
+        assert!(matches!(result, Err(CreateError::AccountService(_))));
+
+        let refund = get(&pool, refund.id).await.expect("failed to get refund");
+        assert_eq!(refund.status, RefundStatus::Pending);
+        let payment = payments::get(&pool, payment.id)
+            .await
+            .expect("failed to get payment");
+        assert_eq!(payment.refunded_amount, 0);
+
+        let refund = approve(&pool, &DummyService::default(), &SystemClock, refund.id)
+            .await
+            .expect("failed to approve refund");
+        assert_eq!(refund.status, RefundStatus::Approved);
+    }
+
+    #[tokio::test]
+    async fn test_list_for_payment_orders_refunds_by_insertion() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let clock =
+            MockClock::new(time::OffsetDateTime::from_unix_timestamp(1_672_531_200).unwrap());
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+
+        // made out of order, the last two at the same time
+        let mut refund_ids = Vec::new();
+        for hours in [2, 1, 1] {
+            let clock = MockClock::new(clock.now() + time::Duration::hours(hours));
+            let refund = create(
+                &pool,
+                &DummyService::default(),
+                &clock,
+                payment.id,
+                1,
+                None,
+                None,
+                None,
+                &[],
+                false,
+                None,
+                None,
+                None,
+            )
+            .await
+            .expect("failed to create refund")
+            .refund;
+            refund_ids.push((refund.inserted_at, refund.id));
+        }
+        refund_ids.sort();
+
+        let refunds = list_for_payment(&pool, payment.id, 10, 0).await.unwrap();
+        let listed: Vec<_> = refunds
+            .iter()
+            .map(|refund| (refund.inserted_at, refund.id))
+            .collect();
+        assert_eq!(listed, refund_ids);
+        let refunds = list_for_payment(&pool, payment.id, 2, 1).await.unwrap();
+        let listed: Vec<_> = refunds
+            .iter()
+            .map(|refund| (refund.inserted_at, refund.id))
+            .collect();
+        assert_eq!(listed, refund_ids[1..]);
+    }
+
+    #[tokio::test]
+    async fn test_list_for_payment_uses_payment_id_index() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let mut transaction = pool.begin().await.unwrap();
+
+        // the table may be small enough for a sequential scan to be cheaper
+        sqlx::query("SET LOCAL enable_seqscan = off")
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+        let plan: serde_json::Value = sqlx::query_scalar(
+            r#"
+                EXPLAIN (FORMAT JSON)
+                SELECT id FROM refunds
+                WHERE payment_id = $1
+                ORDER BY inserted_at, id
+                LIMIT 10
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .fetch_one(&mut transaction)
+        .await
+        .unwrap();
+        let plan = plan.to_string();
+        assert!(plan.contains(PAYMENT_ID_INSERTED_AT_INDEX), "{plan}");
+        assert!(!plan.contains("\"Sort\""), "{plan}");
+    }
+
+    #[tokio::test]
+    async fn test_refund_timestamps_follow_clock() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let clock =
+            MockClock::new(time::OffsetDateTime::from_unix_timestamp(1_672_531_200).unwrap());
+
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+
+        clock.advance(time::Duration::hours(1));
+        let refund = create(
+            &pool,
+            &DummyService::default(),
+            &clock,
+            payment.id,
+            REFUND_AMOUNT,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .expect("failed to create refund")
+        .refund;
+        assert_eq!(refund.inserted_at, clock.now_utc());
+
+        clock.advance(time::Duration::hours(1));
+        create(
+            &pool,
+            &DummyService::default(),
+            &clock,
+            payment.id,
+            REFUND_AMOUNT,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .expect("failed to create refund");
+
+        let payment = payments::get(&pool, payment.id)
+            .await
+            .expect("failed to get payment");
+        assert_eq!(payment.updated_at, clock.now_utc());
+        assert_eq!(
+            payment.updated_at - refund.inserted_at,
+            time::Duration::hours(1)
+        );
+    }
+
+    /// Refunds the payment while a concurrent transaction, standing for settlement, holds
+    /// the payment locked and flips its status to `settled_status`.
+    async fn refund_during_settlement(
+        initial_status: Status,
+        settled_status: Status,
+    ) -> Result<Refund, CreateError> {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test_with_status(&pool, initial_status)
+            .await
+            .expect("failed to create payment");
+
+        let mut settlement = pool.begin().await.expect("failed to begin transaction");
+        sqlx::query!(
+            r#"SELECT id FROM payments WHERE id = $1 FOR UPDATE"#,
+            payment.id
+        )
+        .fetch_one(&mut settlement)
+        .await
+        .expect("failed to lock payment");
+
+        let refund = tokio::spawn({
+            let pool = pool.clone();
+            async move {
+                create(
+                    &pool,
+                    &DummyService::default(),
+                    &SystemClock,
+                    payment.id,
+                    REFUND_AMOUNT,
+                    None,
+                    None,
+                    None,
+                    &[],
+                    false,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .map(|created| created.refund)
+            }
+        });
+
+        // let the refund block on the lock before settling
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        assert!(!refund.is_finished());
+        sqlx::query!(
+            r#"UPDATE payments SET status = $2 WHERE id = $1"#,
+            payment.id,
+            settled_status as Status
+        )
+        .execute(&mut settlement)
+        .await
+        .expect("failed to settle payment");
+        settlement
+            .commit()
+            .await
+            .expect("failed to commit settlement");
+
+        refund.await.expect("refund task panicked")
+    }
+
+    #[tokio::test]
+    async fn test_refund_waits_for_settlement() {
+        let refund = refund_during_settlement(Status::Processing, Status::Approved)
+            .await
+            .expect("failed to create refund");
+        assert_eq!(refund.amount, REFUND_AMOUNT);
+    }
+
+    #[tokio::test]
+    async fn test_refund_rejected_before_settlement() {
+        let err = refund_during_settlement(Status::Approved, Status::Processing)
+            .await
+            .expect_err("refunded an unsettled payment");
+        assert!(matches!(err, CreateError::PaymentNotSettled));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_refunds_dont_exceed_amount() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+        let amount = payment.amount / 2 + 1;
+        let account_service = DummyService::default();
+
+        let refund = || {
+            create(
+                &pool,
+                &account_service,
+                &SystemClock,
+                payment.id,
+                amount,
+                None,
+                None,
+                None,
+                &[],
+                false,
+                None,
+                None,
+                None,
+            )
+        };
+        let (first, second) = tokio::join!(refund(), refund());
+
+        let results = [first, second];
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 1);
+        assert!(results
+            .iter()
+            .any(|result| matches!(result, Err(CreateError::ExcessiveAmount))));
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_idempotent_refunds_refund_once() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+        let account_service = DummyService::default();
+        let idempotency_key = Uuid::new_v4().to_string();
+
+        // a full refund, which the second attempt would find excessive if it weren't keyed
+        let refund = |amount| {
+            create_idempotent(
+                &pool,
+                &account_service,
+                &SystemClock,
+                payment.id,
+                amount,
+                None,
+                None,
+                None,
+                &[],
+                false,
+                None,
+                None,
+                None,
+                &idempotency_key,
+            )
+        };
+        let (first, second) = tokio::join!(refund(payment.amount), refund(payment.amount));
+
+        let (first, second) = (first.unwrap().refund, second.unwrap().refund);
+        assert_eq!(first.id, second.id);
+        assert_eq!(first.amount, second.amount);
+        assert_eq!(count_for_payment(&pool, payment.id).await.unwrap(), 1);
+        assert_eq!(
+            payments::get(&pool, payment.id)
+                .await
+                .unwrap()
+                .refunded_amount,
+            payment.amount
+        );
+        assert!(matches!(
+            refund(payment.amount - 1).await,
+            Err(CreateError::IdempotencyKeyReused)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_many_concurrent_refunds_dont_exceed_amount() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+        let amount = payment.amount / 3 + 1;
+        let account_service = DummyService::default();
+
+        let results = futures::future::join_all((0..4).map(|_| {
+            create(
+                &pool,
+                &account_service,
+                &SystemClock,
+                payment.id,
+                amount,
+                None,
+                None,
+                None,
+                &[],
+                false,
+                None,
+                None,
+                None,
+            )
+        }))
+        .await;
+
+        let refunded: i32 = results
+            .iter()
+            .map(|result| match result {
+                Ok(created) => created.refund.amount,
+                Err(CreateError::ExcessiveAmount) => 0,
+                Err(e) => panic!("unexpected refund failure: {e:?}"),
+            })
+            .sum();
+        assert!(refunded <= payment.amount);
+        assert_eq!(refunded, 2 * amount);
+        let refunded_amount = sqlx::query_scalar!(
+            "SELECT refunded_amount FROM payments WHERE id = $1",
+            payment.id
+        )
+        .fetch_one(&pool)
+        .await
+        .expect("failed to fetch payment");
+        assert_eq!(refunded_amount, refunded);
+    }
 }
@@ -0,0 +1,253 @@
+//! Storage of payments and refunds, abstracted so that the web layer can be tested
+//! without a database.
+//!
+//! Only reads go through the repositories so far: writes need transactions spanning
+//! several statements, which still use the pool directly.
+
+use std::collections::HashMap;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::bank::payments::{self, Payment, Status};
+use crate::bank::refunds::{self, Conversion, LineItem, Refund};
+
+/// Reads payments. Missing payments are reported as `sqlx::Error::RowNotFound`, as by
+/// the queries.
+#[async_trait::async_trait]
+pub trait PaymentRepository: Send + Sync + 'static {
+    async fn get(&self, id: Uuid) -> Result<Payment, sqlx::Error>;
+
+    /// Returns the status of each of the payments found.
+    async fn statuses(&self, ids: &[Uuid]) -> Result<HashMap<Uuid, Status>, sqlx::Error>;
+}
+
+/// Reads refunds. Missing refunds are reported as `sqlx::Error::RowNotFound`, as by
+/// the queries.
+#[async_trait::async_trait]
+pub trait RefundRepository: Send + Sync + 'static {
+    async fn get(&self, id: Uuid) -> Result<Refund, sqlx::Error>;
+
+    /// Lists a page of the refunds made against the payment, oldest first.
+    async fn list_for_payment(
+        &self,
+        payment_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Refund>, sqlx::Error>;
+
+    async fn count_for_payment(&self, payment_id: Uuid) -> Result<i64, sqlx::Error>;
+
+    /// Lists the line items of the refund, in the order they were given.
+    async fn line_items(&self, refund_id: Uuid) -> Result<Vec<LineItem>, sqlx::Error>;
+
+    /// Returns how the refund's amount was converted from another currency, if it was.
+    async fn conversion(&self, refund_id: Uuid) -> Result<Option<Conversion>, sqlx::Error>;
+}
+
+/// Repository backed by the postgres tables.
+#[derive(Debug, Clone)]
+pub struct PgRepository {
+    pool: PgPool,
+}
+
+impl PgRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait::async_trait]
+impl PaymentRepository for PgRepository {
+    async fn get(&self, id: Uuid) -> Result<Payment, sqlx::Error> {
+        payments::get(&self.pool, id).await
+    }
+
+    async fn statuses(&self, ids: &[Uuid]) -> Result<HashMap<Uuid, Status>, sqlx::Error> {
+        payments::statuses(&self.pool, ids).await
+    }
+}
+
+#[async_trait::async_trait]
+impl RefundRepository for PgRepository {
+    async fn get(&self, id: Uuid) -> Result<Refund, sqlx::Error> {
+        refunds::get(&self.pool, id).await
+    }
+
+    async fn list_for_payment(
+        &self,
+        payment_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<Refund>, sqlx::Error> {
+        refunds::list_for_payment(&self.pool, payment_id, limit, offset).await
+    }
+
+    async fn count_for_payment(&self, payment_id: Uuid) -> Result<i64, sqlx::Error> {
+        refunds::count_for_payment(&self.pool, payment_id).await
+    }
+
+    async fn line_items(&self, refund_id: Uuid) -> Result<Vec<LineItem>, sqlx::Error> {
+        refunds::list_line_items(&self.pool, refund_id).await
+    }
+
+    async fn conversion(&self, refund_id: Uuid) -> Result<Option<Conversion>, sqlx::Error> {
+        refunds::get_conversion(&self.pool, refund_id).await
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::Mutex;
+
+    use sqlx::types::Json;
+
+    use super::*;
+    use crate::bank::{
+        clock::{Clock, SystemClock},
+        payment_instruments::Card,
+        payments::Metadata,
+        refunds::RefundStatus,
+    };
+
+    /// Returns a payment of `amount` as inserted in the database, without inserting it.
+    pub fn new_payment(amount: i32, status: Status) -> Payment {
+        let now = SystemClock.now_utc();
+        Payment {
+            id: Uuid::new_v4(),
+            amount,
+            hold_amount: amount,
+            hold_id: None,
+            refunded_amount: 0,
+            fee_amount: 0,
+            net_amount: amount,
+            card_number: Card::new_test().into(),
+            status,
+            decline_reason: None,
+            currency: None,
+            metadata: Json(Metadata::new()),
+            client_ip: None,
+            customer_id: None,
+            merchant_id: None,
+            merchant_reference: None,
+            channel: None,
+            authorized_expires_at: None,
+            livemode: true,
+            archived_at: None,
+            settled_at: None,
+            inserted_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Returns an approved refund of `amount` against the payment, without inserting it.
+    pub fn new_refund(payment: &Payment, amount: i32) -> Refund {
+        let now = SystemClock.now_utc();
+        Refund {
+            id: Uuid::new_v4(),
+            payment_id: payment.id,
+            amount,
+            status: RefundStatus::Approved,
+            destination_card_number: None,
+            currency: payment.currency.clone(),
+            allow_overrefund: false,
+            credit_note_reference: None,
+            execute_at: None,
+            inserted_at: now,
+            updated_at: now,
+        }
+    }
+
+    /// Repository keeping payments and refunds in memory, for tests that don't need a
+    /// database.
+    #[derive(Debug, Default)]
+    pub struct InMemoryRepository {
+        payments: Mutex<HashMap<Uuid, Payment>>,
+        refunds: Mutex<Vec<Refund>>,
+    }
+
+    impl InMemoryRepository {
+        pub fn insert_payment(&self, payment: Payment) {
+            self.payments.lock().unwrap().insert(payment.id, payment);
+        }
+
+        pub fn insert_refund(&self, refund: Refund) {
+            self.refunds.lock().unwrap().push(refund);
+        }
+
+        /// Returns the refunds made against the payment, oldest first.
+        fn refunds_for_payment(&self, payment_id: Uuid) -> Vec<Refund> {
+            let mut refunds: Vec<_> = self
+                .refunds
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|refund| refund.payment_id == payment_id)
+                .cloned()
+                .collect();
+            refunds.sort_by_key(|refund| (refund.inserted_at, refund.id));
+            refunds
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl PaymentRepository for InMemoryRepository {
+        async fn get(&self, id: Uuid) -> Result<Payment, sqlx::Error> {
+            self.payments
+                .lock()
+                .unwrap()
+                .get(&id)
+                .cloned()
+                .ok_or(sqlx::Error::RowNotFound)
+        }
+
+        async fn statuses(&self, ids: &[Uuid]) -> Result<HashMap<Uuid, Status>, sqlx::Error> {
+            let payments = self.payments.lock().unwrap();
+            Ok(ids
+                .iter()
+                .filter_map(|id| payments.get(id).map(|payment| (*id, payment.status)))
+                .collect())
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl RefundRepository for InMemoryRepository {
+        async fn get(&self, id: Uuid) -> Result<Refund, sqlx::Error> {
+            self.refunds
+                .lock()
+                .unwrap()
+                .iter()
+                .find(|refund| refund.id == id)
+                .cloned()
+                .ok_or(sqlx::Error::RowNotFound)
+        }
+
+        async fn list_for_payment(
+            &self,
+            payment_id: Uuid,
+            limit: i64,
+            offset: i64,
+        ) -> Result<Vec<Refund>, sqlx::Error> {
+            Ok(self
+                .refunds_for_payment(payment_id)
+                .into_iter()
+                .skip(offset as usize)
+                .take(limit as usize)
+                .collect())
+        }
+
+        async fn count_for_payment(&self, payment_id: Uuid) -> Result<i64, sqlx::Error> {
+            Ok(self.refunds_for_payment(payment_id).len() as i64)
+        }
+
+        async fn line_items(&self, _refund_id: Uuid) -> Result<Vec<LineItem>, sqlx::Error> {
+            // refunds are inserted without line items
+            Ok(Vec::new())
+        }
+
+        async fn conversion(&self, _refund_id: Uuid) -> Result<Option<Conversion>, sqlx::Error> {
+            // nor conversions
+            Ok(None)
+        }
+    }
+}
@@ -0,0 +1,48 @@
+/// How many times a hold is retried when the account service is unavailable, and how many
+/// times it was for a request.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetryBudget {
+    max_retries: u32,
+    retries: u32,
+}
+
+impl RetryBudget {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            retries: 0,
+        }
+    }
+
+    /// Spends a retry, returning whether any was left.
+    pub fn try_retry(&mut self) -> bool {
+        if self.retries < self.max_retries {
+            self.retries += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns how many retries were spent.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_retry() {
+        let mut budget = RetryBudget::new(2);
+
+        assert!(budget.try_retry());
+        assert!(budget.try_retry());
+        assert!(!budget.try_retry());
+        assert_eq!(budget.retries(), 2);
+
+        assert!(!RetryBudget::default().try_retry());
+    }
+}
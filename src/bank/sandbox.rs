@@ -0,0 +1,293 @@
+//! Behavior specific to sandbox mode, in which merchants integrate against the bank
+//! without moving real money. Sandbox mode must never be enabled in production.
+
+use std::{collections::HashMap, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgConnection;
+
+use super::payments::AccountServiceError;
+
+/// Outcomes forced by the cents of payment amounts in sandbox mode, so that demos can
+/// trigger each unhappy path without configuring the account service.
+///
+/// By default, amounts ending in `01` are declined for insufficient funds, `02` for an
+/// invalid account number, and `03` and `04` fail as if the account service were
+/// unavailable or crashed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MagicAmounts(HashMap<i32, AccountServiceError>);
+
+impl Default for MagicAmounts {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (1, AccountServiceError::InsufficientFunds),
+            (2, AccountServiceError::InvalidAccountNumber),
+            (3, AccountServiceError::ServiceUnavailable),
+            (4, AccountServiceError::InternalError),
+        ]))
+    }
+}
+
+impl MagicAmounts {
+    /// Returns the error the account service is assumed to return for `amount`, if any.
+    pub fn outcome(&self, amount: i32) -> Option<AccountServiceError> {
+        self.0.get(&(amount % 100)).copied()
+    }
+}
+
+/// Prefixes (BINs) of the card numbers reserved for testing, which are rejected outside
+/// sandbox mode so that test cards leaking into live traffic are never charged.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestCardBins(Vec<String>);
+
+impl TestCardBins {
+    /// Returns whether `card_number` starts with one of the test BINs.
+    pub fn contains(&self, card_number: &str) -> bool {
+        self.0.iter().any(|bin| card_number.starts_with(bin))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidTestCardBins;
+
+/// Parses a list of BINs such as `42,4000`.
+impl FromStr for TestCardBins {
+    type Err = InvalidTestCardBins;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|bin| {
+                let bin = bin.trim();
+                if !bin.is_empty() && bin.chars().all(|c| c.is_ascii_digit()) {
+                    Ok(bin.to_owned())
+                } else {
+                    Err(InvalidTestCardBins)
+                }
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidMagicAmounts;
+
+/// Parses a mapping such as `01:insufficient_funds,99:service_unavailable`.
+impl FromStr for MagicAmounts {
+    type Err = InvalidMagicAmounts;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.split(',')
+            .map(|entry| {
+                let (cents, error) = entry.split_once(':').ok_or(InvalidMagicAmounts)?;
+                let cents = cents
+                    .trim()
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|cents| (0..100).contains(cents))
+                    .ok_or(InvalidMagicAmounts)?;
+                let error = error.trim().parse().map_err(|_| InvalidMagicAmounts)?;
+                Ok((cents, error))
+            })
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+}
+
+/// The number of rows deleted from each table by `reset`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ResetSummary {
+    pub payments: u64,
+    pub refunds: u64,
+    pub payment_events: u64,
+    pub webhook_deliveries: u64,
+    pub card_tokens: u64,
+}
+
+/// Deletes the payments and everything recorded about them, for demos to start over.
+/// Merchant configurations are kept.
+///
+/// Run it in a transaction, for the tables not to be left partially emptied.
+pub async fn reset(connection: &mut PgConnection) -> Result<ResetSummary, sqlx::Error> {
+    // referencing the payments, so deleted first
+    let payment_events = sqlx::query!("DELETE FROM payment_events")
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+    let refunds = sqlx::query!("DELETE FROM refunds")
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+    let payments = sqlx::query!("DELETE FROM payments")
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+    let webhook_deliveries = sqlx::query!("DELETE FROM webhook_deliveries")
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+    let card_tokens = sqlx::query!("DELETE FROM card_tokens")
+        .execute(&mut *connection)
+        .await?
+        .rows_affected();
+
+    Ok(ResetSummary {
+        payments,
+        refunds,
+        payment_events,
+        webhook_deliveries,
+        card_tokens,
+    })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use sqlx::Connection;
+
+    use super::*;
+    use crate::bank::{
+        accounts::DummyService, clock::SystemClock, payments::Payment, refunds, tokens,
+    };
+
+    #[test]
+    fn test_outcome() {
+        let magic_amounts = MagicAmounts::default();
+
+        assert_eq!(
+            magic_amounts.outcome(12_01),
+            Some(AccountServiceError::InsufficientFunds)
+        );
+        assert_eq!(magic_amounts.outcome(12_00), None);
+        assert_eq!(magic_amounts.outcome(12_10), None);
+    }
+
+    #[test]
+    fn test_from_str() {
+        let magic_amounts: MagicAmounts = "99:service_unavailable".parse().unwrap();
+
+        assert_eq!(
+            magic_amounts.outcome(12_99),
+            Some(AccountServiceError::ServiceUnavailable)
+        );
+        assert_eq!(magic_amounts.outcome(12_01), None);
+        assert_eq!(
+            "100:service_unavailable".parse::<MagicAmounts>(),
+            Err(InvalidMagicAmounts)
+        );
+        assert_eq!(
+            "01:unknown".parse::<MagicAmounts>(),
+            Err(InvalidMagicAmounts)
+        );
+    }
+
+    #[test]
+    fn test_test_card_bins() {
+        let bins: TestCardBins = "42, 4000".parse().unwrap();
+
+        assert!(bins.contains("421234567890123"));
+        assert!(bins.contains("400012345678901"));
+        assert!(!bins.contains("401234567890123"));
+        assert!(!TestCardBins::default().contains("421234567890123"));
+        assert_eq!("42,".parse::<TestCardBins>(), Err(InvalidTestCardBins));
+        assert_eq!("4x".parse::<TestCardBins>(), Err(InvalidTestCardBins));
+    }
+
+    #[tokio::test]
+    async fn test_reset() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let payment = Payment::new_test(&pool).await.unwrap();
+        refunds::create(
+            &pool,
+            &DummyService::default(),
+            &SystemClock,
+            payment.id,
+            1,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        tokens::tokenize_all(
+            &pool,
+            &SystemClock,
+            std::slice::from_ref(&payment.card_number),
+        )
+        .await
+        .unwrap();
+        let mut connection = pool.acquire().await.unwrap();
+        let mut transaction = connection.begin().await.unwrap();
+
+        // copies of the tables, seeded with the rows created above, so that the other tests'
+        // rows aren't deleted: the schema is dropped with the transaction
+        let schema: String = sqlx::query_scalar("SELECT current_schema()")
+            .fetch_one(&mut transaction)
+            .await
+            .unwrap();
+        sqlx::query("CREATE SCHEMA test_reset")
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+        for table in [
+            "payments",
+            "refunds",
+            "payment_events",
+            "webhook_deliveries",
+            "card_tokens",
+        ] {
+            sqlx::query(&format!(
+                "CREATE TABLE test_reset.{table} (LIKE {schema}.{table} INCLUDING ALL)"
+            ))
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+        }
+        for (table, column) in [
+            ("payments", "id"),
+            ("refunds", "payment_id"),
+            ("payment_events", "payment_id"),
+        ] {
+            sqlx::query(&format!(
+                "INSERT INTO test_reset.{table} SELECT * FROM {schema}.{table} WHERE {column} = $1"
+            ))
+            .bind(payment.id)
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+        }
+        sqlx::query(&format!(
+            "INSERT INTO test_reset.card_tokens SELECT * FROM {schema}.card_tokens WHERE card_number = $1"
+        ))
+        .bind(&payment.card_number)
+        .execute(&mut transaction)
+        .await
+        .unwrap();
+        sqlx::query("SET LOCAL search_path TO test_reset")
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            reset(&mut transaction).await.unwrap(),
+            ResetSummary {
+                payments: 1,
+                refunds: 1,
+                // created, then refunded
+                payment_events: 2,
+                webhook_deliveries: 0,
+                card_tokens: 1,
+            }
+        );
+        assert_eq!(
+            reset(&mut transaction).await.unwrap(),
+            ResetSummary::default()
+        );
+    }
+}
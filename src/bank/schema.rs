@@ -0,0 +1,123 @@
+//! Checks that the database has the constraints the code relies on to tell errors apart.
+//!
+//! Violations are matched by constraint name: were one missing (e.g. migrations out of
+//! date), the error it raises would never occur, silently changing behavior.
+
+use std::fmt;
+
+use sqlx::{PgConnection, PgPool};
+
+use crate::bank::{payments, refunds};
+
+/// Constraints and unique indices whose violations are matched by name.
+pub const REQUIRED_CONSTRAINTS: &[&str] = &[
+    payments::CARD_FINGERPRINT_INDEX,
+    payments::MERCHANT_REFERENCE_INDEX,
+    payments::AMOUNT_CHECK,
+    payments::REFUNDED_AMOUNT_CHECK,
+    refunds::IDEMPOTENCY_KEY_INDEX,
+];
+
+#[derive(Debug)]
+pub enum SchemaError {
+    MissingConstraints(Vec<String>),
+    Database(sqlx::Error),
+}
+
+impl fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingConstraints(names) => write!(
+                f,
+                "the database lacks the constraints {}: are its migrations up to date?",
+                names.join(", ")
+            ),
+            Self::Database(e) => write!(f, "failed to check the database's constraints: {e}"),
+        }
+    }
+}
+
+/// Fails unless every one of the `REQUIRED_CONSTRAINTS` exists.
+pub async fn validate(pool: &PgPool) -> Result<(), SchemaError> {
+    let mut connection = pool.acquire().await.map_err(SchemaError::Database)?;
+    let missing = missing_constraints(&mut connection, REQUIRED_CONSTRAINTS)
+        .await
+        .map_err(SchemaError::Database)?;
+    if missing.is_empty() {
+        Ok(())
+    } else {
+        Err(SchemaError::MissingConstraints(missing))
+    }
+}
+
+/// Returns the `names` that are neither a constraint nor an index of the current schema.
+async fn missing_constraints(
+    connection: &mut PgConnection,
+    names: &[&str],
+) -> Result<Vec<String>, sqlx::Error> {
+    let names: Vec<_> = names.iter().map(ToString::to_string).collect();
+    sqlx::query_scalar!(
+        r#"
+            SELECT name as "name!"
+              FROM unnest($1::text[]) AS name
+             WHERE NOT EXISTS (
+                       SELECT 1
+                         FROM pg_constraint
+                        WHERE conname = name
+                          AND connamespace = current_schema()::regnamespace
+                   )
+               AND NOT EXISTS (
+                       SELECT 1
+                         FROM pg_class
+                        WHERE relname = name
+                          AND relkind = 'i'
+                          AND relnamespace = current_schema()::regnamespace
+                   )
+        "#,
+        &names
+    )
+    .fetch_all(connection)
+    .await
+}
+
+#[cfg(test)]
+pub mod tests {
+    use sqlx::Connection;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_validate() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+
+        validate(&pool).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_missing_constraints() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let mut connection = pool.acquire().await.unwrap();
+        let mut transaction = connection.begin().await.unwrap();
+
+        // an empty schema, standing for a database whose migrations weren't run, which is
+        // dropped with the transaction
+        sqlx::query("CREATE SCHEMA test_missing_constraints")
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+        sqlx::query("SET LOCAL search_path TO test_missing_constraints")
+            .execute(&mut transaction)
+            .await
+            .unwrap();
+        assert_eq!(
+            missing_constraints(&mut transaction, REQUIRED_CONSTRAINTS)
+                .await
+                .unwrap(),
+            REQUIRED_CONSTRAINTS
+        );
+    }
+}
@@ -0,0 +1,174 @@
+//! Settlement files in the fixed-width format ingested by bank integrations.
+//!
+//! A file holds a detail record per approved payment, then a trailer record totalling
+//! them. Every record is the concatenation of its layout's fields, each padded to its
+//! width, followed by a line feed.
+
+use time::Date;
+
+use crate::bank::payments::Payment;
+
+/// How a value is padded to the width of its field.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Align {
+    /// Text, padded with trailing spaces.
+    Left,
+    /// Numbers, padded with leading zeros.
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    pub width: usize,
+    pub align: Align,
+}
+
+impl Field {
+    const fn text(name: &'static str, width: usize) -> Self {
+        Self {
+            name,
+            width,
+            align: Align::Left,
+        }
+    }
+
+    const fn number(name: &'static str, width: usize) -> Self {
+        Self {
+            name,
+            width,
+            align: Align::Right,
+        }
+    }
+}
+
+/// Layout of the record of each payment. Amounts are in cents, after refunds.
+pub const DETAIL_LAYOUT: &[Field] = &[
+    Field::text("record_type", 1),
+    Field::text("payment_id", 32),
+    Field::text("settlement_date", 8),
+    Field::text("currency", 3),
+    Field::number("amount", 12),
+    Field::number("fee_amount", 12),
+    Field::number("net_amount", 12),
+    Field::text("card_last_digits", 4),
+];
+
+/// Layout of the record ending the file.
+pub const TRAILER_LAYOUT: &[Field] = &[
+    Field::text("record_type", 1),
+    Field::text("settlement_date", 8),
+    Field::number("record_count", 8),
+    Field::number("total_amount", 15),
+    Field::number("total_net_amount", 15),
+];
+
+pub const DETAIL_RECORD_TYPE: &str = "D";
+pub const TRAILER_RECORD_TYPE: &str = "T";
+
+/// A value that doesn't fit in its field, which would shift the following ones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldOverflow {
+    pub field: &'static str,
+    pub value: String,
+}
+
+/// Renders a record of the `values` of the `layout`'s fields, in order.
+pub fn render_record(layout: &[Field], values: &[String]) -> Result<String, FieldOverflow> {
+    assert_eq!(layout.len(), values.len(), "one value per field");
+
+    let mut record = String::with_capacity(layout.iter().map(|field| field.width).sum());
+    for (field, value) in layout.iter().zip(values) {
+        if value.chars().count() > field.width {
+            return Err(FieldOverflow {
+                field: field.name,
+                value: value.clone(),
+            });
+        }
+        let width = field.width;
+        match field.align {
+            Align::Left => record.push_str(&format!("{value:<width$}")),
+            Align::Right => record.push_str(&format!("{value:0>width$}")),
+        }
+    }
+    record.push('\n');
+    Ok(record)
+}
+
+/// Renders the settlement file of the approved `payments` made on `date`.
+pub fn render(date: Date, payments: &[Payment]) -> Result<String, FieldOverflow> {
+    let date = format!(
+        "{:04}{:02}{:02}",
+        date.year(),
+        u8::from(date.month()),
+        date.day()
+    );
+
+    let mut file = String::new();
+    let (mut total_amount, mut total_net_amount) = (0_i64, 0_i64);
+    for payment in payments {
+        let amount = payment.amount - payment.refunded_amount;
+        let net_amount = payment.net_amount - payment.refunded_amount;
+        total_amount += i64::from(amount);
+        total_net_amount += i64::from(net_amount);
+        let last_digits = payment
+            .card_number
+            .chars()
+            .skip(payment.card_number.chars().count().saturating_sub(4))
+            .collect();
+        file.push_str(&render_record(
+            DETAIL_LAYOUT,
+            &[
+                DETAIL_RECORD_TYPE.into(),
+                payment.id.simple().to_string(),
+                date.clone(),
+                payment.currency.clone().unwrap_or_default(),
+                amount.to_string(),
+                payment.fee_amount.to_string(),
+                net_amount.to_string(),
+                last_digits,
+            ],
+        )?);
+    }
+    file.push_str(&render_record(
+        TRAILER_LAYOUT,
+        &[
+            TRAILER_RECORD_TYPE.into(),
+            date,
+            payments.len().to_string(),
+            total_amount.to_string(),
+            total_net_amount.to_string(),
+        ],
+    )?);
+
+    Ok(file)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_widths() {
+        let width = |layout: &[Field]| layout.iter().map(|field| field.width).sum::<usize>();
+        assert_eq!(width(DETAIL_LAYOUT), 84);
+        assert_eq!(width(TRAILER_LAYOUT), 47);
+    }
+
+    #[test]
+    fn test_render_record() {
+        let layout = [Field::text("name", 5), Field::number("amount", 6)];
+
+        assert_eq!(
+            render_record(&layout, &["abc".into(), "123".into()]),
+            Ok("abc  000123\n".into())
+        );
+        assert_eq!(
+            render_record(&layout, &["abcdef".into(), "123".into()]),
+            Err(FieldOverflow {
+                field: "name",
+                value: "abcdef".into()
+            })
+        );
+    }
+}
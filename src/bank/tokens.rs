@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use crate::bank::clock::Clock;
+
+/// Prefix of card tokens, telling them apart from card numbers.
+const TOKEN_PREFIX: &str = "tok_";
+
+/// Returns the token of each of the validated `card_numbers`, tokenizing the cards that
+/// have none yet. A card keeps its token once tokenized.
+pub async fn tokenize_all(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    card_numbers: &[String],
+) -> Result<HashMap<String, String>, sqlx::Error> {
+    let mut card_numbers = card_numbers.to_vec();
+    // a card can only be inserted once per statement
+    card_numbers.sort();
+    card_numbers.dedup();
+    let tokens: Vec<_> = card_numbers
+        .iter()
+        .map(|_| format!("{TOKEN_PREFIX}{}", Uuid::new_v4().simple()))
+        .collect();
+
+    let rows = sqlx::query!(
+        r#"
+               INSERT INTO card_tokens ( token, card_number, inserted_at )
+               SELECT token, card_number, $3
+                 FROM unnest($1::text[], $2::text[]) AS cards(token, card_number)
+          ON CONFLICT (card_number) DO UPDATE
+                  SET card_number = EXCLUDED.card_number
+            RETURNING token, card_number
+        "#,
+        &tokens,
+        &card_numbers,
+        clock.now_utc()
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.card_number, row.token))
+        .collect())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::bank::{clock::SystemClock, payment_instruments::Card};
+
+    #[tokio::test]
+    async fn test_tokenize_all() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let (card_number, other_card_number): (String, String) =
+            (Card::new_test().into(), Card::new_test().into());
+
+        let tokens = tokenize_all(
+            &pool,
+            &SystemClock,
+            &[card_number.clone(), card_number.clone()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(tokens.len(), 1);
+        assert!(tokens[&card_number].starts_with(TOKEN_PREFIX));
+
+        // the card keeps its token
+        let retokenized = tokenize_all(
+            &pool,
+            &SystemClock,
+            &[card_number.clone(), other_card_number.clone()],
+        )
+        .await
+        .unwrap();
+        assert_eq!(retokenized[&card_number], tokens[&card_number]);
+        assert_ne!(retokenized[&other_card_number], tokens[&card_number]);
+    }
+}
@@ -0,0 +1,73 @@
+use std::sync::Arc;
+
+/// External vault storing card numbers, for deployments which must never persist them:
+/// payments then store the vault's token in place of the card number.
+///
+/// The trait is object-safe, so that the vault can be chosen at runtime through a
+/// `DynCardVault`.
+#[async_trait::async_trait]
+pub trait CardVault: Send + Sync + 'static {
+    /// Stores the card number, returning the token to persist instead.
+    async fn store(&self, card_number: &str) -> Result<String, String>;
+
+    /// Returns the card number the token was stored for, for talking to the account service
+    /// about a persisted payment. Only tests read card numbers back so far.
+    #[cfg(test)]
+    async fn retrieve(&self, token: &str) -> Result<String, String>;
+}
+
+/// A card vault whose implementation is chosen at runtime.
+pub type DynCardVault = Arc<dyn CardVault>;
+
+/// Stores no card number, so that payments persist card numbers themselves: its tokens
+/// are the card numbers.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoVault;
+
+#[async_trait::async_trait]
+impl CardVault for NoVault {
+    async fn store(&self, card_number: &str) -> Result<String, String> {
+        Ok(card_number.to_owned())
+    }
+
+    #[cfg(test)]
+    async fn retrieve(&self, token: &str) -> Result<String, String> {
+        Ok(token.to_owned())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::{collections::HashMap, sync::Mutex};
+
+    use uuid::Uuid;
+
+    use super::*;
+
+    /// Vault keeping card numbers in memory, shared by its clones.
+    #[derive(Debug, Clone, Default)]
+    pub struct FakeVault {
+        card_numbers: Arc<Mutex<HashMap<String, String>>>,
+    }
+
+    #[async_trait::async_trait]
+    impl CardVault for FakeVault {
+        async fn store(&self, card_number: &str) -> Result<String, String> {
+            let token = format!("vault_{}", Uuid::new_v4().simple());
+            self.card_numbers
+                .lock()
+                .unwrap()
+                .insert(token.clone(), card_number.to_owned());
+            Ok(token)
+        }
+
+        async fn retrieve(&self, token: &str) -> Result<String, String> {
+            self.card_numbers
+                .lock()
+                .unwrap()
+                .get(token)
+                .cloned()
+                .ok_or_else(|| format!("unknown token {token}"))
+        }
+    }
+}
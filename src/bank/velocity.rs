@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+use time::Duration;
+
+/// Largest total amount, in cents, approved for a card within a rolling window.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VelocityLimit {
+    pub max_amount: i64,
+    pub window: Duration,
+}
+
+impl VelocityLimit {
+    pub fn new(max_amount: i64, window: Duration) -> Self {
+        Self { max_amount, window }
+    }
+
+    /// Returns whether a payment of `amount` is allowed on a card for which
+    /// `approved_amount` was approved within the window.
+    pub fn allows(&self, approved_amount: i64, amount: i32) -> bool {
+        approved_amount + i64::from(amount) <= self.max_amount
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct InvalidVelocityLimit;
+
+/// Parses a limit such as `500000/24h`: the max amount, then the window in hours.
+impl FromStr for VelocityLimit {
+    type Err = InvalidVelocityLimit;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (max_amount, window) = s.split_once('/').ok_or(InvalidVelocityLimit)?;
+        let max_amount = max_amount
+            .trim()
+            .parse()
+            .map_err(|_| InvalidVelocityLimit)?;
+        let hours: i64 = window
+            .trim()
+            .strip_suffix('h')
+            .and_then(|hours| hours.parse().ok())
+            .ok_or(InvalidVelocityLimit)?;
+        if max_amount < 0 || hours <= 0 {
+            return Err(InvalidVelocityLimit);
+        }
+        Ok(Self::new(max_amount, Duration::hours(hours)))
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allows() {
+        let limit = VelocityLimit::new(10_000, Duration::hours(24));
+
+        assert!(limit.allows(0, 10_000));
+        assert!(limit.allows(6_000, 4_000));
+        assert!(!limit.allows(6_000, 4_001));
+        assert!(!limit.allows(0, 10_001));
+    }
+
+    #[test]
+    fn test_from_str() {
+        assert_eq!(
+            "500000/24h".parse(),
+            Ok(VelocityLimit::new(500_000, Duration::hours(24)))
+        );
+        assert_eq!("500000".parse::<VelocityLimit>(), Err(InvalidVelocityLimit));
+        assert_eq!(
+            "500000/24".parse::<VelocityLimit>(),
+            Err(InvalidVelocityLimit)
+        );
+        assert_eq!(
+            "500000/0h".parse::<VelocityLimit>(),
+            Err(InvalidVelocityLimit)
+        );
+        assert_eq!("-1/24h".parse::<VelocityLimit>(), Err(InvalidVelocityLimit));
+    }
+}
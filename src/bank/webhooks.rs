@@ -0,0 +1,736 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use hmac::{Hmac, Mac};
+use hyper::{header::CONTENT_TYPE, Body, Client, Method, Request, StatusCode};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sqlx::PgPool;
+use time::PrimitiveDateTime;
+use uuid::Uuid;
+
+use crate::bank::clock::Clock;
+
+/// Maximum number of deliveries attempted by a single `deliver_due` run.
+const DELIVERY_BATCH_SIZE: i64 = 100;
+
+/// Longest a receiver may take to acknowledge a delivery.
+pub const DELIVERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// How long deliveries claimed by a `deliver_due` run are left to it: as long as its whole
+/// batch may take.
+const DELIVERY_CLAIM_DURATION: time::Duration =
+    time::Duration::seconds(DELIVERY_TIMEOUT.as_secs() as i64 * DELIVERY_BATCH_SIZE);
+
+/// Header carrying the id of the delivery, the same across its retries.
+pub const WEBHOOK_ID_HEADER: &str = "x-webhook-id";
+
+/// Header carrying the signature of a test delivery's body.
+pub const WEBHOOK_SIGNATURE_HEADER: &str = "x-webhook-signature";
+
+/// Secret test deliveries are signed with, so that receivers under test can check their
+/// signatures without the merchant's secret.
+pub const TEST_WEBHOOK_SECRET: &str = "whsec_test";
+
+/// Returns the hex-encoded HMAC-SHA256 of a webhook's `body` with `secret`.
+pub fn sign_payload(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookDeliveryStatus {
+    /// The delivery hasn't succeeded yet, and will be attempted again.
+    Pending,
+    /// The receiver acknowledged the delivery.
+    Delivered,
+    /// Every attempt failed: the delivery was given up on.
+    Failed,
+}
+
+/// A webhook delivery, persisted so that it survives the receiver being down.
+///
+/// Deliveries are created `Pending` and retried with an exponential backoff by
+/// `deliver_due` until they are either delivered, or `RetryPolicy::max_attempts`
+/// is reached and they are marked as failed.
+///
+/// Its payload carries its `delivery_id` and `event_id`, and is sent as is on every attempt,
+/// so that receivers can ignore the retries they already processed.
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: Uuid,
+    /// The event the delivery is about, shared with the other deliveries of the event (e.g.
+    /// when it's resent).
+    pub event_id: Uuid,
+    pub url: String,
+    pub payload: serde_json::Value,
+    pub status: WebhookDeliveryStatus,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: PrimitiveDateTime,
+    pub inserted_at: PrimitiveDateTime,
+    pub updated_at: PrimitiveDateTime,
+}
+
+/// How failed deliveries are retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: i32,
+    /// Delay before the first retry, doubled on each subsequent one.
+    pub base_delay: time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 8,
+            base_delay: time::Duration::seconds(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Returns the delay to wait for after the `attempts`-th failed attempt.
+    pub fn backoff(&self, attempts: i32) -> time::Duration {
+        self.base_delay * 2_i32.pow(attempts.saturating_sub(1).clamp(0, 16) as u32)
+    }
+}
+
+/// Client sending webhook payloads to their receiver.
+#[async_trait::async_trait]
+pub trait WebhookSender: Send + Sync + 'static {
+    /// POSTs the `payload` of the delivery to `url`, returning an error unless it was
+    /// acknowledged.
+    async fn send(
+        &self,
+        url: &str,
+        delivery_id: Uuid,
+        payload: &serde_json::Value,
+    ) -> Result<(), String>;
+}
+
+/// Sends webhooks as JSON over HTTP, any 2xx response counting as an acknowledgement.
+///
+/// The delivery id is sent in the `X-Webhook-Id` header.
+#[derive(Clone, Default)]
+pub struct HttpSender {
+    client: Client<hyper::client::HttpConnector>,
+}
+
+#[async_trait::async_trait]
+impl WebhookSender for HttpSender {
+    async fn send(
+        &self,
+        url: &str,
+        delivery_id: Uuid,
+        payload: &serde_json::Value,
+    ) -> Result<(), String> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(WEBHOOK_ID_HEADER, delivery_id.to_string())
+            .body(Body::from(payload.to_string()))
+            .map_err(|e| e.to_string())?;
+
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("receiver responded with {}", response.status()))
+        }
+    }
+}
+
+impl HttpSender {
+    /// POSTs the `payload` to `url` once, signed with `TEST_WEBHOOK_SECRET`, returning the
+    /// status the receiver responded with, whatever it is. Nothing is persisted nor retried.
+    pub async fn send_test(
+        &self,
+        url: &str,
+        payload: &serde_json::Value,
+    ) -> Result<StatusCode, String> {
+        let body = payload.to_string();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(
+                WEBHOOK_SIGNATURE_HEADER,
+                sign_payload(TEST_WEBHOOK_SECRET.as_bytes(), body.as_bytes()),
+            )
+            .body(Body::from(body))
+            .map_err(|e| e.to_string())?;
+
+        let response = self
+            .client
+            .request(request)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok(response.status())
+    }
+}
+
+/// Persists a pending delivery to `url` of the `payload` of the event, to be sent by
+/// `deliver_due`.
+///
+/// The delivery's id and the `event_id` are added to the payload, as `delivery_id` and
+/// `event_id`, when it's an object.
+pub async fn enqueue(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    url: &str,
+    event_id: Uuid,
+    mut payload: serde_json::Value,
+) -> Result<WebhookDelivery, sqlx::Error> {
+    let id = Uuid::new_v4();
+    if let Some(object) = payload.as_object_mut() {
+        object.insert("delivery_id".into(), id.to_string().into());
+        object.insert("event_id".into(), event_id.to_string().into());
+    }
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+               INSERT INTO webhook_deliveries ( id, event_id, url, payload, status, next_attempt_at, inserted_at, updated_at )
+               VALUES ( $1, $2, $3, $4, 'Pending', $5, $5, $5 )
+            RETURNING id, event_id, url, payload, status as "status: _", attempts, last_error, next_attempt_at, inserted_at, updated_at
+        "#,
+        id,
+        event_id,
+        url,
+        payload,
+        clock.now_utc()
+    )
+    .fetch_one(pool)
+    .await
+}
+
+/// Most deliveries kept by `UnqueuedDeliveries`, beyond which the oldest are dropped.
+const MAX_UNQUEUED_DELIVERIES: usize = 10_000;
+
+/// A delivery which failed to be queued.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnqueuedDelivery {
+    pub url: String,
+    pub event_id: Uuid,
+    /// The payload as given to `enqueue`, without its delivery id.
+    pub payload: serde_json::Value,
+}
+
+/// Deliveries which failed to be queued once the change they're about was committed (e.g.
+/// while the database was unavailable), kept to be queued again by `requeue`. Shared by its
+/// clones.
+///
+/// The change isn't rolled back, as money may already have moved, so the deliveries are
+/// recorded here instead of failing the request. They're only kept in memory: each one is
+/// logged with its payload when recorded, to reconcile those a restart loses by hand.
+#[derive(Debug, Clone, Default)]
+pub struct UnqueuedDeliveries(Arc<Mutex<VecDeque<UnqueuedDelivery>>>);
+
+impl UnqueuedDeliveries {
+    pub fn record(&self, delivery: UnqueuedDelivery) {
+        tracing::error!(
+            event_id = %delivery.event_id,
+            url = %delivery.url,
+            payload = %delivery.payload,
+            "recording unqueued webhook delivery for reconciliation"
+        );
+        let mut deliveries = self.0.lock().unwrap();
+        deliveries.push_back(delivery);
+        if deliveries.len() > MAX_UNQUEUED_DELIVERIES {
+            if let Some(dropped) = deliveries.pop_front() {
+                tracing::error!(event_id = %dropped.event_id, "dropping unqueued webhook delivery");
+            }
+        }
+    }
+
+    /// Queues the recorded deliveries again, oldest first, returning how many were.
+    ///
+    /// Stops as soon as the database is unavailable again, keeping the deliveries left.
+    /// Deliveries failing otherwise (e.g. their URL is too long) can never be queued, and
+    /// are dropped.
+    pub async fn requeue(&self, pool: &PgPool, clock: &dyn Clock) -> usize {
+        let mut deliveries = std::mem::take(&mut *self.0.lock().unwrap());
+
+        let mut requeued = 0;
+        while let Some(delivery) = deliveries.pop_front() {
+            let result = enqueue(
+                pool,
+                clock,
+                &delivery.url,
+                delivery.event_id,
+                delivery.payload.clone(),
+            )
+            .await;
+            match result {
+                Ok(_) => requeued += 1,
+                Err(e) if crate::bank::payments::is_database_unavailable(&e) => {
+                    deliveries.push_front(delivery);
+                    // kept ahead of the deliveries recorded since
+                    let mut recorded = self.0.lock().unwrap();
+                    deliveries.append(&mut recorded);
+                    *recorded = deliveries;
+                    break;
+                }
+                Err(e) => {
+                    tracing::error!(event_id = %delivery.event_id, error = ?e, "dropping unqueued webhook delivery");
+                }
+            }
+        }
+        requeued
+    }
+}
+
+/// Returns the id of the earliest event delivered to `url` as the `event` about the object
+/// with the `data_id`, if any, e.g. for resending it as the same event.
+///
+/// Deliveries aren't indexed by their payload, which is fine for occasional lookups only.
+pub async fn find_event_id(
+    pool: &PgPool,
+    url: &str,
+    event: &str,
+    data_id: Uuid,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    sqlx::query_scalar!(
+        r#"
+            SELECT event_id
+              FROM webhook_deliveries
+             WHERE url = $1
+               AND payload->>'event' = $2
+               AND payload->'data'->>'id' = $3
+          ORDER BY inserted_at, id
+             LIMIT 1
+        "#,
+        url,
+        event,
+        data_id.to_string()
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Attempts every pending delivery that is due, returning the number of attempts made.
+///
+/// Deliveries are claimed before being attempted, by pushing their next attempt back past
+/// the time the whole batch may take, so that several instances can run this concurrently
+/// without sending a webhook twice. No row is locked while waiting on receivers, and an
+/// attempt taking longer than `DELIVERY_TIMEOUT` fails. Deliveries claimed by a run which
+/// died are attempted again once their claim expires.
+pub async fn deliver_due(
+    pool: &PgPool,
+    clock: &dyn Clock,
+    sender: &dyn WebhookSender,
+    policy: &RetryPolicy,
+) -> Result<usize, sqlx::Error> {
+    let now = clock.now_utc();
+    let deliveries = sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+               UPDATE webhook_deliveries
+                  SET next_attempt_at = $3
+                WHERE id IN (
+                          SELECT id
+                            FROM webhook_deliveries
+                           WHERE status = 'Pending'
+                             AND next_attempt_at <= $1
+                        ORDER BY next_attempt_at
+                           LIMIT $2
+                             FOR UPDATE SKIP LOCKED
+                      )
+            RETURNING id, event_id, url, payload, status as "status: _", attempts, last_error, next_attempt_at, inserted_at, updated_at
+        "#,
+        now,
+        DELIVERY_BATCH_SIZE,
+        now + DELIVERY_CLAIM_DURATION
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for delivery in &deliveries {
+        let result = tokio::time::timeout(
+            DELIVERY_TIMEOUT,
+            sender.send(&delivery.url, delivery.id, &delivery.payload),
+        )
+        .await
+        .unwrap_or_else(|_| Err("receiver timed out".into()));
+        let attempts = delivery.attempts + 1;
+        let now = clock.now();
+        let (status, last_error) = match result {
+            Ok(()) => (WebhookDeliveryStatus::Delivered, None),
+            Err(err) if attempts >= policy.max_attempts => {
+                tracing::warn!(delivery_id = %delivery.id, error = %err, "giving up on webhook delivery");
+                (WebhookDeliveryStatus::Failed, Some(err))
+            }
+            Err(err) => (WebhookDeliveryStatus::Pending, Some(err)),
+        };
+
+        sqlx::query!(
+            r#"
+                UPDATE webhook_deliveries
+                   SET status = $2,
+                       attempts = $3,
+                       last_error = $4,
+                       next_attempt_at = $5,
+                       updated_at = $6
+                 WHERE id = $1
+            "#,
+            delivery.id,
+            status as WebhookDeliveryStatus,
+            attempts,
+            last_error,
+            crate::bank::clock::to_primitive_utc(now + policy.backoff(attempts)),
+            crate::bank::clock::to_primitive_utc(now)
+        )
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(deliveries.len())
+}
+
+/// Spawns a background task running `deliver_due` every `interval`, once the `unqueued`
+/// deliveries are queued again.
+pub fn spawn_worker(
+    pool: PgPool,
+    clock: Arc<dyn Clock>,
+    sender: Arc<dyn WebhookSender>,
+    policy: RetryPolicy,
+    unqueued: UnqueuedDeliveries,
+    interval: std::time::Duration,
+) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            let requeued = unqueued.requeue(&pool, clock.as_ref()).await;
+            if requeued > 0 {
+                tracing::info!(count = requeued, "queued unqueued webhook deliveries");
+            }
+            if let Err(err) = deliver_due(&pool, clock.as_ref(), sender.as_ref(), &policy).await {
+                tracing::error!(error = ?err, "failed to deliver webhooks");
+            }
+        }
+    })
+}
+
+/// Lists the most recent deliveries, newest first.
+pub async fn list(pool: &PgPool, limit: i64) -> Result<Vec<WebhookDelivery>, sqlx::Error> {
+    sqlx::query_as!(
+        WebhookDelivery,
+        r#"
+            SELECT id, event_id, url, payload, status as "status: _", attempts, last_error, next_attempt_at, inserted_at, updated_at
+              FROM webhook_deliveries
+          ORDER BY inserted_at DESC, id
+             LIMIT $1
+        "#,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[cfg(test)]
+pub mod tests {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    };
+
+    use super::*;
+    use crate::bank::clock::tests::MockClock;
+
+    impl UnqueuedDeliveries {
+        /// Returns the deliveries awaiting to be queued again, oldest first.
+        pub fn list(&self) -> Vec<UnqueuedDelivery> {
+            self.0.lock().unwrap().iter().cloned().collect()
+        }
+    }
+
+    async fn get(pool: &PgPool, id: Uuid) -> Result<WebhookDelivery, sqlx::Error> {
+        sqlx::query_as!(
+            WebhookDelivery,
+            r#"
+                SELECT id, event_id, url, payload, status as "status: _", attempts, last_error, next_attempt_at, inserted_at, updated_at
+                  FROM webhook_deliveries
+                 WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_one(pool)
+        .await
+    }
+
+    /// Sender failing the first `failures` deliveries to `url`, and acknowledging any other.
+    pub struct FlakySender {
+        pub url: String,
+        pub failures: usize,
+        pub calls: AtomicUsize,
+        /// Ids of the deliveries sent to `url`, in order.
+        pub delivery_ids: Mutex<Vec<Uuid>>,
+    }
+
+    impl FlakySender {
+        pub fn new(failures: usize) -> Self {
+            Self {
+                url: format!("http://receiver.test/{}", Uuid::new_v4()),
+                failures,
+                calls: AtomicUsize::new(0),
+                delivery_ids: Mutex::new(Vec::new()),
+            }
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl WebhookSender for FlakySender {
+        async fn send(
+            &self,
+            url: &str,
+            delivery_id: Uuid,
+            _payload: &serde_json::Value,
+        ) -> Result<(), String> {
+            if url != self.url {
+                return Ok(());
+            }
+            self.delivery_ids.lock().unwrap().push(delivery_id);
+            if self.calls.fetch_add(1, Ordering::SeqCst) < self.failures {
+                Err("receiver is down".into())
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Sender recording whether the deliveries sent to `url` were locked while being sent.
+    struct LockCheckingSender {
+        pool: PgPool,
+        url: String,
+        locked: Mutex<Vec<bool>>,
+    }
+
+    #[async_trait::async_trait]
+    impl WebhookSender for LockCheckingSender {
+        async fn send(
+            &self,
+            url: &str,
+            delivery_id: Uuid,
+            _payload: &serde_json::Value,
+        ) -> Result<(), String> {
+            if url != self.url {
+                return Ok(());
+            }
+            let result = sqlx::query!(
+                "SELECT id FROM webhook_deliveries WHERE id = $1 FOR UPDATE NOWAIT",
+                delivery_id
+            )
+            .fetch_one(&self.pool)
+            .await;
+            self.locked.lock().unwrap().push(result.is_err());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: time::Duration::seconds(10),
+        };
+
+        assert_eq!(policy.backoff(1), time::Duration::seconds(10));
+        assert_eq!(policy.backoff(2), time::Duration::seconds(20));
+        assert_eq!(policy.backoff(3), time::Duration::seconds(40));
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_delivered() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let clock = MockClock::new(
+            time::OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap(),
+        );
+        let sender = FlakySender::new(2);
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: time::Duration::seconds(10),
+        };
+
+        let delivery = enqueue(
+            &pool,
+            &clock,
+            &sender.url,
+            Uuid::new_v4(),
+            serde_json::json!({ "ok": true }),
+        )
+        .await
+        .expect("failed to enqueue delivery");
+
+        for attempt in 1..=2 {
+            deliver_due(&pool, &clock, &sender, &policy)
+                .await
+                .expect("failed to deliver webhooks");
+            let delivery = get(&pool, delivery.id)
+                .await
+                .expect("failed to get delivery");
+            assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+            assert_eq!(delivery.attempts, attempt);
+            assert_eq!(delivery.last_error.as_deref(), Some("receiver is down"));
+
+            // not retried before the backoff elapses
+            deliver_due(&pool, &clock, &sender, &policy)
+                .await
+                .expect("failed to deliver webhooks");
+            assert_eq!(sender.calls.load(Ordering::SeqCst), attempt as usize);
+
+            clock.advance(policy.backoff(attempt));
+        }
+
+        deliver_due(&pool, &clock, &sender, &policy)
+            .await
+            .expect("failed to deliver webhooks");
+        let delivery = get(&pool, delivery.id)
+            .await
+            .expect("failed to get delivery");
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(delivery.attempts, 3);
+        assert_eq!(delivery.last_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_retries_keep_delivery_id() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let clock = MockClock::new(
+            time::OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap(),
+        );
+        let sender = FlakySender::new(2);
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            base_delay: time::Duration::seconds(10),
+        };
+        let event_id = Uuid::new_v4();
+
+        let delivery = enqueue(
+            &pool,
+            &clock,
+            &sender.url,
+            event_id,
+            serde_json::json!({ "event": "test" }),
+        )
+        .await
+        .expect("failed to enqueue delivery");
+        assert_eq!(delivery.event_id, event_id);
+        assert_eq!(
+            delivery.payload,
+            serde_json::json!({
+                "event": "test",
+                "delivery_id": delivery.id.to_string(),
+                "event_id": event_id.to_string(),
+            })
+        );
+
+        for attempt in 1..=3 {
+            deliver_due(&pool, &clock, &sender, &policy)
+                .await
+                .expect("failed to deliver webhooks");
+            clock.advance(policy.backoff(attempt));
+        }
+
+        assert_eq!(*sender.delivery_ids.lock().unwrap(), vec![delivery.id; 3]);
+        let retried = get(&pool, delivery.id)
+            .await
+            .expect("failed to get delivery");
+        assert_eq!(retried.status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(retried.payload, delivery.payload);
+    }
+
+    #[tokio::test]
+    async fn test_give_up_after_max_attempts() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let clock = MockClock::new(
+            time::OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap(),
+        );
+        let sender = FlakySender::new(usize::MAX);
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            base_delay: time::Duration::seconds(10),
+        };
+
+        let delivery = enqueue(
+            &pool,
+            &clock,
+            &sender.url,
+            Uuid::new_v4(),
+            serde_json::json!({ "ok": true }),
+        )
+        .await
+        .expect("failed to enqueue delivery");
+
+        for attempt in 1..=3 {
+            deliver_due(&pool, &clock, &sender, &policy)
+                .await
+                .expect("failed to deliver webhooks");
+            clock.advance(policy.backoff(attempt));
+        }
+
+        let delivery = get(&pool, delivery.id)
+            .await
+            .expect("failed to get delivery");
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Failed);
+        assert_eq!(delivery.attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn test_deliveries_are_not_locked_while_sent() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let clock = MockClock::new(
+            time::OffsetDateTime::now_utc()
+                .replace_nanosecond(0)
+                .unwrap(),
+        );
+        let sender = LockCheckingSender {
+            pool: pool.clone(),
+            url: format!("http://receiver.test/{}", Uuid::new_v4()),
+            locked: Mutex::new(Vec::new()),
+        };
+
+        let delivery = enqueue(
+            &pool,
+            &clock,
+            &sender.url,
+            Uuid::new_v4(),
+            serde_json::json!({ "ok": true }),
+        )
+        .await
+        .expect("failed to enqueue delivery");
+        deliver_due(&pool, &clock, &sender, &RetryPolicy::default())
+            .await
+            .expect("failed to deliver webhooks");
+
+        assert_eq!(*sender.locked.lock().unwrap(), vec![false]);
+        let delivery = get(&pool, delivery.id)
+            .await
+            .expect("failed to get delivery");
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Delivered);
+    }
+}
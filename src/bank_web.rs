@@ -1,44 +1,733 @@
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Duration};
+
 use axum::{
-    routing::{get, post},
-    Router,
+    error_handling::HandleErrorLayer,
+    extract::DefaultBodyLimit,
+    http::StatusCode,
+    middleware,
+    routing::{get, patch, post, put},
+    BoxError, Router,
 };
 use sqlx::PgPool;
+use uuid::Uuid;
 
-use crate::bank::accounts::AccountService;
+use crate::bank::{
+    accepting_window::AcceptingWindow,
+    accounts::{AccountService, DynAccountService},
+    amount_bounds::{AmountBounds, AmountBoundsTable},
+    clock::{Clock, SystemClock},
+    concurrency_limiter::{ConcurrencyLimit, ConcurrencyLimiter},
+    config_snapshots::CardLimits,
+    fees::FeeConfig,
+    fingerprints::{FingerprintKey, FingerprintKeys},
+    merchants::{ImplicitIdempotency, MerchantCurrencies},
+    payment_events::StatusChanges,
+    payment_queue::PaymentQueue,
+    payments::CardNumberDisplay,
+    repository::{PaymentRepository, PgRepository, RefundRepository},
+    sandbox::{MagicAmounts, TestCardBins},
+    vault::{DynCardVault, NoVault},
+    velocity::VelocityLimit,
+    webhooks::UnqueuedDeliveries,
+};
 
+mod admin;
+mod callbacks;
+mod cards;
+mod client_ip;
+mod health;
+mod https;
+mod imports;
+mod locale;
+mod merchants;
+mod methods;
 mod payments;
 mod refunds;
+mod reports;
+mod sandbox;
+mod tokens;
+mod versions;
+mod webhooks;
 
 #[derive(Clone)]
 pub struct BankWeb<T> {
     pool: PgPool,
-    #[allow(dead_code)]
+    payment_repository: Arc<dyn PaymentRepository>,
+    refund_repository: Arc<dyn RefundRepository>,
     account_service: T,
+    clock: Arc<dyn Clock>,
+    fees: FeeConfig,
+    balance_precheck: bool,
+    default_currency: Option<String>,
+    amount_bounds: AmountBoundsTable,
+    merchant_currencies: MerchantCurrencies,
+    webhook_url: Option<String>,
+    refund_approval_threshold: Option<i32>,
+    refund_window: Option<time::Duration>,
+    accepting_window: Option<AcceptingWindow>,
+    velocity_limit: Option<VelocityLimit>,
+    daily_card_limit: Option<u32>,
+    implicit_idempotency: Option<ImplicitIdempotency>,
+    trusted_proxies: Vec<IpAddr>,
+    admin_token: Option<String>,
+    merchant_api_keys: HashMap<Uuid, String>,
+    sandbox: bool,
+    sandbox_reset: bool,
+    magic_amounts: MagicAmounts,
+    test_card_bins: TestCardBins,
+    status_changes: StatusChanges,
+    unqueued_webhooks: UnqueuedDeliveries,
+    payment_queue: Option<PaymentQueue>,
+    card_vault: DynCardVault,
+    mask_pan: bool,
+    card_number_display: CardNumberDisplay,
+    strict_payment_ids: bool,
+    request_timeout: Option<Duration>,
+    settlement_delay: Option<time::Duration>,
+    auto_capture_delay: Option<time::Duration>,
+    hold_retries: u32,
+    require_https: bool,
+    expose_database_errors: bool,
+    collapse_not_approved: bool,
+    demote_duplicate_cards: bool,
+    card_fingerprint_keys: FingerprintKeys,
 }
 
-impl<T: AccountService> BankWeb<T> {
-    pub fn new(pool: PgPool, account_service: T) -> Self {
+impl<T: AccountService + Clone> BankWeb<T> {
+    /// Card numbers are fingerprinted with `card_fingerprint_key`, which must stay the
+    /// same across restarts for duplicates to keep being detected, unless it's rotated.
+    pub fn new(pool: PgPool, account_service: T, card_fingerprint_key: FingerprintKey) -> Self {
+        let repository = Arc::new(PgRepository::new(pool.clone()));
         Self {
             pool,
+            payment_repository: repository.clone(),
+            refund_repository: repository,
             account_service,
+            clock: Arc::new(SystemClock),
+            fees: FeeConfig::default(),
+            balance_precheck: false,
+            default_currency: None,
+            amount_bounds: AmountBoundsTable::new(),
+            merchant_currencies: MerchantCurrencies::new(),
+            webhook_url: None,
+            refund_approval_threshold: None,
+            refund_window: None,
+            accepting_window: None,
+            velocity_limit: None,
+            daily_card_limit: None,
+            implicit_idempotency: None,
+            trusted_proxies: Vec::new(),
+            admin_token: None,
+            merchant_api_keys: HashMap::new(),
+            sandbox: false,
+            sandbox_reset: false,
+            magic_amounts: MagicAmounts::default(),
+            test_card_bins: TestCardBins::default(),
+            status_changes: StatusChanges::default(),
+            unqueued_webhooks: UnqueuedDeliveries::default(),
+            payment_queue: None,
+            card_vault: Arc::new(NoVault),
+            mask_pan: true,
+            card_number_display: CardNumberDisplay::default(),
+            strict_payment_ids: false,
+            request_timeout: None,
+            settlement_delay: None,
+            auto_capture_delay: None,
+            hold_retries: 0,
+            require_https: false,
+            expose_database_errors: false,
+            collapse_not_approved: false,
+            demote_duplicate_cards: false,
+            card_fingerprint_keys: FingerprintKeys::new(card_fingerprint_key),
+        }
+    }
+
+    /// Sets the fees charged on approved payments.
+    pub fn with_fees(mut self, fees: FeeConfig) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Queries the account's balance before placing a hold, declining payments it can't
+    /// cover without attempting the hold.
+    ///
+    /// Off by default: it costs the account service an extra request for every payment.
+    pub fn with_balance_precheck(mut self, balance_precheck: bool) -> Self {
+        self.balance_precheck = balance_precheck;
+        self
+    }
+
+    /// Sets the currency of the payments requested without one, which their refunds inherit.
+    pub fn with_default_currency(mut self, currency: impl Into<String>) -> Self {
+        self.default_currency = Some(currency.into());
+        self
+    }
+
+    /// Sets the bounds of the amounts of payments made in `currency`.
+    pub fn with_amount_bounds(mut self, currency: impl Into<String>, bounds: AmountBounds) -> Self {
+        self.amount_bounds.insert(currency.into(), bounds);
+        self
+    }
+
+    /// Enables the merchant for the `currencies` only, rejecting its payments in any other
+    /// currency. Merchants are enabled for every currency otherwise.
+    pub fn with_merchant_currencies(
+        mut self,
+        merchant_id: Uuid,
+        currencies: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        let currencies = currencies
+            .into_iter()
+            .map(|currency| currency.into().to_ascii_uppercase());
+        self.merchant_currencies
+            .entry(merchant_id)
+            .or_default()
+            .extend(currencies);
+        self
+    }
+
+    /// Sets the URL notified of created payments.
+    ///
+    /// Deliveries are only queued here: they are sent by the `bank::webhooks` worker.
+    pub fn with_webhook_url(mut self, webhook_url: impl Into<String>) -> Self {
+        self.webhook_url = Some(webhook_url.into());
+        self
+    }
+
+    /// Sets the amount above which refunds are held pending until approved.
+    pub fn with_refund_approval_threshold(mut self, threshold: i32) -> Self {
+        self.refund_approval_threshold = Some(threshold);
+        self
+    }
+
+    /// Rejects refunds made longer than `window` after the payment was settled. Payments can
+    /// be refunded at any time otherwise.
+    pub fn with_refund_window(mut self, window: time::Duration) -> Self {
+        self.refund_window = Some(window);
+        self
+    }
+
+    /// Restricts payments to the given days and hours. Payments are accepted at any time otherwise.
+    pub fn with_accepting_window(mut self, window: AcceptingWindow) -> Self {
+        self.accepting_window = Some(window);
+        self
+    }
+
+    /// Caps the total amount approved for a card within a rolling window. Payments on a
+    /// card aren't capped otherwise.
+    pub fn with_velocity_limit(mut self, velocity_limit: VelocityLimit) -> Self {
+        self.velocity_limit = Some(velocity_limit);
+        self
+    }
+
+    /// Caps the number of payments made on a card each day, from midnight UTC. Payments on
+    /// a card aren't counted otherwise.
+    pub fn with_daily_card_limit(mut self, limit: u32) -> Self {
+        self.daily_card_limit = Some(limit);
+        self
+    }
+
+    /// Returns the payment already made instead of making another when the merchants opted in
+    /// repeat one within the window. Every request makes a payment otherwise.
+    pub fn with_implicit_idempotency(mut self, implicit_idempotency: ImplicitIdempotency) -> Self {
+        self.implicit_idempotency = Some(implicit_idempotency);
+        self
+    }
+
+    /// Sets the proxies trusted to report the client IP in `Forwarded` or `X-Forwarded-For`.
+    pub fn with_trusted_proxies(mut self, trusted_proxies: Vec<IpAddr>) -> Self {
+        self.trusted_proxies = trusted_proxies;
+        self
+    }
+
+    /// Sets the bearer token required by the endpoints guarded by `admin::AdminAuth`.
+    pub fn with_admin_token(mut self, token: impl Into<String>) -> Self {
+        self.admin_token = Some(token.into());
+        self
+    }
+
+    /// Sets the API key authenticating the merchant to the endpoints guarded by
+    /// `merchants::MerchantAuth`, replacing its previous one.
+    pub fn with_merchant_api_key(mut self, merchant_id: Uuid, api_key: impl Into<String>) -> Self {
+        self.merchant_api_keys.insert(merchant_id, api_key.into());
+        self
+    }
+
+    /// Enables the sandbox-only behaviors, such as magic amounts and the endpoints for
+    /// drills. Sandbox mode must never be enabled in production, where it is off by default.
+    pub fn with_sandbox(mut self, sandbox: bool) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Enables `POST /api/admin/reset`, deleting all payments, in sandbox mode only. It's
+    /// disabled by default even in sandbox mode, as sandboxes shared by several merchants
+    /// must not be reset by one of them.
+    pub fn with_sandbox_reset(mut self, sandbox_reset: bool) -> Self {
+        self.sandbox_reset = sandbox_reset;
+        self
+    }
+
+    /// Sets the outcomes forced by payment amounts in sandbox mode, replacing the defaults.
+    pub fn with_magic_amounts(mut self, magic_amounts: MagicAmounts) -> Self {
+        self.magic_amounts = magic_amounts;
+        self
+    }
+
+    /// Sets the BINs of the test cards, which are declined unless in sandbox mode.
+    pub fn with_test_card_bins(mut self, test_card_bins: TestCardBins) -> Self {
+        self.test_card_bins = test_card_bins;
+        self
+    }
+
+    /// Queues held payments while the database is unavailable, responding 202 with their
+    /// status processing instead of failing them. They're persisted by the
+    /// `bank::payment_queue` worker.
+    pub fn with_payment_queue(mut self, payment_queue: PaymentQueue) -> Self {
+        self.payment_queue = Some(payment_queue);
+        self
+    }
+
+    /// Stores the card numbers of payments in the vault, persisting its tokens instead.
+    /// Card numbers are persisted themselves otherwise.
+    ///
+    /// No vault is built in yet, so only tests plug one in.
+    #[cfg(test)]
+    pub fn with_card_vault(mut self, card_vault: impl crate::bank::vault::CardVault) -> Self {
+        self.card_vault = Arc::new(card_vault);
+        self
+    }
+
+    /// Sets whether the card numbers of payments are masked in responses and webhooks,
+    /// which they are by default.
+    ///
+    /// Disabling masking is deprecated: it's only meant for clients still migrating off
+    /// full card numbers.
+    pub fn with_mask_pan(mut self, mask_pan: bool) -> Self {
+        if !mask_pan {
+            tracing::warn!("card number masking is disabled, which is deprecated");
         }
+        self.mask_pan = mask_pan;
+        self
+    }
+
+    /// Sets how masked card numbers are displayed in responses and webhooks, contiguous by
+    /// default.
+    pub fn with_card_number_display(mut self, display: CardNumberDisplay) -> Self {
+        self.card_number_display = display;
+        self
+    }
+
+    /// Sets whether refunds of the nil payment id, which is responded for payments that
+    /// failed to be created, are rejected with 404 without looking the payment up.
+    pub fn with_strict_payment_ids(mut self, strict_payment_ids: bool) -> Self {
+        self.strict_payment_ids = strict_payment_ids;
+        self
+    }
+
+    /// Responds with 504 to the requests not handled within `timeout`. Requests aren't
+    /// timed out otherwise.
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
+
+    /// Creates the payments processing rather than approved in sandbox mode, for them to be
+    /// settled by `payments::spawn_settler` once `delay` has elapsed. Payments are approved
+    /// on creation otherwise.
+    pub fn with_settlement_delay(mut self, delay: time::Duration) -> Self {
+        self.settlement_delay = Some(delay);
+        self
+    }
+
+    /// Captures the authorizations not voided nor captured within `delay`, once
+    /// `spawn_auto_capturer` is called. Authorizations await capture indefinitely otherwise.
+    pub fn with_auto_capture_delay(mut self, delay: time::Duration) -> Self {
+        self.auto_capture_delay = Some(delay);
+        self
+    }
+
+    /// Sets the channel the status changes are published to, to share it with the
+    /// background tasks changing statuses.
+    pub fn with_status_changes(mut self, status_changes: StatusChanges) -> Self {
+        self.status_changes = status_changes;
+        self
+    }
+
+    /// Sets where the webhook deliveries failing to be queued are recorded, to share it with
+    /// the background task queuing them again.
+    pub fn with_unqueued_webhooks(mut self, unqueued_webhooks: UnqueuedDeliveries) -> Self {
+        self.unqueued_webhooks = unqueued_webhooks;
+        self
+    }
+
+    /// Retries the holds of created payments up to `retries` times while the account service
+    /// is unavailable, telling clients how many times in the `X-Retry-Count` header. Holds
+    /// aren't retried otherwise.
+    pub fn with_hold_retries(mut self, retries: u32) -> Self {
+        self.hold_retries = retries;
+        self
+    }
+
+    /// Runs at most `limit.permits` calls to the account service at once, so that a flood
+    /// of payments can't open unbounded connections to it. See `ConcurrencyLimiter`.
+    ///
+    /// The background tasks given the account service directly aren't limited.
+    pub fn with_account_service_concurrency(
+        self,
+        limit: ConcurrencyLimit,
+    ) -> BankWeb<DynAccountService> {
+        let Self {
+            pool,
+            payment_repository,
+            refund_repository,
+            account_service,
+            clock,
+            fees,
+            balance_precheck,
+            default_currency,
+            amount_bounds,
+            merchant_currencies,
+            webhook_url,
+            refund_approval_threshold,
+            refund_window,
+            accepting_window,
+            velocity_limit,
+            daily_card_limit,
+            implicit_idempotency,
+            trusted_proxies,
+            admin_token,
+            merchant_api_keys,
+            sandbox,
+            sandbox_reset,
+            magic_amounts,
+            test_card_bins,
+            status_changes,
+            unqueued_webhooks,
+            payment_queue,
+            card_vault,
+            mask_pan,
+            card_number_display,
+            strict_payment_ids,
+            request_timeout,
+            settlement_delay,
+            auto_capture_delay,
+            hold_retries,
+            require_https,
+            expose_database_errors,
+            collapse_not_approved,
+            demote_duplicate_cards,
+            card_fingerprint_keys,
+        } = self;
+        BankWeb {
+            pool,
+            payment_repository,
+            refund_repository,
+            account_service: Arc::new(ConcurrencyLimiter::new(account_service, limit)),
+            clock,
+            fees,
+            balance_precheck,
+            default_currency,
+            amount_bounds,
+            merchant_currencies,
+            webhook_url,
+            refund_approval_threshold,
+            refund_window,
+            accepting_window,
+            velocity_limit,
+            daily_card_limit,
+            implicit_idempotency,
+            trusted_proxies,
+            admin_token,
+            merchant_api_keys,
+            sandbox,
+            sandbox_reset,
+            magic_amounts,
+            test_card_bins,
+            status_changes,
+            unqueued_webhooks,
+            payment_queue,
+            card_vault,
+            mask_pan,
+            card_number_display,
+            strict_payment_ids,
+            request_timeout,
+            settlement_delay,
+            auto_capture_delay,
+            hold_retries,
+            require_https,
+            expose_database_errors,
+            collapse_not_approved,
+            demote_duplicate_cards,
+            card_fingerprint_keys,
+        }
+    }
+
+    /// Rejects the requests not forwarded over HTTPS by the proxy terminating TLS, as is
+    /// needed in production. Plaintext HTTP is accepted otherwise, for development.
+    pub fn with_require_https(mut self, require_https: bool) -> Self {
+        self.require_https = require_https;
+        self
+    }
+
+    /// Includes the SQLSTATE and detail of the database errors payment creations fail with
+    /// in their responses, for development. Production keeps them opaque.
+    pub fn with_expose_database_errors(mut self, expose_database_errors: bool) -> Self {
+        self.expose_database_errors = expose_database_errors;
+        self
+    }
+
+    /// Responds declined and failed payments alike, as `not_approved`, for merchants not
+    /// telling them apart. Their actual status is still stored, and responded to admins.
+    pub fn with_collapse_not_approved(mut self, collapse_not_approved: bool) -> Self {
+        self.collapse_not_approved = collapse_not_approved;
+        self
+    }
+
+    /// Makes payments with cards already in use rather than rejecting them as duplicated,
+    /// for merchants reusing cards who don't rely on it for idempotency.
+    ///
+    /// Demoting duplicated cards is deprecated: it's only meant for the migration dropping
+    /// the unique index on card fingerprints.
+    pub fn with_demote_duplicate_cards(mut self, demote_duplicate_cards: bool) -> Self {
+        if demote_duplicate_cards {
+            tracing::warn!("duplicated cards are demoted, which is deprecated");
+        }
+        self.demote_duplicate_cards = demote_duplicate_cards;
+        self
+    }
+
+    /// Spawns the background task auto-capturing the authorizations every `interval`, with
+    /// this instance's clock and fees, if an auto-capture delay is set.
+    pub fn spawn_auto_capturer(&self, interval: Duration) -> Option<tokio::task::JoinHandle<()>> {
+        let delay = self.auto_capture_delay?;
+        Some(crate::bank::payments::spawn_auto_capturer(
+            self.pool.clone(),
+            self.account_service.clone(),
+            self.clock.clone(),
+            self.fees,
+            delay,
+            self.status_changes.clone(),
+            interval,
+        ))
+    }
+
+    /// Spawns the background task executing the scheduled refunds every `interval`, with
+    /// this instance's clock and refund approval threshold.
+    pub fn spawn_refund_executor(&self, interval: Duration) -> tokio::task::JoinHandle<()> {
+        crate::bank::refunds::spawn_executor(
+            self.pool.clone(),
+            self.account_service.clone(),
+            self.clock.clone(),
+            self.refund_approval_threshold,
+            interval,
+        )
+    }
+
+    /// Returns the limits checked on the cards of payments.
+    fn card_limits(&self) -> CardLimits {
+        CardLimits {
+            velocity_limit: self.velocity_limit,
+            daily_payments: self.daily_card_limit,
+        }
+    }
+
+    /// Returns whether payments are created processing, to be settled later.
+    fn delays_settlement(&self) -> bool {
+        self.sandbox && self.settlement_delay.is_some()
     }
 
     pub fn into_router(self) -> Router {
-        Router::new()
-            .route("/api/payments", post(payments::post::<T>))
+        let router = Router::new()
+            .route("/health", get(health::check::<T>))
+            .route("/health/live", get(health::live))
+            .route("/health/ready", get(health::ready::<T>))
+            .route(
+                "/api/payments",
+                get(payments::list::<T>).post(payments::post::<T>),
+            )
+            .route("/api/payments/export", get(payments::export::<T>))
+            .route("/api/payments/recent", get(payments::recent::<T>))
+            .route("/api/payments/authorize", post(payments::authorize::<T>))
+            .route("/api/payments/batch", post(payments::batch::<T>))
+            .route("/api/payments/validate", post(payments::validate::<T>))
+            .route(
+                "/api/payments/status-lookup",
+                post(payments::status_lookup::<T>),
+            )
+            // `get` also routes HEAD requests to the same handler
+            .route(
+                "/api/payments/by-reference/:merchant_reference",
+                get(payments::get_by_reference::<T>),
+            )
             .route("/api/payments/:payment_id", get(payments::get::<T>))
+            .route(
+                "/api/payments/:payment_id/events",
+                get(payments::events::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/config-snapshot",
+                get(payments::config_snapshot::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/events/stream",
+                get(payments::stream_events::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/metadata",
+                patch(payments::update_metadata::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/resend-webhook",
+                post(payments::resend_webhook::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/capture",
+                post(payments::capture::<T>),
+            )
             .route(
                 "/api/payments/:payment_id/refunds",
-                post(refunds::post::<T>),
+                post(refunds::post::<T>).get(refunds::list::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/refunds/full",
+                post(refunds::post_remaining::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/refunds/eligibility",
+                get(refunds::eligibility::<T>),
+            )
+            .route(
+                "/api/payments/:payment_id/refund",
+                get(refunds::get_sole::<T>),
             )
             .route(
                 "/api/payments/:payment_id/refunds/:refund_id",
                 get(refunds::get::<T>),
             )
+            .route("/api/refunds", get(refunds::search::<T>))
+            .route(
+                "/api/refunds/:refund_id/approve",
+                post(refunds::approve::<T>),
+            )
+            .route("/api/refunds/:refund_id/reject", post(refunds::reject::<T>))
+            .route(
+                "/api/admin/reconcile-refunds",
+                post(refunds::reconcile::<T>),
+            )
+            .route(
+                "/api/admin/payments/:payment_id/recompute-refunds",
+                post(refunds::recompute::<T>),
+            )
+            .route("/api/tokens/batch", post(tokens::batch::<T>))
+            .route(
+                "/api/callbacks/hold-confirmation",
+                post(callbacks::hold_confirmation::<T>),
+            )
+            .route(
+                "/api/reports/status-counts",
+                get(reports::status_counts::<T>),
+            )
+            .route(
+                "/api/reports/refunds/daily",
+                get(reports::refund_daily_totals::<T>),
+            )
+            .route(
+                "/api/reports/amount-histogram",
+                get(reports::amount_histogram::<T>),
+            )
+            .route(
+                "/api/reports/amount-percentiles",
+                get(reports::amount_percentiles::<T>),
+            )
+            .route(
+                "/api/reports/holds-vs-settled",
+                get(reports::holds_vs_settled::<T>),
+            )
+            .route(
+                "/api/reports/net-settlement",
+                get(reports::net_settlement::<T>),
+            )
+            .route(
+                "/api/reports/settlement-file",
+                get(reports::settlement_file::<T>),
+            )
+            .route(
+                "/api/admin/payments/:payment_id/archive",
+                post(payments::archive::<T>),
+            )
+            .route(
+                "/api/admin/payments/:payment_id/verify",
+                get(payments::verify::<T>),
+            )
+            .route(
+                "/api/admin/authorizations/expiring",
+                get(payments::expiring_authorizations::<T>),
+            )
+            .route("/api/admin/stuck-holds", get(payments::stuck_holds::<T>))
+            .route("/api/admin/reset", post(sandbox::reset::<T>))
+            .route("/api/admin/import", post(imports::import::<T>))
+            .route(
+                "/api/admin/import/stream",
+                // the file is read row by row, so it isn't bounded like other bodies
+                post(imports::import_stream::<T>).layer(DefaultBodyLimit::disable()),
+            )
+            .route(
+                "/api/admin/payments/:payment_id/webhook-test",
+                post(payments::webhook_test::<T>),
+            )
+            .route(
+                "/api/admin/payments/:payment_id/force-fail",
+                post(payments::force_fail::<T>),
+            )
+            .route("/api/admin/cards/history", post(cards::history::<T>))
+            .route(
+                "/api/admin/cards/void-authorizations",
+                post(cards::void_authorizations::<T>),
+            )
+            .route(
+                "/api/admin/rotate-fingerprint-key",
+                post(cards::rotate_fingerprint_key::<T>),
+            )
+            .route(
+                "/api/admin/merchants/:merchant_id/webhook",
+                put(merchants::put_webhook::<T>)
+                    .get(merchants::get_webhook::<T>)
+                    .delete(merchants::delete_webhook::<T>),
+            )
+            .route(
+                "/api/admin/webhook-deliveries",
+                get(webhooks::list_deliveries::<T>),
+            )
+            .layer(middleware::from_fn_with_state(
+                self.clone(),
+                sandbox::simulate_delay,
+            ));
+        // the timeout covers the simulated delays, which are meant to trigger it
+        let router = match self.request_timeout {
+            Some(timeout) => router.layer(
+                tower::ServiceBuilder::new()
+                    .layer(HandleErrorLayer::new(|_: BoxError| async {
+                        StatusCode::GATEWAY_TIMEOUT
+                    }))
+                    .timeout(timeout),
+            ),
+            None => router,
+        };
+        let router = router
+            .layer(middleware::from_fn_with_state(
+                self.clone(),
+                https::require_https,
+            ))
+            .layer(middleware::from_fn(versions::set_api_version))
             .layer(axum_tracing_opentelemetry::opentelemetry_tracing_layer())
             .with_state(self)
-            .with_state(())
+            .with_state(());
+        // the router sets the `Allow` header of 405 responses outside the layers of its
+        // routes, so the header is rewritten around the whole router
+        Router::new()
+            .fallback_service(router)
+            .layer(middleware::from_fn(methods::list_allowed_methods))
     }
 }
 
@@ -46,32 +735,116 @@ impl<T: AccountService> BankWeb<T> {
 pub mod tests {
     use axum::{
         body::Bytes,
-        http::{header::CONTENT_TYPE, Method, Request},
+        http::{
+            header::{AUTHORIZATION, CONTENT_TYPE},
+            Method, Request,
+        },
     };
     use http_body::combinators::UnsyncBoxBody;
     use serde::{de::DeserializeOwned, Serialize};
+    use sqlx::postgres::PgPoolOptions;
     use tower::ServiceExt;
 
     use super::*;
-    use crate::bank::accounts::DummyService;
+    use crate::bank::{accounts::DummyService, repository::tests::InMemoryRepository};
 
     impl BankWeb<DummyService> {
         pub async fn new_test() -> Self {
+            let pool = crate::pg_pool()
+                .await
+                .expect("failed to create postgres pool");
+            Self::new_test_with_pool(pool)
+        }
+
+        /// Returns a bank reading payments and refunds from the `repository`, and which
+        /// never connects to postgres: handlers writing to it fail.
+        pub fn new_test_in_memory(repository: Arc<InMemoryRepository>) -> Self {
+            let pool = PgPoolOptions::new()
+                .connect_lazy("postgres://localhost/unused")
+                .expect("failed to create lazy postgres pool");
+            Self {
+                payment_repository: repository.clone(),
+                refund_repository: repository,
+                ..Self::new_test_with_pool(pool)
+            }
+        }
+
+        fn new_test_with_pool(pool: PgPool) -> Self {
+            let repository = Arc::new(PgRepository::new(pool.clone()));
             Self {
-                pool: crate::pg_pool()
-                    .await
-                    .expect("failed to create postgres pool"),
+                pool,
+                payment_repository: repository.clone(),
+                refund_repository: repository,
                 account_service: DummyService::default(),
+                clock: Arc::new(SystemClock),
+                fees: FeeConfig::default(),
+                balance_precheck: false,
+                default_currency: None,
+                amount_bounds: AmountBoundsTable::new(),
+                merchant_currencies: MerchantCurrencies::new(),
+                webhook_url: None,
+                refund_approval_threshold: None,
+                refund_window: None,
+                accepting_window: None,
+                velocity_limit: None,
+                daily_card_limit: None,
+                implicit_idempotency: None,
+                trusted_proxies: Vec::new(),
+                admin_token: None,
+                merchant_api_keys: HashMap::new(),
+                sandbox: false,
+                sandbox_reset: false,
+                magic_amounts: MagicAmounts::default(),
+                test_card_bins: TestCardBins::default(),
+                status_changes: StatusChanges::default(),
+                unqueued_webhooks: UnqueuedDeliveries::default(),
+                payment_queue: None,
+                card_vault: Arc::new(NoVault),
+                mask_pan: true,
+                card_number_display: CardNumberDisplay::default(),
+                strict_payment_ids: false,
+                request_timeout: None,
+                settlement_delay: None,
+                auto_capture_delay: None,
+                hold_retries: 0,
+                require_https: false,
+                expose_database_errors: true,
+                collapse_not_approved: false,
+                demote_duplicate_cards: false,
+                card_fingerprint_keys: FingerprintKeys::new(FingerprintKey::new_test()),
             }
         }
 
+        pub async fn new_test_with_balance(balance: i32) -> Self {
+            let mut bank_web = Self::new_test().await;
+            bank_web.account_service.balance = Some(balance);
+            bank_web
+        }
+
+        pub fn with_clock(mut self, clock: impl Clock) -> Self {
+            self.clock = Arc::new(clock);
+            self
+        }
+
         pub async fn new_test_with_response(response: impl Into<String>) -> Self {
             let mut bank_web = Self::new_test().await;
             bank_web.account_service.response = Some(response.into());
             bank_web
         }
+
+        pub async fn new_test_with_ping_error(error: impl Into<String>) -> Self {
+            let mut bank_web = Self::new_test().await;
+            bank_web.account_service.ping_error = Some(error.into());
+            bank_web
+        }
     }
 
+    /// Token of operators, for banks built `with_admin_token(ADMIN_TOKEN)`.
+    pub const ADMIN_TOKEN: &str = "secret";
+
+    /// API key of a merchant, for banks built `with_merchant_api_key(merchant_id, MERCHANT_API_KEY)`.
+    pub const MERCHANT_API_KEY: &str = "merchant-secret";
+
     pub async fn send_request(
         router: &Router,
         request: Request<hyper::Body>,
@@ -83,6 +856,56 @@ pub mod tests {
             .expect("failed to send oneshot request")
     }
 
+    pub async fn get(
+        router: &Router,
+        uri: impl AsRef<str>,
+    ) -> hyper::Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri.as_ref())
+            .body(hyper::Body::empty())
+            .expect("failed to build GET request");
+        send_request(router, request).await
+    }
+
+    pub async fn get_as_admin(
+        router: &Router,
+        uri: impl AsRef<str>,
+    ) -> hyper::Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri.as_ref())
+            .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .body(hyper::Body::empty())
+            .expect("failed to build GET request");
+        send_request(router, request).await
+    }
+
+    pub async fn get_as_merchant(
+        router: &Router,
+        uri: impl AsRef<str>,
+    ) -> hyper::Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri.as_ref())
+            .header(AUTHORIZATION, format!("Bearer {MERCHANT_API_KEY}"))
+            .body(hyper::Body::empty())
+            .expect("failed to build GET request");
+        send_request(router, request).await
+    }
+
+    pub async fn head(
+        router: &Router,
+        uri: impl AsRef<str>,
+    ) -> hyper::Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let request = Request::builder()
+            .method(Method::HEAD)
+            .uri(uri.as_ref())
+            .body(hyper::Body::empty())
+            .expect("failed to build HEAD request");
+        send_request(router, request).await
+    }
+
     pub async fn post<T: Serialize>(
         router: &Router,
         uri: impl AsRef<str>,
@@ -101,6 +924,57 @@ pub mod tests {
         send_request(router, request).await
     }
 
+    pub async fn post_as_admin<T: Serialize>(
+        router: &Router,
+        uri: impl AsRef<str>,
+        body: &T,
+    ) -> hyper::Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri.as_ref())
+            .header(CONTENT_TYPE, "application/json")
+            .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .body(
+                serde_json::to_vec(body)
+                    .expect("failed to serialize POST body")
+                    .into(),
+            )
+            .expect("failed to build POST request");
+        send_request(router, request).await
+    }
+
+    pub async fn patch<T: Serialize>(
+        router: &Router,
+        uri: impl AsRef<str>,
+        body: &T,
+    ) -> hyper::Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let request = Request::builder()
+            .method(Method::PATCH)
+            .uri(uri.as_ref())
+            .header(CONTENT_TYPE, "application/json")
+            .body(
+                serde_json::to_vec(body)
+                    .expect("failed to serialize PATCH body")
+                    .into(),
+            )
+            .expect("failed to build PATCH request");
+        send_request(router, request).await
+    }
+
+    pub async fn post_form(
+        router: &Router,
+        uri: impl AsRef<str>,
+        form: impl Into<String>,
+    ) -> hyper::Response<UnsyncBoxBody<Bytes, axum::Error>> {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(uri.as_ref())
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(form.into().into())
+            .expect("failed to build POST request");
+        send_request(router, request).await
+    }
+
     pub async fn deserialize_response_body<T>(
         response: hyper::Response<UnsyncBoxBody<Bytes, axum::Error>>,
     ) -> T
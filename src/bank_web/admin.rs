@@ -0,0 +1,43 @@
+use axum::{
+    extract::FromRequestParts,
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+};
+
+use super::BankWeb;
+use crate::bank::accounts::AccountService;
+
+/// Extractor rejecting requests without the admin bearer token.
+///
+/// Responds with 403 when no admin token is configured, and 401 when the request
+/// doesn't carry it.
+#[derive(Debug, Clone, Copy)]
+pub struct AdminAuth;
+
+#[async_trait::async_trait]
+impl<T: AccountService + Clone> FromRequestParts<BankWeb<T>> for AdminAuth {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        bank_web: &BankWeb<T>,
+    ) -> Result<Self, Self::Rejection> {
+        let Some(admin_token) = &bank_web.admin_token else {
+            return Err(StatusCode::FORBIDDEN);
+        };
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        match token {
+            Some(token) if constant_time_eq(token.as_bytes(), admin_token.as_bytes()) => Ok(Self),
+            _ => Err(StatusCode::UNAUTHORIZED),
+        }
+    }
+}
+
+/// Compares secrets without leaking how many leading bytes match through timing.
+pub(super) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
@@ -0,0 +1,158 @@
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::BankWeb;
+use crate::bank::{
+    accounts::{AccountService, HoldRef},
+    payments::{self, ConfirmHoldError, HoldOutcome},
+};
+
+/// Header carrying the account service's signature of a callback's body.
+pub const CALLBACK_SIGNATURE_HEADER: &str = "x-callback-signature";
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HoldConfirmation {
+    pub hold_ref: Uuid,
+    pub outcome: HoldOutcome,
+}
+
+/// Confirms a hold the account service placed asynchronously, approving or declining the
+/// processing payment it was placed for.
+///
+/// The body must be signed as the account service's `verify_callback` expects, with the
+/// signature in the `X-Callback-Signature` header: responds with 401 otherwise. Responds
+/// with 404 if no payment was made with the hold, and 409 if the payment isn't processing
+/// anymore (e.g. when the confirmation is repeated).
+pub async fn hold_confirmation<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, StatusCode> {
+    let signature = headers
+        .get(CALLBACK_SIGNATURE_HEADER)
+        .and_then(|signature| signature.to_str().ok())
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    if !bank_web.account_service.verify_callback(&body, signature) {
+        return Err(StatusCode::UNAUTHORIZED);
+    }
+    let confirmation: HoldConfirmation =
+        serde_json::from_slice(&body).map_err(|_| StatusCode::BAD_REQUEST)?;
+
+    let payment = payments::confirm_hold(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        &bank_web.fees,
+        HoldRef::from(confirmation.hold_ref),
+        confirmation.outcome,
+    )
+    .await
+    .map_err(|e| match e {
+        ConfirmHoldError::PaymentNotFound => StatusCode::NOT_FOUND,
+        ConfirmHoldError::NotProcessing => StatusCode::CONFLICT,
+        ConfirmHoldError::Database(err) => panic!("Database error: {:?}", err),
+    })?;
+    bank_web.status_changes.publish(payment.id, payment.status);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::{
+            accounts::{sign_callback, DummyService},
+            payment_instruments::Card,
+            payments::Status,
+        },
+        bank_web::{
+            payments as payments_web,
+            tests::{deserialize_response_body, post, send_request},
+        },
+    };
+    use axum::{
+        http::{header::CONTENT_TYPE, Method, Request},
+        Router,
+    };
+    use rstest::rstest;
+
+    async fn confirm(router: &Router, body: &[u8], signature: &str) -> StatusCode {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/callbacks/hold-confirmation")
+            .header(CONTENT_TYPE, "application/json")
+            .header(CALLBACK_SIGNATURE_HEADER, signature)
+            .body(body.to_vec().into())
+            .unwrap();
+        send_request(router, request).await.status()
+    }
+
+    #[rstest]
+    #[case(HoldOutcome::Approved, Status::Approved)]
+    #[case(HoldOutcome::Declined, Status::Declined)]
+    #[tokio::test]
+    async fn should_confirm_hold_of_processing_payment(
+        #[case] outcome: HoldOutcome,
+        #[case] expected_status: Status,
+    ) {
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_sandbox(true)
+            .with_settlement_delay(time::Duration::hours(1));
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let request_body = payments_web::RequestBody {
+            payment: payments_web::RequestData {
+                amount: 10_00,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        let payment_id = deserialize_response_body::<payments_web::ResponseBody>(response)
+            .await
+            .data
+            .id;
+        let payment = payments::get(&pool, payment_id).await.unwrap();
+        assert_eq!(payment.status, Status::Processing);
+
+        let body = serde_json::to_vec(&HoldConfirmation {
+            hold_ref: payment.hold_id.expect("processing payment without a hold"),
+            outcome,
+        })
+        .unwrap();
+        let signature = sign_callback(DummyService::CALLBACK_SECRET.as_bytes(), &body);
+        let forged_signature = sign_callback(b"forged", &body);
+        assert_eq!(
+            confirm(&router, &body, &forged_signature).await,
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            payments::get(&pool, payment_id).await.unwrap().status,
+            Status::Processing
+        );
+
+        assert_eq!(
+            confirm(&router, &body, &signature).await,
+            StatusCode::NO_CONTENT
+        );
+        assert_eq!(
+            payments::get(&pool, payment_id).await.unwrap().status,
+            expected_status
+        );
+        assert_eq!(
+            confirm(&router, &body, &signature).await,
+            StatusCode::CONFLICT
+        );
+    }
+}
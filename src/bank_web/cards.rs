@@ -0,0 +1,399 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use super::{admin::AdminAuth, BankWeb};
+use crate::bank::{
+    accounts::AccountService,
+    fingerprints::FingerprintKey,
+    payments::{self, ForceFailError},
+};
+
+/// Payments fingerprinted again per transaction while rotating the key.
+const REFINGERPRINT_BATCH_SIZE: i64 = 500;
+
+/// Body of the requests about a card, which take its number in the body rather than the
+/// URL, so that it doesn't end up in access logs.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct CardRequestBody {
+    card_number: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct HistoryData {
+    /// The card number, masked down to its last digits.
+    card_number: String,
+    has_prior: bool,
+    count: i64,
+    #[serde(with = "time::serde::rfc3339::option")]
+    first_seen: Option<OffsetDateTime>,
+    #[serde(with = "time::serde::rfc3339::option")]
+    last_seen: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistoryBody {
+    data: HistoryData,
+}
+
+/// Returns whether payments were already made with the card, for returning-customer
+/// detection and velocity checks.
+///
+/// Responds with 422 if the card number is malformed.
+pub async fn history<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Json(body): Json<CardRequestBody>,
+) -> Result<(StatusCode, Json<HistoryBody>), StatusCode> {
+    let card_number = payments::validate_card_number(&body.card_number)
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let history = payments::card_history(
+        &bank_web.pool,
+        &bank_web.card_fingerprint_keys.fingerprints(&card_number),
+        &card_number,
+    )
+    .await
+    .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(HistoryBody {
+            data: HistoryData {
+                card_number: payments::mask_card_number(&card_number, bank_web.card_number_display),
+                has_prior: history.count > 0,
+                count: history.count,
+                first_seen: history.first_seen.map(|datetime| datetime.assume_utc()),
+                last_seen: history.last_seen.map(|datetime| datetime.assume_utc()),
+            },
+        }),
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VoidData {
+    voided: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VoidBody {
+    data: VoidData,
+}
+
+/// Voids every authorization awaiting capture on the card, e.g. once it's compromised.
+///
+/// Responds with 422 if the card number is malformed.
+pub async fn void_authorizations<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Json(body): Json<CardRequestBody>,
+) -> Result<(StatusCode, Json<VoidBody>), StatusCode> {
+    let card_number = payments::validate_card_number(&body.card_number)
+        .map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let voided = payments::void_all_authorizations_for_card(
+        &bank_web.pool,
+        &bank_web.account_service,
+        bank_web.clock.as_ref(),
+        &bank_web.card_fingerprint_keys,
+        &card_number,
+    )
+    .await
+    .map_err(|e| match e {
+        ForceFailError::AccountService(err) => err.http_status(),
+        ForceFailError::PaymentNotFound
+        | ForceFailError::Terminal
+        | ForceFailError::Database(_) => {
+            panic!("failed to void authorizations: {e:?}")
+        }
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(VoidBody {
+            data: VoidData { voided },
+        }),
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RotateKeyRequestBody {
+    key: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RotateKeyData {
+    refingerprinted: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RotateKeyBody {
+    data: RotateKeyData,
+}
+
+/// Rotates the key card numbers are fingerprinted with, fingerprinting those of the
+/// unarchived payments again in batches. Until they all are, duplicates are looked up with
+/// both the previous and the new key.
+///
+/// The rotation only lasts as long as the process: `CARD_FINGERPRINT_KEY` has to be set
+/// to the new key before restarting. Responds with 422 if the key is empty, and with 409
+/// if a rotation is already under way.
+pub async fn rotate_fingerprint_key<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Json(body): Json<RotateKeyRequestBody>,
+) -> Result<(StatusCode, Json<RotateKeyBody>), StatusCode> {
+    if body.key.is_empty() {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let key = FingerprintKey::new(&body.key);
+    if !bank_web.card_fingerprint_keys.begin_rotation(key.clone()) {
+        return Err(StatusCode::CONFLICT);
+    }
+    let refingerprinted =
+        payments::refingerprint_cards(&bank_web.pool, &key, REFINGERPRINT_BATCH_SIZE)
+            .await
+            .unwrap();
+    bank_web.card_fingerprint_keys.end_rotation();
+
+    Ok((
+        StatusCode::OK,
+        Json(RotateKeyBody {
+            data: RotateKeyData { refingerprinted },
+        }),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::{
+            clock::{tests::MockClock, SystemClock},
+            payment_instruments::Card,
+            payments::Status,
+        },
+        bank_web::{
+            payments as payments_web,
+            tests::{deserialize_response_body, post, post_as_admin, send_request, ADMIN_TOKEN},
+        },
+    };
+    use axum::{
+        http::{
+            header::{AUTHORIZATION, CONTENT_TYPE},
+            Method, Request,
+        },
+        response::Response,
+        Router,
+    };
+    use uuid::Uuid;
+
+    async fn history(router: &Router, card_number: &str) -> HistoryData {
+        let request_body = CardRequestBody {
+            card_number: card_number.into(),
+        };
+        let response = post_as_admin(router, "/api/admin/cards/history", &request_body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        deserialize_response_body::<HistoryBody>(response)
+            .await
+            .data
+    }
+
+    #[tokio::test]
+    async fn should_return_card_history() {
+        let now = OffsetDateTime::from_unix_timestamp(1_000_000_000).unwrap();
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .with_clock(MockClock::new(now))
+            .into_router();
+        let card_number: String = Card::new_test().into();
+
+        let data = history(&router, &card_number).await;
+        assert!(!data.has_prior);
+        assert_eq!(data.count, 0);
+        assert_eq!(data.first_seen, None);
+
+        let request_body = payments_web::RequestBody {
+            payment: payments_web::RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: card_number.clone(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let data = history(&router, &card_number).await;
+        assert!(data.has_prior);
+        assert_eq!(data.count, 1);
+        assert_eq!(data.first_seen, Some(now));
+        assert_eq!(data.last_seen, Some(now));
+        assert_eq!(
+            data.card_number,
+            format!("{}{}", "*".repeat(11), &card_number[11..])
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_malformed_card_number() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+
+        let request_body = CardRequestBody {
+            card_number: "not-a-card".into(),
+        };
+        let response = post_as_admin(&router, "/api/admin/cards/history", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_only_return_card_history_to_operators() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+
+        let request_body = CardRequestBody {
+            card_number: Card::new_test().into(),
+        };
+        let response = post(&router, "/api/admin/cards/history", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    async fn void_authorizations(router: &Router, card_number: &str) -> Response {
+        let request_body = CardRequestBody {
+            card_number: card_number.into(),
+        };
+        post_as_admin(
+            router,
+            "/api/admin/cards/void-authorizations",
+            &request_body,
+        )
+        .await
+    }
+
+    async fn authorize(router: &Router, card_number: &str) -> Uuid {
+        let request_body = payments_web::AuthorizeRequestBody {
+            payment: payments_web::AuthorizeRequestData {
+                amount: 10_00,
+                card_number: card_number.into(),
+                hold_amount: None,
+            },
+        };
+        let response = post(router, "/api/payments/authorize", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        deserialize_response_body::<payments_web::ResponseBody>(response)
+            .await
+            .data
+            .id
+    }
+
+    #[tokio::test]
+    async fn should_void_all_authorizations_for_card() {
+        let bank_web = BankWeb::new_test().await.with_admin_token(ADMIN_TOKEN);
+        let pool = bank_web.pool.clone();
+        let released_holds = bank_web.account_service.released_holds.clone();
+        let router = bank_web.into_router();
+        let card_number: String = Card::new_test().into();
+
+        // the card can only be authorized again once the first authorization is archived
+        let archived_id = authorize(&router, &card_number).await;
+        payments::archive(&pool, &SystemClock, archived_id)
+            .await
+            .unwrap();
+        let id = authorize(&router, &card_number).await;
+        let hold_ids = [
+            payments::get(&pool, archived_id).await.unwrap().hold_id,
+            payments::get(&pool, id).await.unwrap().hold_id,
+        ];
+
+        let response = void_authorizations(&router, &card_number).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<VoidBody>(response).await.data,
+            VoidData { voided: 2 }
+        );
+        for id in [archived_id, id] {
+            let payment = payments::get(&pool, id).await.unwrap();
+            assert_eq!(payment.status, Status::Failed);
+            assert_eq!(
+                payment.decline_reason.as_deref(),
+                Some(payments::VOIDED_REASON)
+            );
+            assert_eq!(payment.hold_id, None);
+        }
+        for hold_id in hold_ids {
+            assert!(released_holds.lock().unwrap().contains(&hold_id.unwrap()));
+        }
+
+        let response = void_authorizations(&router, &card_number).await;
+        assert_eq!(
+            deserialize_response_body::<VoidBody>(response).await.data,
+            VoidData { voided: 0 }
+        );
+        let response = void_authorizations(&router, "not-a-card").await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_refingerprint_cards_when_rotating_key() {
+        let bank_web = BankWeb::new_test().await.with_admin_token(ADMIN_TOKEN);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let card_number: String = Card::new_test().into();
+        let id = authorize(&router, &card_number).await;
+        sqlx::query!(
+            "UPDATE payments SET card_fingerprint = $1 WHERE id = $2",
+            FingerprintKey::new("stale").fingerprint(&card_number),
+            id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        // rotating to the key the other tests use leaves their fingerprints unchanged
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/admin/rotate-fingerprint-key")
+            .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"key":"test"}"#.into())
+            .unwrap();
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(
+            deserialize_response_body::<RotateKeyBody>(response)
+                .await
+                .data
+                .refingerprinted
+                >= 1
+        );
+        let fingerprint =
+            sqlx::query_scalar!("SELECT card_fingerprint FROM payments WHERE id = $1", id)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            fingerprint,
+            Some(FingerprintKey::new_test().fingerprint(&card_number))
+        );
+
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/admin/rotate-fingerprint-key")
+            .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"key":""}"#.into())
+            .unwrap();
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    convert::Infallible,
+    net::{IpAddr, SocketAddr},
+};
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts},
+    http::{header::FORWARDED, request::Parts, HeaderMap},
+};
+
+use super::BankWeb;
+use crate::bank::accounts::AccountService;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// IP address of the client that sent the request, if known.
+///
+/// This is the socket's peer address, unless the peer is a trusted proxy: the client
+/// is then read from the `Forwarded` or `X-Forwarded-For` header set by the proxy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+#[async_trait::async_trait]
+impl<T: AccountService + Clone> FromRequestParts<BankWeb<T>> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        bank_web: &BankWeb<T>,
+    ) -> Result<Self, Self::Rejection> {
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        Ok(Self(peer.map(|peer| {
+            resolve(peer, &parts.headers, &bank_web.trusted_proxies)
+        })))
+    }
+}
+
+/// Returns the IP address of the client of a request received from `peer`.
+///
+/// Forwarded addresses are walked back from the closest hop, as long as they were
+/// appended by a trusted proxy: the first untrusted address is the client's, since the
+/// ones before it could have been forged.
+pub fn resolve(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpAddr]) -> IpAddr {
+    let mut client = peer;
+    for hop in forwarded_for(headers).into_iter().rev() {
+        if !trusted_proxies.contains(&client) {
+            break;
+        }
+        match hop {
+            Some(hop) => client = hop,
+            // obfuscated or unknown addresses can't be walked past
+            None => break,
+        }
+    }
+    client
+}
+
+/// Returns the forwarded addresses, from the farthest hop to the closest.
+///
+/// The standard `Forwarded` header takes precedence over `X-Forwarded-For`. Hops whose
+/// address can't be parsed are `None`.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    let values = |name| {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(str::trim)
+    };
+
+    if headers.contains_key(FORWARDED) {
+        values(FORWARDED.as_str())
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, value)| parse_node(value.trim().trim_matches('"')))
+            })
+            .collect()
+    } else {
+        values(X_FORWARDED_FOR).map(parse_node).collect()
+    }
+}
+
+/// Parses an address, optionally followed by a port, IPv6 addresses then being bracketed.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|node| node.strip_suffix(']'))
+                .and_then(|node| node.parse().ok())
+        })
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use rstest::rstest;
+
+    const PEER: &str = "10.0.0.1";
+
+    #[rstest]
+    #[case(&[], &[], PEER)]
+    #[case(&[(X_FORWARDED_FOR, "203.0.113.7")], &[], PEER)]
+    #[case(&[(X_FORWARDED_FOR, "203.0.113.7")], &[PEER], "203.0.113.7")]
+    #[case(&[(X_FORWARDED_FOR, "198.51.100.1, 203.0.113.7")], &[PEER], "203.0.113.7")]
+    #[case(
+        &[(X_FORWARDED_FOR, "198.51.100.1, 10.0.0.2")],
+        &[PEER, "10.0.0.2"],
+        "198.51.100.1"
+    )]
+    #[case(&[(X_FORWARDED_FOR, "garbage")], &[PEER], PEER)]
+    #[case(&[], &[PEER], PEER)]
+    #[case(&[("forwarded", "for=203.0.113.7;proto=https")], &[PEER], "203.0.113.7")]
+    #[case(
+        &[("forwarded", r#"for="[2001:db8::1]:4711""#), (X_FORWARDED_FOR, "203.0.113.7")],
+        &[PEER],
+        "2001:db8::1"
+    )]
+    #[case(&[("forwarded", "for=203.0.113.7:80, for=unknown")], &[PEER], PEER)]
+    fn test_resolve(
+        #[case] headers: &[(&'static str, &'static str)],
+        #[case] trusted_proxies: &[&str],
+        #[case] expected: &str,
+    ) {
+        let headers = headers
+            .iter()
+            .map(|(name, value)| (name.parse().unwrap(), HeaderValue::from_static(value)))
+            .collect();
+        let trusted_proxies: Vec<IpAddr> = trusted_proxies
+            .iter()
+            .map(|proxy| proxy.parse().unwrap())
+            .collect();
+
+        assert_eq!(
+            resolve(PEER.parse().unwrap(), &headers, &trusted_proxies),
+            expected.parse::<IpAddr>().unwrap()
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+
+use super::BankWeb;
+use crate::bank::accounts::AccountService;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DependencyStatus {
+    Ok,
+    Unavailable,
+}
+
+impl DependencyStatus {
+    fn from_result<T, E: std::fmt::Debug>(dependency: &str, result: Result<T, E>) -> Self {
+        match result {
+            Ok(_) => Self::Ok,
+            Err(err) => {
+                tracing::warn!(dependency, error = ?err, "health check failed");
+                Self::Unavailable
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct HealthData {
+    database: DependencyStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    account_service: Option<DependencyStatus>,
+}
+
+impl HealthData {
+    fn status_code(&self) -> StatusCode {
+        let statuses = [Some(self.database), self.account_service];
+        if statuses.contains(&Some(DependencyStatus::Unavailable)) {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            StatusCode::OK
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HealthBody {
+    data: HealthData,
+}
+
+async fn database_status<T: AccountService + Clone>(bank_web: &BankWeb<T>) -> DependencyStatus {
+    let result = sqlx::query_scalar!("SELECT 1")
+        .fetch_one(&bank_web.pool)
+        .await;
+    DependencyStatus::from_result("database", result)
+}
+
+/// Checks that the database is reachable.
+pub async fn check<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+) -> (StatusCode, Json<HealthBody>) {
+    let data = HealthData {
+        database: database_status(&bank_web).await,
+        account_service: None,
+    };
+
+    (data.status_code(), Json(HealthBody { data }))
+}
+
+/// Liveness probe: the process is up and serving requests, whatever its dependencies.
+pub async fn live() -> StatusCode {
+    StatusCode::OK
+}
+
+/// Readiness probe: checks every dependency, responding with 503 if any is down.
+pub async fn ready<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+) -> (StatusCode, Json<HealthBody>) {
+    let (database, account_service) =
+        futures::join!(database_status(&bank_web), bank_web.account_service.ping());
+    let data = HealthData {
+        database,
+        account_service: Some(DependencyStatus::from_result(
+            "account_service",
+            account_service,
+        )),
+    };
+
+    (data.status_code(), Json(HealthBody { data }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank_web::tests::{deserialize_response_body, get};
+
+    #[tokio::test]
+    async fn should_be_live_and_healthy() {
+        let router = BankWeb::new_test().await.into_router();
+
+        assert_eq!(get(&router, "/health/live").await.status(), StatusCode::OK);
+
+        let response = get(&router, "/health").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = deserialize_response_body::<HealthBody>(response).await;
+        assert_eq!(body.data.database, DependencyStatus::Ok);
+        assert_eq!(body.data.account_service, None);
+    }
+
+    #[tokio::test]
+    async fn should_be_ready_with_every_dependency_up() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let response = get(&router, "/health/ready").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = deserialize_response_body::<HealthBody>(response).await;
+        assert_eq!(body.data.database, DependencyStatus::Ok);
+        assert_eq!(body.data.account_service, Some(DependencyStatus::Ok));
+    }
+
+    #[tokio::test]
+    async fn should_not_be_ready_with_account_service_down() {
+        let router = BankWeb::new_test_with_ping_error("service_unavailable")
+            .await
+            .into_router();
+
+        let response = get(&router, "/health/ready").await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = deserialize_response_body::<HealthBody>(response).await;
+        assert_eq!(body.data.database, DependencyStatus::Ok);
+        assert_eq!(
+            body.data.account_service,
+            Some(DependencyStatus::Unavailable)
+        );
+
+        // liveness doesn't depend on the account service
+        assert_eq!(get(&router, "/health/live").await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,77 @@
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use super::BankWeb;
+
+/// Header set by the proxy terminating TLS to the protocol the client connected with.
+pub const FORWARDED_PROTO_HEADER: &str = "x-forwarded-proto";
+
+/// Rejects with 426 the requests that didn't reach the proxy over HTTPS, when HTTPS is
+/// required. Requests without the header are rejected too, as they bypassed the proxy.
+///
+/// Health checks are exempt: probes are sent to the server directly, over HTTP.
+pub async fn require_https<T, B>(
+    State(bank_web): State<BankWeb<T>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let is_https = request
+        .headers()
+        .get(FORWARDED_PROTO_HEADER)
+        .and_then(|proto| proto.to_str().ok())
+        .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+    let is_health_check = request.uri().path().starts_with("/health");
+    if bank_web.require_https && !is_https && !is_health_check {
+        return (StatusCode::UPGRADE_REQUIRED, "HTTPS is required").into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank_web::tests::send_request;
+    use axum::http::Method;
+
+    async fn send(
+        bank_web: BankWeb<crate::bank::accounts::DummyService>,
+        uri: &str,
+        proto: &str,
+    ) -> StatusCode {
+        let router = bank_web.into_router();
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .header(FORWARDED_PROTO_HEADER, proto)
+            .body(hyper::Body::empty())
+            .unwrap();
+        send_request(&router, request).await.status()
+    }
+
+    #[tokio::test]
+    async fn should_reject_plaintext_request_when_https_is_required() {
+        let bank_web = BankWeb::new_test().await.with_require_https(true);
+        let uri = "/api/reports/status-counts";
+
+        assert_eq!(
+            send(bank_web.clone(), uri, "http").await,
+            StatusCode::UPGRADE_REQUIRED
+        );
+        assert_eq!(send(bank_web.clone(), uri, "https").await, StatusCode::OK);
+        assert_eq!(send(bank_web, "/health/live", "http").await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn should_allow_plaintext_request_by_default() {
+        let bank_web = BankWeb::new_test().await;
+
+        assert_eq!(
+            send(bank_web, "/api/reports/status-counts", "http").await,
+            StatusCode::OK
+        );
+    }
+}
@@ -0,0 +1,504 @@
+use std::convert::Infallible;
+
+use axum::{
+    body::StreamBody,
+    extract::{multipart::Field, Multipart, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use futures::{channel::mpsc, SinkExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::oneshot;
+
+use super::{admin::AdminAuth, BankWeb};
+use crate::bank::{
+    accounts::AccountService,
+    payments::{self, CreateError, Metadata, Status},
+    retry_budget::RetryBudget,
+};
+
+/// Largest CSV file imported at once, in bytes.
+pub const MAX_IMPORT_FILE_SIZE: usize = 1024 * 1024;
+/// Name of the multipart field holding the CSV file.
+const FILE_FIELD: &str = "file";
+/// Number of rows between the progress lines of a streamed import.
+pub const IMPORT_PROGRESS_INTERVAL: usize = 100;
+/// Longest row of a streamed import, in bytes, so that a file without line breaks isn't
+/// buffered whole.
+pub const MAX_IMPORT_ROW_LENGTH: usize = 64 * 1024;
+/// Number of lines of a streamed import buffered ahead of the client.
+const IMPORT_BUFFER_SIZE: usize = 64;
+
+/// A row of the import that wasn't imported.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FailedRow {
+    /// Number of the row in the file, the header being row 1.
+    pub row: usize,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImportBody {
+    pub imported: usize,
+    pub failed: Vec<FailedRow>,
+}
+
+/// How many rows of a streamed import were processed so far.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ImportCounts {
+    pub rows: usize,
+    pub imported: usize,
+    pub failed: usize,
+}
+
+/// A line of the newline-delimited JSON response of a streamed import.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ImportLine {
+    /// Sent every `IMPORT_PROGRESS_INTERVAL` rows.
+    Progress(ImportCounts),
+    FailedRow(FailedRow),
+    /// Sent last, with the error which stopped the import early, if any.
+    Summary {
+        #[serde(flatten)]
+        counts: ImportCounts,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+}
+
+/// A row of the CSV file, before its payment is validated.
+struct ImportRow<'a> {
+    amount: &'a str,
+    currency: Option<&'a str>,
+    card_number: &'a str,
+    customer_id: Option<&'a str>,
+}
+
+/// Positions of the columns in the rows, as named by the header.
+struct Columns {
+    amount: usize,
+    currency: Option<usize>,
+    card_number: usize,
+    customer_id: Option<usize>,
+}
+
+impl Columns {
+    /// Locates the columns in the header, which must name `amount` and `card_number`.
+    fn parse(header: &str) -> Option<Self> {
+        let names: Vec<_> = header.split(',').map(str::trim).collect();
+        let position = |name| names.iter().position(|column| *column == name);
+        Some(Self {
+            amount: position("amount")?,
+            currency: position("currency"),
+            card_number: position("card_number")?,
+            customer_id: position("customer_id"),
+        })
+    }
+
+    fn row<'a>(&self, line: &'a str) -> Option<ImportRow<'a>> {
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let optional = |column: Option<usize>| {
+            column
+                .and_then(|column| fields.get(column).copied())
+                .filter(|field| !field.is_empty())
+        };
+        Some(ImportRow {
+            amount: fields.get(self.amount)?,
+            currency: optional(self.currency),
+            card_number: fields.get(self.card_number)?,
+            customer_id: optional(self.customer_id),
+        })
+    }
+}
+
+/// Imports the payments of a CSV file uploaded as the `file` field of a multipart form,
+/// e.g. when migrating from another processor.
+///
+/// The header names the columns: `amount`, in minor units, and `card_number` are required,
+/// `currency` and `customer_id` are optional. Fields can't be quoted. Rows are created
+/// independently, with the same validation as `POST /api/payments`: rows failing it are
+/// reported without aborting the import. No webhook is sent for imported payments. See
+/// `import_stream` for files too large to be uploaded whole.
+///
+/// Responds with 400 if the form has no `file` field, 413 if the file is larger than
+/// `MAX_IMPORT_FILE_SIZE` and 422 if it isn't UTF-8 or its header lacks a required column.
+pub async fn import<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    mut multipart: Multipart,
+) -> Result<(StatusCode, Json<ImportBody>), StatusCode> {
+    let file = loop {
+        let Some(mut field) = multipart
+            .next_field()
+            .await
+            .map_err(|_| StatusCode::BAD_REQUEST)?
+        else {
+            return Err(StatusCode::BAD_REQUEST);
+        };
+        if field.name() != Some(FILE_FIELD) {
+            continue;
+        }
+        let mut file = Vec::new();
+        while let Some(chunk) = field.chunk().await.map_err(|_| StatusCode::BAD_REQUEST)? {
+            if file.len() + chunk.len() > MAX_IMPORT_FILE_SIZE {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE);
+            }
+            file.extend_from_slice(&chunk);
+        }
+        break file;
+    };
+    let file = String::from_utf8(file).map_err(|_| StatusCode::UNPROCESSABLE_ENTITY)?;
+    let mut lines = file.lines().enumerate();
+    let columns = lines
+        .next()
+        .and_then(|(_, header)| Columns::parse(header))
+        .ok_or(StatusCode::UNPROCESSABLE_ENTITY)?;
+
+    let mut imported = 0;
+    let mut failed = Vec::new();
+    for (index, line) in lines.filter(|(_, line)| !line.trim().is_empty()) {
+        match import_row(&bank_web, &columns, line).await {
+            Ok(()) => imported += 1,
+            Err(error) => failed.push(FailedRow {
+                row: index + 1,
+                error,
+            }),
+        }
+    }
+
+    Ok((StatusCode::OK, Json(ImportBody { imported, failed })))
+}
+
+/// Imports the payments of a CSV file like `import`, reading it row by row as it's
+/// uploaded rather than whole, so that files of any size can be imported.
+///
+/// Responds with newline-delimited JSON as rows are processed: each failed row, progress
+/// counts every `IMPORT_PROGRESS_INTERVAL` rows, and a final summary. The status code is
+/// sent once the header is read, so that it's only 400 if the form has no `file` field and
+/// 422 if the header isn't UTF-8 or lacks a required column. Later errors, such as a row
+/// longer than `MAX_IMPORT_ROW_LENGTH` or an interrupted upload, stop the import and are
+/// reported by the summary; rows imported until then stay imported, as they do if the
+/// client disconnects.
+pub async fn import_stream<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    multipart: Multipart,
+) -> Result<impl IntoResponse, StatusCode> {
+    let (header_sender, header_receiver) = oneshot::channel();
+    let (sender, receiver) = mpsc::channel(IMPORT_BUFFER_SIZE);
+    tokio::spawn(stream_import(bank_web, multipart, header_sender, sender));
+
+    header_receiver
+        .await
+        .unwrap_or(Err(StatusCode::INTERNAL_SERVER_ERROR))?;
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(receiver),
+    ))
+}
+
+/// Imports the rows of the multipart's file, sending whether its header is valid to
+/// `header` before sending the response's lines to `lines`.
+///
+/// The multipart's fields borrow it, so the file is read from start to end by this task.
+async fn stream_import<T: AccountService + Clone>(
+    bank_web: BankWeb<T>,
+    mut multipart: Multipart,
+    header: oneshot::Sender<Result<(), StatusCode>>,
+    mut lines: mpsc::Sender<Result<String, Infallible>>,
+) {
+    let mut field = loop {
+        let Ok(Some(field)) = multipart.next_field().await else {
+            let _ = header.send(Err(StatusCode::BAD_REQUEST));
+            return;
+        };
+        if field.name() == Some(FILE_FIELD) {
+            break field;
+        }
+    };
+    let mut buffer = Vec::new();
+    let columns = match next_line(&mut field, &mut buffer).await {
+        Ok(Some(line)) => std::str::from_utf8(&line)
+            .ok()
+            .and_then(Columns::parse)
+            .ok_or(StatusCode::UNPROCESSABLE_ENTITY),
+        Ok(None) => Err(StatusCode::UNPROCESSABLE_ENTITY),
+        Err(_) => Err(StatusCode::BAD_REQUEST),
+    };
+    let columns = match columns {
+        Ok(columns) => columns,
+        Err(status_code) => {
+            let _ = header.send(Err(status_code));
+            return;
+        }
+    };
+    if header.send(Ok(())).is_err() {
+        return;
+    }
+
+    let mut counts = ImportCounts::default();
+    // the header is row 1
+    let mut row = 1;
+    let error = loop {
+        let line = match next_line(&mut field, &mut buffer).await {
+            Ok(Some(line)) => line,
+            Ok(None) => break None,
+            Err(error) => break Some(error.to_owned()),
+        };
+        row += 1;
+        let result = match std::str::from_utf8(&line) {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => import_row(&bank_web, &columns, line).await,
+            Err(_) => Err("invalid_encoding".into()),
+        };
+
+        counts.rows += 1;
+        let mut sent = Vec::new();
+        match result {
+            Ok(()) => counts.imported += 1,
+            Err(error) => {
+                counts.failed += 1;
+                sent.push(ImportLine::FailedRow(FailedRow { row, error }));
+            }
+        }
+        if counts.rows % IMPORT_PROGRESS_INTERVAL == 0 {
+            sent.push(ImportLine::Progress(counts));
+        }
+        for line in sent {
+            if send_line(&mut lines, &line).await.is_err() {
+                tracing::warn!(?counts, "import stopped by the client disconnecting");
+                return;
+            }
+        }
+    };
+
+    let _ = send_line(&mut lines, &ImportLine::Summary { counts, error }).await;
+}
+
+/// Returns the next line of the file, without its line break, or `None` at its end.
+///
+/// `buffer` holds what was read past the previous line.
+async fn next_line(
+    field: &mut Field<'_>,
+    buffer: &mut Vec<u8>,
+) -> Result<Option<Vec<u8>>, &'static str> {
+    loop {
+        if let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let mut line: Vec<_> = buffer.drain(..=end).collect();
+            line.pop();
+            return Ok(Some(line));
+        }
+        if buffer.len() > MAX_IMPORT_ROW_LENGTH {
+            return Err("row_too_long");
+        }
+        match field.chunk().await.map_err(|_| "upload_interrupted")? {
+            Some(chunk) => buffer.extend_from_slice(&chunk),
+            None if buffer.is_empty() => return Ok(None),
+            None => return Ok(Some(std::mem::take(buffer))),
+        }
+    }
+}
+
+async fn send_line(
+    lines: &mut mpsc::Sender<Result<String, Infallible>>,
+    line: &ImportLine,
+) -> Result<(), mpsc::SendError> {
+    let mut line = serde_json::to_string(line).expect("failed to serialize import line");
+    line.push('\n');
+    lines.send(Ok(line)).await
+}
+
+/// Creates the payment of the row, returning why it failed otherwise.
+async fn import_row<T: AccountService + Clone>(
+    bank_web: &BankWeb<T>,
+    columns: &Columns,
+    line: &str,
+) -> Result<(), String> {
+    let row = columns.row(line).ok_or("missing_column")?;
+    let amount = row.amount.parse().map_err(|_| "invalid_amount")?;
+    let currency = row.currency.or(bank_web.default_currency.as_deref());
+    let result = payments::create(
+        &bank_web.pool,
+        &bank_web.account_service,
+        bank_web.clock.as_ref(),
+        &bank_web.card_fingerprint_keys.current(),
+        bank_web.card_vault.as_ref(),
+        &bank_web.fees,
+        bank_web.balance_precheck,
+        &bank_web.amount_bounds,
+        &bank_web.merchant_currencies,
+        &bank_web.card_limits(),
+        None,
+        bank_web.payment_queue.as_ref(),
+        &mut RetryBudget::new(bank_web.hold_retries),
+        !bank_web.sandbox,
+        bank_web.demote_duplicate_cards,
+        amount,
+        currency,
+        row.card_number,
+        Status::Approved,
+        Metadata::new(),
+        None,
+        row.customer_id,
+        None,
+        None,
+        None,
+    )
+    .await;
+    match result {
+        // queued payments are persisted once the database recovers
+        Ok(_) | Err(CreateError::Queued(_)) => Ok(()),
+        Err(e) => {
+            tracing::warn!(error = ?e, "failed to import payment");
+            Err(e
+                .decline_reason()
+                .unwrap_or_else(|| "internal_error".into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::payment_instruments::Card,
+        bank_web::tests::{deserialize_response_body, send_request},
+    };
+    use axum::http::{
+        header::{AUTHORIZATION, CONTENT_TYPE},
+        Method, Request,
+    };
+
+    const ADMIN_TOKEN: &str = "secret";
+    const BOUNDARY: &str = "import-boundary";
+
+    fn import_request(csv: &str) -> Request<hyper::Body> {
+        let body = format!(
+            "--{BOUNDARY}\r\n\
+             Content-Disposition: form-data; name=\"file\"; filename=\"payments.csv\"\r\n\
+             Content-Type: text/csv\r\n\
+             \r\n\
+             {csv}\r\n\
+             --{BOUNDARY}--\r\n"
+        );
+        Request::builder()
+            .method(Method::POST)
+            .uri("/api/admin/import")
+            .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .header(
+                CONTENT_TYPE,
+                format!("multipart/form-data; boundary={BOUNDARY}"),
+            )
+            .body(body.into())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn should_import_valid_rows_and_report_invalid_ones() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+        let card_number: String = Card::new_test().into();
+        let csv =
+            format!("amount,currency,card_number\n1205,EUR,{card_number}\n1205,EUR,not-a-card\n");
+
+        let response = send_request(&router, import_request(&csv)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<ImportBody>(response).await,
+            ImportBody {
+                imported: 1,
+                failed: vec![FailedRow {
+                    row: 3,
+                    error: "invalid_card_format".into(),
+                }],
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn should_stream_import_progress_and_summary() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+        let mut csv = String::from("amount,card_number\n");
+        for row in 2..=251 {
+            let card_number: String = if row == 150 {
+                "not-a-card".into()
+            } else {
+                Card::new_test().into()
+            };
+            csv.push_str(&format!("1205,{card_number}\n"));
+        }
+        let mut request = import_request(&csv);
+        *request.uri_mut() = "/api/admin/import/stream".parse().unwrap();
+
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/x-ndjson"
+        );
+        let bytes = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let lines: Vec<ImportLine> = std::str::from_utf8(&bytes)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).expect("failed to deserialize NDJSON line"))
+            .collect();
+        assert_eq!(
+            lines,
+            vec![
+                ImportLine::Progress(ImportCounts {
+                    rows: 100,
+                    imported: 100,
+                    failed: 0,
+                }),
+                ImportLine::FailedRow(FailedRow {
+                    row: 150,
+                    error: "invalid_card_format".into(),
+                }),
+                ImportLine::Progress(ImportCounts {
+                    rows: 200,
+                    imported: 199,
+                    failed: 1,
+                }),
+                ImportLine::Summary {
+                    counts: ImportCounts {
+                        rows: 250,
+                        imported: 249,
+                        failed: 1,
+                    },
+                    error: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_streamed_import_without_required_columns() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+        let mut request = import_request("amount,currency\n1205,EUR\n");
+        *request.uri_mut() = "/api/admin/import/stream".parse().unwrap();
+
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_reject_import_without_required_columns() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+
+        let response = send_request(&router, import_request("amount,currency\n1205,EUR\n")).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
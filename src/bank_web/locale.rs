@@ -0,0 +1,150 @@
+//! Languages the human-readable messages of error responses are localized in. Machine
+//! codes, such as the problem types, stay the same whatever the language.
+
+use axum::http::{header::ACCEPT_LANGUAGE, HeaderMap};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    French,
+}
+
+impl Language {
+    /// Returns the language's tag, as in `Content-Language`.
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Self::English => "en",
+            Self::French => "fr",
+        }
+    }
+
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split('-').next().unwrap_or_default();
+        if primary.eq_ignore_ascii_case("en") || primary == "*" {
+            Some(Self::English)
+        } else if primary.eq_ignore_ascii_case("fr") {
+            Some(Self::French)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the supported language the client prefers by its `Accept-Language`, falling
+    /// back to English.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut ranges: Vec<(&str, f32)> = headers
+            .get_all(ACCEPT_LANGUAGE)
+            .iter()
+            .filter_map(|accept| accept.to_str().ok())
+            .flat_map(|accept| accept.split(','))
+            .map(|range| {
+                let mut params = range.split(';');
+                let tag = params.next().unwrap_or_default().trim();
+                let quality = params
+                    .filter_map(|param| param.trim().strip_prefix("q="))
+                    .find_map(|quality| quality.parse().ok())
+                    .unwrap_or(1.0);
+                (tag, quality)
+            })
+            .filter(|(_, quality)| *quality > 0.0)
+            .collect();
+        // stable, so that ranges of the same quality keep the client's order
+        ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+        ranges
+            .into_iter()
+            .find_map(|(tag, _)| Self::from_tag(tag))
+            .unwrap_or_default()
+    }
+
+    /// Returns the message of the error `code` (e.g. a decline reason) in the language.
+    pub fn message(&self, code: &str) -> String {
+        match self {
+            Self::French => french_message(code).map_or_else(|| english_message(code), Into::into),
+            Self::English => english_message(code),
+        }
+    }
+}
+
+/// Spells out the code, e.g. `Insufficient funds` for `insufficient_funds`.
+fn english_message(code: &str) -> String {
+    let message = code.replace('_', " ");
+    let mut chars = message.chars();
+    chars
+        .next()
+        .map(|first| first.to_uppercase().chain(chars).collect())
+        .unwrap_or_default()
+}
+
+fn french_message(code: &str) -> Option<&'static str> {
+    let message = match code {
+        "duplicated_card_number" => "Numéro de carte déjà utilisé",
+        "duplicate_merchant_reference" => "Référence marchand déjà utilisée",
+        "outside_accepting_window" => "Paiement hors des horaires acceptés",
+        "card_not_accepted" => "Carte non acceptée",
+        "velocity_limit_exceeded" => "Plafond de paiements de la carte dépassé",
+        "daily_card_limit_exceeded" => "Nombre de paiements quotidiens de la carte dépassé",
+        "negative_amount" => "Montant négatif",
+        "zero_amount" => "Montant nul",
+        "missing_card_number" => "Numéro de carte manquant",
+        "invalid_card_format" => "Format de numéro de carte invalide",
+        "hold_amount_below_amount" => "Montant réservé inférieur au montant",
+        "metadata_too_large" => "Métadonnées trop volumineuses",
+        "invalid_currency" => "Devise invalide",
+        "amount_too_small" => "Montant trop faible",
+        "amount_too_large" => "Montant trop élevé",
+        "invalid_customer_id" => "Identifiant client invalide",
+        "invalid_merchant_reference" => "Référence marchand invalide",
+        "insufficient_funds" => "Fonds insuffisants",
+        "invalid_account_number" => "Numéro de compte invalide",
+        "service_unavailable" => "Service indisponible",
+        "internal_error" => "Erreur interne",
+        "do_not_honor" => "Paiement refusé par l'émetteur",
+        "expired_card" => "Carte expirée",
+        "lost_card" => "Carte perdue",
+        "stolen_card" => "Carte volée",
+        "suspected_fraud" => "Fraude suspectée",
+        "database_unavailable" => "Base de données indisponible",
+        _ => return None,
+    };
+    Some(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn language(accept_language: &str) -> Language {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            ACCEPT_LANGUAGE,
+            HeaderValue::from_str(accept_language).unwrap(),
+        );
+        Language::from_headers(&headers)
+    }
+
+    #[test]
+    fn test_from_headers() {
+        assert_eq!(Language::from_headers(&HeaderMap::new()), Language::English);
+        assert_eq!(language("fr"), Language::French);
+        assert_eq!(language("fr-CA, en;q=0.5"), Language::French);
+        assert_eq!(language("de, fr;q=0.8, en;q=0.5"), Language::French);
+        assert_eq!(language("en;q=0.5, fr;q=0.9"), Language::French);
+        assert_eq!(language("fr;q=0, en"), Language::English);
+        assert_eq!(language("de"), Language::English);
+    }
+
+    #[test]
+    fn test_message() {
+        assert_eq!(
+            Language::English.message("insufficient_funds"),
+            "Insufficient funds"
+        );
+        assert_eq!(
+            Language::French.message("insufficient_funds"),
+            "Fonds insuffisants"
+        );
+        assert_eq!(Language::French.message("unknown_code"), "Unknown code");
+    }
+}
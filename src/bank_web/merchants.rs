@@ -0,0 +1,265 @@
+use axum::{
+    extract::{FromRequestParts, Path, State},
+    http::{header::AUTHORIZATION, request::Parts, StatusCode},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    admin::{constant_time_eq, AdminAuth},
+    BankWeb,
+};
+use crate::bank::merchants::WebhookConfig;
+use crate::bank::{accounts::AccountService, merchants};
+
+/// Longest webhook URL accepted, as stored.
+const MAX_WEBHOOK_URL_LENGTH: usize = 2048;
+/// Longest webhook secret accepted, as stored.
+const MAX_WEBHOOK_SECRET_LENGTH: usize = 255;
+
+/// Extractor of the merchant authenticated by the API key it sends as bearer token, for
+/// the endpoints scoped to the merchant's own payments.
+///
+/// Responds with 401 when the request doesn't carry the API key of a merchant.
+#[derive(Debug, Clone, Copy)]
+pub struct MerchantAuth(pub Uuid);
+
+#[async_trait::async_trait]
+impl<T: AccountService + Clone> FromRequestParts<BankWeb<T>> for MerchantAuth {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        bank_web: &BankWeb<T>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(StatusCode::UNAUTHORIZED)?;
+
+        bank_web
+            .merchant_api_keys
+            .iter()
+            .find(|(_, api_key)| constant_time_eq(token.as_bytes(), api_key.as_bytes()))
+            .map(|(merchant_id, _)| Self(*merchant_id))
+            .ok_or(StatusCode::UNAUTHORIZED)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookRequestData {
+    url: String,
+    secret: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookRequestBody {
+    webhook: WebhookRequestData,
+}
+
+/// The merchant's webhook configuration as responded: its secret is never returned.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct WebhookData {
+    merchant_id: Uuid,
+    url: String,
+}
+
+impl From<WebhookConfig> for WebhookData {
+    fn from(config: WebhookConfig) -> Self {
+        Self {
+            merchant_id: config.merchant_id,
+            url: config.webhook_url,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookBody {
+    data: WebhookData,
+}
+
+/// Sets the URL and signing secret of the merchant's webhooks.
+///
+/// Responds with 422 if either is empty or too long.
+pub async fn put_webhook<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(merchant_id): Path<Uuid>,
+    Json(body): Json<WebhookRequestBody>,
+) -> Result<(StatusCode, Json<WebhookBody>), StatusCode> {
+    let WebhookRequestData { url, secret } = body.webhook;
+    if !(1..=MAX_WEBHOOK_URL_LENGTH).contains(&url.len())
+        || !(1..=MAX_WEBHOOK_SECRET_LENGTH).contains(&secret.len())
+    {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let config = merchants::put_webhook_config(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        merchant_id,
+        &url,
+        &secret,
+    )
+    .await
+    .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(WebhookBody {
+            data: config.into(),
+        }),
+    ))
+}
+
+/// Returns the merchant's webhook configuration, or 404 if it has none.
+pub async fn get_webhook<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(merchant_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<WebhookBody>), StatusCode> {
+    let config = merchants::get_webhook_config(&bank_web.pool, merchant_id)
+        .await
+        .unwrap()
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(WebhookBody {
+            data: config.into(),
+        }),
+    ))
+}
+
+/// Removes the merchant's webhook configuration, or responds with 404 if it has none.
+pub async fn delete_webhook<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(merchant_id): Path<Uuid>,
+) -> StatusCode {
+    if merchants::delete_webhook_config(&bank_web.pool, merchant_id)
+        .await
+        .unwrap()
+    {
+        StatusCode::NO_CONTENT
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank_web::tests::{deserialize_response_body, send_request};
+    use axum::{
+        http::{
+            header::{AUTHORIZATION, CONTENT_TYPE},
+            Method, Request,
+        },
+        response::Response,
+        Router,
+    };
+
+    const ADMIN_TOKEN: &str = "secret";
+
+    async fn send(
+        router: &Router,
+        method: Method,
+        merchant_id: Uuid,
+        body: Option<&WebhookRequestBody>,
+    ) -> Response {
+        let body = body.map_or_else(hyper::Body::empty, |body| {
+            serde_json::to_vec(body).unwrap().into()
+        });
+        let request = Request::builder()
+            .method(method)
+            .uri(format!("/api/admin/merchants/{merchant_id}/webhook"))
+            .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .unwrap();
+        send_request(router, request).await
+    }
+
+    fn webhook(url: &str, secret: &str) -> WebhookRequestBody {
+        WebhookRequestBody {
+            webhook: WebhookRequestData {
+                url: url.into(),
+                secret: secret.into(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn should_configure_merchant_webhook() {
+        let bank_web = BankWeb::new_test().await.with_admin_token(ADMIN_TOKEN);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let merchant_id = Uuid::new_v4();
+
+        let response = send(&router, Method::GET, merchant_id, None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let body = webhook("http://merchant.test/hooks", "whsec");
+        let response = send(&router, Method::PUT, merchant_id, Some(&body)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let expected = WebhookData {
+            merchant_id,
+            url: "http://merchant.test/hooks".into(),
+        };
+        assert_eq!(
+            deserialize_response_body::<WebhookBody>(response)
+                .await
+                .data,
+            expected
+        );
+
+        let response = send(&router, Method::GET, merchant_id, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<WebhookBody>(response)
+                .await
+                .data,
+            expected
+        );
+        let config = merchants::get_webhook_config(&pool, merchant_id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(config.webhook_secret, "whsec");
+
+        let response = send(&router, Method::DELETE, merchant_id, None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        let response = send(&router, Method::DELETE, merchant_id, None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_merchant_webhook() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+
+        let response = send(
+            &router,
+            Method::PUT,
+            Uuid::new_v4(),
+            Some(&webhook("", "whsec")),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = send(
+            &router,
+            Method::PUT,
+            Uuid::new_v4(),
+            Some(&webhook("http://merchant.test/hooks", "")),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
@@ -0,0 +1,63 @@
+use axum::{
+    http::{header::ALLOW, HeaderValue, Request, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+
+/// Lists the methods in the `Allow` header of 405 responses as `GET, HEAD, POST`, where
+/// the router joins them with bare commas.
+///
+/// The router sets the header from the methods routed on the requested path, so `HEAD` is
+/// allowed wherever `GET` is.
+pub async fn list_allowed_methods<B>(request: Request<B>, next: Next<B>) -> Response {
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+    let allowed = response
+        .headers()
+        .get(ALLOW)
+        .and_then(|allow| allow.to_str().ok())
+        .map(|allow| {
+            allow
+                .split(',')
+                .map(str::trim)
+                .filter(|method| !method.is_empty())
+                .collect::<Vec<_>>()
+                .join(", ")
+        });
+    if let Some(allow) = allowed.and_then(|allow| HeaderValue::from_str(&allow).ok()) {
+        response.headers_mut().insert(ALLOW, allow);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank_web::{tests::send_request, BankWeb};
+    use axum::http::Method;
+    use uuid::Uuid;
+
+    async fn send(method: Method, uri: &str) -> Response {
+        let router = BankWeb::new_test().await.into_router();
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(hyper::Body::empty())
+            .unwrap();
+        send_request(&router, request).await
+    }
+
+    #[tokio::test]
+    async fn should_list_allowed_methods_on_method_mismatch() {
+        let uri = format!("/api/payments/{}", Uuid::new_v4());
+        let response = send(Method::DELETE, &uri).await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD");
+
+        let response = send(Method::PUT, "/api/payments").await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+        assert_eq!(response.headers()[ALLOW], "GET, HEAD, POST");
+    }
+}
@@ -1,20 +1,97 @@
-use super::BankWeb;
+use std::net::IpAddr;
+
+use super::{
+    admin::AdminAuth, client_ip::ClientIp, locale::Language, merchants::MerchantAuth, BankWeb,
+};
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
-    Json,
+    body::{Body, StreamBody},
+    extract::{FromRequest, Path, Query, State},
+    http::{
+        header::{ACCEPT, CONTENT_LANGUAGE, CONTENT_TYPE, LOCATION, RETRY_AFTER},
+        HeaderMap, HeaderValue, Request, StatusCode, Uri,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    Form, Json,
 };
+use futures::{SinkExt, Stream, StreamExt};
 use payments::Status;
 use serde::{Deserialize, Serialize};
+use sqlx::{postgres::PgDatabaseError, PgPool};
+use time::OffsetDateTime;
+use tokio::sync::broadcast::{self, error::RecvError};
 use uuid::Uuid;
 
-use crate::bank::payments::{AccountServiceError, CreateError, InvalidArgumentError};
-use crate::bank::{accounts::AccountService, payments};
+use crate::bank::payment_events::{self, PaymentEvent, PaymentEventKind, StatusChange};
+use crate::bank::payments::{
+    AccountServiceError, CaptureError, Channel, CreateError, ErrorClass, ForceFailError,
+    InvalidArgumentError, Metadata, UpdateMetadataError,
+};
+use crate::bank::{
+    accounts::AccountService,
+    clock::to_primitive_utc,
+    config_snapshots::ConfigSnapshot,
+    currencies::{self, ParseAmountError},
+    payments, refunds,
+    retry_budget::RetryBudget,
+    webhooks,
+};
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "RawRequestData")]
 pub struct RequestData {
     pub amount: i32,
+    /// ISO 4217 code, optional for backward compatibility.
+    pub currency: Option<String>,
     pub card_number: String,
+    pub metadata: Metadata,
+    /// The merchant's identifier of the customer, for looking their payments up.
+    pub customer_id: Option<String>,
+    /// The merchant the payment is made to.
+    pub merchant_id: Option<Uuid>,
+    /// The merchant's own reference of the payment, e.g. an order number.
+    pub merchant_reference: Option<String>,
+    /// Where the payment is made from, e.g. a point of sale terminal.
+    pub channel: Option<Channel>,
+}
+
+/// `RequestData` as sent, before its amount is converted to the minor units of its currency.
+#[derive(Deserialize)]
+struct RawRequestData {
+    amount: AmountInput,
+    #[serde(default)]
+    currency: Option<String>,
+    card_number: String,
+    #[serde(default)]
+    metadata: Metadata,
+    #[serde(default)]
+    customer_id: Option<String>,
+    #[serde(default)]
+    merchant_id: Option<Uuid>,
+    #[serde(default)]
+    merchant_reference: Option<String>,
+    #[serde(default)]
+    channel: Option<Channel>,
+}
+
+impl TryFrom<RawRequestData> for RequestData {
+    type Error = String;
+
+    fn try_from(raw: RawRequestData) -> Result<Self, Self::Error> {
+        let currency = raw.currency.as_deref().map(str::to_ascii_uppercase);
+        Ok(Self {
+            amount: raw.amount.into_minor_units(currency.as_deref())?,
+            currency: raw.currency,
+            card_number: raw.card_number,
+            metadata: raw.metadata,
+            customer_id: raw.customer_id,
+            merchant_id: raw.merchant_id,
+            merchant_reference: raw.merchant_reference,
+            channel: raw.channel,
+        })
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -22,12 +99,360 @@ pub struct RequestBody {
     pub payment: RequestData,
 }
 
+/// Amounts with more digits than this are implausible in cents, and more likely to be a card
+/// number sent in the wrong field.
+const MAX_AMOUNT_DIGITS: u32 = 10;
+
+/// An amount as sent, which may be a number of cents or a string.
+///
+/// Strings of digits are cents too, for clients avoiding JSON numbers, while strings with a
+/// decimal point are in major units, e.g. `"12.05"` EUR.
+enum AmountInput {
+    Cents(i64),
+    String(String),
+}
+
+impl<'de> Deserialize<'de> for AmountInput {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct AmountVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for AmountVisitor {
+            type Value = AmountInput;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("an amount in cents")
+            }
+
+            fn visit_i64<E: serde::de::Error>(self, amount: i64) -> Result<AmountInput, E> {
+                Ok(AmountInput::Cents(amount))
+            }
+
+            fn visit_u64<E: serde::de::Error>(self, amount: u64) -> Result<AmountInput, E> {
+                self.visit_i64(i64::try_from(amount).unwrap_or(i64::MAX))
+            }
+
+            fn visit_str<E: serde::de::Error>(self, amount: &str) -> Result<AmountInput, E> {
+                Ok(AmountInput::String(amount.to_owned()))
+            }
+        }
+
+        // form values are strings, and JSON amounts may be numbers or strings
+        deserializer.deserialize_any(AmountVisitor)
+    }
+}
+
+impl AmountInput {
+    /// Converts the amount to the minor units of `currency`, rejecting those that look like
+    /// a card number with an explicit message.
+    fn into_minor_units(self, currency: Option<&str>) -> Result<i32, String> {
+        let amount = match self {
+            Self::Cents(amount) => amount,
+            Self::String(amount) if amount.contains('.') => {
+                currencies::parse_amount(&amount, currency).map_err(|e| match e {
+                    ParseAmountError::Invalid => format!("amount {amount:?} isn't a number"),
+                    ParseAmountError::TooManyDecimals => format!(
+                        "amount {amount:?} has more than the {} decimals of its currency",
+                        currencies::exponent(currency)
+                    ),
+                })?
+            }
+            Self::String(amount) => amount
+                .parse()
+                .map_err(|_| format!("amount {amount:?} isn't a number of cents"))?,
+        };
+        let digits = amount.unsigned_abs().checked_ilog10().unwrap_or(0) + 1;
+        if digits > MAX_AMOUNT_DIGITS {
+            return Err(format!(
+                "amount has {digits} digits, which looks like a card number: \
+                 check that amount and card_number aren't swapped"
+            ));
+        }
+        i32::try_from(amount).map_err(|_| "amount is out of range".to_owned())
+    }
+}
+
+/// Deserializes an amount without currency, i.e. with 2 decimals.
+fn deserialize_amount<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+    AmountInput::deserialize(deserializer)?
+        .into_minor_units(None)
+        .map_err(serde::de::Error::custom)
+}
+
+/// Payment creation request, extracted according to its `Content-Type`.
+///
+/// HTML form integrations post the flat `RequestData` fields as
+/// `application/x-www-form-urlencoded`, any other request is expected to be a
+/// JSON `RequestBody`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentRequest(pub RequestData);
+
+#[async_trait::async_trait]
+impl<S: Send + Sync> FromRequest<S, Body> for PaymentRequest {
+    type Rejection = Response;
+
+    async fn from_request(request: Request<Body>, state: &S) -> Result<Self, Self::Rejection> {
+        let is_form = request
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| {
+                content_type.starts_with("application/x-www-form-urlencoded")
+            });
+
+        if is_form {
+            Form::<RequestData>::from_request(request, state)
+                .await
+                .map(|Form(payment)| Self(payment))
+                .map_err(IntoResponse::into_response)
+        } else {
+            Json::<RequestBody>::from_request(request, state)
+                .await
+                .map(|Json(body)| Self(body.payment))
+                .map_err(IntoResponse::into_response)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuthorizeRequestData {
+    #[serde(deserialize_with = "deserialize_amount")]
+    pub amount: i32,
+    pub card_number: String,
+    /// Amount to hold, when above the authorized `amount`.
+    pub hold_amount: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuthorizeRequestBody {
+    pub payment: AuthorizeRequestData,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct CaptureRequestData {
+    /// Amount to capture, defaulting to the authorized amount.
+    pub amount: Option<i32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct CaptureRequestBody {
+    pub capture: CaptureRequestData,
+}
+
+/// A payment's status as responded, which is its `Status` unless the bank collapses the
+/// statuses of payments that weren't approved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseStatus {
+    Processing,
+    Approved,
+    Declined,
+    Failed,
+    Authorized,
+    Capturing,
+    /// The payment was either declined or failed, for clients not telling them apart.
+    NotApproved,
+}
+
+impl From<Status> for ResponseStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Processing => Self::Processing,
+            Status::Approved => Self::Approved,
+            Status::Declined => Self::Declined,
+            Status::Failed => Self::Failed,
+            Status::Authorized => Self::Authorized,
+            Status::Capturing => Self::Capturing,
+        }
+    }
+}
+
+impl PartialEq<Status> for ResponseStatus {
+    fn eq(&self, status: &Status) -> bool {
+        *self == Self::from(*status)
+    }
+}
+
+impl ResponseStatus {
+    /// Returns `NotApproved` for declined and failed payments, and the status otherwise.
+    pub fn collapsed(self) -> Self {
+        match self {
+            Self::Declined | Self::Failed => Self::NotApproved,
+            status => status,
+        }
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
 pub struct ResponseData {
     pub id: Uuid,
     pub amount: i32,
+    /// The amount in major units of the currency, for display.
+    pub amount_formatted: String,
+    pub hold_amount: i32,
+    pub fee_amount: i32,
+    pub net_amount: i32,
+    pub amount_details: AmountDetails,
     pub card_number: String,
-    pub status: Status,
+    pub status: ResponseStatus,
+    pub decline_reason: Option<String>,
+    pub currency: Option<String>,
+    pub metadata: Metadata,
+    pub customer_id: Option<String>,
+    pub merchant_id: Option<Uuid>,
+    pub merchant_reference: Option<String>,
+    pub channel: Option<Channel>,
+    /// How long the payment took to settle, or `None` if it wasn't created processing to
+    /// be settled later, or isn't settled yet.
+    pub processing_duration_ms: Option<i64>,
+    /// Until when the payment can be refunded, or `None` if it isn't settled or can be
+    /// refunded at any time.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub refundable_until: Option<OffsetDateTime>,
+    /// When the hold of an authorization lapses, unless it's captured or voided first.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub authorized_expires_at: Option<OffsetDateTime>,
+    /// Whether the payment was made live, as opposed to a test payment made in sandbox mode.
+    pub livemode: bool,
+    /// The database error the creation failed with, when database errors are exposed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub database_error: Option<DatabaseErrorData>,
+}
+
+/// The payment's amounts, grouped apart from the other fields of `ResponseData` so that
+/// more can be added without crowding it. The flat `amount`, `fee_amount` and `net_amount`
+/// are the same, and kept for existing clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AmountDetails {
+    pub gross: i32,
+    pub fee: i32,
+    /// What the merchant is paid: `gross - fee`.
+    pub net: i32,
+    pub refunded: i32,
+    /// What remains to refund, short of over-refunds: `gross - refunded` once the payment
+    /// is approved, and 0 otherwise.
+    pub refundable: i32,
+}
+
+impl From<&payments::Payment> for AmountDetails {
+    fn from(payment: &payments::Payment) -> Self {
+        Self {
+            gross: payment.amount,
+            fee: payment.fee_amount,
+            net: payment.net_amount,
+            refunded: payment.refunded_amount,
+            refundable: if payment.status == Status::Approved {
+                payment.amount - payment.refunded_amount
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// A database error as responded outside production, for debugging.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct DatabaseErrorData {
+    /// The SQLSTATE of the error, if postgres rejected the query.
+    pub code: Option<String>,
+    /// What postgres detailed, or the error's message, with card numbers masked.
+    pub detail: String,
+}
+
+impl From<&sqlx::Error> for DatabaseErrorData {
+    fn from(e: &sqlx::Error) -> Self {
+        let (code, detail) = match e {
+            sqlx::Error::Database(err) => (
+                err.code().map(|code| code.into_owned()),
+                err.try_downcast_ref::<PgDatabaseError>()
+                    .and_then(PgDatabaseError::detail)
+                    .unwrap_or_else(|| err.message())
+                    .to_owned(),
+            ),
+            err => (None, err.to_string()),
+        };
+        Self {
+            code,
+            detail: payments::mask_card_numbers(&detail),
+        }
+    }
+}
+
+impl From<payments::Payment> for ResponseData {
+    fn from(payment: payments::Payment) -> Self {
+        Self {
+            amount_details: AmountDetails::from(&payment),
+            id: payment.id,
+            amount: payment.amount,
+            amount_formatted: currencies::format_amount(
+                payment.amount,
+                payment.currency.as_deref(),
+            ),
+            hold_amount: payment.hold_amount,
+            fee_amount: payment.fee_amount,
+            net_amount: payment.net_amount,
+            card_number: payment.card_number,
+            status: payment.status.into(),
+            decline_reason: payment.decline_reason,
+            currency: payment.currency,
+            metadata: payment.metadata.0,
+            customer_id: payment.customer_id,
+            merchant_id: payment.merchant_id,
+            merchant_reference: payment.merchant_reference,
+            channel: payment.channel,
+            processing_duration_ms: payment
+                .settled_at
+                .map(|settled_at| (settled_at - payment.inserted_at).whole_milliseconds() as i64),
+            refundable_until: None,
+            authorized_expires_at: payment
+                .authorized_expires_at
+                .map(|expires_at| expires_at.assume_utc()),
+            livemode: payment.livemode,
+            database_error: None,
+        }
+    }
+}
+
+/// Returns the payment as responded and notified, with its card number masked unless
+/// masking is disabled, and its status collapsed if the bank collapses them.
+fn response_data<T>(bank_web: &BankWeb<T>, payment: payments::Payment) -> ResponseData {
+    let data = admin_response_data(bank_web, payment);
+    ResponseData {
+        status: collapse_status(bank_web, data.status),
+        ..data
+    }
+}
+
+/// Returns the payment as responded to admins, whose actual status is never collapsed.
+fn admin_response_data<T>(bank_web: &BankWeb<T>, payment: payments::Payment) -> ResponseData {
+    let refundable_until = bank_web
+        .refund_window
+        .and_then(|window| refunds::refundable_until(&payment, window))
+        .map(|refundable_until| refundable_until.assume_utc());
+    mask_response_data(
+        bank_web,
+        ResponseData {
+            refundable_until,
+            ..payment.into()
+        },
+    )
+}
+
+/// Responds declined and failed payments as `NotApproved` when the bank collapses them.
+fn collapse_status<T>(bank_web: &BankWeb<T>, status: ResponseStatus) -> ResponseStatus {
+    if bank_web.collapse_not_approved {
+        status.collapsed()
+    } else {
+        status
+    }
+}
+
+fn mask_response_data<T>(bank_web: &BankWeb<T>, mut data: ResponseData) -> ResponseData {
+    if bank_web.mask_pan {
+        data.card_number =
+            payments::mask_card_number(&data.card_number, bank_web.card_number_display);
+    }
+    data
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
@@ -35,22 +460,195 @@ pub struct ResponseBody {
     pub data: ResponseData,
 }
 
-fn status_from_error(e: CreateError) -> (StatusCode, Status) {
+#[derive(Debug, Clone, PartialEq, Eq, Default, Deserialize, Serialize)]
+pub struct ExportParams {
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub from: Option<OffsetDateTime>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub to: Option<OffsetDateTime>,
+    /// Resume cursor: only payments strictly after this one are exported.
+    pub after_id: Option<Uuid>,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub after_inserted_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct MetadataRequestBody {
+    pub metadata: Metadata,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StatusLookupRequestBody {
+    pub ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StatusLookupData {
+    pub id: Uuid,
+    /// `None` for unknown payments.
+    pub status: Option<Status>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct StatusLookupResponseBody {
+    pub statuses: Vec<StatusLookupData>,
+}
+
+/// Maximum number of payments looked up by a single status lookup.
+pub const MAX_STATUS_LOOKUP_IDS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BatchRequestBody {
+    pub payments: Vec<RequestData>,
+}
+
+/// Why a batch item failed, named like the problem types of `post`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ErrorBody {
+    pub error: String,
+}
+
+/// The result of a batch item: `status` is the one `post` would respond, but 422 for zero
+/// amounts.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BatchResultData {
+    pub status: u16,
+    /// The payment, unless it failed before being persisted.
+    pub data: Option<ResponseData>,
+    pub error: Option<ErrorBody>,
+}
+
+impl BatchResultData {
+    fn new(status_code: StatusCode, data: ResponseData) -> Self {
+        if status_code == StatusCode::NO_CONTENT {
+            return Self {
+                status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+                data: None,
+                error: Some(ErrorBody {
+                    error: "zero_amount".to_owned(),
+                }),
+            };
+        }
+        let error =
+            (status_code.is_client_error() || status_code.is_server_error()).then(|| ErrorBody {
+                error: error_code(status_code, &data).to_owned(),
+            });
+        Self {
+            status: status_code.as_u16(),
+            data: (!data.id.is_nil()).then_some(data),
+            error,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct BatchResponseBody {
+    pub results: Vec<BatchResultData>,
+}
+
+/// Maximum number of payments created by a single batch.
+pub const MAX_BATCH_PAYMENTS: usize = 100;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct EventData {
+    pub kind: PaymentEventKind,
+    pub status: Status,
+    pub amount: i32,
+    pub reason: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub inserted_at: OffsetDateTime,
+}
+
+/// A payment's status, as streamed by `stream_events`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct StatusEventData {
+    pub id: Uuid,
+    pub status: Status,
+}
+
+impl From<PaymentEvent> for EventData {
+    fn from(event: PaymentEvent) -> Self {
+        Self {
+            kind: event.kind,
+            status: event.status,
+            amount: event.amount,
+            reason: event.reason,
+            inserted_at: event.inserted_at.assume_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EventsBody {
+    pub data: Vec<EventData>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConfigSnapshotBody {
+    pub data: ConfigSnapshot,
+}
+
+/// Number of exported payments buffered ahead of the client.
+const EXPORT_BUFFER_SIZE: usize = 64;
+
+impl AccountServiceError {
+    /// Returns the status responded for a payment the account service rejected with this error.
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            Self::InsufficientFunds => StatusCode::PAYMENT_REQUIRED,
+            Self::InvalidAccountNumber => StatusCode::FORBIDDEN,
+            Self::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
+            Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::DoNotHonor | Self::ExpiredCard => StatusCode::PAYMENT_REQUIRED,
+            Self::LostCard | Self::StolenCard | Self::SuspectedFraud => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl InvalidArgumentError {
+    /// Returns the status responded for a request rejected with this error.
+    pub fn http_status(&self) -> StatusCode {
+        match self {
+            Self::NegativeAmount => StatusCode::BAD_REQUEST,
+            Self::ZeroAmount => StatusCode::NO_CONTENT,
+            Self::MissingCardNumber => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InvalidCardFormat => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::HoldAmountBelowAmount => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::MetadataTooLarge => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InvalidCurrency => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::UnsupportedCurrency => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::AmountTooSmall => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::AmountTooLarge => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InvalidCustomerId => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::InvalidMerchantReference => StatusCode::UNPROCESSABLE_ENTITY,
+        }
+    }
+}
+
+fn status_from_error(e: &CreateError) -> (StatusCode, Status) {
     let status_code = match e {
         CreateError::DuplicatedCardNumber => StatusCode::UNPROCESSABLE_ENTITY,
-        CreateError::InvalidArgument(err) => match err {
-            InvalidArgumentError::NegativeAmount => StatusCode::BAD_REQUEST,
-            InvalidArgumentError::ZeroAmount => StatusCode::NO_CONTENT,
-            InvalidArgumentError::InvalidCardFormat => StatusCode::UNPROCESSABLE_ENTITY,
-        },
-        CreateError::AccountService(err) => match err {
-            AccountServiceError::InsufficientFunds => StatusCode::PAYMENT_REQUIRED,
-            AccountServiceError::InvalidAccountNumber => StatusCode::FORBIDDEN,
-            AccountServiceError::ServiceUnavailable => StatusCode::SERVICE_UNAVAILABLE,
-            AccountServiceError::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
-        },
-        CreateError::Database(err) => panic!("Database error: {:?}", err),
+        CreateError::DuplicateMerchantReference => StatusCode::CONFLICT,
+        CreateError::OutsideAcceptingWindow => StatusCode::FORBIDDEN,
+        CreateError::CardNotAccepted => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::VelocityLimitExceeded => StatusCode::FORBIDDEN,
+        CreateError::DailyCardLimitExceeded => StatusCode::TOO_MANY_REQUESTS,
+        CreateError::InvalidArgument(err) => err.http_status(),
+        CreateError::AccountService(err, _) => err.http_status(),
+        CreateError::Queued(_) => return (StatusCode::ACCEPTED, Status::Processing),
+        CreateError::Vault(_) => StatusCode::SERVICE_UNAVAILABLE,
+        CreateError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        CreateError::Database(err)
+            if payments::is_too_many_connections(err)
+                || payments::is_serialization_failure(err) =>
+        {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        CreateError::Database(_) => StatusCode::INTERNAL_SERVER_ERROR,
     };
+    log_error(e);
     let status = if status_code.is_server_error() {
         Status::Failed
     } else {
@@ -59,252 +657,4838 @@ fn status_from_error(e: CreateError) -> (StatusCode, Status) {
     (status_code, status)
 }
 
-pub async fn post<T: AccountService>(
+/// Logs client errors as warnings and server ones as errors, tagged with their class so that
+/// they can be counted apart.
+fn log_error(e: &CreateError) {
+    // the payment of account service errors holds the card number, so it's left out
+    let error = match e {
+        CreateError::Vault(msg) | CreateError::Internal(msg) => msg.clone(),
+        CreateError::Database(err) => format!("{err:?}"),
+        e => e.decline_reason().unwrap_or_default(),
+    };
+    let class = e.class();
+    match class {
+        ErrorClass::ClientError => {
+            tracing::warn!(error = %error, error_class = %class, "payment declined")
+        }
+        ErrorClass::ServerError => {
+            tracing::error!(error = %error, error_class = %class, "failed to create payment")
+        }
+    }
+}
+
+/// Creates and approves a payment, or leaves it processing when settlement is delayed.
+///
+/// Responds with `RETRY_COUNT_HEADER` when holds are retried.
+///
+/// Created payments are responded with their `Location`, and without a body to clients
+/// sending `Prefer: return=minimal`.
+///
+/// Responds with the payment already made and 200 instead when a merchant opted in to
+/// implicit idempotency repeats it within the window.
+///
+/// Failures are responded as problem details to clients accepting `PROBLEM_JSON`, and as
+/// the failed payment otherwise.
+pub async fn post<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    ClientIp(client_ip): ClientIp,
+    request_headers: HeaderMap,
+    uri: Uri,
+    PaymentRequest(payment): PaymentRequest,
+) -> Response {
+    let response = create_payment(bank_web, client_ip, payment).await;
+    if response.0 == StatusCode::CREATED {
+        let location = format!("/api/payments/{}", response.2.data.id);
+        return respond_created(&request_headers, location, response);
+    }
+    problem_or_payment(&request_headers, &uri, response)
+}
+
+/// Creates the requested `payment` like `post`, answering with the response to convert.
+async fn create_payment<T: AccountService + Clone>(
+    bank_web: BankWeb<T>,
+    client_ip: Option<IpAddr>,
+    payment: RequestData,
+) -> (StatusCode, HeaderMap, Json<ResponseBody>) {
+    let payment = RequestData {
+        currency: payment
+            .currency
+            .or_else(|| bank_web.default_currency.clone()),
+        ..payment
+    };
+    match find_repeated(&bank_web, &payment).await {
+        Ok(Some(repeated)) => {
+            let data = response_data(&bank_web, repeated);
+            return (
+                StatusCode::OK,
+                HeaderMap::new(),
+                Json(ResponseBody { data }),
+            );
+        }
+        Ok(None) => {}
+        Err(e) => {
+            return respond_to_create(&bank_web, Err(CreateError::Database(e)), payment).await;
+        }
+    }
+    let checks = check_accepting_window(&bank_web)
+        .and_then(|()| check_test_card(&bank_web, &payment.card_number));
+    let checks = match checks {
+        Ok(()) => check_velocity(&bank_web, &payment.card_number, payment.amount).await,
+        checks => checks,
+    };
+    let checks = match checks {
+        Ok(()) => check_daily_card_limit(&bank_web, &payment.card_number).await,
+        checks => checks,
+    };
+    let checks = match checks {
+        Ok(()) => check_rotated_fingerprint(&bank_web, &payment.card_number).await,
+        checks => checks,
+    };
+    let mut retry_budget = RetryBudget::new(bank_web.hold_retries);
+    let result = match checks {
+        Err(e) => Err(e),
+        Ok(()) => {
+            payments::create(
+                &bank_web.pool,
+                &bank_web.account_service,
+                bank_web.clock.as_ref(),
+                &bank_web.card_fingerprint_keys.current(),
+                bank_web.card_vault.as_ref(),
+                &bank_web.fees,
+                bank_web.balance_precheck,
+                &bank_web.amount_bounds,
+                &bank_web.merchant_currencies,
+                &bank_web.card_limits(),
+                bank_web.sandbox.then_some(&bank_web.magic_amounts),
+                bank_web.payment_queue.as_ref(),
+                &mut retry_budget,
+                !bank_web.sandbox,
+                bank_web.demote_duplicate_cards,
+                payment.amount,
+                payment.currency.as_deref(),
+                payment.card_number.as_str(),
+                if bank_web.delays_settlement() {
+                    Status::Processing
+                } else {
+                    Status::Approved
+                },
+                payment.metadata.clone(),
+                client_ip,
+                payment.customer_id.as_deref(),
+                payment.merchant_id,
+                payment.merchant_reference.as_deref(),
+                payment.channel,
+            )
+            .await
+        }
+    };
+
+    let mut response = respond_to_create(&bank_web, result, payment).await;
+    if bank_web.hold_retries > 0 {
+        response
+            .1
+            .insert(RETRY_COUNT_HEADER, retry_budget.retries().into());
+    }
+    response
+}
+
+/// Creates each of the requested payments in turn, like `post`, answering with the result
+/// of each in the requested order.
+///
+/// Zero amounts, which `post` answers with 204, are reported as items failed with 422 and
+/// the `zero_amount` error. Creating more than `MAX_BATCH_PAYMENTS` payments at once is
+/// rejected with 422.
+pub async fn batch<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    ClientIp(client_ip): ClientIp,
+    Json(body): Json<BatchRequestBody>,
+) -> Result<(StatusCode, Json<BatchResponseBody>), StatusCode> {
+    if body.payments.len() > MAX_BATCH_PAYMENTS {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let mut results = Vec::with_capacity(body.payments.len());
+    for payment in body.payments {
+        let (status_code, _, Json(body)) =
+            create_payment(bank_web.clone(), client_ip, payment).await;
+        results.push(BatchResultData::new(status_code, body.data));
+    }
+
+    Ok((StatusCode::OK, Json(BatchResponseBody { results })))
+}
+
+/// A reason a payment request would be rejected.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationError {
+    /// The invalid field of the request, if the reason is about a single one.
+    pub field: Option<String>,
+    /// The decline reason the payment would be responded with.
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValidationBody {
+    pub errors: Vec<ValidationError>,
+}
+
+/// Validates a payment request without creating the payment, responding with every reason it
+/// would be rejected for rather than the first one, for forms to show them at once.
+///
+/// The request is checked like by `post`, bar the account service, the card's duplicates and
+/// implicit idempotency: the velocity and daily card limits are estimated from the payments
+/// made so far. Responds with 200 and no errors if the payment would be accepted, and 422
+/// with the errors otherwise.
+pub async fn validate<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    PaymentRequest(payment): PaymentRequest,
+) -> Result<(StatusCode, Json<ValidationBody>), StatusCode> {
+    let currency = payment
+        .currency
+        .as_deref()
+        .or(bank_web.default_currency.as_deref());
+    let mut errors: Vec<_> = payments::validate_all(
+        payment.amount,
+        currency,
+        payment.merchant_id,
+        &bank_web.merchant_currencies,
+        &bank_web.amount_bounds,
+        &payment.card_number,
+        &payment.metadata,
+        payment.customer_id.as_deref(),
+        payment.merchant_reference.as_deref(),
+    )
+    .into_iter()
+    .map(|err| ValidationError {
+        field: Some(err.field().to_owned()),
+        reason: err.to_string(),
+    })
+    .collect();
+    let checks = [
+        check_accepting_window(&bank_web),
+        check_test_card(&bank_web, &payment.card_number),
+        check_velocity(&bank_web, &payment.card_number, payment.amount).await,
+        check_daily_card_limit(&bank_web, &payment.card_number).await,
+    ];
+    for check in checks {
+        match check {
+            Ok(()) => {}
+            Err(CreateError::Database(err)) => {
+                tracing::error!(error = ?err, "failed to validate payment");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+            Err(e) => errors.push(ValidationError {
+                field: matches!(e, CreateError::CardNotAccepted).then(|| "card_number".into()),
+                reason: e.decline_reason().unwrap_or_default(),
+            }),
+        }
+    }
+
+    let status_code = if errors.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::UNPROCESSABLE_ENTITY
+    };
+    Ok((status_code, Json(ValidationBody { errors })))
+}
+
+/// Authorizes a payment, holding `hold_amount` (defaulting to `amount`) until it is captured.
+///
+/// Failures are responded like by `post`.
+pub async fn authorize<T: AccountService + Clone>(
     State(bank_web): State<BankWeb<T>>,
-    Json(body): Json<RequestBody>,
-) -> (StatusCode, Json<ResponseBody>) {
-    let payment_amount = body.payment.amount;
-    let payment_card_number = body.payment.card_number.as_str();
-    payments::create(
+    ClientIp(client_ip): ClientIp,
+    request_headers: HeaderMap,
+    uri: Uri,
+    Json(body): Json<AuthorizeRequestBody>,
+) -> Response {
+    let payment = body.payment;
+    let checks = check_accepting_window(&bank_web)
+        .and_then(|()| check_test_card(&bank_web, &payment.card_number));
+    let checks = match checks {
+        Ok(()) => check_velocity(&bank_web, &payment.card_number, payment.amount).await,
+        checks => checks,
+    };
+    let checks = match checks {
+        Ok(()) => check_daily_card_limit(&bank_web, &payment.card_number).await,
+        checks => checks,
+    };
+    let checks = match checks {
+        Ok(()) => check_rotated_fingerprint(&bank_web, &payment.card_number).await,
+        checks => checks,
+    };
+    let result = match checks {
+        Err(e) => Err(e),
+        Ok(()) => {
+            payments::authorize(
+                &bank_web.pool,
+                &bank_web.account_service,
+                bank_web.clock.as_ref(),
+                bank_web.balance_precheck,
+                &bank_web.card_fingerprint_keys.current(),
+                payment.amount,
+                payment.hold_amount,
+                payment.card_number.as_str(),
+                client_ip,
+                !bank_web.sandbox,
+            )
+            .await
+        }
+    };
+
+    let payment = RequestData {
+        amount: payment.amount,
+        currency: None,
+        card_number: payment.card_number,
+        metadata: Metadata::new(),
+        customer_id: None,
+        merchant_id: None,
+        merchant_reference: None,
+        channel: None,
+    };
+    let response = respond_to_create(&bank_web, result, payment).await;
+    problem_or_payment(&request_headers, &uri, response)
+}
+
+/// Rejects payments made outside the accepting window, when one is configured.
+fn check_accepting_window<T>(bank_web: &BankWeb<T>) -> Result<(), CreateError> {
+    match &bank_web.accepting_window {
+        Some(window) if !window.contains(bank_web.clock.now()) => {
+            Err(CreateError::OutsideAcceptingWindow)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Rejects test cards outside sandbox mode. Invalid card numbers are left to the payment's
+/// own validation.
+fn check_test_card<T>(bank_web: &BankWeb<T>, card_number: &str) -> Result<(), CreateError> {
+    match payments::validate_card_number(card_number) {
+        Ok(card_number) if !bank_web.sandbox && bank_web.test_card_bins.contains(&card_number) => {
+            Err(CreateError::CardNotAccepted)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Rejects payments that would push the amount approved for the card within the velocity
+/// limit's window over its max, when one is configured.
+async fn check_velocity<T>(
+    bank_web: &BankWeb<T>,
+    card_number: &str,
+    amount: i32,
+) -> Result<(), CreateError> {
+    let (Some(limit), Ok(card_number)) = (
+        &bank_web.velocity_limit,
+        payments::validate_card_number(card_number),
+    ) else {
+        return Ok(());
+    };
+    let since = bank_web.clock.now_utc() - limit.window;
+    let approved_amount = payments::approved_amount(
         &bank_web.pool,
-        &bank_web.account_service,
-        payment_amount,
-        payment_card_number,
-        Status::Approved,
+        &bank_web.card_fingerprint_keys.fingerprints(&card_number),
+        &card_number,
+        since,
     )
     .await
-    .map_or_else(
-        |e| {
-            let (payment_status_code, payment_status) = status_from_error(e);
-            (
-                payment_status_code,
-                Json(ResponseBody {
-                    data: ResponseData {
-                        id: Uuid::nil(),
-                        amount: payment_amount,
-                        card_number: payment_card_number.to_string(),
-                        status: payment_status,
-                    },
-                }),
-            )
-        },
-        |payment| {
-            (
-                StatusCode::CREATED,
-                Json(ResponseBody {
-                    data: ResponseData {
-                        id: payment.id,
-                        amount: payment.amount,
-                        card_number: payment.card_number,
-                        status: payment.status,
-                    },
-                }),
-            )
+    .map_err(CreateError::Database)?;
+    if limit.allows(approved_amount, amount) {
+        Ok(())
+    } else {
+        Err(CreateError::VelocityLimitExceeded)
+    }
+}
+
+/// Rejects payments on a card which already made the daily card limit's number of payments
+/// since midnight UTC, as told by the bank's clock, when one is configured.
+async fn check_daily_card_limit<T>(
+    bank_web: &BankWeb<T>,
+    card_number: &str,
+) -> Result<(), CreateError> {
+    let (Some(limit), Ok(card_number)) = (
+        bank_web.daily_card_limit,
+        payments::validate_card_number(card_number),
+    ) else {
+        return Ok(());
+    };
+    let since = bank_web.clock.now_utc().date().midnight();
+    let count = payments::count_held(
+        &bank_web.pool,
+        &bank_web.card_fingerprint_keys.fingerprints(&card_number),
+        &card_number,
+        since,
+    )
+    .await
+    .map_err(CreateError::Database)?;
+    if count < i64::from(limit) {
+        Ok(())
+    } else {
+        Err(CreateError::DailyCardLimitExceeded)
+    }
+}
+
+/// Returns the payment made with the same card, amount and merchant reference within the
+/// implicit idempotency window, when the payment's merchant opted in.
+async fn find_repeated<T>(
+    bank_web: &BankWeb<T>,
+    payment: &RequestData,
+) -> Result<Option<payments::Payment>, sqlx::Error> {
+    let (Some(idempotency), Some(merchant_id), Some(merchant_reference), Ok(card_number)) = (
+        &bank_web.implicit_idempotency,
+        payment.merchant_id,
+        payment.merchant_reference.as_deref(),
+        payments::validate_card_number(&payment.card_number),
+    ) else {
+        return Ok(None);
+    };
+    if !idempotency.applies_to(merchant_id) {
+        return Ok(None);
+    }
+    payments::find_repeated(
+        &bank_web.pool,
+        &bank_web.card_fingerprint_keys.fingerprints(&card_number),
+        payment.amount,
+        merchant_id,
+        merchant_reference,
+        bank_web.clock.now_utc() - idempotency.window,
+    )
+    .await
+}
+
+/// Rejects duplicated cards whose payments were fingerprinted with the previous key, while
+/// it's being rotated: the unique index only catches those made with the current one.
+///
+/// Duplicated cards are let through when they're demoted.
+async fn check_rotated_fingerprint<T>(
+    bank_web: &BankWeb<T>,
+    card_number: &str,
+) -> Result<(), CreateError> {
+    if bank_web.demote_duplicate_cards {
+        return Ok(());
+    }
+    let Ok(card_number) = payments::validate_card_number(card_number) else {
+        return Ok(());
+    };
+    let previous_fingerprints = &bank_web.card_fingerprint_keys.fingerprints(&card_number)[1..];
+    if previous_fingerprints.is_empty() {
+        return Ok(());
+    }
+    if payments::is_card_fingerprint_in_use(&bank_web.pool, previous_fingerprints)
+        .await
+        .map_err(CreateError::Database)?
+    {
+        Err(CreateError::DuplicatedCardNumber)
+    } else {
+        Ok(())
+    }
+}
+
+/// Media type of RFC 7807 problem details, which clients accepting it get instead of the
+/// failed payment when its creation fails.
+pub const PROBLEM_JSON: &str = "application/problem+json";
+
+/// RFC 7807 problem details of a failed payment creation.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Problem {
+    /// `/problems/` followed by the payment's decline reason, e.g.
+    /// `/problems/insufficient_funds`: clients may rely on it staying the same.
+    #[serde(rename = "type")]
+    pub problem_type: String,
+    pub title: String,
+    pub status: u16,
+    /// Localized in the language the client accepts, unlike the type.
+    pub detail: String,
+    /// The declined payment when it was persisted, and the request's path otherwise.
+    pub instance: String,
+}
+
+/// Returns the code of the failed payment creation responded with `status_code`.
+fn error_code(status_code: StatusCode, data: &ResponseData) -> &str {
+    data.decline_reason.as_deref().unwrap_or(
+        // the errors not recorded as decline reasons are those of the bank itself
+        if status_code == StatusCode::SERVICE_UNAVAILABLE {
+            "database_unavailable"
+        } else {
+            "internal_error"
         },
     )
 }
 
-pub async fn get<T: AccountService>(
-    State(bank_web): State<BankWeb<T>>,
-    Path(payment_id): Path<Uuid>,
-) -> (StatusCode, Json<ResponseBody>) {
-    let payment = payments::get(&bank_web.pool, payment_id).await.unwrap();
+impl Problem {
+    fn new(status_code: StatusCode, data: &ResponseData, uri: &Uri, language: Language) -> Self {
+        let code = error_code(status_code, data);
+        let instance = if data.id.is_nil() {
+            uri.path().to_owned()
+        } else {
+            format!("/api/payments/{}", data.id)
+        };
+        Self {
+            problem_type: format!("/problems/{code}"),
+            title: status_code
+                .canonical_reason()
+                .unwrap_or_default()
+                .to_owned(),
+            status: status_code.as_u16(),
+            detail: language.message(code),
+            instance,
+        }
+    }
+}
+
+fn accepts_problem_json(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(ACCEPT)
+        .iter()
+        .filter_map(|accept| accept.to_str().ok())
+        .flat_map(|accept| accept.split(','))
+        .any(|media_type| {
+            media_type
+                .split(';')
+                .next()
+                .is_some_and(|media_type| media_type.trim().eq_ignore_ascii_case(PROBLEM_JSON))
+        })
+}
+
+/// Converts the response to a payment creation into problem details if it failed and the
+/// client accepts them, in the language it prefers.
+fn problem_or_payment(
+    request_headers: &HeaderMap,
+    uri: &Uri,
+    (status_code, mut headers, Json(body)): (StatusCode, HeaderMap, Json<ResponseBody>),
+) -> Response {
+    if !(status_code.is_client_error() || status_code.is_server_error())
+        || !accepts_problem_json(request_headers)
+    {
+        return (status_code, headers, Json(body)).into_response();
+    }
+
+    let language = Language::from_headers(request_headers);
+    let problem = Problem::new(status_code, &body.data, uri, language);
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static(PROBLEM_JSON));
+    headers.insert(CONTENT_LANGUAGE, HeaderValue::from_static(language.tag()));
+    let problem = serde_json::to_vec(&problem).expect("failed to serialize problem");
+    (status_code, headers, problem).into_response()
+}
+
+/// Header of the RFC 7240 preferences of the client, e.g. `return=minimal`.
+pub const PREFER_HEADER: &str = "prefer";
+/// Header telling which of the client's preferences were honored.
+pub const PREFERENCE_APPLIED_HEADER: &str = "preference-applied";
+const RETURN_MINIMAL: &str = "return=minimal";
+
+/// Whether the client prefers not to be responded the resources it creates, with
+/// `Prefer: return=minimal`. Other preferences, like `return=representation`, are ignored.
+fn prefers_minimal_return(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(PREFER_HEADER)
+        .iter()
+        .filter_map(|prefer| prefer.to_str().ok())
+        .flat_map(|prefer| prefer.split(','))
+        .any(|preference| {
+            preference.split(';').next().is_some_and(|preference| {
+                let preference: String = preference.split_whitespace().collect();
+                preference.eq_ignore_ascii_case(RETURN_MINIMAL)
+            })
+        })
+}
+
+/// Responds the resource created at `location`, without its body if the client prefers a
+/// minimal return.
+pub fn respond_created(
+    request_headers: &HeaderMap,
+    location: String,
+    (status_code, mut headers, body): (StatusCode, HeaderMap, impl IntoResponse),
+) -> Response {
+    headers.insert(
+        LOCATION,
+        HeaderValue::try_from(location).expect("invalid location"),
+    );
+    if prefers_minimal_return(request_headers) {
+        headers.insert(
+            PREFERENCE_APPLIED_HEADER,
+            HeaderValue::from_static(RETURN_MINIMAL),
+        );
+        return (status_code, headers).into_response();
+    }
+    (status_code, headers, body).into_response()
+}
+
+/// Header telling how many times the hold of a created payment was retried, when holds are.
+pub const RETRY_COUNT_HEADER: &str = "x-retry-count";
+
+/// Seconds after which clients are told to retry when postgres is out of connections.
+pub const DATABASE_RETRY_AFTER_SECONDS: u32 = 1;
+
+/// Returns the headers of a response to a request that failed with the database error `e`:
+/// `Retry-After` if postgres was out of connections or the insertion kept conflicting with
+/// concurrent ones, which is transient.
+pub fn database_error_headers(e: &sqlx::Error) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if payments::is_too_many_connections(e) || payments::is_serialization_failure(e) {
+        headers.insert(RETRY_AFTER, DATABASE_RETRY_AFTER_SECONDS.into());
+    }
+    headers
+}
+
+/// Builds the response to the creation of the requested `payment`, notifying persisted payments.
+async fn respond_to_create<T: AccountService + Clone>(
+    bank_web: &BankWeb<T>,
+    result: Result<payments::Payment, CreateError>,
+    payment: RequestData,
+) -> (StatusCode, HeaderMap, Json<ResponseBody>) {
+    let (headers, database_error) = match &result {
+        Err(CreateError::Database(e)) => (
+            database_error_headers(e),
+            bank_web
+                .expose_database_errors
+                .then(|| DatabaseErrorData::from(e)),
+        ),
+        _ => (HeaderMap::new(), None),
+    };
+    // queued payments aren't persisted yet
+    let queued = matches!(result, Err(CreateError::Queued(_)));
+    let (status_code, body) = result.map_or_else(
+        |e| {
+            let (payment_status_code, payment_status) = status_from_error(&e);
+            let decline_reason = e.decline_reason();
+            let data = match e {
+                CreateError::AccountService(_, payment) | CreateError::Queued(payment) => {
+                    response_data(bank_web, *payment)
+                }
+                _ => mask_response_data(
+                    bank_web,
+                    ResponseData {
+                        id: Uuid::nil(),
+                        amount: payment.amount,
+                        amount_formatted: currencies::format_amount(
+                            payment.amount,
+                            payment.currency.as_deref(),
+                        ),
+                        hold_amount: payment.amount,
+                        fee_amount: 0,
+                        net_amount: payment.amount,
+                        amount_details: AmountDetails {
+                            gross: payment.amount,
+                            fee: 0,
+                            net: payment.amount,
+                            refunded: 0,
+                            refundable: 0,
+                        },
+                        card_number: payment.card_number,
+                        status: collapse_status(bank_web, payment_status.into()),
+                        decline_reason,
+                        currency: payment.currency,
+                        metadata: payment.metadata,
+                        customer_id: payment.customer_id,
+                        merchant_id: payment.merchant_id,
+                        merchant_reference: payment.merchant_reference,
+                        channel: payment.channel,
+                        processing_duration_ms: None,
+                        refundable_until: None,
+                        authorized_expires_at: None,
+                        livemode: !bank_web.sandbox,
+                        database_error,
+                    },
+                ),
+            };
+            (payment_status_code, Json(ResponseBody { data }))
+        },
+        |payment| {
+            (
+                StatusCode::CREATED,
+                Json(ResponseBody {
+                    data: response_data(bank_web, payment),
+                }),
+            )
+        },
+    );
+
+    // only persisted payments are notified
+    if !body.data.id.is_nil() && !queued {
+        notify(bank_web, PAYMENT_CREATED_EVENT, &body.data).await;
+    }
+
+    (status_code, headers, body)
+}
+
+/// Captures an authorized payment, at most once per `Idempotency-Key` header when one is
+/// sent: retrying the capture with the same key responds with the captured payment.
+///
+/// Responds with 404 if the payment doesn't exist, 409 if it isn't awaiting capture (or is
+/// being captured for another amount), and 422 if the amount exceeds the hold or the key
+/// was already used for another amount.
+pub async fn capture<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+    headers: HeaderMap,
+    body: Option<Json<CaptureRequestBody>>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    let idempotency_key = super::refunds::idempotency_key(&headers)?;
+    let amount = body.and_then(|Json(body)| body.capture.amount);
+    let capture = payments::capture(
+        &bank_web.pool,
+        &bank_web.account_service,
+        bank_web.clock.as_ref(),
+        &bank_web.fees,
+        payment_id,
+        amount,
+        idempotency_key,
+    )
+    .await
+    .map_err(|e| match e {
+        CaptureError::PaymentNotFound => StatusCode::NOT_FOUND,
+        CaptureError::NotAuthorized => StatusCode::CONFLICT,
+        CaptureError::ExcessiveAmount | CaptureError::IdempotencyKeyReused => {
+            StatusCode::UNPROCESSABLE_ENTITY
+        }
+        // same as when creating a payment with that amount
+        CaptureError::InvalidArgument(err) => err.http_status(),
+        CaptureError::AccountService(err) => err.http_status(),
+        CaptureError::Internal(msg) => {
+            tracing::error!(error = %msg, "failed to capture payment");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        CaptureError::Database(err) => panic!("Database error: {:?}", err),
+    })?;
+
+    if capture.retried {
+        let data = response_data(&bank_web, capture.payment);
+        return Ok((StatusCode::OK, Json(ResponseBody { data })));
+    }
+    let payment = capture.payment;
+    bank_web.status_changes.publish(payment.id, payment.status);
+    let data = response_data(&bank_web, payment);
+    notify(&bank_web, PAYMENT_CAPTURED_EVENT, &data).await;
+
+    Ok((StatusCode::OK, Json(ResponseBody { data })))
+}
+
+pub const PAYMENT_CREATED_EVENT: &str = "payment.created";
+pub const PAYMENT_CAPTURED_EVENT: &str = "payment.captured";
+pub const PAYMENT_FAILED_EVENT: &str = "payment.failed";
+
+/// Queues a webhook delivery of the `event` for the payment, if a webhook URL is configured.
+///
+/// The payment is already persisted at this point, so failing to queue the delivery is
+/// recorded
+/// rather than failing the request, to be queued again by the webhook worker.
+async fn notify<T: AccountService + Clone>(
+    bank_web: &BankWeb<T>,
+    event: &str,
+    data: &ResponseData,
+) {
+    let Some(url) = &bank_web.webhook_url else {
+        return;
+    };
+    let payload = webhook_payload(event, data);
+    let event_id = Uuid::new_v4();
+    if let Err(err) = webhooks::enqueue(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        url,
+        event_id,
+        payload.clone(),
+    )
+    .await
+    {
+        tracing::error!(payment_id = %data.id, error = ?err, "failed to queue webhook delivery");
+        bank_web
+            .unqueued_webhooks
+            .record(webhooks::UnqueuedDelivery {
+                url: url.clone(),
+                event_id,
+                payload,
+            });
+    }
+}
+
+/// Returns the payload of a webhook delivery of the `event`, with the `data` it's about.
+pub fn webhook_payload(event: &str, data: &impl Serialize) -> serde_json::Value {
+    serde_json::json!({ "event": event, "data": data })
+}
+
+/// Queues a new delivery of the payment's creation webhook, e.g. after the previous
+/// deliveries exhausted their retries.
+///
+/// The delivery is about the same event as the previous ones, so that receivers which
+/// processed one of them can ignore it.
+///
+/// Responds with 404 if the payment doesn't exist, and 409 if no webhook URL is configured.
+pub async fn resend_webhook<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> StatusCode {
+    let payment = match payments::get(&bank_web.pool, payment_id).await {
+        Ok(payment) => payment,
+        Err(sqlx::Error::RowNotFound) => return StatusCode::NOT_FOUND,
+        Err(e) => panic!("Database error: {:?}", e),
+    };
+    let Some(url) = &bank_web.webhook_url else {
+        return StatusCode::CONFLICT;
+    };
+
+    let event_id = webhooks::find_event_id(&bank_web.pool, url, PAYMENT_CREATED_EVENT, payment.id)
+        .await
+        .expect("failed to find webhook event")
+        .unwrap_or_else(Uuid::new_v4);
+    let payload = webhook_payload(PAYMENT_CREATED_EVENT, &response_data(&bank_web, payment));
+    webhooks::enqueue(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        url,
+        event_id,
+        payload,
+    )
+    .await
+    .expect("failed to queue webhook delivery");
+
+    StatusCode::ACCEPTED
+}
+
+/// Longest URL a test webhook is sent to.
+const MAX_WEBHOOK_TEST_URL_LENGTH: usize = 2048;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct WebhookTestRequestBody {
+    pub url: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct WebhookTestData {
+    /// The status the receiver responded with.
+    pub status_code: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct WebhookTestBody {
+    pub data: WebhookTestData,
+}
+
+/// Sends the payment's creation webhook to the URL in the body once, e.g. for merchants to
+/// test a new endpoint without changing their configuration, responding with the status the
+/// receiver responded with.
+///
+/// The payload is signed with `webhooks::TEST_WEBHOOK_SECRET`, and the delivery is neither
+/// persisted nor retried. Responds with 404 if the payment doesn't exist, 422 unless the URL
+/// is an HTTP one, and 502 if the receiver couldn't be reached.
+pub async fn webhook_test<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+    Json(body): Json<WebhookTestRequestBody>,
+) -> Result<(StatusCode, Json<WebhookTestBody>), StatusCode> {
+    let is_http = body.url.len() <= MAX_WEBHOOK_TEST_URL_LENGTH
+        && body
+            .url
+            .parse::<Uri>()
+            .is_ok_and(|uri| uri.scheme_str() == Some("http") && uri.host().is_some());
+    if !is_http {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+    let payment = match payments::get(&bank_web.pool, payment_id).await {
+        Ok(payment) => payment,
+        Err(sqlx::Error::RowNotFound) => return Err(StatusCode::NOT_FOUND),
+        Err(e) => panic!("Database error: {:?}", e),
+    };
+
+    let payload = webhook_payload(PAYMENT_CREATED_EVENT, &response_data(&bank_web, payment));
+    let status_code = webhooks::HttpSender::default()
+        .send_test(&body.url, &payload)
+        .await
+        .map_err(|err| {
+            tracing::warn!(%payment_id, error = %err, "failed to send test webhook");
+            StatusCode::BAD_GATEWAY
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(WebhookTestBody {
+            data: WebhookTestData {
+                status_code: status_code.as_u16(),
+            },
+        }),
+    ))
+}
+
+/// Returns the status of each of the requested payments, in the requested order.
+///
+/// Unknown payments are reported with a `null` status. Looking up more than
+/// `MAX_STATUS_LOOKUP_IDS` payments at once is rejected with 422.
+pub async fn status_lookup<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Json(body): Json<StatusLookupRequestBody>,
+) -> Result<(StatusCode, Json<StatusLookupResponseBody>), StatusCode> {
+    if body.ids.len() > MAX_STATUS_LOOKUP_IDS {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let statuses = bank_web
+        .payment_repository
+        .statuses(&body.ids)
+        .await
+        .unwrap();
+    let statuses = body
+        .ids
+        .into_iter()
+        .map(|id| StatusLookupData {
+            id,
+            status: statuses.get(&id).copied(),
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(StatusLookupResponseBody { statuses })))
+}
+
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 1_000;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListParams {
+    pub customer_id: String,
+    /// Only list the payments made from this channel.
+    pub channel: Option<Channel>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ListBody {
+    pub data: Vec<ResponseData>,
+}
+
+/// Lists the payments of the merchant's customer, oldest first, omitting archived ones,
+/// optionally only those made from a `channel`.
+///
+/// Lists hold `limit` payments, defaulting to `DEFAULT_LIST_LIMIT` and capped to
+/// `MAX_LIST_LIMIT`. Responds with 400 if the customer id is empty or too long.
+pub async fn list<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<ListParams>,
+) -> Result<(StatusCode, Json<ListBody>), StatusCode> {
+    payments::validate_customer_id(&params.customer_id).map_err(|_| StatusCode::BAD_REQUEST)?;
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let payments =
+        payments::list_by_customer(&bank_web.pool, &params.customer_id, params.channel, limit)
+            .await
+            .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(ListBody {
+            data: payments
+                .into_iter()
+                .map(|payment| response_data(&bank_web, payment))
+                .collect(),
+        }),
+    ))
+}
+
+/// Longest period recent payments are listed over, in seconds.
+pub const MAX_RECENT_SECONDS: i64 = 24 * 60 * 60;
+const MAX_RECENT_PAYMENTS: i64 = 100;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecentParams {
+    pub seconds: i64,
+}
+
+/// Lists the payments created within the last `seconds`, newest first and at most
+/// `MAX_RECENT_PAYMENTS` of them, omitting archived ones. Meant for debugging webhook
+/// deliveries.
+///
+/// Responds with 400 unless `seconds` is positive and at most `MAX_RECENT_SECONDS`.
+pub async fn recent<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<RecentParams>,
+) -> Result<(StatusCode, Json<ListBody>), StatusCode> {
+    if !(1..=MAX_RECENT_SECONDS).contains(&params.seconds) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let now = bank_web.clock.now_utc();
+    let payments = payments::list_inserted_between(
+        &bank_web.pool,
+        now - time::Duration::seconds(params.seconds),
+        now,
+        MAX_RECENT_PAYMENTS,
+    )
+    .await
+    .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(ListBody {
+            data: payments
+                .into_iter()
+                .map(|payment| response_data(&bank_web, payment))
+                .collect(),
+        }),
+    ))
+}
+
+/// Longest window listed authorizations can expire within, in seconds: their lifetime.
+const MAX_EXPIRING_WITHIN_SECONDS: i64 = payments::AUTHORIZATION_LIFETIME.whole_seconds();
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ExpiringParams {
+    /// In seconds.
+    pub within: i64,
+}
+
+/// Lists the authorizations awaiting capture whose holds lapse within the next `within`
+/// seconds, soonest first, so that they can be captured or extended in time.
+///
+/// Responds with 400 unless `within` is positive and at most `MAX_EXPIRING_WITHIN_SECONDS`.
+pub async fn expiring_authorizations<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<ExpiringParams>,
+) -> Result<(StatusCode, Json<ListBody>), StatusCode> {
+    if !(1..=MAX_EXPIRING_WITHIN_SECONDS).contains(&params.within) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let payments = payments::list_expiring_authorizations(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        time::Duration::seconds(params.within),
+    )
+    .await
+    .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(ListBody {
+            data: payments
+                .into_iter()
+                .map(|payment| admin_response_data(&bank_web, payment))
+                .collect(),
+        }),
+    ))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StuckHoldsParams {
+    /// In seconds.
+    pub older_than: i64,
+}
+
+/// Lists the payments still processing or awaiting capture with a hold placed more than
+/// `older_than` seconds ago, oldest first, to catch the holds whose confirmation or
+/// capture was lost.
+///
+/// Responds with 400 unless `older_than` is positive.
+pub async fn stuck_holds<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<StuckHoldsParams>,
+) -> Result<(StatusCode, Json<ListBody>), StatusCode> {
+    if params.older_than <= 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let payments = payments::list_stuck_holds(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        time::Duration::seconds(params.older_than),
+    )
+    .await
+    .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(ListBody {
+            data: payments
+                .into_iter()
+                .map(|payment| admin_response_data(&bank_web, payment))
+                .collect(),
+        }),
+    ))
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct GetParams {
+    /// Comma-separated names of the fields to return, all of them by default.
+    pub fields: Option<String>,
+}
+
+/// Returns the payment, or 404 if it doesn't exist or was archived.
+///
+/// Only the payment's `fields` are returned when given, responding with 400 if one of them
+/// isn't a field. This also serves `HEAD` requests, for which the body is dropped.
+pub async fn get<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+    Query(params): Query<GetParams>,
+) -> Result<(StatusCode, Json<serde_json::Value>), StatusCode> {
+    let payment = bank_web
+        .payment_repository
+        .get(payment_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            e => panic!("Database error: {:?}", e),
+        })?;
+    if payment.archived_at.is_some() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    let data =
+        serde_json::to_value(response_data(&bank_web, payment)).expect("failed to serialize");
+    let data = match params.fields {
+        Some(fields) => select_fields(data, &fields).ok_or(StatusCode::BAD_REQUEST)?,
+        None => data,
+    };
+
+    Ok((StatusCode::OK, Json(serde_json::json!({ "data": data }))))
+}
+
+/// Returns the authenticated merchant's payment with the reference, or 404 if it has none
+/// or it was archived.
+///
+/// The payments of other merchants are never returned, even when they use the same
+/// reference.
+pub async fn get_by_reference<T: AccountService + Clone>(
+    MerchantAuth(merchant_id): MerchantAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(merchant_reference): Path<String>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    let payment = payments::get_by_reference(&bank_web.pool, merchant_id, &merchant_reference)
+        .await
+        .map_err(|e| {
+            if payments::is_too_many_connections(&e) {
+                StatusCode::SERVICE_UNAVAILABLE
+            } else {
+                tracing::error!(error = ?e, "failed to get payment by reference");
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseBody {
+            data: response_data(&bank_web, payment),
+        }),
+    ))
+}
+
+/// Keeps only the comma-separated `fields` of the serialized object, or returns `None` if
+/// one of them isn't among its keys.
+fn select_fields(data: serde_json::Value, fields: &str) -> Option<serde_json::Value> {
+    let serde_json::Value::Object(mut object) = data else {
+        return None;
+    };
+    fields
+        .split(',')
+        .map(|field| object.remove_entry(field.trim()))
+        .collect::<Option<serde_json::Map<_, _>>>()
+        .map(serde_json::Value::Object)
+}
+
+/// Returns the payment's history, oldest event first, or 404 if it doesn't exist.
+pub async fn events<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<EventsBody>), StatusCode> {
+    payments::get(&bank_web.pool, payment_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            e => panic!("Database error: {:?}", e),
+        })?;
+    let events = payment_events::list_for_payment(&bank_web.pool, payment_id)
+        .await
+        .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(EventsBody {
+            data: events.into_iter().map(EventData::from).collect(),
+        }),
+    ))
+}
+
+/// Returns the fees, currency and limits the payment was created under, which may have
+/// changed since.
+///
+/// Responds with 404 if the payment doesn't exist or was created without a snapshot, as
+/// authorizations and payments created before snapshots were stored are.
+pub async fn config_snapshot<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ConfigSnapshotBody>), StatusCode> {
+    let snapshot = payments::get_config_snapshot(&bank_web.pool, payment_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            e => panic!("Database error: {:?}", e),
+        })?
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok((StatusCode::OK, Json(ConfigSnapshotBody { data: snapshot })))
+}
+
+pub const STATUS_SSE_EVENT: &str = "status";
+
+/// Streams the payment's status as server-sent events: its current status first, then each
+/// change until it reaches a terminal status, when the stream closes.
+///
+/// Responds with 404 if the payment doesn't exist.
+pub async fn stream_events<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<Sse<impl Stream<Item = Result<Event, serde_json::Error>>>, StatusCode> {
+    // subscribed before reading the payment, so that no change is missed in between
+    let receiver = bank_web.status_changes.subscribe();
+    let payment = payments::get(&bank_web.pool, payment_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            e => panic!("Database error: {:?}", e),
+        })?;
+
+    let pool = bank_web.pool;
+    let events = futures::stream::unfold(Some((receiver, Some(payment.status))), move |state| {
+        let pool = pool.clone();
+        async move {
+            let (mut receiver, current) = state?;
+            let status = match current {
+                Some(status) => status,
+                None => next_status(&mut receiver, &pool, payment_id).await?,
+            };
+            // closes the stream right after the terminal status
+            let state = (!status.is_terminal()).then_some((receiver, None));
+            Some((status, state))
+        }
+    })
+    .map(move |status| {
+        Event::default()
+            .event(STATUS_SSE_EVENT)
+            .json_data(StatusEventData {
+                id: payment_id,
+                status,
+            })
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+/// Waits for the payment's next status, or returns `None` if it can't be known anymore.
+async fn next_status(
+    receiver: &mut broadcast::Receiver<StatusChange>,
+    pool: &PgPool,
+    payment_id: Uuid,
+) -> Option<Status> {
+    loop {
+        match receiver.recv().await {
+            Ok(change) if change.payment_id == payment_id => return Some(change.status),
+            Ok(_) => {}
+            // the missed changes may include the terminal one
+            Err(RecvError::Lagged(_)) => {
+                return payments::get(pool, payment_id)
+                    .await
+                    .ok()
+                    .map(|payment| payment.status)
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
+
+/// Merges the given keys into the payment's metadata, leaving its other keys and
+/// financial fields untouched.
+///
+/// Responds with 404 if the payment doesn't exist, and 422 if the merged metadata
+/// exceeds the limits enforced on creation.
+pub async fn update_metadata<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+    Json(body): Json<MetadataRequestBody>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    let payment = payments::merge_metadata(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        payment_id,
+        body.metadata,
+    )
+    .await
+    .map_err(|e| match e {
+        UpdateMetadataError::PaymentNotFound => StatusCode::NOT_FOUND,
+        UpdateMetadataError::InvalidArgument(err) => err.http_status(),
+        UpdateMetadataError::Database(err) => panic!("Database error: {:?}", err),
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseBody {
+            data: response_data(&bank_web, payment),
+        }),
+    ))
+}
+
+/// Fails a payment still processing or awaiting capture, releasing its hold and notifying
+/// the webhook, for disaster-recovery drills.
+///
+/// Only available to admins in sandbox mode, and responds with 404 otherwise. Responds with
+/// 409 if the payment is already approved, declined or failed.
+pub async fn force_fail<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    if !bank_web.sandbox {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let payment = payments::force_fail(
+        &bank_web.pool,
+        &bank_web.account_service,
+        bank_web.clock.as_ref(),
+        payment_id,
+    )
+    .await
+    .map_err(|e| match e {
+        ForceFailError::PaymentNotFound => StatusCode::NOT_FOUND,
+        ForceFailError::Terminal => StatusCode::CONFLICT,
+        ForceFailError::AccountService(err) => err.http_status(),
+        ForceFailError::Database(err) => panic!("Database error: {:?}", err),
+    })?;
+
+    bank_web.status_changes.publish(payment.id, payment.status);
+    let data = admin_response_data(&bank_web, payment);
+    notify(&bank_web, PAYMENT_FAILED_EVENT, &data).await;
+
+    Ok((StatusCode::OK, Json(ResponseBody { data })))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VerifyData {
+    pub consistent: bool,
+    pub discrepancies: Vec<payments::Discrepancy>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VerifyBody {
+    pub data: VerifyData,
+}
+
+/// Checks that the stored payment matches the state its audit events imply.
+///
+/// Responds with 404 if the payment doesn't exist.
+pub async fn verify<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<VerifyBody>), StatusCode> {
+    let discrepancies = payments::rebuild_from_events(&bank_web.pool, payment_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            e => panic!("Database error: {:?}", e),
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(VerifyBody {
+            data: VerifyData {
+                consistent: discrepancies.is_empty(),
+                discrepancies,
+            },
+        }),
+    ))
+}
+
+/// Archives the payment, masking its card number.
+///
+/// Responds with 404 if the payment doesn't exist or was already archived.
+pub async fn archive<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    let payment = payments::archive(&bank_web.pool, bank_web.clock.as_ref(), payment_id)
+        .await
+        .map_err(|e| match e {
+            sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+            e => panic!("Database error: {:?}", e),
+        })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseBody {
+            data: response_data(&bank_web, payment),
+        }),
+    ))
+}
+
+/// Streams all payments as newline-delimited JSON, optionally restricted to
+/// those inserted within `[from, to)`.
+///
+/// An interrupted client can resume the export by passing the id of the last
+/// payment it received as `after_id` (optionally with `after_inserted_at`).
+///
+/// Payments are read from the database as the client consumes the response, so
+/// memory usage stays bounded regardless of the number of exported payments.
+pub async fn export<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<ExportParams>,
+) -> impl IntoResponse {
+    let from = params.from.map(to_primitive_utc);
+    let to = params.to.map(to_primitive_utc);
+    let after_inserted_at = params.after_inserted_at.map(to_primitive_utc);
+    let after_id = params.after_id;
+    let (mut sender, receiver) = futures::channel::mpsc::channel(EXPORT_BUFFER_SIZE);
+
+    tokio::spawn(async move {
+        let mut payments = payments::stream(&bank_web.pool, from, to, after_inserted_at, after_id);
+        while let Some(payment) = payments.next().await {
+            let line = payment.map(|payment| {
+                let mut line = serde_json::to_string(&response_data(&bank_web, payment))
+                    .expect("failed to serialize payment");
+                line.push('\n');
+                line
+            });
+            let failed = line.is_err();
+            if sender.send(line).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    (
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        StreamBody::new(receiver),
+    )
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::{
+        bank::{
+            accounts::DummyService,
+            amount_bounds::AmountBounds,
+            clock::{tests::MockClock, Clock},
+            concurrency_limiter::ConcurrencyLimit,
+            fees::FeeConfig,
+            fingerprints::FingerprintKey,
+            merchants::ImplicitIdempotency,
+            money::Rounding,
+            payment_instruments::Card,
+            payments::{CardNumberDisplay, Status},
+            repository::{self, tests::InMemoryRepository},
+            vault::{tests::FakeVault, CardVault},
+            velocity::VelocityLimit,
+        },
+        bank_web::tests::{
+            deserialize_response_body, get, get_as_admin, get_as_merchant, head, patch, post,
+            post_as_admin, post_form, send_request, ADMIN_TOKEN, MERCHANT_API_KEY,
+        },
+    };
+    use axum::{
+        extract::ConnectInfo,
+        http::{
+            header::{ACCEPT_LANGUAGE, AUTHORIZATION},
+            Method, Request,
+        },
+        Router,
+    };
+    use rstest::rstest;
+    use std::{
+        collections::HashSet,
+        net::{IpAddr, SocketAddr},
+        sync::Arc,
+    };
+
+    #[rstest]
+    #[case(AccountServiceError::InsufficientFunds, StatusCode::PAYMENT_REQUIRED)]
+    #[case(AccountServiceError::InvalidAccountNumber, StatusCode::FORBIDDEN)]
+    #[case(
+        AccountServiceError::ServiceUnavailable,
+        StatusCode::SERVICE_UNAVAILABLE
+    )]
+    #[case(AccountServiceError::InternalError, StatusCode::INTERNAL_SERVER_ERROR)]
+    #[case(AccountServiceError::DoNotHonor, StatusCode::PAYMENT_REQUIRED)]
+    #[case(AccountServiceError::ExpiredCard, StatusCode::PAYMENT_REQUIRED)]
+    #[case(AccountServiceError::LostCard, StatusCode::FORBIDDEN)]
+    #[case(AccountServiceError::StolenCard, StatusCode::FORBIDDEN)]
+    #[case(AccountServiceError::SuspectedFraud, StatusCode::FORBIDDEN)]
+    fn test_account_service_error_http_status(
+        #[case] err: AccountServiceError,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        assert_eq!(err.http_status(), expected_status_code);
+    }
+
+    #[rstest]
+    #[case(InvalidArgumentError::NegativeAmount, StatusCode::BAD_REQUEST)]
+    #[case(InvalidArgumentError::ZeroAmount, StatusCode::NO_CONTENT)]
+    #[case(
+        InvalidArgumentError::MissingCardNumber,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    #[case(
+        InvalidArgumentError::InvalidCardFormat,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    #[case(
+        InvalidArgumentError::HoldAmountBelowAmount,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    #[case(
+        InvalidArgumentError::MetadataTooLarge,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    #[case(
+        InvalidArgumentError::InvalidCurrency,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    #[case(
+        InvalidArgumentError::UnsupportedCurrency,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    #[case(InvalidArgumentError::AmountTooSmall, StatusCode::UNPROCESSABLE_ENTITY)]
+    #[case(InvalidArgumentError::AmountTooLarge, StatusCode::UNPROCESSABLE_ENTITY)]
+    #[case(
+        InvalidArgumentError::InvalidCustomerId,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    #[case(
+        InvalidArgumentError::InvalidMerchantReference,
+        StatusCode::UNPROCESSABLE_ENTITY
+    )]
+    fn test_invalid_argument_error_http_status(
+        #[case] err: InvalidArgumentError,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        assert_eq!(err.http_status(), expected_status_code);
+    }
+
+    async fn do_payment(
+        router: &Router,
+        payment_amount: i32,
+        payment_card_number: String,
+        expected_status_code: StatusCode,
+        expected_status: Status,
+    ) {
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: payment_amount,
+                currency: None,
+                card_number: payment_card_number,
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.amount, request_body.payment.amount);
+        assert_eq!(
+            response_body.data.card_number,
+            payments::mask_card_number(
+                &request_body.payment.card_number,
+                CardNumberDisplay::Contiguous
+            )
+        );
+        assert_eq!(response_body.data.status, expected_status);
+        assert_eq!(
+            expected_status == Status::Approved,
+            response_body.data.decline_reason.is_none()
+        );
+
+        // approved payments and those rejected by the account service are persisted
+        if expected_status_code == StatusCode::CREATED || !response_body.data.id.is_nil() {
+            let uri = format!("/api/payments/{}", response_body.data.id);
+            let persisted = deserialize_response_body::<ResponseBody>(get(router, uri).await).await;
+            assert_eq!(persisted, response_body);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_approve_valid_payment() {
+        let router = BankWeb::new_test().await.into_router();
+        let payment_amount = 12_05;
+        let payment_card_number: String = Card::new_test().into();
+        do_payment(
+            &router,
+            payment_amount,
+            payment_card_number.clone(),
+            StatusCode::CREATED,
+            Status::Approved,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_charge_fees_on_approved_payment() {
+        let router = BankWeb::new_test()
+            .await
+            .with_fees(FeeConfig::new(30, 250))
+            .into_router();
+
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 10_00,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.fee_amount, 55);
+        assert_eq!(response_body.data.net_amount, 9_45);
+    }
+
+    #[tokio::test]
+    async fn should_not_charge_fees_on_declined_payment() {
+        let router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .with_fees(FeeConfig::new(30, 250))
+            .into_router();
+
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 10_00,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.fee_amount, 0);
+        assert_eq!(response_body.data.net_amount, 10_00);
+    }
+
+    #[tokio::test]
+    async fn should_respond_consistent_amount_details_of_refunded_payment() {
+        let router = BankWeb::new_test()
+            .await
+            .with_fees(FeeConfig::new(30, 250))
+            .into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 10_00,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment_id = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data
+            .id;
+
+        let uri = format!("/api/payments/{payment_id}/refunds");
+        let refund_body = serde_json::json!({ "refund": { "amount": 3_00 } });
+        let response = post(&router, uri, &refund_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = get(&router, format!("/api/payments/{payment_id}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        let details = data.amount_details;
+        assert_eq!(
+            details,
+            AmountDetails {
+                gross: 10_00,
+                fee: 55,
+                net: 9_45,
+                refunded: 3_00,
+                refundable: 7_00,
+            }
+        );
+        assert_eq!(details.gross - details.fee, details.net);
+        assert_eq!(details.gross - details.refunded, details.refundable);
+        assert_eq!(
+            (details.gross, details.fee, details.net),
+            (data.amount, data.fee_amount, data.net_amount)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_approve_valid_payment_with_dyn_account_service() {
+        use crate::bank::{
+            accounts::{DummyService, DynAccountService},
+            fingerprints::FingerprintKey,
+        };
+
+        let account_service: DynAccountService = std::sync::Arc::new(DummyService::default());
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to create postgres pool");
+        let router = BankWeb::new(pool, account_service, FingerprintKey::new_test()).into_router();
+
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::CREATED,
+            Status::Approved,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_approve_form_encoded_payment() {
+        let router = BankWeb::new_test().await.into_router();
+        let card_number: String = Card::new_test().into();
+
+        let response = post_form(
+            &router,
+            "/api/payments",
+            format!("amount=1205&card_number={card_number}"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert!(!response_body.data.id.is_nil());
+        assert_eq!(response_body.data.amount, 12_05);
+        assert_eq!(
+            response_body.data.card_number,
+            payments::mask_card_number(&card_number, CardNumberDisplay::Contiguous)
+        );
+        assert_eq!(response_body.data.status, Status::Approved);
+    }
+
+    #[tokio::test]
+    async fn should_round_trip_payment_metadata() {
+        let router = BankWeb::new_test().await.into_router();
+        let metadata = Metadata::from([
+            ("order_id".to_string(), "1234".to_string()),
+            ("cart_id".to_string(), "abcd".to_string()),
+        ]);
+
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: metadata.clone(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(payment.data.metadata, metadata);
+
+        let uri = format!("/api/payments/{}", payment.data.id);
+        let persisted = deserialize_response_body::<ResponseBody>(get(&router, uri).await).await;
+        assert_eq!(persisted.data.metadata, metadata);
+    }
+
+    #[tokio::test]
+    async fn should_reject_payment_with_too_many_metadata_keys() {
+        let router = BankWeb::new_test().await.into_router();
+        let metadata = (0..=payments::MAX_METADATA_KEYS)
+            .map(|i| (format!("key_{i}"), "value".to_string()))
+            .collect();
+
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata,
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let payment = deserialize_response_body::<ResponseBody>(response).await;
+        assert!(payment.data.id.is_nil());
+    }
+
+    #[rstest]
+    #[case(vec![], None)]
+    #[case(vec!["10.0.0.1".parse().unwrap()], Some("203.0.113.7"))]
+    #[tokio::test]
+    async fn should_store_forwarded_client_ip_from_trusted_proxies(
+        #[case] trusted_proxies: Vec<IpAddr>,
+        #[case] forwarded_ip: Option<&str>,
+    ) {
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_trusted_proxies(trusted_proxies);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/payments")
+            .header(CONTENT_TYPE, "application/json")
+            .header("x-forwarded-for", "203.0.113.7")
+            .extension(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 4000))))
+            .body(serde_json::to_vec(&request_body).unwrap().into())
+            .unwrap();
+
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment_id = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data
+            .id;
+        let payment = payments::get(&pool, payment_id).await.unwrap();
+        assert_eq!(
+            payment.client_ip.as_deref(),
+            Some(forwarded_ip.unwrap_or("10.0.0.1"))
+        );
+    }
+
+    #[rstest]
+    #[case(Some("EUR"), StatusCode::CREATED, None)]
+    #[case(Some("eur"), StatusCode::CREATED, None)]
+    #[case(
+        Some("JPY"),
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Some("amount_too_small")
+    )]
+    #[case(
+        Some("USD"),
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Some("amount_too_large")
+    )]
+    // not bounded
+    #[case(Some("GBP"), StatusCode::CREATED, None)]
+    #[case(None, StatusCode::CREATED, None)]
+    #[case(
+        Some("EURO"),
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Some("invalid_currency")
+    )]
+    #[tokio::test]
+    async fn should_bound_amounts_by_currency(
+        #[case] currency: Option<&str>,
+        #[case] expected_status_code: StatusCode,
+        #[case] expected_decline_reason: Option<&str>,
+    ) {
+        let router = BankWeb::new_test()
+            .await
+            .with_amount_bounds("EUR", AmountBounds::new(50, 1_000_000))
+            .with_amount_bounds("JPY", AmountBounds::new(1_000, 100_000_000))
+            .with_amount_bounds("USD", AmountBounds::new(1, 1_00))
+            .into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 5_00,
+                currency: currency.map(str::to_string),
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.decline_reason.as_deref(), expected_decline_reason);
+        if expected_status_code == StatusCode::CREATED {
+            assert_eq!(
+                data.currency,
+                currency.map(|currency| currency.to_ascii_uppercase())
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(Some("EUR"), StatusCode::CREATED, None)]
+    #[case(Some("usd"), StatusCode::CREATED, None)]
+    // supported by other merchants, but not by this one
+    #[case(
+        Some("GBP"),
+        StatusCode::UNPROCESSABLE_ENTITY,
+        Some("unsupported_currency")
+    )]
+    #[case(None, StatusCode::CREATED, None)]
+    #[tokio::test]
+    async fn should_restrict_currencies_by_merchant(
+        #[case] currency: Option<&str>,
+        #[case] expected_status_code: StatusCode,
+        #[case] expected_decline_reason: Option<&str>,
+    ) {
+        let merchant_id = Uuid::new_v4();
+        let router = BankWeb::new_test()
+            .await
+            .with_merchant_currencies(merchant_id, ["EUR", "USD"])
+            .with_merchant_currencies(Uuid::new_v4(), ["GBP"])
+            .into_router();
+        let request_body = |merchant_id| RequestBody {
+            payment: RequestData {
+                amount: 5_00,
+                currency: currency.map(str::to_string),
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body(Some(merchant_id))).await;
+        assert_eq!(response.status(), expected_status_code);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.decline_reason.as_deref(), expected_decline_reason);
+
+        // payments without a merchant may use any valid currency
+        let response = post(&router, "/api/payments", &request_body(None)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[rstest]
+    #[case(true, 10_01, StatusCode::PAYMENT_REQUIRED, Status::Declined)]
+    #[case(true, 10_03, StatusCode::SERVICE_UNAVAILABLE, Status::Failed)]
+    #[case(true, 10_00, StatusCode::CREATED, Status::Approved)]
+    // magic amounts are ignored outside sandbox mode
+    #[case(false, 10_01, StatusCode::CREATED, Status::Approved)]
+    #[tokio::test]
+    async fn should_force_outcome_of_magic_amounts_in_sandbox(
+        #[case] sandbox: bool,
+        #[case] amount: i32,
+        #[case] expected_status_code: StatusCode,
+        #[case] expected_status: Status,
+    ) {
+        let bank_web = BankWeb::new_test().await.with_sandbox(sandbox);
+        let hold_calls = bank_web.account_service.hold_calls.clone();
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.status, expected_status);
+        assert!(!data.id.is_nil());
+        let held = usize::from(expected_status == Status::Approved);
+        assert_eq!(hold_calls.load(std::sync::atomic::Ordering::SeqCst), held);
+    }
+
+    #[rstest]
+    #[case(None, "EUR")]
+    #[case(Some("usd"), "USD")]
+    #[tokio::test]
+    async fn should_default_payment_currency(
+        #[case] currency: Option<&str>,
+        #[case] expected_currency: &str,
+    ) {
+        let router = BankWeb::new_test()
+            .await
+            .with_default_currency("EUR")
+            .into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: currency.map(str::to_string),
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.currency.as_deref(), Some(expected_currency));
+    }
+
+    #[tokio::test]
+    async fn should_list_payments_by_customer_id() {
+        let router = BankWeb::new_test().await.into_router();
+        let (alice, bob) = (Uuid::new_v4().to_string(), Uuid::new_v4().to_string());
+        let mut payment_ids = Vec::new();
+        for customer_id in [&alice, &bob, &alice] {
+            let request_body = RequestBody {
+                payment: RequestData {
+                    amount: 1_23,
+                    currency: None,
+                    card_number: Card::new_test().into(),
+                    metadata: Default::default(),
+                    customer_id: Some(customer_id.clone()),
+                    merchant_id: None,
+                    merchant_reference: None,
+                    channel: None,
+                },
+            };
+            let response = post(&router, "/api/payments", &request_body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let data = deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data;
+            assert_eq!(data.customer_id.as_ref(), Some(customer_id));
+            payment_ids.push(data.id);
+        }
+
+        let response = get(&router, format!("/api/payments/{}", payment_ids[1])).await;
+        let data = deserialize_response_body::<serde_json::Value>(response)
+            .await
+            .get("data")
+            .cloned()
+            .unwrap();
+        assert_eq!(data["customer_id"], bob.as_str());
+        let response = get(&router, format!("/api/payments?customer_id={alice}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut ids: Vec<_> = deserialize_response_body::<ListBody>(response)
+            .await
+            .data
+            .into_iter()
+            .map(|payment| payment.id)
+            .collect();
+        // payments inserted within the same second are ordered by id
+        ids.sort();
+        let mut expected_ids = [payment_ids[0], payment_ids[2]];
+        expected_ids.sort();
+        assert_eq!(ids, expected_ids);
+        let response = get(
+            &router,
+            format!("/api/payments?customer_id={alice}&limit=1"),
+        )
+        .await;
+        let body = deserialize_response_body::<ListBody>(response).await;
+        assert_eq!(body.data.len(), 1);
+    }
+
+    #[rstest]
+    #[case::minimal(Some("return=minimal"), true)]
+    #[case::representation(Some("return=representation"), false)]
+    #[case::no_preference(None, false)]
+    #[tokio::test]
+    async fn should_omit_created_payment_only_when_minimal_return_is_preferred(
+        #[case] prefer: Option<&str>,
+        #[case] minimal: bool,
+    ) {
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/payments")
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(prefer) = prefer {
+            request = request.header(PREFER_HEADER, prefer);
+        }
+        let request = request
+            .body(serde_json::to_vec(&request_body).unwrap().into())
+            .unwrap();
+
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers()[LOCATION].to_str().unwrap().to_owned();
+        assert_eq!(
+            response.headers().contains_key(PREFERENCE_APPLIED_HEADER),
+            minimal
+        );
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("failed to read response body into bytes");
+        let response = get(&router, &location).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(location, format!("/api/payments/{}", data.id));
+        if minimal {
+            assert!(bytes.is_empty());
+        } else {
+            let created: ResponseBody = serde_json::from_slice(&bytes).unwrap();
+            assert_eq!(created.data.id, data.id);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_list_payments_by_channel() {
+        let router = BankWeb::new_test().await.into_router();
+        let customer_id = Uuid::new_v4().to_string();
+        let mut payment_ids = Vec::new();
+        for channel in [Channel::Web, Channel::Pos, Channel::Pos] {
+            let request_body = RequestBody {
+                payment: RequestData {
+                    amount: 1_23,
+                    currency: None,
+                    card_number: Card::new_test().into(),
+                    metadata: Default::default(),
+                    customer_id: Some(customer_id.clone()),
+                    merchant_id: None,
+                    merchant_reference: None,
+                    channel: Some(channel),
+                },
+            };
+            let response = post(&router, "/api/payments", &request_body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let data = deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data;
+            assert_eq!(data.channel, Some(channel));
+            payment_ids.push(data.id);
+        }
+
+        let response = get(
+            &router,
+            format!("/api/payments?customer_id={customer_id}&channel=pos"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let mut ids: Vec<_> = deserialize_response_body::<ListBody>(response)
+            .await
+            .data
+            .into_iter()
+            .map(|payment| payment.id)
+            .collect();
+        ids.sort();
+        let mut expected_ids = [payment_ids[1], payment_ids[2]];
+        expected_ids.sort();
+        assert_eq!(ids, expected_ids);
+    }
+
+    #[tokio::test]
+    async fn should_reject_unknown_channel() {
+        let router = BankWeb::new_test().await.into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = serde_json::json!({
+            "payment": {
+                "amount": 1_23,
+                "card_number": card_number,
+                "channel": "fax",
+            }
+        });
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_list_recent_payments() {
+        use rand::Rng;
+
+        // a random time of the 20th century, around which no other payment is created
+        let now = time::Date::from_calendar_date(1900, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(now.midnight().assume_utc());
+        let router = BankWeb::new_test()
+            .await
+            .with_clock(clock.clone())
+            .into_router();
+        let mut payment_ids = Vec::new();
+        for elapsed in [0, 90, 20] {
+            clock.advance(time::Duration::seconds(elapsed));
+            let request_body = RequestBody {
+                payment: RequestData {
+                    amount: 1_23,
+                    currency: None,
+                    card_number: Card::new_test().into(),
+                    metadata: Default::default(),
+                    customer_id: None,
+                    merchant_id: None,
+                    merchant_reference: None,
+                    channel: None,
+                },
+            };
+            let response = post(&router, "/api/payments", &request_body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let data = deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data;
+            payment_ids.push(data.id);
+        }
+        clock.advance(time::Duration::seconds(10));
+
+        let response = get(&router, "/api/payments/recent?seconds=60").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let ids: Vec<_> = deserialize_response_body::<ListBody>(response)
+            .await
+            .data
+            .into_iter()
+            .map(|payment| payment.id)
+            .collect();
+        assert_eq!(ids, [payment_ids[2], payment_ids[1]]);
+
+        for seconds in ["0", "-1", &(MAX_RECENT_SECONDS + 1).to_string()] {
+            let response = get(&router, format!("/api/payments/recent?seconds={seconds}")).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_invalid_customer_id() {
+        let router = BankWeb::new_test().await.into_router();
+        let customer_id = "c".repeat(payments::MAX_CUSTOMER_ID_LENGTH + 1);
+
+        for customer_id in ["", &customer_id] {
+            let response = get(&router, format!("/api/payments?customer_id={customer_id}")).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+            let request_body = RequestBody {
+                payment: RequestData {
+                    amount: 1_23,
+                    currency: None,
+                    card_number: Card::new_test().into(),
+                    metadata: Default::default(),
+                    customer_id: Some(customer_id.to_owned()),
+                    merchant_id: None,
+                    merchant_reference: None,
+                    channel: None,
+                },
+            };
+            let response = post(&router, "/api/payments", &request_body).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let data = deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data;
+            assert_eq!(data.decline_reason.as_deref(), Some("invalid_customer_id"));
+        }
+    }
+
+    #[rstest]
+    #[case(Some("EUR"), "12.05")]
+    #[case(Some("JPY"), "1205")]
+    #[case(None, "12.05")]
+    #[tokio::test]
+    async fn should_format_amount_with_currency_exponent(
+        #[case] currency: Option<&str>,
+        #[case] expected_amount_formatted: &str,
+    ) {
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1205,
+                currency: currency.map(str::to_string),
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.amount, 1205);
+        assert_eq!(data.amount_formatted, expected_amount_formatted);
+    }
+
+    #[rstest]
+    // too_many_connections
+    #[case("53300")]
+    // serialization_failure, once the insertion's retries are exhausted
+    #[case("40001")]
+    #[tokio::test]
+    async fn should_return_503_with_retry_after_for_transient_database_errors(
+        #[case] code: &'static str,
+    ) {
+        let bank_web = BankWeb::new_test_in_memory(Default::default());
+        let request = RequestData {
+            amount: 1_23,
+            currency: None,
+            card_number: Card::new_test().into(),
+            metadata: Default::default(),
+            customer_id: None,
+            merchant_id: None,
+            merchant_reference: None,
+            channel: None,
+        };
+
+        let result = Err(CreateError::Database(payments::tests::database_error(code)));
+        let (status_code, headers, Json(body)) =
+            respond_to_create(&bank_web, result, request).await;
+        assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            headers.get(RETRY_AFTER).unwrap(),
+            &DATABASE_RETRY_AFTER_SECONDS.to_string()
+        );
+        assert_eq!(body.data.status, Status::Failed);
+        assert!(body.data.id.is_nil());
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn should_expose_database_error_only_when_enabled(
+        #[values(true, false)] expose_database_errors: bool,
+    ) {
+        let bank_web = BankWeb::new_test_in_memory(Default::default())
+            .with_expose_database_errors(expose_database_errors);
+        let request = RequestData {
+            amount: 1_23,
+            currency: None,
+            card_number: Card::new_test().into(),
+            metadata: Default::default(),
+            customer_id: None,
+            merchant_id: None,
+            merchant_reference: None,
+            channel: None,
+        };
+
+        // 23505 = unique_violation
+        let result = Err(CreateError::Database(payments::tests::database_error(
+            "23505",
+        )));
+        let (status_code, _, Json(body)) = respond_to_create(&bank_web, result, request).await;
+        assert_eq!(status_code, StatusCode::INTERNAL_SERVER_ERROR);
+        let expected = expose_database_errors.then(|| DatabaseErrorData {
+            code: Some("23505".into()),
+            detail: "mock database error".into(),
+        });
+        assert_eq!(body.data.database_error, expected);
+    }
+
+    #[tokio::test]
+    async fn should_get_payment_from_repository() {
+        let repository = Arc::new(InMemoryRepository::default());
+        let payment = repository::tests::new_payment(1_23, Status::Approved);
+        repository.insert_payment(payment.clone());
+        let router = BankWeb::new_test_in_memory(repository).into_router();
+
+        let response = get(&router, format!("/api/payments/{}", payment.id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.amount, 1_23);
+        assert_eq!(data.status, Status::Approved);
+
+        let response = get(&router, format!("/api/payments/{}", Uuid::new_v4())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_get_payment_by_merchant_reference() {
+        let merchant_id = Uuid::new_v4();
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_merchant_api_key(merchant_id, MERCHANT_API_KEY);
+        let router = bank_web.into_router();
+        let merchant_reference = format!("order-{}", Uuid::new_v4().simple());
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: Some(merchant_id),
+                merchant_reference: Some(merchant_reference.clone()),
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let created = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        let uri = format!("/api/payments/by-reference/{merchant_reference}");
+
+        let response = get_as_merchant(&router, &uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data, created);
+
+        let response = get_as_merchant(&router, "/api/payments/by-reference/unknown").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let response = get(&router, &uri).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn should_not_get_payment_of_another_merchant_by_reference() {
+        let merchant_id = Uuid::new_v4();
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_merchant_api_key(merchant_id, MERCHANT_API_KEY);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let merchant_reference = format!("order-{}", Uuid::new_v4().simple());
+        let payment = payments::Payment::new_test(&pool)
+            .await
+            .expect("failed to create payment");
+        payments::tests::set_merchant_reference(
+            &pool,
+            payment.id,
+            Uuid::new_v4(),
+            &merchant_reference,
+        )
+        .await
+        .expect("failed to set merchant reference");
+
+        let response = get_as_merchant(
+            &router,
+            format!("/api/payments/by-reference/{merchant_reference}"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_look_up_statuses_from_repository() {
+        let repository = Arc::new(InMemoryRepository::default());
+        let payment = repository::tests::new_payment(1_23, Status::Declined);
+        repository.insert_payment(payment.clone());
+        let router = BankWeb::new_test_in_memory(repository).into_router();
+        let unknown_id = Uuid::new_v4();
+
+        let response = post(
+            &router,
+            "/api/payments/status-lookup",
+            &StatusLookupRequestBody {
+                ids: vec![payment.id, unknown_id],
+            },
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = deserialize_response_body::<StatusLookupResponseBody>(response).await;
+        assert_eq!(
+            body.statuses,
+            vec![
+                StatusLookupData {
+                    id: payment.id,
+                    status: Some(Status::Declined),
+                },
+                StatusLookupData {
+                    id: unknown_id,
+                    status: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_merge_payment_metadata() {
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Metadata::from([
+                    ("order_id".to_string(), "1234".to_string()),
+                    ("cart_id".to_string(), "abcd".to_string()),
+                ]),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        let payment = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+
+        let uri = format!("/api/payments/{}/metadata", payment.id);
+        let request_body = MetadataRequestBody {
+            metadata: Metadata::from([
+                ("cart_id".to_string(), "efgh".to_string()),
+                ("tracking_number".to_string(), "1Z999".to_string()),
+            ]),
+        };
+        let response = patch(&router, &uri, &request_body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let updated = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(
+            updated.metadata,
+            Metadata::from([
+                ("order_id".to_string(), "1234".to_string()),
+                ("cart_id".to_string(), "efgh".to_string()),
+                ("tracking_number".to_string(), "1Z999".to_string()),
+            ])
+        );
+        assert_eq!(
+            ResponseData {
+                metadata: payment.metadata.clone(),
+                ..updated
+            },
+            payment
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_metadata_update_exceeding_limits() {
+        let router = BankWeb::new_test().await.into_router();
+        let payment_id = create_payment(&router).await;
+
+        let uri = format!("/api/payments/{payment_id}/metadata");
+        let request_body = MetadataRequestBody {
+            metadata: (0..=payments::MAX_METADATA_KEYS)
+                .map(|i| (format!("key_{i}"), "value".to_string()))
+                .collect(),
+        };
+        let response = patch(&router, &uri, &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let uri = format!("/api/payments/{}/metadata", Uuid::new_v4());
+        let request_body = MetadataRequestBody {
+            metadata: Metadata::new(),
+        };
+        let response = patch(&router, &uri, &request_body).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_decline_payment_and_return_402_with_insufficient_funds() {
+        let router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .into_router();
+
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::PAYMENT_REQUIRED,
+            Status::Declined,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_persist_decline_reason_for_insufficient_funds() {
+        let router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .into_router();
+
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert!(!response_body.data.id.is_nil());
+
+        let uri = format!("/api/payments/{}", response_body.data.id);
+        let response_body =
+            deserialize_response_body::<ResponseBody>(get(&router, uri).await).await;
+        assert_eq!(response_body.data.status, Status::Declined);
+        assert_eq!(
+            response_body.data.decline_reason.as_deref(),
+            Some("insufficient_funds")
+        );
+    }
+
+    #[rstest]
+    #[case::do_not_honor("do_not_honor", StatusCode::PAYMENT_REQUIRED, "do_not_honor")]
+    #[case::expired_card("expired_card", StatusCode::PAYMENT_REQUIRED, "expired_card")]
+    #[case::lost_card("lost_card", StatusCode::FORBIDDEN, "lost_card")]
+    #[case::stolen_card("stolen_card", StatusCode::FORBIDDEN, "stolen_card")]
+    #[tokio::test]
+    async fn should_decline_payment_with_decline_code(
+        #[case] response: &str,
+        #[case] expected_status_code: StatusCode,
+        #[case] expected_decline_reason: &str,
+    ) {
+        let router = BankWeb::new_test_with_response(response)
+            .await
+            .into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.status, Status::Declined);
+        assert_eq!(
+            response_body.data.decline_reason.as_deref(),
+            Some(expected_decline_reason)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_approve_retry_of_declined_card() {
+        let declining_router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .into_router();
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                currency: None,
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&declining_router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::PAYMENT_REQUIRED);
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.status, Status::Approved);
+
+        // the card is used once approved
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_decline_payment_exceeding_known_balance_without_hold() {
+        let bank_web = BankWeb::new_test_with_balance(5_00)
+            .await
+            .with_balance_precheck(true);
+        let hold_calls = bank_web.account_service.hold_calls.clone();
+        let router = bank_web.into_router();
+
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::PAYMENT_REQUIRED,
+            Status::Declined,
+        )
+        .await;
+        assert_eq!(hold_calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+
+        do_payment(
+            &router,
+            4_00,
+            Card::new_test().into(),
+            StatusCode::CREATED,
+            Status::Approved,
+        )
+        .await;
+        assert_eq!(hold_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn should_hold_payment_exceeding_known_balance_without_precheck() {
+        let bank_web = BankWeb::new_test_with_balance(5_00).await;
+        let hold_calls = bank_web.account_service.hold_calls.clone();
+        let router = bank_web.into_router();
+
+        // the dummy service's holds don't look at the balance
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::CREATED,
+            Status::Approved,
+        )
+        .await;
+        assert_eq!(hold_calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn should_release_hold_of_payment_failing_to_be_persisted() {
+        let bank_web = BankWeb::new_test().await;
+        let hold_calls = bank_web.account_service.hold_calls.clone();
+        let released_holds = bank_web.account_service.released_holds.clone();
+        let router = bank_web.into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = serde_json::json!({
+            "payment": { "amount": 1_23, "card_number": card_number }
+        });
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(released_holds.lock().unwrap().is_empty());
+
+        // held before the unique index rejects the duplicated card
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(hold_calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        assert_eq!(released_holds.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn should_decline_payment_and_return_403_for_invalid_account_number() {
+        let router = BankWeb::new_test_with_response("invalid_account_number")
+            .await
+            .into_router();
+
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::FORBIDDEN,
+            Status::Declined,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_fail_payment_and_return_503_for_service_unavailable() {
+        let router = BankWeb::new_test_with_response("service_unavailable")
+            .await
+            .into_router();
+
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::SERVICE_UNAVAILABLE,
+            Status::Failed,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_collapse_failed_payment_to_not_approved() {
+        let bank_web = BankWeb::new_test_with_response("service_unavailable")
+            .await
+            .with_collapse_not_approved(true);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.status, ResponseStatus::NotApproved);
+        assert_eq!(
+            payments::get(&pool, data.id).await.unwrap().status,
+            Status::Failed
+        );
+
+        let response = get(&router, format!("/api/payments/{}", data.id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.status, ResponseStatus::NotApproved);
+    }
+
+    #[tokio::test]
+    async fn should_retry_hold_while_service_is_unavailable() {
+        let bank_web = BankWeb::new_test().await.with_hold_retries(2);
+        let hold_calls = bank_web.account_service.hold_calls.clone();
+        bank_web
+            .account_service
+            .unavailable_holds
+            .store(1, std::sync::atomic::Ordering::SeqCst);
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[RETRY_COUNT_HEADER], "1");
+        assert_eq!(hold_calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.status, Status::Approved);
+    }
+
+    #[rstest]
+    #[case::waiting_for_its_turn(1, StatusCode::CREATED)]
+    #[case::wait_queue_full(0, StatusCode::SERVICE_UNAVAILABLE)]
+    #[tokio::test]
+    async fn should_limit_concurrent_holds(
+        #[case] max_waiting: usize,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let account_service = DummyService {
+            hold_delay: Some(std::time::Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let max_concurrent_holds = account_service.max_concurrent_holds.clone();
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let router = BankWeb::new(pool, account_service, FingerprintKey::new_test())
+            .with_account_service_concurrency(ConcurrencyLimit::new(1, max_waiting))
+            .into_router();
+        let request_body = || RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let (first_body, second_body) = (request_body(), request_body());
+
+        let (first, second) = tokio::join!(
+            post(&router, "/api/payments", &first_body),
+            post(&router, "/api/payments", &second_body)
+        );
+        let mut status_codes = [first.status(), second.status()];
+        status_codes.sort();
+        assert_eq!(status_codes, [StatusCode::CREATED, expected_status_code]);
+        assert_eq!(
+            max_concurrent_holds.load(std::sync::atomic::Ordering::SeqCst),
+            1
+        );
+    }
+
+    #[rstest]
+    #[case(1, StatusCode::CREATED)]
+    #[case(2, StatusCode::PAYMENT_REQUIRED)]
+    #[tokio::test]
+    async fn should_retry_hold_declined_once_despite_sufficient_balance(
+        #[case] insufficient_holds: usize,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let bank_web = BankWeb::new_test_with_balance(10_000)
+            .await
+            .with_balance_precheck(true);
+        let hold_calls = bank_web.account_service.hold_calls.clone();
+        bank_web
+            .account_service
+            .insufficient_holds
+            .store(insufficient_holds, std::sync::atomic::Ordering::SeqCst);
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+        // the retry is bounded, honoring the service's second answer
+        assert_eq!(hold_calls.load(std::sync::atomic::Ordering::SeqCst), 2);
+        if expected_status_code == StatusCode::CREATED {
+            let response_body = deserialize_response_body::<ResponseBody>(response).await;
+            assert_eq!(response_body.data.status, Status::Approved);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_validate_payment_reporting_every_error() {
+        let router = BankWeb::new_test()
+            .await
+            .with_amount_bounds("EUR", AmountBounds::new(1_00, 10_000))
+            .into_router();
+        let request_body = |amount, card_number: &str| RequestBody {
+            payment: RequestData {
+                amount,
+                currency: Some("EUR".into()),
+                card_number: card_number.into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(
+            &router,
+            "/api/payments/validate",
+            &request_body(20_000, "not-a-card"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            deserialize_response_body::<ValidationBody>(response)
+                .await
+                .errors,
+            vec![
+                ValidationError {
+                    field: Some("amount".into()),
+                    reason: "amount_too_large".into(),
+                },
+                ValidationError {
+                    field: Some("card_number".into()),
+                    reason: "invalid_card_format".into(),
+                },
+            ]
+        );
+
+        let card_number: String = Card::new_test().into();
+        let response = post(
+            &router,
+            "/api/payments/validate",
+            &request_body(50_00, &card_number),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = deserialize_response_body::<ValidationBody>(response).await;
+        assert!(body.errors.is_empty());
+        // validating doesn't create the payment, so the card can still pay
+        do_payment(
+            &router,
+            50_00,
+            card_number,
+            StatusCode::CREATED,
+            Status::Approved,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_fail_payment_and_return_500_for_unrecognized_service_error() {
+        let router = BankWeb::new_test_with_response("card_on_fire")
+            .await
+            .into_router();
+
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Status::Failed,
+        )
+        .await;
+    }
+
+    async fn post_accepting_problem(router: &Router, amount: i32) -> (StatusCode, Problem) {
+        post_accepting_problem_in(router, amount, "en").await
+    }
+
+    async fn post_accepting_problem_in(
+        router: &Router,
+        amount: i32,
+        accept_language: &str,
+    ) -> (StatusCode, Problem) {
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/payments")
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, format!("application/json, {PROBLEM_JSON}"))
+            .header(ACCEPT_LANGUAGE, accept_language)
+            .body(serde_json::to_vec(&request_body).unwrap().into())
+            .unwrap();
+        let response = send_request(router, request).await;
+        assert_eq!(response.headers()[CONTENT_TYPE], PROBLEM_JSON);
+        (response.status(), deserialize_response_body(response).await)
+    }
+
+    #[tokio::test]
+    async fn should_respond_problem_details_to_invalid_payment() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let (status_code, problem) = post_accepting_problem(&router, -1).await;
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            problem,
+            Problem {
+                problem_type: "/problems/negative_amount".into(),
+                title: "Bad Request".into(),
+                status: 400,
+                detail: "Negative amount".into(),
+                instance: "/api/payments".into(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn should_localize_problem_details() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let (status_code, problem) = post_accepting_problem_in(&router, -1, "fr").await;
+        assert_eq!(status_code, StatusCode::BAD_REQUEST);
+        assert_eq!(problem.problem_type, "/problems/negative_amount");
+        assert_eq!(problem.detail, "Montant négatif");
+        // unsupported languages fall back to English
+        let (_, problem) = post_accepting_problem_in(&router, -1, "de").await;
+        assert_eq!(problem.problem_type, "/problems/negative_amount");
+        assert_eq!(problem.detail, "Negative amount");
+    }
+
+    #[tokio::test]
+    async fn should_respond_problem_details_to_failed_payment() {
+        let router = BankWeb::new_test_with_response("service_unavailable")
+            .await
+            .into_router();
+
+        let (status_code, problem) = post_accepting_problem(&router, 12_05).await;
+        assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(problem.problem_type, "/problems/service_unavailable");
+        assert_eq!(problem.title, "Service Unavailable");
+        assert_eq!(problem.status, 503);
+        assert_eq!(problem.detail, "Service unavailable");
+        // the failed payment is persisted
+        let response = get(&router, &problem.instance).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn should_fail_payment_and_return_500_for_internal_error() {
+        let router = BankWeb::new_test_with_response("internal_error")
+            .await
+            .into_router();
+
+        do_payment(
+            &router,
+            12_05,
+            Card::new_test().into(),
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Status::Failed,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_return_204_for_zero_amount() {
+        let router = BankWeb::new_test().await.into_router();
+
+        do_payment(
+            &router,
+            0,
+            Card::new_test().into(),
+            StatusCode::NO_CONTENT,
+            Status::Declined,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_return_400_for_negative_amount() {
+        let router = BankWeb::new_test().await.into_router();
+
+        do_payment(
+            &router,
+            -1_00,
+            Card::new_test().into(),
+            StatusCode::BAD_REQUEST,
+            Status::Declined,
+        )
+        .await;
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn should_return_422_for_invalid_card_format(
+        #[values("xxx", "", " ", "12345123451234", "ABCDEABCDEABCDEABCDE")]
+        invalid_card_number: String,
+    ) {
+        let router = BankWeb::new_test().await.into_router();
+
+        do_payment(
+            &router,
+            1_23,
+            invalid_card_number,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Status::Declined,
+        )
+        .await;
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn should_report_missing_card_number(#[values("", " ", "\t\n")] card_number: &str) {
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: card_number.into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.decline_reason.as_deref(), Some("missing_card_number"));
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn should_store_normalized_card_number(#[values(" ", "-", " - ")] separator: &str) {
+        let router = BankWeb::new_test().await.with_mask_pan(false).into_router();
+        let card_number: String = Card::new_test().into();
+        let (head, tail) = card_number.split_at(5);
+        let (middle, tail) = tail.split_at(5);
+
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: [head, middle, tail].join(separator),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.card_number, card_number);
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn should_mask_card_number_unless_disabled(#[values(true, false)] mask_pan: bool) {
+        let router = BankWeb::new_test()
+            .await
+            .with_mask_pan(mask_pan)
+            .into_router();
+        let card_number: String = Card::new_test().into();
+
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: card_number.clone(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let created = deserialize_response_body::<ResponseBody>(response).await;
+
+        let response = get(&router, format!("/api/payments/{}", created.data.id)).await;
+        let fetched = deserialize_response_body::<ResponseBody>(response).await;
+
+        let expected = if mask_pan {
+            format!("{}{}", "*".repeat(11), &card_number[11..])
+        } else {
+            card_number.clone()
+        };
+        assert_eq!(created.data.card_number, expected);
+        assert_eq!(fetched.data.card_number, expected);
+
+        // masking only applies to responses
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to create postgres pool");
+        let stored = payments::get(&pool, created.data.id)
+            .await
+            .expect("failed to get payment");
+        assert_eq!(stored.card_number, card_number);
+    }
+
+    #[tokio::test]
+    async fn should_return_422_for_card_number_with_letters_after_normalization() {
+        let router = BankWeb::new_test().await.into_router();
+
+        do_payment(
+            &router,
+            1_23,
+            "12345 12345 1234A".into(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Status::Declined,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_return_422_for_existing_card_number() {
+        let router = BankWeb::new_test().await.into_router();
+        let payment_card_number: String = Card::new_test().into();
+
+        do_payment(
+            &router,
+            1_23,
+            payment_card_number.clone(),
+            StatusCode::CREATED,
+            Status::Approved,
+        )
+        .await;
+        do_payment(
+            &router,
+            1_23,
+            payment_card_number,
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Status::Declined,
+        )
+        .await;
+    }
+
+    async fn create_payment(router: &Router) -> Uuid {
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data
+            .id
+    }
+
+    fn format_query_timestamp(datetime: OffsetDateTime) -> String {
+        datetime
+            .format(&time::format_description::well_known::Rfc3339)
+            .expect("failed to format timestamp")
+            .replace('+', "%2B")
+    }
+
+    async fn export_ids(router: &Router, from: OffsetDateTime, to: OffsetDateTime) -> Vec<Uuid> {
+        export_ids_with_query(
+            router,
+            format!(
+                "from={}&to={}",
+                format_query_timestamp(from),
+                format_query_timestamp(to)
+            ),
+        )
+        .await
+    }
+
+    async fn export_ids_with_query(router: &Router, query: String) -> Vec<Uuid> {
+        let response = get(router, format!("/api/payments/export?{query}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/x-ndjson"
+        );
+
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("failed to read response body into bytes");
+        std::str::from_utf8(&bytes)
+            .expect("export is not valid UTF-8")
+            .lines()
+            .map(|line| {
+                serde_json::from_str::<ResponseData>(line)
+                    .expect("failed to deserialize NDJSON line")
+                    .id
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn should_export_payments_as_ndjson() {
+        let router = BankWeb::new_test().await.into_router();
+        let from = OffsetDateTime::now_utc() - time::Duration::seconds(1);
+
+        let mut payment_ids = Vec::new();
+        for _ in 0..3 {
+            payment_ids.push(create_payment(&router).await);
+        }
+
+        let to = OffsetDateTime::now_utc() + time::Duration::seconds(1);
+        let exported_ids = export_ids(&router, from, to).await;
+        for payment_id in payment_ids {
+            assert!(exported_ids.contains(&payment_id));
+        }
+    }
+
+    #[rstest]
+    // the mock clock is on Saturday 2000-01-01, at 09:00 in UTC+09:00
+    #[case("mon,tue,wed,thu,fri 00:00-23:59 +09:00", StatusCode::FORBIDDEN)]
+    #[case("sat 10:00-18:00 +09:00", StatusCode::FORBIDDEN)]
+    #[case("sat 09:00-18:00 +09:00", StatusCode::CREATED)]
+    #[tokio::test]
+    async fn should_only_accept_payments_within_accepting_window(
+        #[case] window: &str,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let clock = MockClock::new(OffsetDateTime::from_unix_timestamp(946_684_800).unwrap());
+        let router = BankWeb::new_test()
+            .await
+            .with_clock(clock)
+            .with_accepting_window(window.parse().unwrap())
+            .into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        if expected_status_code == StatusCode::FORBIDDEN {
+            assert_eq!(data.status, Status::Declined);
+            assert_eq!(
+                data.decline_reason.as_deref(),
+                Some("outside_accepting_window")
+            );
+        } else {
+            assert_eq!(data.status, Status::Approved);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_decline_payment_over_velocity_limit() {
+        let router = BankWeb::new_test()
+            .await
+            .with_velocity_limit(VelocityLimit::new(3_00, time::Duration::hours(24)))
+            .into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = |amount, card_number: &str| RequestBody {
+            payment: RequestData {
+                amount,
+                currency: None,
+                card_number: card_number.into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body(2_00, &card_number)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // 2.00 already approved for the card
+        let response = post(&router, "/api/payments", &request_body(1_23, &card_number)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.status, Status::Declined);
+        assert_eq!(
+            data.decline_reason.as_deref(),
+            Some("velocity_limit_exceeded")
+        );
+
+        let card_number: String = Card::new_test().into();
+        let response = post(&router, "/api/payments", &request_body(3_01, &card_number)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = post(&router, "/api/payments", &request_body(3_00, &card_number)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn should_decline_payments_over_daily_card_limit_until_next_day() {
+        // an hour before midnight, so that the limit resets well within 24 hours
+        let start =
+            OffsetDateTime::from_unix_timestamp(946_684_800).unwrap() + time::Duration::hours(23);
+        let clock = MockClock::new(start);
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_clock(clock.clone())
+            .with_daily_card_limit(2);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        for _ in 0..2 {
+            let response = post(&router, "/api/payments", &request_body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let payment = deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data;
+            // the card can't be used again until its payment is archived, as it's unique
+            payments::archive(&pool, &clock, payment.id).await.unwrap();
+        }
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.status, Status::Declined);
+        assert_eq!(
+            data.decline_reason.as_deref(),
+            Some("daily_card_limit_exceeded")
+        );
+
+        clock.advance(time::Duration::hours(2));
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn should_return_config_snapshot_of_payment_after_config_changes() {
+        let bank_web = BankWeb::new_test().await;
+        let router = bank_web
+            .clone()
+            .with_fees(FeeConfig::new(30, 250))
+            .with_amount_bounds("EUR", AmountBounds::new(50, 100_000))
+            .with_daily_card_limit(3)
+            .into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: Some("EUR".into()),
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment_id = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data
+            .id;
+
+        let router = bank_web.with_fees(FeeConfig::new(50, 100)).into_router();
+        let response = get(
+            &router,
+            format!("/api/payments/{payment_id}/config-snapshot"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<ConfigSnapshotBody>(response)
+                .await
+                .data,
+            ConfigSnapshot {
+                fee_flat: 30,
+                fee_basis_points: 250,
+                fee_rounding: Rounding::HalfEven,
+                currency: Some("EUR".into()),
+                min_amount: Some(50),
+                max_amount: Some(100_000),
+                velocity_max_amount: None,
+                velocity_window_hours: None,
+                daily_card_payments: Some(3),
+            }
+        );
+
+        let response = get(
+            &router,
+            format!("/api/payments/{}/config-snapshot", Uuid::new_v4()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_store_vault_token_instead_of_card_number() {
+        let vault = FakeVault::default();
+        let bank_web = BankWeb::new_test().await.with_card_vault(vault.clone());
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: card_number.clone(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment_id = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data
+            .id;
+        let stored = payments::get(&pool, payment_id).await.unwrap();
+        assert_ne!(stored.card_number, card_number);
+        assert_eq!(vault.retrieve(&stored.card_number).await, Ok(card_number));
+
+        // the card is still fingerprinted by its number
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_limit_cards_stored_in_vault() {
+        let router = BankWeb::new_test()
+            .await
+            .with_card_vault(FakeVault::default())
+            .with_velocity_limit(VelocityLimit::new(3_00, time::Duration::hours(24)))
+            .with_daily_card_limit(1)
+            .into_router();
+        let request_body = |amount, card_number: &str| RequestBody {
+            payment: RequestData {
+                amount,
+                currency: None,
+                card_number: card_number.into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let card_number: String = Card::new_test().into();
+        let response = post(&router, "/api/payments", &request_body(2_00, &card_number)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // 2.00 already approved for the card, though stored as a token
+        let response = post(&router, "/api/payments", &request_body(1_23, &card_number)).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(
+            data.decline_reason.as_deref(),
+            Some("velocity_limit_exceeded")
+        );
+
+        // within the velocity limit, but over the daily one
+        let response = post(&router, "/api/payments", &request_body(1_00, &card_number)).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(
+            data.decline_reason.as_deref(),
+            Some("daily_card_limit_exceeded")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_duplicate_merchant_reference_of_same_merchant() {
+        let router = BankWeb::new_test().await.into_router();
+        let merchant_id = Uuid::new_v4();
+        let other_merchant_id = Uuid::new_v4();
+        let request_body = |merchant_id| RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: Some(merchant_id),
+                merchant_reference: Some("order-42".into()),
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body(merchant_id)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = post(&router, "/api/payments", &request_body(merchant_id)).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.status, Status::Declined);
+        assert_eq!(
+            data.decline_reason.as_deref(),
+            Some("duplicate_merchant_reference")
+        );
+
+        let response = post(&router, "/api/payments", &request_body(other_merchant_id)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+    }
+
+    #[tokio::test]
+    async fn should_return_repeated_payment_within_implicit_idempotency_window() {
+        let merchant_id = Uuid::new_v4();
+        let clock = MockClock::new(OffsetDateTime::now_utc());
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_clock(clock.clone())
+            .with_implicit_idempotency(ImplicitIdempotency::new(
+                HashSet::from([merchant_id]),
+                time::Duration::minutes(10),
+            ));
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: Some(merchant_id),
+                merchant_reference: Some("order-42".into()),
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let first = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+
+        clock.advance(time::Duration::minutes(9));
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let second = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(second.id, first.id);
+
+        // the card can't be used again until its payment is archived, as it's unique
+        payments::archive(&pool, &clock, first.id).await.unwrap();
+        clock.advance(time::Duration::minutes(2));
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let third = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_ne!(third.id, first.id);
+    }
+
+    #[tokio::test]
+    async fn should_not_return_repeated_payment_of_another_merchant() {
+        let merchant_id = Uuid::new_v4();
+        let other_merchant_id = Uuid::new_v4();
+        let router = BankWeb::new_test()
+            .await
+            .with_implicit_idempotency(ImplicitIdempotency::new(
+                HashSet::from([merchant_id, other_merchant_id]),
+                time::Duration::minutes(10),
+            ))
+            .into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = |merchant_id| RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: card_number.clone(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: Some(merchant_id),
+                merchant_reference: Some("order-42".into()),
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body(merchant_id)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // made again rather than returned, so the card is rejected as duplicated
+        let response = post(&router, "/api/payments", &request_body(other_merchant_id)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(
+            data.decline_reason.as_deref(),
+            Some("duplicated_card_number")
+        );
+    }
+
+    #[tokio::test]
+    async fn should_make_payment_with_duplicated_card_when_demoted() {
+        let bank_web = BankWeb::new_test().await.with_demote_duplicate_cards(true);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let response = post(&router, "/api/payments", &request_body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            let data = deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data;
+            assert_eq!(data.status, Status::Approved);
+            ids.push(data.id);
+        }
+        assert_ne!(ids[0], ids[1]);
+        for id in ids {
+            assert_eq!(
+                payments::get(&pool, id).await.unwrap().status,
+                Status::Approved
+            );
+        }
+    }
+
+    #[rstest]
+    #[case(false, StatusCode::UNPROCESSABLE_ENTITY)]
+    #[case(true, StatusCode::CREATED)]
+    #[tokio::test]
+    async fn should_only_accept_test_cards_in_sandbox(
+        #[case] sandbox: bool,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let router = BankWeb::new_test()
+            .await
+            .with_sandbox(sandbox)
+            .with_test_card_bins("99".parse().unwrap())
+            .into_router();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 1_20,
+                currency: None,
+                card_number: Card::new_with_account_number("99").into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        if sandbox {
+            assert_eq!(data.status, Status::Approved);
+        } else {
+            assert_eq!(data.status, Status::Declined);
+            assert_eq!(data.decline_reason.as_deref(), Some("card_not_accepted"));
+        }
+    }
+
+    #[tokio::test]
+    async fn should_timestamp_payments_with_clock() {
+        let start = OffsetDateTime::from_unix_timestamp(946_684_800).unwrap();
+        let clock = MockClock::new(start);
+        let router = BankWeb::new_test()
+            .await
+            .with_clock(clock.clone())
+            .into_router();
+
+        let first_payment_id = create_payment(&router).await;
+        clock.advance(time::Duration::days(1));
+        let second_payment_id = create_payment(&router).await;
+
+        let exported_ids = export_ids(&router, start, start + time::Duration::hours(1)).await;
+        assert!(exported_ids.contains(&first_payment_id));
+        assert!(!exported_ids.contains(&second_payment_id));
+    }
+
+    #[tokio::test]
+    async fn should_resume_export_after_cursor() {
+        // all payments share the same timestamp, so the id breaks the tie
+        let start = OffsetDateTime::from_unix_timestamp(978_307_200).unwrap();
+        let router = BankWeb::new_test()
+            .await
+            .with_clock(MockClock::new(start))
+            .into_router();
+        for _ in 0..4 {
+            create_payment(&router).await;
+        }
+
+        let end = start + time::Duration::seconds(1);
+        let all_ids = export_ids(&router, start, end).await;
+        assert!(all_ids.len() >= 4);
+        let (first_half, second_half) = all_ids.split_at(all_ids.len() / 2);
+
+        let resumed_ids = export_ids_with_query(
+            &router,
+            format!(
+                "from={}&to={}&after_id={}",
+                format_query_timestamp(start),
+                format_query_timestamp(end),
+                first_half.last().unwrap()
+            ),
+        )
+        .await;
+        assert_eq!(resumed_ids, second_half);
+
+        let resumed_ids = export_ids_with_query(
+            &router,
+            format!(
+                "to={}&after_id={}&after_inserted_at={}",
+                format_query_timestamp(end),
+                first_half.last().unwrap(),
+                format_query_timestamp(start)
+            ),
+        )
+        .await;
+        assert_eq!(resumed_ids, second_half);
+    }
+
+    #[tokio::test]
+    async fn should_return_head_of_existing_payment() {
+        let router = BankWeb::new_test().await.into_router();
+        let payment_id = create_payment(&router).await;
+
+        let response = head(&router, format!("/api/payments/{payment_id}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            "application/json"
+        );
+
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("failed to read response body into bytes");
+        assert!(bytes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_return_404_for_head_and_get_of_unknown_payment() {
+        let router = BankWeb::new_test().await.into_router();
+        let uri = format!("/api/payments/{}", Uuid::new_v4());
+
+        assert_eq!(head(&router, &uri).await.status(), StatusCode::NOT_FOUND);
+        assert_eq!(get(&router, &uri).await.status(), StatusCode::NOT_FOUND);
+    }
+
+    async fn authorize_payment(
+        router: &Router,
+        amount: i32,
+        hold_amount: Option<i32>,
+    ) -> (StatusCode, ResponseData) {
+        let request_body = AuthorizeRequestBody {
+            payment: AuthorizeRequestData {
+                amount,
+                card_number: Card::new_test().into(),
+                hold_amount,
+            },
+        };
+        let response = post(router, "/api/payments/authorize", &request_body).await;
+        let status_code = response.status();
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        (status_code, data)
+    }
+
+    async fn force_fail_payment(router: &Router, payment_id: Uuid, token: &str) -> Response {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("/api/admin/payments/{payment_id}/force-fail"))
+            .header(AUTHORIZATION, format!("Bearer {token}"))
+            .body(hyper::Body::empty())
+            .unwrap();
+        send_request(router, request).await
+    }
+
+    #[tokio::test]
+    async fn should_force_fail_uncaptured_authorization_in_sandbox() {
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_admin_token("secret")
+            .with_sandbox(true)
+            .with_webhook_url(format!("http://receiver.test/{}", Uuid::new_v4()));
+        let pool = bank_web.pool.clone();
+        let released_holds = bank_web.account_service.released_holds.clone();
+        let webhook_url = bank_web.webhook_url.clone().unwrap();
+        let router = bank_web.into_router();
+        let (_, authorization) = authorize_payment(&router, 10_00, None).await;
+        let hold_id = payments::get(&pool, authorization.id)
+            .await
+            .unwrap()
+            .hold_id
+            .expect("authorization has no hold");
+
+        let response = force_fail_payment(&router, authorization.id, "wrong").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = force_fail_payment(&router, authorization.id, "secret").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.status, Status::Failed);
+        assert_eq!(
+            data.decline_reason.as_deref(),
+            Some(payments::FORCED_FAILURE_REASON)
+        );
+        assert_eq!(*released_holds.lock().unwrap(), vec![hold_id]);
+        let events: Vec<_> = webhooks::list(&pool, 1_000)
+            .await
+            .unwrap()
+            .into_iter()
+            .filter(|delivery| delivery.url == webhook_url)
+            .map(|delivery| delivery.payload["event"].clone())
+            .collect();
+        assert!(events.contains(&PAYMENT_FAILED_EVENT.into()));
+
+        // failed payments are terminal
+        let response = force_fail_payment(&router, authorization.id, "secret").await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn should_not_force_fail_outside_sandbox() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token("secret")
+            .into_router();
+        let (_, authorization) = authorize_payment(&router, 10_00, None).await;
+
+        let response = force_fail_payment(&router, authorization.id, "secret").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_send_test_webhook_to_one_off_url() {
+        let received = Arc::new(std::sync::Mutex::new(None));
+        let receiver = Router::new().route(
+            "/hook",
+            axum::routing::post({
+                let received = received.clone();
+                move |headers: HeaderMap, body: axum::body::Bytes| async move {
+                    *received.lock().unwrap() = Some((headers, body));
+                    StatusCode::ACCEPTED
+                }
+            }),
+        );
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(
+            axum::Server::from_tcp(listener)
+                .unwrap()
+                .serve(receiver.into_make_service()),
+        );
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token("secret")
+            .into_router();
+        let payment_id = create_payment(&router).await;
+        let webhook_test = |payment_id: Uuid, url: String| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(format!("/api/admin/payments/{payment_id}/webhook-test"))
+                .header(AUTHORIZATION, "Bearer secret")
+                .header(CONTENT_TYPE, "application/json")
+                .body(
+                    serde_json::to_vec(&WebhookTestRequestBody { url })
+                        .unwrap()
+                        .into(),
+                )
+                .unwrap();
+            send_request(&router, request)
+        };
+
+        let response = webhook_test(payment_id, format!("http://{addr}/hook")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<WebhookTestBody>(response)
+                .await
+                .data,
+            WebhookTestData { status_code: 202 }
+        );
+        let (headers, body) = received
+            .lock()
+            .unwrap()
+            .take()
+            .expect("webhook wasn't delivered");
+        let payload: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(payload["event"], PAYMENT_CREATED_EVENT);
+        assert_eq!(payload["data"]["id"], payment_id.to_string().as_str());
+        assert_eq!(
+            headers[webhooks::WEBHOOK_SIGNATURE_HEADER],
+            webhooks::sign_payload(webhooks::TEST_WEBHOOK_SECRET.as_bytes(), &body).as_str()
+        );
+
+        let response = webhook_test(Uuid::new_v4(), format!("http://{addr}/hook")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let response = webhook_test(payment_id, "ftp://receiver.test/hook".into()).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    async fn capture_payment(router: &Router, payment_id: Uuid, amount: Option<i32>) -> Response {
+        let request_body = CaptureRequestBody {
+            capture: CaptureRequestData { amount },
+        };
+        let uri = format!("/api/payments/{payment_id}/capture");
+        post(router, uri, &request_body).await
+    }
+
+    #[tokio::test]
+    async fn should_capture_less_than_held_amount() {
+        let router = BankWeb::new_test()
+            .await
+            .with_fees(FeeConfig::new(30, 0))
+            .into_router();
+
+        let (status_code, payment) = authorize_payment(&router, 50_00, Some(80_00)).await;
+        assert_eq!(status_code, StatusCode::CREATED);
+        assert_eq!(payment.status, Status::Authorized);
+        assert_eq!(payment.amount, 50_00);
+        assert_eq!(payment.hold_amount, 80_00);
+        assert_eq!(payment.fee_amount, 0);
+
+        let response = capture_payment(&router, payment.id, Some(50_00)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let captured = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(captured.status, Status::Approved);
+        assert_eq!(captured.amount, 50_00);
+        assert_eq!(captured.hold_amount, 80_00);
+        assert_eq!(captured.fee_amount, 30);
+        assert_eq!(captured.net_amount, 49_70);
+
+        // a captured payment can't be captured again
+        let response = capture_payment(&router, payment.id, None).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn should_capture_once_per_idempotency_key() {
+        let bank_web = BankWeb::new_test().await;
+        let captured_holds = bank_web.account_service.captured_holds.clone();
+        let router = bank_web.into_router();
+        let (_, payment) = authorize_payment(&router, 50_00, Some(80_00)).await;
+        let idempotency_key = Uuid::new_v4().to_string();
+        let request = |amount: Option<i32>, idempotency_key: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!("/api/payments/{}/capture", payment.id))
+                .header(CONTENT_TYPE, "application/json")
+                .header(
+                    crate::bank_web::refunds::IDEMPOTENCY_KEY_HEADER,
+                    idempotency_key,
+                )
+                .body(
+                    serde_json::to_vec(&CaptureRequestBody {
+                        capture: CaptureRequestData { amount },
+                    })
+                    .unwrap()
+                    .into(),
+                )
+                .unwrap()
+        };
+
+        let (first, second) = tokio::join!(
+            send_request(&router, request(Some(60_00), &idempotency_key)),
+            send_request(&router, request(Some(60_00), &idempotency_key))
+        );
+        assert_eq!(first.status(), StatusCode::OK);
+        assert_eq!(second.status(), StatusCode::OK);
+        let first = deserialize_response_body::<ResponseBody>(first).await.data;
+        let second = deserialize_response_body::<ResponseBody>(second).await.data;
+        assert_eq!(first.status, Status::Approved);
+        assert_eq!(first.amount, 60_00);
+        assert_eq!(first, second);
+        // the hold was settled once, a concurrent retry at most capturing it again
+        let hold_captures = captured_holds.lock().unwrap().clone();
+        assert!(!hold_captures.is_empty());
+        assert!(hold_captures
+            .iter()
+            .all(|hold_id| *hold_id == hold_captures[0]));
+
+        let response = send_request(&router, request(None, &idempotency_key)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let response = send_request(&router, request(Some(70_00), &idempotency_key)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = send_request(&router, request(None, "another key")).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        let response = send_request(&router, request(None, "")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_auto_capture_authorizations_not_voided_within_delay() {
+        use rand::Rng;
+
+        // a random day of the 17th century, before which no other test authorizes
+        let day = time::Date::from_calendar_date(1600, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc());
+        let fees = FeeConfig::new(30, 0);
+        let delay = time::Duration::hours(24);
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_admin_token("secret")
+            .with_sandbox(true)
+            .with_clock(clock.clone())
+            .with_fees(fees)
+            .with_auto_capture_delay(delay);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+
+        let (_, authorized) = authorize_payment(&router, 10_00, Some(15_00)).await;
+        let (_, voided) = authorize_payment(&router, 20_00, None).await;
+        let response = force_fail_payment(&router, voided.id, "secret").await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        clock.advance(delay - time::Duration::seconds(1));
+        let captured = payments::capture_due(&pool, &DummyService::default(), &clock, &fees, delay)
+            .await
+            .unwrap();
+        assert!(captured.iter().all(|captured| captured.id != authorized.id));
+
+        clock.advance(time::Duration::seconds(1));
+        let captured = payments::capture_due(&pool, &DummyService::default(), &clock, &fees, delay)
+            .await
+            .unwrap();
+        assert!(captured.iter().any(|captured| captured.id == authorized.id));
+        assert!(captured.iter().all(|captured| captured.id != voided.id));
+
+        let persisted = payments::get(&pool, authorized.id).await.unwrap();
+        assert_eq!(persisted.status, Status::Approved);
+        assert_eq!(persisted.amount, 10_00);
+        assert_eq!(persisted.fee_amount, 30);
+        assert_eq!(persisted.hold_id, None);
+        let persisted = payments::get(&pool, voided.id).await.unwrap();
+        assert_eq!(persisted.status, Status::Failed);
+    }
 
-    (
-        StatusCode::OK,
-        Json(ResponseBody {
-            data: ResponseData {
-                id: payment.id,
-                amount: payment.amount,
-                card_number: payment.card_number,
-                status: payment.status,
-            },
-        }),
-    )
-}
+    #[tokio::test]
+    async fn should_list_authorizations_expiring_within_window() {
+        use rand::Rng;
 
-#[cfg(test)]
-pub mod tests {
-    use super::*;
-    use crate::{
-        bank::{payment_instruments::Card, payments::Status},
-        bank_web::tests::{deserialize_response_body, post},
-    };
-    use axum::Router;
-    use rstest::rstest;
+        // a random day of the 23rd century, after which no other test authorizes
+        let day = time::Date::from_calendar_date(2200, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc());
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token("secret")
+            .with_clock(clock.clone())
+            .into_router();
+        let list_expiring = |within: i64| {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(format!(
+                    "/api/admin/authorizations/expiring?within={within}"
+                ))
+                .header(AUTHORIZATION, "Bearer secret")
+                .body(hyper::Body::empty())
+                .unwrap();
+            send_request(&router, request)
+        };
 
-    async fn do_payment(
-        router: &Router,
-        payment_amount: i32,
-        payment_card_number: String,
-        expected_status_code: StatusCode,
-        expected_status: Status,
-    ) {
+        let (_, expiring) = authorize_payment(&router, 10_00, None).await;
+        clock.advance(time::Duration::days(6));
+        let (_, far_off) = authorize_payment(&router, 20_00, None).await;
+        assert_eq!(
+            far_off.authorized_expires_at,
+            Some(clock.now_utc().assume_utc() + payments::AUTHORIZATION_LIFETIME)
+        );
+
+        let day = time::Duration::days(1).whole_seconds();
+        let response = list_expiring(day).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let ids: Vec<_> = deserialize_response_body::<ListBody>(response)
+            .await
+            .data
+            .into_iter()
+            .map(|payment| payment.id)
+            .collect();
+        assert_eq!(ids, vec![expiring.id]);
+
+        let response = list_expiring(0).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_list_stuck_holds() {
+        use rand::Rng;
+
+        // a random day of the 25th century, after which no other test holds
+        let day = time::Date::from_calendar_date(2400, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc());
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token("secret")
+            .with_clock(clock.clone())
+            .into_router();
+        let list_stuck = |older_than: i64| {
+            let request = Request::builder()
+                .method(Method::GET)
+                .uri(format!("/api/admin/stuck-holds?older_than={older_than}"))
+                .header(AUTHORIZATION, "Bearer secret")
+                .body(hyper::Body::empty())
+                .unwrap();
+            send_request(&router, request)
+        };
+
+        let (_, stuck) = authorize_payment(&router, 10_00, None).await;
+        clock.advance(time::Duration::hours(2));
+        let (_, healthy) = authorize_payment(&router, 20_00, None).await;
+
+        let hour = time::Duration::hours(1).whole_seconds();
+        let response = list_stuck(hour).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let ids: Vec<_> = deserialize_response_body::<ListBody>(response)
+            .await
+            .data
+            .into_iter()
+            .map(|payment| payment.id)
+            .collect();
+        assert!(ids.contains(&stuck.id));
+        assert!(!ids.contains(&healthy.id));
+
+        let response = list_stuck(0).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_settle_delayed_payment_once_delay_elapsed_in_sandbox() {
+        use rand::Rng;
+
+        // a random day of the 20th century, on which no other payment is settled
+        let day = time::Date::from_calendar_date(1900, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc());
+        let fees = FeeConfig::new(30, 0);
+        let delay = time::Duration::seconds(30);
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_sandbox(true)
+            .with_clock(clock.clone())
+            .with_fees(fees)
+            .with_settlement_delay(delay);
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
         let request_body = RequestBody {
             payment: RequestData {
-                amount: payment_amount,
-                card_number: payment_card_number,
+                amount: 10_00,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
             },
         };
 
-        let response = post(router, "/api/payments", &request_body).await;
-        assert_eq!(response.status(), expected_status_code);
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(payment.status, Status::Processing);
+        assert_eq!(payment.fee_amount, 0);
+        assert_eq!(payment.processing_duration_ms, None);
 
-        let response_body = deserialize_response_body::<ResponseBody>(response).await;
-        assert_eq!(response_body.data.amount, request_body.payment.amount);
-        assert_eq!(
-            response_body.data.card_number,
-            request_body.payment.card_number
-        );
-        assert_eq!(response_body.data.status, expected_status);
-        assert!((expected_status_code == StatusCode::CREATED) ^ response_body.data.id.is_nil())
+        clock.advance(delay - time::Duration::seconds(1));
+        let settled = payments::settle_due(&pool, &clock, &fees, delay)
+            .await
+            .unwrap();
+        assert!(settled.iter().all(|settled| settled.id != payment.id));
+
+        clock.advance(time::Duration::seconds(1));
+        let settled = payments::settle_due(&pool, &clock, &fees, delay)
+            .await
+            .unwrap();
+        assert!(settled.iter().any(|settled| settled.id == payment.id));
+        let uri = format!("/api/payments/{}", payment.id);
+        let persisted = deserialize_response_body::<ResponseBody>(get(&router, uri).await)
+            .await
+            .data;
+        assert_eq!(persisted.status, Status::Approved);
+        assert_eq!(persisted.fee_amount, 30);
+        assert_eq!(persisted.net_amount, 10_00 - 30);
+        assert_eq!(persisted.processing_duration_ms, Some(30_000));
     }
 
     #[tokio::test]
-    async fn should_approve_valid_payment() {
+    async fn should_omit_processing_duration_of_instantly_approved_payment() {
         let router = BankWeb::new_test().await.into_router();
-        let payment_amount = 12_05;
-        let payment_card_number: String = Card::new_test().into();
-        do_payment(
+        let payment_id = create_payment(&router).await;
+
+        let uri = format!("/api/payments/{payment_id}");
+        let payment = deserialize_response_body::<ResponseBody>(get(&router, uri).await)
+            .await
+            .data;
+        assert_eq!(payment.status, Status::Approved);
+        assert_eq!(payment.processing_duration_ms, None);
+    }
+
+    #[tokio::test]
+    async fn should_stream_status_changes_until_terminal() {
+        let router = BankWeb::new_test().await.into_router();
+        let (_, payment) = authorize_payment(&router, 50_00, None).await;
+
+        let response = get(
             &router,
-            payment_amount,
-            payment_card_number.clone(),
-            StatusCode::CREATED,
-            Status::Approved,
+            format!("/api/payments/{}/events/stream", payment.id),
         )
         .await;
-    }
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[CONTENT_TYPE], "text/event-stream");
 
-    #[tokio::test]
-    async fn should_decline_payment_and_return_402_with_insufficient_funds() {
-        let router = BankWeb::new_test_with_response("insufficient_funds")
-            .await
-            .into_router();
+        let captured = capture_payment(&router, payment.id, None).await;
+        assert_eq!(captured.status(), StatusCode::OK);
 
-        do_payment(
+        let body = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            hyper::body::to_bytes(response.into_body()),
+        )
+        .await
+        .expect("the stream didn't close after the terminal status")
+        .expect("failed to read the stream");
+        let statuses: Vec<StatusEventData> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| serde_json::from_str(data.trim_start()).unwrap())
+            .collect();
+        assert_eq!(
+            statuses,
+            [Status::Authorized, Status::Approved].map(|status| StatusEventData {
+                id: payment.id,
+                status
+            })
+        );
+
+        let response = get(
             &router,
-            12_05,
-            Card::new_test().into(),
-            StatusCode::PAYMENT_REQUIRED,
-            Status::Declined,
+            format!("/api/payments/{}/events/stream", Uuid::new_v4()),
         )
         .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn should_decline_payment_and_return_403_for_invalid_account_number() {
-        let router = BankWeb::new_test_with_response("invalid_account_number")
+    async fn should_reject_hold_amount_below_amount() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let (status_code, payment) = authorize_payment(&router, 50_00, Some(40_00)).await;
+        assert_eq!(status_code, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(payment.id.is_nil());
+    }
+
+    #[tokio::test]
+    async fn should_reject_capture_above_held_amount() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let (_, payment) = authorize_payment(&router, 50_00, None).await;
+        assert_eq!(payment.hold_amount, 50_00);
+
+        let response = capture_payment(&router, payment.id, Some(50_01)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = capture_payment(&router, Uuid::new_v4(), None).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_resume_capture_interrupted_after_the_hold_was_captured() {
+        let router = BankWeb::new_test().await.into_router();
+        let (_, payment) = authorize_payment(&router, 50_00, Some(80_00)).await;
+
+        // the account service captures the hold, but its answer is lost
+        let mut bank_web = BankWeb::new_test().await;
+        bank_web.account_service.capture_response = Some("service_unavailable".into());
+        let interrupted_router = bank_web.into_router();
+        let response = capture_payment(&interrupted_router, payment.id, Some(30_00)).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        let response = get(&router, format!("/api/payments/{}", payment.id)).await;
+        let capturing = deserialize_response_body::<ResponseBody>(response)
             .await
-            .into_router();
+            .data;
+        assert_eq!(capturing.status, Status::Capturing);
+        assert_eq!(capturing.amount, 30_00);
 
-        do_payment(
-            &router,
-            12_05,
-            Card::new_test().into(),
-            StatusCode::FORBIDDEN,
-            Status::Declined,
-        )
-        .await;
+        // the hold may already be captured for 30_00: capturing another amount would
+        // withdraw the funds twice
+        let response = capture_payment(&router, payment.id, Some(50_00)).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = capture_payment(&router, payment.id, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let captured = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(captured.status, Status::Approved);
+        assert_eq!(captured.amount, 30_00);
+        assert_eq!(captured.hold_amount, 80_00);
     }
 
     #[tokio::test]
-    async fn should_fail_payment_and_return_503_for_service_unavailable() {
-        let router = BankWeb::new_test_with_response("service_unavailable")
+    async fn should_look_up_statuses_of_known_and_unknown_payments() {
+        let router = BankWeb::new_test().await.into_router();
+        let approved_id = create_payment(&router).await;
+        let unknown_id = Uuid::new_v4();
+
+        let request_body = StatusLookupRequestBody {
+            ids: vec![unknown_id, approved_id],
+        };
+        let response = post(&router, "/api/payments/status-lookup", &request_body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response_body = deserialize_response_body::<StatusLookupResponseBody>(response).await;
+        assert_eq!(
+            response_body.statuses,
+            vec![
+                StatusLookupData {
+                    id: unknown_id,
+                    status: None,
+                },
+                StatusLookupData {
+                    id: approved_id,
+                    status: Some(Status::Approved),
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_status_lookup_of_too_many_payments() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let request_body = StatusLookupRequestBody {
+            ids: (0..=MAX_STATUS_LOOKUP_IDS)
+                .map(|_| Uuid::new_v4())
+                .collect(),
+        };
+        let response = post(&router, "/api/payments/status-lookup", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[tokio::test]
+    async fn should_report_zero_amount_batch_item_as_failed() {
+        let router = BankWeb::new_test().await.into_router();
+        let payment = |amount| RequestData {
+            amount,
+            currency: None,
+            card_number: Card::new_test().into(),
+            metadata: Default::default(),
+            customer_id: None,
+            merchant_id: None,
+            merchant_reference: None,
+            channel: None,
+        };
+
+        let request_body = BatchRequestBody {
+            payments: vec![payment(1_23), payment(0), payment(4_56)],
+        };
+        let response = post(&router, "/api/payments/batch", &request_body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let results = deserialize_response_body::<BatchResponseBody>(response)
             .await
-            .into_router();
+            .results;
+        assert_eq!(results.len(), 3);
+        for (result, amount) in [(&results[0], 1_23), (&results[2], 4_56)] {
+            assert_eq!(result.status, StatusCode::CREATED.as_u16());
+            assert_eq!(result.error, None);
+            let data = result.data.as_ref().expect("missing payment");
+            assert_eq!(data.amount, amount);
+            assert_eq!(data.status, Status::Approved);
+        }
+        assert_eq!(
+            results[1],
+            BatchResultData {
+                status: StatusCode::UNPROCESSABLE_ENTITY.as_u16(),
+                data: None,
+                error: Some(ErrorBody {
+                    error: "zero_amount".to_owned(),
+                }),
+            }
+        );
+    }
 
-        do_payment(
-            &router,
-            12_05,
-            Card::new_test().into(),
-            StatusCode::SERVICE_UNAVAILABLE,
-            Status::Failed,
-        )
-        .await;
+    #[tokio::test]
+    async fn should_reject_batch_of_too_many_payments() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let request_body = BatchRequestBody {
+            payments: (0..=MAX_BATCH_PAYMENTS)
+                .map(|_| RequestData {
+                    amount: 1_23,
+                    currency: None,
+                    card_number: Card::new_test().into(),
+                    metadata: Default::default(),
+                    customer_id: None,
+                    merchant_id: None,
+                    merchant_reference: None,
+                    channel: None,
+                })
+                .collect(),
+        };
+        let response = post(&router, "/api/payments/batch", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
     }
 
     #[tokio::test]
-    async fn should_fail_payment_and_return_500_for_internal_error() {
-        let router = BankWeb::new_test_with_response("internal_error")
+    async fn should_mask_card_number_of_archived_payment() {
+        let router = BankWeb::new_test()
             .await
+            .with_admin_token(ADMIN_TOKEN)
             .into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = RequestBody {
+            payment: RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: card_number.clone(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        let payment_id = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data
+            .id;
 
-        do_payment(
-            &router,
-            12_05,
-            Card::new_test().into(),
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Status::Failed,
-        )
-        .await;
+        let uri = format!("/api/admin/payments/{payment_id}/archive");
+        let response = post(&router, &uri, &()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = post_as_admin(&router, &uri, &()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let archived = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(
+            archived.data.card_number,
+            format!("{}{}", "*".repeat(11), &card_number[11..])
+        );
+
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to create postgres pool");
+        let stored = payments::get(&pool, payment_id)
+            .await
+            .expect("failed to get payment");
+        assert_eq!(stored.card_number, archived.data.card_number);
+        assert!(stored.archived_at.is_some());
+
+        // PANs of archived payments are no longer served
+        let response = get(&router, format!("/api/payments/{payment_id}")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            post_as_admin(&router, &uri, &()).await.status(),
+            StatusCode::NOT_FOUND
+        );
     }
 
     #[tokio::test]
-    async fn should_return_204_for_zero_amount() {
+    async fn should_reject_card_number_sent_as_amount() {
         let router = BankWeb::new_test().await.into_router();
+        let request_body = serde_json::json!({
+            "payment": {
+                "amount": 1234_5678_1234_5678_i64,
+                "card_number": "1205",
+            }
+        });
 
-        do_payment(
-            &router,
-            0,
-            Card::new_test().into(),
-            StatusCode::NO_CONTENT,
-            Status::Declined,
-        )
-        .await;
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("failed to read response body into bytes");
+        let message = String::from_utf8_lossy(&bytes);
+        assert!(
+            message.contains("amount has 16 digits, which looks like a card number"),
+            "unexpected rejection: {message}"
+        );
     }
 
     #[tokio::test]
-    async fn should_return_400_for_negative_amount() {
+    async fn should_accept_amount_as_numeric_string() {
         let router = BankWeb::new_test().await.into_router();
 
-        do_payment(
-            &router,
-            -1_00,
-            Card::new_test().into(),
-            StatusCode::BAD_REQUEST,
-            Status::Declined,
-        )
-        .await;
+        let mut payments = Vec::new();
+        for amount in [serde_json::json!(1205), serde_json::json!("1205")] {
+            let request_body = serde_json::json!({
+                "payment": {
+                    "amount": amount,
+                    "card_number": String::from(Card::new_test()),
+                }
+            });
+            let response = post(&router, "/api/payments", &request_body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+            payments.push(
+                deserialize_response_body::<ResponseBody>(response)
+                    .await
+                    .data,
+            );
+        }
+
+        for payment in payments {
+            assert_eq!(payment.amount, 12_05);
+            assert_eq!(payment.status, Status::Approved);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_non_numeric_amount_string() {
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = serde_json::json!({
+            "payment": {
+                "amount": "12,05",
+                "card_number": String::from(Card::new_test()),
+            }
+        });
+
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("failed to read response body into bytes");
+        let message = String::from_utf8_lossy(&bytes);
+        assert!(
+            message.contains("isn't a number of cents"),
+            "unexpected rejection: {message}"
+        );
     }
 
     #[rstest]
+    #[case("12.05", "EUR", Some(12_05))]
+    #[case("12.055", "EUR", None)]
+    #[case("1205", "EUR", Some(12_05))]
+    #[case("12.5", "jpy", None)]
+    #[case("1205", "JPY", Some(1205))]
     #[tokio::test]
-    async fn should_return_422_for_invalid_card_format(
-        #[values("xxx", "", " ", "12345123451234", "ABCDEABCDEABCDEABCDE")]
-        invalid_card_number: String,
+    async fn should_reject_amount_with_fractional_minor_units(
+        #[case] amount: &str,
+        #[case] currency: &str,
+        #[case] expected_amount: Option<i32>,
     ) {
         let router = BankWeb::new_test().await.into_router();
+        let request_body = serde_json::json!({
+            "payment": {
+                "amount": amount,
+                "currency": currency,
+                "card_number": String::from(Card::new_test()),
+            }
+        });
 
-        do_payment(
+        let response = post(&router, "/api/payments", &request_body).await;
+        let Some(expected_amount) = expected_amount else {
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            let bytes = hyper::body::to_bytes(response.into_body())
+                .await
+                .expect("failed to read response body into bytes");
+            let message = String::from_utf8_lossy(&bytes);
+            assert!(
+                message.contains("decimals of its currency"),
+                "unexpected rejection: {message}"
+            );
+            return;
+        };
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.amount, expected_amount);
+    }
+
+    #[tokio::test]
+    async fn should_verify_payment_against_its_events() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+        let payment_id = create_payment(&router).await;
+        let uri = format!("/api/admin/payments/{payment_id}/verify");
+
+        let response = get(&router, &uri).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = get_as_admin(&router, &uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<VerifyBody>(response).await.data;
+        assert!(data.consistent);
+        assert_eq!(data.discrepancies, []);
+
+        let response = get_as_admin(
             &router,
-            1_23,
-            invalid_card_number,
-            StatusCode::UNPROCESSABLE_ENTITY,
-            Status::Declined,
+            format!("/api/admin/payments/{}/verify", Uuid::new_v4()),
         )
         .await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn should_return_422_for_existing_card_number() {
+    async fn should_forbid_verifying_payments_without_admin_token_configured() {
         let router = BankWeb::new_test().await.into_router();
-        let payment_card_number: String = Card::new_test().into();
+        let payment_id = create_payment(&router).await;
 
-        do_payment(
-            &router,
-            1_23,
-            payment_card_number.clone(),
-            StatusCode::CREATED,
-            Status::Approved,
-        )
-        .await;
-        do_payment(
+        let uri = format!("/api/admin/payments/{payment_id}/verify");
+        let response = get_as_admin(&router, uri).await;
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn should_only_return_selected_fields() {
+        let router = BankWeb::new_test().await.into_router();
+        let payment_id = create_payment(&router).await;
+
+        let response = get(
             &router,
-            1_23,
-            payment_card_number,
-            StatusCode::UNPROCESSABLE_ENTITY,
-            Status::Declined,
+            format!("/api/payments/{payment_id}?fields=id,status"),
         )
         .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = deserialize_response_body::<serde_json::Value>(response).await;
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "data": { "id": payment_id, "status": "approved" }
+            })
+        );
+
+        for fields in ["id,unknown", "id,id", ""] {
+            let response = get(
+                &router,
+                format!("/api/payments/{payment_id}?fields={fields}"),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{fields:?}");
+        }
     }
 }
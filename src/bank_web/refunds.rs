@@ -1,18 +1,42 @@
 use axum::{
-    extract::{Path, State},
-    http::StatusCode,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
     Json,
 };
 use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, PrimitiveDateTime};
 use uuid::Uuid;
 
-use super::BankWeb;
-use crate::bank::refunds::CreateError;
-use crate::bank::{accounts::AccountService, refunds};
+use super::{admin::AdminAuth, payments, BankWeb};
+use crate::bank::refunds::{
+    Conversion, CreateError, CreatedRefund, IneligibilityReason, LineItem, Refund, RefundStatus,
+    RemainingRefund, SearchFilter, SearchOrder,
+};
+use crate::bank::{accounts::AccountService, clock::to_primitive_utc, refunds, webhooks};
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RequestData {
     amount: i32,
+    /// Defaults to the payment's currency, which is the only one accepted.
+    #[serde(default)]
+    currency: Option<String>,
+    /// Card to credit instead of the payment's, e.g. when the latter was closed.
+    #[serde(default)]
+    destination_card_number: Option<String>,
+    /// Lets the refund exceed what remains refundable, for goodwill credits. Only admins
+    /// may set it.
+    #[serde(default)]
+    allow_overrefund: bool,
+    /// Reference of the credit note the merchant's accounting issued for the refund.
+    #[serde(default)]
+    credit_note_reference: Option<String>,
+    /// The items of an itemized order refunded, which must sum to `amount`.
+    #[serde(default)]
+    line_items: Option<Vec<LineItem>>,
+    /// When to credit the refund, if later: it's scheduled until then.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    execute_at: Option<OffsetDateTime>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -20,11 +44,106 @@ pub struct RequestBody {
     refund: RequestData,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
 pub struct ResponseData {
     id: Uuid,
     amount: i32,
     payment_id: Uuid,
+    currency: Option<String>,
+    /// `None` for refunds that weren't persisted.
+    status: Option<RefundStatus>,
+    credit_note_reference: Option<String>,
+    /// Omitted unless the refund was scheduled for later.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "time::serde::rfc3339::option"
+    )]
+    execute_at: Option<OffsetDateTime>,
+    /// `None` for refunds that weren't persisted.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    inserted_at: Option<OffsetDateTime>,
+    /// `None` for refunds that weren't persisted.
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    updated_at: Option<OffsetDateTime>,
+    /// Only responded when getting or creating the refund, and omitted without any.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    line_items: Vec<LineItem>,
+    /// Only responded when getting the refund, and omitted unless its amount was converted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    conversion: Option<ConversionData>,
+    /// Why the refund was rejected, omitted unless its status code doesn't tell (e.g.
+    /// `cannot_refund_voided` with 409).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The payment's refunded amount once the refund was applied, only responded when
+    /// creating the refund.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payment_refunded_amount: Option<i32>,
+    /// What remains refundable of the payment once the refund was applied, only responded
+    /// when creating the refund.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payment_refundable_amount: Option<i32>,
+}
+
+/// How the refund's amount was converted from `source_amount` in `source_currency`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct ConversionData {
+    exchange_rate: String,
+    source_amount: i32,
+    source_currency: String,
+    #[serde(with = "time::serde::rfc3339")]
+    converted_at: OffsetDateTime,
+}
+
+impl From<Conversion> for ConversionData {
+    fn from(conversion: Conversion) -> Self {
+        Self {
+            exchange_rate: conversion.exchange_rate,
+            source_amount: conversion.source_amount,
+            source_currency: conversion.source_currency,
+            converted_at: conversion.converted_at.assume_utc(),
+        }
+    }
+}
+
+impl From<Refund> for ResponseData {
+    fn from(refund: Refund) -> Self {
+        // destructured without `..`, so that fields added to `Refund` must be handled here
+        let Refund {
+            id,
+            payment_id,
+            amount,
+            status,
+            // a card number, which isn't responded
+            destination_card_number: _,
+            // only admins may set it, when requesting the refund
+            allow_overrefund: _,
+            currency,
+            credit_note_reference,
+            execute_at,
+            inserted_at,
+            updated_at,
+        } = refund;
+        Self {
+            id,
+            amount,
+            payment_id,
+            currency,
+            status: Some(status),
+            credit_note_reference,
+            execute_at: execute_at.map(PrimitiveDateTime::assume_utc),
+            inserted_at: Some(inserted_at.assume_utc()),
+            updated_at: Some(updated_at.assume_utc()),
+            line_items: Vec::new(),
+            conversion: None,
+            error: None,
+            payment_refunded_amount: None,
+            payment_refundable_amount: None,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -32,88 +151,652 @@ pub struct ResponseBody {
     data: ResponseData,
 }
 
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 1_000;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListParams {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct PaginationData {
+    limit: i64,
+    offset: i64,
+    /// Number of refunds across all pages.
+    total: i64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListBody {
+    data: Vec<ResponseData>,
+    pagination: PaginationData,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchParams {
+    credit_note_reference: Option<String>,
+    amount_gte: Option<i32>,
+    amount_lte: Option<i32>,
+    /// One of `inserted_at` (the default), `amount` and `-amount`: other values are rejected.
+    #[serde(default)]
+    sort: SearchOrder,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SearchBody {
+    data: Vec<ResponseData>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EligibilityParams {
+    amount: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct EligibilityData {
+    eligible: bool,
+    reason: Option<IneligibilityReason>,
+    max_refundable: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct EligibilityBody {
+    data: EligibilityData,
+}
+
 fn status_from_error(e: CreateError) -> StatusCode {
     match e {
         CreateError::PaymentNotFound => StatusCode::NOT_FOUND,
+        CreateError::PaymentNotSettled => StatusCode::CONFLICT,
+        CreateError::CannotRefundVoided => StatusCode::CONFLICT,
         CreateError::ExcessiveAmount => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::NothingToRefund => StatusCode::CONFLICT,
+        CreateError::InvalidDestinationCard => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::InvalidCreditNoteReference => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::InvalidLineItem => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::LineItemsMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::RefundNotFound => StatusCode::NOT_FOUND,
+        CreateError::RefundNotPending => StatusCode::CONFLICT,
+        CreateError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::CurrencyMismatch => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::RefundWindowExpired => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::ExecuteAtTooFar => StatusCode::UNPROCESSABLE_ENTITY,
+        CreateError::AccountService(err) => err.http_status(),
+        CreateError::Internal(msg) => {
+            tracing::error!(error = %msg, "failed to refund payment");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+        CreateError::Database(err) if crate::bank::payments::is_too_many_connections(&err) => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
         CreateError::Database(err) => panic!("Database error: {:?}", err),
     }
 }
 
-pub async fn post<T: AccountService>(
+/// Responds with 202 rather than 201 if the refund awaits approval or its execution.
+fn status_code_from_refund(refund: &Refund) -> StatusCode {
+    match refund.status {
+        RefundStatus::Pending | RefundStatus::Scheduled => StatusCode::ACCEPTED,
+        RefundStatus::Approved | RefundStatus::Rejected => StatusCode::CREATED,
+    }
+}
+
+/// Header making a refund idempotent: retrying it with the same key returns the first refund.
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+/// Maximum length of an idempotency key, as stored.
+const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 255;
+
+/// Reads the `Idempotency-Key` header, responding with 400 if it's empty or too long.
+pub(super) fn idempotency_key(headers: &HeaderMap) -> Result<Option<&str>, StatusCode> {
+    headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|key| {
+            key.to_str()
+                .ok()
+                .filter(|key| (1..=MAX_IDEMPOTENCY_KEY_LENGTH).contains(&key.len()))
+                .ok_or(StatusCode::BAD_REQUEST)
+        })
+        .transpose()
+}
+
+pub const REFUND_CREATED_EVENT: &str = "refund.created";
+
+/// Queues a webhook delivery of the refund's creation, if a webhook URL is configured.
+///
+/// The refund is already persisted at this point, so failing to queue the delivery is
+/// recorded
+/// rather than failing the request, to be queued again by the webhook worker.
+async fn notify_created<T>(bank_web: &BankWeb<T>, data: &ResponseData) {
+    let Some(url) = &bank_web.webhook_url else {
+        return;
+    };
+    let payload = payments::webhook_payload(REFUND_CREATED_EVENT, data);
+    let event_id = Uuid::new_v4();
+    if let Err(err) = webhooks::enqueue(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        url,
+        event_id,
+        payload.clone(),
+    )
+    .await
+    {
+        tracing::error!(refund_id = %data.id, error = ?err, "failed to queue webhook delivery");
+        bank_web
+            .unqueued_webhooks
+            .record(webhooks::UnqueuedDelivery {
+                url: url.clone(),
+                event_id,
+                payload,
+            });
+    }
+}
+
+/// Responds with 404 if the payment id is nil and the bank is strict about payment ids.
+fn check_payment_id<T>(bank_web: &BankWeb<T>, payment_id: Uuid) -> Result<(), StatusCode> {
+    if bank_web.strict_payment_ids && payment_id.is_nil() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    Ok(())
+}
+
+/// Refunds the payment, at most once per `Idempotency-Key` header when one is sent.
+///
+/// Responds with 400 if the key is empty or too long, and 422 if it was already used for
+/// another amount. Over-refunds are only allowed to admins, responding with 403 otherwise.
+///
+/// Made refunds are notified with a `REFUND_CREATED_EVENT` webhook, and responded with
+/// their `Location` like created payments. Refunds with an `execute_at` in the future are
+/// scheduled, responding with 202, or 422 if they're scheduled too far ahead.
+pub async fn post<T: AccountService + Clone>(
     State(bank_web): State<BankWeb<T>>,
     Path(payment_id): Path<Uuid>,
+    admin: Option<AdminAuth>,
+    headers: HeaderMap,
     Json(body): Json<RequestBody>,
-) -> (StatusCode, Json<ResponseBody>) {
-    refunds::create(&bank_web.pool, payment_id, body.refund.amount)
+) -> Result<Response, StatusCode> {
+    check_payment_id(&bank_web, payment_id)?;
+    if body.refund.allow_overrefund && admin.is_none() {
+        return Err(StatusCode::FORBIDDEN);
+    }
+    let idempotency_key = idempotency_key(&headers)?;
+    let line_items = body.refund.line_items.unwrap_or_default();
+    let execute_at = body.refund.execute_at.map(to_primitive_utc);
+    let result = match idempotency_key {
+        Some(idempotency_key) => {
+            refunds::create_idempotent(
+                &bank_web.pool,
+                &bank_web.account_service,
+                bank_web.clock.as_ref(),
+                payment_id,
+                body.refund.amount,
+                body.refund.currency.as_deref(),
+                body.refund.destination_card_number.as_deref(),
+                body.refund.credit_note_reference.as_deref(),
+                &line_items,
+                body.refund.allow_overrefund,
+                bank_web.refund_approval_threshold,
+                bank_web.refund_window,
+                execute_at,
+                idempotency_key,
+            )
+            .await
+        }
+        None => {
+            refunds::create(
+                &bank_web.pool,
+                &bank_web.account_service,
+                bank_web.clock.as_ref(),
+                payment_id,
+                body.refund.amount,
+                body.refund.currency.as_deref(),
+                body.refund.destination_card_number.as_deref(),
+                body.refund.credit_note_reference.as_deref(),
+                &line_items,
+                body.refund.allow_overrefund,
+                bank_web.refund_approval_threshold,
+                bank_web.refund_window,
+                execute_at,
+            )
+            .await
+        }
+    };
+    let response = result.map_or_else(
+        |e| {
+            let headers = match &e {
+                CreateError::Database(err) => payments::database_error_headers(err),
+                _ => HeaderMap::new(),
+            };
+            let error = e.reason().map(Into::into);
+            (
+                status_from_error(e),
+                headers,
+                Json(ResponseBody {
+                    data: ResponseData {
+                        id: Uuid::nil(),
+                        amount: body.refund.amount,
+                        payment_id,
+                        currency: body.refund.currency,
+                        status: None,
+                        credit_note_reference: body.refund.credit_note_reference,
+                        execute_at: body.refund.execute_at,
+                        inserted_at: None,
+                        updated_at: None,
+                        line_items: line_items.clone(),
+                        conversion: None,
+                        error,
+                        payment_refunded_amount: None,
+                        payment_refundable_amount: None,
+                    },
+                }),
+            )
+        },
+        |CreatedRefund {
+             refund,
+             payment_balance,
+         }| {
+            (
+                status_code_from_refund(&refund),
+                HeaderMap::new(),
+                Json(ResponseBody {
+                    data: ResponseData {
+                        line_items: line_items.clone(),
+                        payment_refunded_amount: Some(payment_balance.refunded_amount),
+                        payment_refundable_amount: Some(payment_balance.refundable_amount),
+                        ..refund.into()
+                    },
+                }),
+            )
+        },
+    );
+
+    // only persisted refunds are notified
+    let (_, _, Json(body)) = &response;
+    if body.data.id.is_nil() {
+        return Ok(response.into_response());
+    }
+    notify_created(&bank_web, &body.data).await;
+    let location = format!("/api/payments/{payment_id}/refunds/{}", body.data.id);
+    Ok(payments::respond_created(&headers, location, response))
+}
+
+/// Refunds whatever remains refundable of the payment, or responds with 409 if nothing does.
+///
+/// Repeating this once it fully refunded the payment responds with the same refund and 200,
+/// e.g. when double-clicked, while payments fully refunded otherwise still get 409.
+pub async fn post_remaining<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    check_payment_id(&bank_web, payment_id)?;
+    let RemainingRefund { refund, repeated } = refunds::refund_remaining(
+        &bank_web.pool,
+        &bank_web.account_service,
+        bank_web.clock.as_ref(),
+        payment_id,
+        bank_web.refund_approval_threshold,
+        bank_web.refund_window,
+    )
+    .await
+    .map_err(status_from_error)?;
+    let status_code = if repeated {
+        StatusCode::OK
+    } else {
+        status_code_from_refund(&refund)
+    };
+    let data = ResponseData::from(refund);
+    if !repeated {
+        notify_created(&bank_web, &data).await;
+    }
+
+    Ok((status_code, Json(ResponseBody { data })))
+}
+
+/// Approves a pending refund, crediting the money to the customer.
+///
+/// Responds with 404 if the refund doesn't exist, and 409 if it isn't pending.
+pub async fn approve<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(refund_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    let refund = refunds::approve(
+        &bank_web.pool,
+        &bank_web.account_service,
+        bank_web.clock.as_ref(),
+        refund_id,
+    )
+    .await
+    .map_err(status_from_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseBody {
+            data: refund.into(),
+        }),
+    ))
+}
+
+/// Rejects a pending refund.
+///
+/// Responds with 404 if the refund doesn't exist, and 409 if it isn't pending.
+pub async fn reject<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(refund_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    let refund = refunds::reject(&bank_web.pool, bank_web.clock.as_ref(), refund_id)
         .await
-        .map_or_else(
-            |e| {
-                (
-                    status_from_error(e),
-                    Json(ResponseBody {
-                        data: ResponseData {
-                            id: Uuid::nil(),
-                            amount: body.refund.amount,
-                            payment_id,
-                        },
-                    }),
-                )
-            },
-            |refund| {
-                (
-                    StatusCode::CREATED,
-                    Json(ResponseBody {
-                        data: ResponseData {
-                            id: refund.id,
-                            amount: body.refund.amount,
-                            payment_id,
-                        },
-                    }),
-                )
-            },
-        )
+        .map_err(status_from_error)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(ResponseBody {
+            data: refund.into(),
+        }),
+    ))
 }
 
-pub async fn get<T: AccountService>(
+pub async fn get<T: AccountService + Clone>(
     State(bank_web): State<BankWeb<T>>,
     Path((payment_id, refund_id)): Path<(Uuid, Uuid)>,
 ) -> (StatusCode, Json<ResponseBody>) {
-    let data = refunds::get(&bank_web.pool, refund_id).await.unwrap();
+    let data = bank_web.refund_repository.get(refund_id).await.unwrap();
+    let line_items = bank_web
+        .refund_repository
+        .line_items(refund_id)
+        .await
+        .unwrap();
+    let conversion = bank_web
+        .refund_repository
+        .conversion(refund_id)
+        .await
+        .unwrap();
 
     (
         StatusCode::OK,
         Json(ResponseBody {
             data: ResponseData {
-                id: data.id,
-                amount: data.amount,
                 payment_id,
+                line_items,
+                conversion: conversion.map(ConversionData::from),
+                ..data.into()
+            },
+        }),
+    )
+}
+
+/// Lists the refunds linked to the credit note and within the amount bounds, when given,
+/// in the requested order and at most `MAX_LIST_LIMIT` of them, e.g. to audit the largest
+/// refunds.
+///
+/// Responds with 400 if the reference is empty or too long, or the order isn't allowed.
+pub async fn search<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<SearchParams>,
+) -> Result<(StatusCode, Json<SearchBody>), StatusCode> {
+    if let Some(credit_note_reference) = &params.credit_note_reference {
+        if !(1..=refunds::MAX_CREDIT_NOTE_REFERENCE_LENGTH).contains(&credit_note_reference.len()) {
+            return Err(StatusCode::BAD_REQUEST);
+        }
+    }
+    let filter = SearchFilter {
+        credit_note_reference: params.credit_note_reference.as_deref(),
+        min_amount: params.amount_gte,
+        max_amount: params.amount_lte,
+    };
+    let refunds = refunds::search(&bank_web.pool, filter, params.sort, MAX_LIST_LIMIT)
+        .await
+        .unwrap();
+
+    Ok((
+        StatusCode::OK,
+        Json(SearchBody {
+            data: refunds.into_iter().map(ResponseData::from).collect(),
+        }),
+    ))
+}
+
+/// Tells whether a refund of `amount` against the payment would succeed.
+pub async fn eligibility<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+    Query(params): Query<EligibilityParams>,
+) -> (StatusCode, Json<EligibilityBody>) {
+    let eligibility = refunds::eligibility(&bank_web.pool, payment_id, params.amount)
+        .await
+        .unwrap();
+
+    (
+        StatusCode::OK,
+        Json(EligibilityBody {
+            data: EligibilityData {
+                eligible: eligibility.reason.is_none(),
+                reason: eligibility.reason,
+                max_refundable: eligibility.max_refundable,
+            },
+        }),
+    )
+}
+
+/// Lists a page of the refunds made against the payment, oldest first.
+///
+/// Pages hold `limit` refunds, defaulting to `DEFAULT_LIST_LIMIT` and capped to
+/// `MAX_LIST_LIMIT`.
+pub async fn list<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+    Query(params): Query<ListParams>,
+) -> (StatusCode, Json<ListBody>) {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let offset = params.offset.unwrap_or(0).max(0);
+    let refunds = bank_web
+        .refund_repository
+        .list_for_payment(payment_id, limit, offset)
+        .await
+        .unwrap();
+    let total = bank_web
+        .refund_repository
+        .count_for_payment(payment_id)
+        .await
+        .unwrap();
+
+    (
+        StatusCode::OK,
+        Json(ListBody {
+            data: refunds.into_iter().map(ResponseData::from).collect(),
+            pagination: PaginationData {
+                limit,
+                offset,
+                total,
             },
         }),
     )
 }
 
+/// Returns the only refund made against the payment.
+///
+/// Responds with 404 if the payment has no refunds, and 409 if it has several
+/// (in which case the refund must be fetched by its id).
+pub async fn get_sole<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<ResponseBody>), StatusCode> {
+    // a second refund is enough to tell the refund isn't the only one
+    let refunds = refunds::list_for_payment(&bank_web.pool, payment_id, 2, 0)
+        .await
+        .unwrap();
+
+    match refunds.as_slice() {
+        [] => Err(StatusCode::NOT_FOUND),
+        [refund] => Ok((
+            StatusCode::OK,
+            Json(ResponseBody {
+                data: refund.clone().into(),
+            }),
+        )),
+        _ => Err(StatusCode::CONFLICT),
+    }
+}
+
+/// Maximum number of payments reconciled by a single request.
+pub const MAX_RECONCILED_PAYMENTS: usize = 1_000;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReconcileRequestData {
+    payment_id: Uuid,
+    expected_refunded_amount: i32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct ReconcileData {
+    payment_id: Uuid,
+    /// Whether the expected refunded amount is the actual one. Never for unknown payments.
+    #[serde(rename = "match")]
+    matches: bool,
+    /// `None` for unknown payments.
+    actual_refunded_amount: Option<i32>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ReconcileBody {
+    data: Vec<ReconcileData>,
+}
+
+/// Compares the refunded amounts of payments expected by a ledger with the actual ones,
+/// in the order of the request.
+///
+/// Reconciling more than `MAX_RECONCILED_PAYMENTS` payments at once is rejected with 422.
+pub async fn reconcile<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Json(body): Json<Vec<ReconcileRequestData>>,
+) -> Result<(StatusCode, Json<ReconcileBody>), StatusCode> {
+    if body.len() > MAX_RECONCILED_PAYMENTS {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let ids: Vec<_> = body.iter().map(|expected| expected.payment_id).collect();
+    let refunded_amounts = crate::bank::payments::refunded_amounts(&bank_web.pool, &ids)
+        .await
+        .unwrap();
+    let data = body
+        .into_iter()
+        .map(|expected| {
+            let actual_refunded_amount = refunded_amounts.get(&expected.payment_id).copied();
+            ReconcileData {
+                payment_id: expected.payment_id,
+                matches: actual_refunded_amount == Some(expected.expected_refunded_amount),
+                actual_refunded_amount,
+            }
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(ReconcileBody { data })))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct RecomputeData {
+    payment_id: Uuid,
+    refunded_amount_before: i32,
+    refunded_amount_after: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RecomputeBody {
+    data: RecomputeData,
+}
+
+/// Recomputes the payment's refunded amount from its refunds, correcting it if it drifted,
+/// and responds with both values.
+///
+/// Responds with 404 if the payment doesn't exist.
+pub async fn recompute<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Path(payment_id): Path<Uuid>,
+) -> Result<(StatusCode, Json<RecomputeBody>), StatusCode> {
+    let correction = crate::bank::payments::recompute_refunded_amount(
+        &bank_web.pool,
+        bank_web.clock.as_ref(),
+        payment_id,
+    )
+    .await
+    .map_err(|e| match e {
+        sqlx::Error::RowNotFound => StatusCode::NOT_FOUND,
+        e => panic!("Database error: {:?}", e),
+    })?;
+
+    Ok((
+        StatusCode::OK,
+        Json(RecomputeBody {
+            data: RecomputeData {
+                payment_id,
+                refunded_amount_before: correction.before,
+                refunded_amount_after: correction.after,
+            },
+        }),
+    ))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::bank::accounts::DummyService;
     use crate::{
-        bank::{payment_instruments::Card, payments::Status},
+        bank::{
+            clock::tests::MockClock,
+            payment_events::PaymentEventKind,
+            payment_instruments::Card,
+            payments::Status,
+            repository::{self, tests::InMemoryRepository},
+            webhooks::tests::FlakySender,
+        },
         bank_web::{
             payments,
-            tests::{deserialize_response_body, post},
+            tests::{
+                deserialize_response_body, get, post, post_as_admin, send_request, ADMIN_TOKEN,
+            },
+        },
+    };
+    use axum::{
+        http::{
+            header::{AUTHORIZATION, CONTENT_TYPE, LOCATION},
+            Method, Request,
         },
+        Router,
     };
-    use axum::Router;
-    use std::future::Future;
+    use rstest::rstest;
+    use std::{future::Future, sync::Arc};
 
     async fn setup_successful_payment(payment_amount: i32) -> (Router, payments::ResponseBody) {
-        let router = BankWeb::new_test().await.into_router();
+        setup_successful_payment_with(BankWeb::new_test().await, payment_amount).await
+    }
+
+    async fn setup_successful_payment_with(
+        bank_web: BankWeb<DummyService>,
+        payment_amount: i32,
+    ) -> (Router, payments::ResponseBody) {
+        let router = bank_web.into_router();
 
         let request_body = payments::RequestBody {
             payment: payments::RequestData {
                 amount: payment_amount,
+                currency: None,
                 card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
             },
         };
 
@@ -137,7 +820,13 @@ mod tests {
         let request_body = payments::RequestBody {
             payment: payments::RequestData {
                 amount: payment_amount,
+                currency: None,
                 card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
             },
         };
 
@@ -155,10 +844,33 @@ mod tests {
         refund_amount: i32,
         payment_id: Uuid,
         expected_status_code: StatusCode,
+    ) {
+        do_refund_to(
+            router,
+            refund_amount,
+            payment_id,
+            None,
+            expected_status_code,
+        )
+        .await
+    }
+
+    async fn do_refund_to(
+        router: &Router,
+        refund_amount: i32,
+        payment_id: Uuid,
+        destination_card_number: Option<String>,
+        expected_status_code: StatusCode,
     ) {
         let request_body = RequestBody {
             refund: RequestData {
                 amount: refund_amount,
+                currency: None,
+                destination_card_number,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
             },
         };
 
@@ -172,61 +884,1320 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn should_full_refund() {
-        let amount = 10_00;
-        let (router, payment_response_body) = setup_successful_payment(amount).await;
+    async fn should_queue_refund_created_webhook() {
+        let sender = FlakySender::new(0);
+        let bank_web = BankWeb::new_test().await.with_webhook_url(&sender.url);
+        let pool = bank_web.pool.clone();
+        let (router, payment_response_body) = setup_successful_payment_with(bank_web, 10_00).await;
         let payment_id = payment_response_body.data.id;
 
-        do_refund(&router, amount, payment_id, StatusCode::CREATED).await;
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount: 4_00,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let response = post(
+            &router,
+            format!("/api/payments/{payment_id}/refunds"),
+            &request_body,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let refund = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+
+        let delivery = webhooks::list(&pool, MAX_LIST_LIMIT)
+            .await
+            .unwrap()
+            .into_iter()
+            .find(|delivery| {
+                delivery.url == sender.url && delivery.payload["event"] == REFUND_CREATED_EVENT
+            })
+            .expect("delivery wasn't queued");
+        assert_eq!(
+            delivery.payload["data"],
+            serde_json::to_value(&refund).unwrap()
+        );
+        assert_eq!(
+            delivery.payload["data"]["payment_id"],
+            payment_id.to_string().as_str()
+        );
     }
 
     #[tokio::test]
-    async fn should_partial_refunds_up_to_payment_amount() {
+    async fn should_return_the_same_refund_for_the_same_idempotency_key() {
         let (router, payment_response_body) = setup_successful_payment(10_00).await;
         let payment_id = payment_response_body.data.id;
+        let idempotency_key = Uuid::new_v4().to_string();
+        let request = |amount: i32, idempotency_key: &str| {
+            Request::builder()
+                .method(Method::POST)
+                .uri(format!("/api/payments/{payment_id}/refunds"))
+                .header(CONTENT_TYPE, "application/json")
+                .header(IDEMPOTENCY_KEY_HEADER, idempotency_key)
+                .body(
+                    serde_json::to_vec(&RequestBody {
+                        refund: RequestData {
+                            amount,
+                            currency: None,
+                            destination_card_number: None,
+                            allow_overrefund: false,
+                            credit_note_reference: None,
+                            line_items: None,
+                            execute_at: None,
+                        },
+                    })
+                    .unwrap()
+                    .into(),
+                )
+                .unwrap()
+        };
 
-        do_refund(&router, 2_00, payment_id, StatusCode::CREATED).await;
-        do_refund(&router, 5_00, payment_id, StatusCode::CREATED).await;
-        do_refund(&router, 3_00, payment_id, StatusCode::CREATED).await;
+        let (first, second) = tokio::join!(
+            send_request(&router, request(4_00, &idempotency_key)),
+            send_request(&router, request(4_00, &idempotency_key))
+        );
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert_eq!(second.status(), StatusCode::CREATED);
+        let first = hyper::body::to_bytes(first.into_body()).await.unwrap();
+        let second = hyper::body::to_bytes(second.into_body()).await.unwrap();
+        assert_eq!(first, second);
+
+        let response = send_request(&router, request(5_00, &idempotency_key)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = send_request(&router, request(5_00, "")).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        // only the keyed refund was made
+        let response = get(&router, format!("/api/payments/{payment_id}/refunds")).await;
+        let body = deserialize_response_body::<ListBody>(response).await;
+        assert_eq!(body.pagination.total, 1);
     }
 
+    #[rstest]
+    #[case(None, StatusCode::CREATED)]
+    #[case(Some("eur"), StatusCode::CREATED)]
+    #[case(Some("USD"), StatusCode::UNPROCESSABLE_ENTITY)]
     #[tokio::test]
-    async fn should_reject_refund_of_unknown_payment() {
-        let router = BankWeb::new_test().await.into_router();
-        let payment_id: Uuid = Uuid::new_v4();
+    async fn should_refund_in_payment_currency(
+        #[case] currency: Option<&str>,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let bank_web = BankWeb::new_test().await.with_default_currency("EUR");
+        let (router, payment_response_body) = setup_successful_payment_with(bank_web, 10_00).await;
+        let payment_id = payment_response_body.data.id;
 
-        do_refund(&router, 2_00, payment_id, StatusCode::NOT_FOUND).await;
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount: 1_00,
+                currency: currency.map(str::to_string),
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let uri = format!("/api/payments/{payment_id}/refunds");
+        let response = post(&router, uri, &request_body).await;
+        assert_eq!(response.status(), expected_status_code);
+
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        if expected_status_code == StatusCode::CREATED {
+            assert_eq!(data.currency.as_deref(), Some("EUR"));
+            assert_eq!(data.status, Some(RefundStatus::Approved));
+        } else {
+            assert_eq!(data.status, None);
+        }
     }
 
     #[tokio::test]
-    async fn should_reject_refund_of_declined_payment() {
-        let payment_amount = 1205;
-        let (router, payment_response_body) = setup_failed_payment(
-            BankWeb::new_test_with_response("insufficient_funds"),
-            payment_amount,
-            StatusCode::PAYMENT_REQUIRED,
-            Status::Declined,
+    async fn should_reject_refund_of_voided_authorization() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token("secret")
+            .into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = payments::AuthorizeRequestBody {
+            payment: payments::AuthorizeRequestData {
+                amount: 10_00,
+                card_number: card_number.clone(),
+                hold_amount: None,
+            },
+        };
+        let response = post(&router, "/api/payments/authorize", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment_id = deserialize_response_body::<payments::ResponseBody>(response)
+            .await
+            .data
+            .id;
+        let refund = || async {
+            let request_body = RequestBody {
+                refund: RequestData {
+                    amount: 10_00,
+                    currency: None,
+                    destination_card_number: None,
+                    allow_overrefund: false,
+                    credit_note_reference: None,
+                    line_items: None,
+                    execute_at: None,
+                },
+            };
+            let response = post(
+                &router,
+                format!("/api/payments/{payment_id}/refunds"),
+                &request_body,
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::CONFLICT);
+            deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data
+                .error
+        };
+        assert_eq!(refund().await.as_deref(), Some("payment_not_settled"));
+
+        let response = post_as_admin(
+            &router,
+            "/api/admin/cards/void-authorizations",
+            &serde_json::json!({ "card_number": card_number }),
         )
         .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        assert_eq!(refund().await.as_deref(), Some("cannot_refund_voided"));
+    }
+
+    #[tokio::test]
+    async fn should_recompute_drifted_refunded_amount() {
+        let bank_web = BankWeb::new_test().await.with_admin_token("secret");
+        let pool = bank_web.pool.clone();
+        let (router, payment_response_body) = setup_successful_payment_with(bank_web, 10_00).await;
         let payment_id = payment_response_body.data.id;
+        do_refund(&router, 3_00, payment_id, StatusCode::CREATED).await;
+        sqlx::query!(
+            "UPDATE payments SET refunded_amount = 900 WHERE id = $1",
+            payment_id
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
 
-        do_refund(&router, 2_00, payment_id, StatusCode::NOT_FOUND).await;
+        let recompute = |payment_id: Uuid| {
+            let request = Request::builder()
+                .method(Method::POST)
+                .uri(format!(
+                    "/api/admin/payments/{payment_id}/recompute-refunds"
+                ))
+                .header(AUTHORIZATION, "Bearer secret")
+                .body(hyper::Body::empty())
+                .unwrap();
+            send_request(&router, request)
+        };
+        let response = recompute(payment_id).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<RecomputeBody>(response)
+                .await
+                .data,
+            RecomputeData {
+                payment_id,
+                refunded_amount_before: 9_00,
+                refunded_amount_after: 3_00,
+            }
+        );
+        // the repaired amount is refundable again
+        do_refund(&router, 7_00, payment_id, StatusCode::CREATED).await;
+
+        let response = recompute(payment_id).await;
+        assert_eq!(
+            deserialize_response_body::<RecomputeBody>(response)
+                .await
+                .data,
+            RecomputeData {
+                payment_id,
+                refunded_amount_before: 10_00,
+                refunded_amount_after: 10_00,
+            }
+        );
+        let response = recompute(Uuid::new_v4()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 
     #[tokio::test]
-    async fn should_reject_full_refund_with_excessive_amount() {
+    async fn should_respond_with_payment_balance_after_refund() {
         let (router, payment_response_body) = setup_successful_payment(10_00).await;
         let payment_id = payment_response_body.data.id;
+        let refund = |amount| {
+            let request_body = RequestBody {
+                refund: RequestData {
+                    amount,
+                    currency: None,
+                    destination_card_number: None,
+                    allow_overrefund: false,
+                    credit_note_reference: None,
+                    line_items: None,
+                    execute_at: None,
+                },
+            };
+            let router = router.clone();
+            async move {
+                let response = post(
+                    &router,
+                    format!("/api/payments/{payment_id}/refunds"),
+                    &request_body,
+                )
+                .await;
+                assert_eq!(response.status(), StatusCode::CREATED);
+                let data = deserialize_response_body::<ResponseBody>(response)
+                    .await
+                    .data;
+                (data.payment_refunded_amount, data.payment_refundable_amount)
+            }
+        };
+
+        assert_eq!(refund(3_00).await, (Some(3_00), Some(7_00)));
+        assert_eq!(refund(2_50).await, (Some(5_50), Some(4_50)));
+    }
+
+    #[tokio::test]
+    async fn should_full_refund() {
+        let amount = 10_00;
+        let (router, payment_response_body) = setup_successful_payment(amount).await;
+        let payment_id = payment_response_body.data.id;
 
-        do_refund(&router, 11_00, payment_id, StatusCode::UNPROCESSABLE_ENTITY).await;
+        do_refund(&router, amount, payment_id, StatusCode::CREATED).await;
     }
 
     #[tokio::test]
-    async fn should_reject_partial_refund_with_excessive_amount() {
+    async fn should_partial_refunds_up_to_payment_amount() {
         let (router, payment_response_body) = setup_successful_payment(10_00).await;
         let payment_id = payment_response_body.data.id;
 
         do_refund(&router, 2_00, payment_id, StatusCode::CREATED).await;
-        do_refund(&router, 9_00, payment_id, StatusCode::UNPROCESSABLE_ENTITY).await;
+        do_refund(&router, 5_00, payment_id, StatusCode::CREATED).await;
+        do_refund(&router, 3_00, payment_id, StatusCode::CREATED).await;
+    }
+
+    #[tokio::test]
+    async fn should_name_fields_according_to_casing_feature() {
+        let (card_number, payment_id) = if cfg!(feature = "camel-case") {
+            ("cardNumber", "paymentId")
+        } else {
+            ("card_number", "payment_id")
+        };
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id_value = payment_response_body.data.id;
+
+        let uri = format!("/api/payments/{payment_id_value}");
+        let payment = deserialize_response_body::<serde_json::Value>(get(&router, uri).await).await;
+        assert!(payment["data"].get(card_number).is_some());
+
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount: 1_00,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let uri = format!("/api/payments/{payment_id_value}/refunds");
+        let refund =
+            deserialize_response_body::<serde_json::Value>(post(&router, uri, &request_body).await)
+                .await;
+        assert!(refund["data"].get(payment_id).is_some());
+    }
+
+    #[tokio::test]
+    async fn should_refund_to_alternate_card() {
+        let amount = 10_00;
+        let (router, payment_response_body) = setup_successful_payment(amount).await;
+        let payment_id = payment_response_body.data.id;
+        let destination: String = Card::new_test().into();
+        assert_ne!(destination, payment_response_body.data.card_number);
+
+        do_refund_to(
+            &router,
+            amount,
+            payment_id,
+            Some(destination),
+            StatusCode::CREATED,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn should_reject_refund_to_invalid_card() {
+        let amount = 10_00;
+        let (router, payment_response_body) = setup_successful_payment(amount).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund_to(
+            &router,
+            amount,
+            payment_id,
+            Some("1234".into()),
+            StatusCode::UNPROCESSABLE_ENTITY,
+        )
+        .await;
+        // the rejected refund didn't count against the payment
+        do_refund(&router, amount, payment_id, StatusCode::CREATED).await;
+    }
+
+    async fn do_refund_remaining(
+        router: &Router,
+        payment_id: Uuid,
+        expected_status_code: StatusCode,
+    ) -> Option<ResponseData> {
+        let uri = format!("/api/payments/{payment_id}/refunds/full");
+        let response = post(router, uri, &()).await;
+        assert_eq!(response.status(), expected_status_code);
+
+        if expected_status_code.is_success() {
+            Some(
+                deserialize_response_body::<ResponseBody>(response)
+                    .await
+                    .data,
+            )
+        } else {
+            None
+        }
+    }
+
+    #[tokio::test]
+    async fn should_refund_remaining_amount_in_one_shot() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 3_00, payment_id, StatusCode::CREATED).await;
+        let refund = do_refund_remaining(&router, payment_id, StatusCode::CREATED)
+            .await
+            .unwrap();
+        assert_eq!(refund.amount, 7_00);
+        assert!(!refund.id.is_nil());
+    }
+
+    #[tokio::test]
+    async fn should_look_refunds_up_by_credit_note_reference() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+        let reference = format!("CN-{}", Uuid::new_v4());
+        let refund = |amount, credit_note_reference| RequestBody {
+            refund: RequestData {
+                amount,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let uri = format!("/api/payments/{payment_id}/refunds");
+
+        let response = post(&router, &uri, &refund(3_00, Some(reference.clone()))).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let id = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data
+            .id;
+        let response = post(&router, &uri, &refund(2_00, None)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        let response = get(&router, format!("/api/payments/{payment_id}/refunds/{id}")).await;
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.credit_note_reference.as_ref(), Some(&reference));
+
+        let response = get(
+            &router,
+            format!("/api/refunds?credit_note_reference={reference}"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<SearchBody>(response).await.data;
+        assert_eq!(data.len(), 1);
+        assert_eq!(data[0].id, id);
+
+        let response = post(&router, &uri, &refund(1_00, Some("".into()))).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let response = get(&router, "/api/refunds?credit_note_reference=").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_search_refunds_by_amount_largest_first() {
+        let (router, payment_response_body) = setup_successful_payment(20_000).await;
+        let payment_id = payment_response_body.data.id;
+        // the reference keeps other tests' refunds out of the search
+        let reference = Uuid::new_v4().to_string();
+        for amount in [1_00, 50_00, 20_00, 2_00, 30_00] {
+            let request_body = RequestBody {
+                refund: RequestData {
+                    amount,
+                    currency: None,
+                    destination_card_number: None,
+                    allow_overrefund: false,
+                    credit_note_reference: Some(reference.clone()),
+                    line_items: None,
+                    execute_at: None,
+                },
+            };
+            let uri = format!("/api/payments/{payment_id}/refunds");
+            let response = post(&router, uri, &request_body).await;
+            assert_eq!(response.status(), StatusCode::CREATED);
+        }
+
+        let response = get(
+            &router,
+            format!("/api/refunds?credit_note_reference={reference}&amount_gte=2000&sort=-amount"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<SearchBody>(response).await.data;
+        let amounts: Vec<_> = data.iter().map(|refund| refund.amount).collect();
+        assert_eq!(amounts, [50_00, 30_00, 20_00]);
+
+        let response = get(
+            &router,
+            format!("/api/refunds?credit_note_reference={reference}&amount_lte=2000&sort=amount"),
+        )
+        .await;
+        let data = deserialize_response_body::<SearchBody>(response).await.data;
+        let amounts: Vec<_> = data.iter().map(|refund| refund.amount).collect();
+        assert_eq!(amounts, [1_00, 2_00, 20_00]);
+
+        let response = get(&router, "/api/refunds?sort=payment_id").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_omit_created_refund_when_minimal_return_is_preferred() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount: 2_00,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("/api/payments/{payment_id}/refunds"))
+            .header(CONTENT_TYPE, "application/json")
+            .header(payments::PREFER_HEADER, "return=minimal")
+            .body(serde_json::to_vec(&request_body).unwrap().into())
+            .unwrap();
+
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let location = response.headers()[LOCATION].to_str().unwrap().to_owned();
+        let bytes = hyper::body::to_bytes(response.into_body())
+            .await
+            .expect("failed to read response body into bytes");
+        assert!(bytes.is_empty());
+        let response = get(&router, &location).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.amount, 2_00);
+        assert_eq!(
+            location,
+            format!("/api/payments/{payment_id}/refunds/{}", data.id)
+        );
+    }
+
+    #[tokio::test]
+    async fn should_store_line_items_summing_to_refund_amount() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+        let uri = format!("/api/payments/{payment_id}/refunds");
+        let line_items = vec![
+            LineItem {
+                sku: "SKU-1".into(),
+                amount: 1_50,
+            },
+            LineItem {
+                sku: "SKU-2".into(),
+                amount: 50,
+            },
+        ];
+        let request_body = |amount| RequestBody {
+            refund: RequestData {
+                amount,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: Some(line_items.clone()),
+                execute_at: None,
+            },
+        };
+
+        let response = post(&router, &uri, &request_body(3_00)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let response = post(&router, &uri, &request_body(2_00)).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.line_items, line_items);
+        let response = get(&router, format!("{uri}/{}", data.id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.line_items, line_items);
+        assert_eq!(data.amount, 2_00);
+    }
+
+    #[tokio::test]
+    async fn should_return_conversion_of_converted_refund() {
+        let pool = crate::pg_pool()
+            .await
+            .expect("failed to connect to postgres");
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount: 2_00,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let response = post(
+            &router,
+            format!("/api/payments/{payment_id}/refunds"),
+            &request_body,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let refund = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        let uri = format!("/api/payments/{payment_id}/refunds/{}", refund.id);
+
+        let response = get(&router, &uri).await;
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(data.conversion, None);
+
+        let converted_at = time::PrimitiveDateTime::new(
+            time::Date::from_calendar_date(2026, time::Month::October, 15).unwrap(),
+            time::Time::from_hms(9, 30, 0).unwrap(),
+        );
+        refunds::tests::record_conversion(
+            &pool,
+            refund.id,
+            &Conversion {
+                exchange_rate: "1.0825".into(),
+                source_amount: 1_85,
+                source_currency: "USD".into(),
+                converted_at,
+            },
+        )
+        .await
+        .unwrap();
+        let response = get(&router, &uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(
+            data.conversion,
+            Some(ConversionData {
+                exchange_rate: "1.0825".into(),
+                source_amount: 1_85,
+                source_currency: "USD".into(),
+                converted_at: converted_at.assume_utc(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn should_return_same_refund_when_refunding_remaining_again() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        let refund = do_refund_remaining(&router, payment_id, StatusCode::CREATED)
+            .await
+            .unwrap();
+        for _ in 0..2 {
+            let repeated = do_refund_remaining(&router, payment_id, StatusCode::OK)
+                .await
+                .unwrap();
+            assert_eq!(repeated.id, refund.id);
+            assert_eq!(repeated.amount, 10_00);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_refund_remaining_of_otherwise_fully_refunded_payment() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 10_00, payment_id, StatusCode::CREATED).await;
+        do_refund_remaining(&router, payment_id, StatusCode::CONFLICT).await;
+    }
+
+    #[tokio::test]
+    async fn should_reject_refund_of_unknown_payment() {
+        let router = BankWeb::new_test().await.into_router();
+        let payment_id: Uuid = Uuid::new_v4();
+
+        do_refund(&router, 2_00, payment_id, StatusCode::NOT_FOUND).await;
+    }
+
+    #[tokio::test]
+    async fn should_reject_refund_of_nil_payment_id_without_lookup_when_strict() {
+        // The in-memory bank never connects to postgres, so any lookup would fail
+        let router = BankWeb::new_test_in_memory(Arc::new(InMemoryRepository::default()))
+            .with_strict_payment_ids(true)
+            .into_router();
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount: 2_00,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+
+        let uri = format!("/api/payments/{}/refunds", Uuid::nil());
+        let response = post(&router, uri, &request_body).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        do_refund_remaining(&router, Uuid::nil(), StatusCode::NOT_FOUND).await;
+    }
+
+    #[tokio::test]
+    async fn should_respond_refund_fields() {
+        let repository = Arc::new(InMemoryRepository::default());
+        let payment = repository::tests::new_payment(10_00, Status::Approved);
+        let refund = Refund {
+            currency: Some("EUR".into()),
+            credit_note_reference: Some("CN-1".into()),
+            ..repository::tests::new_refund(&payment, 2_00)
+        };
+        repository.insert_payment(payment.clone());
+        repository.insert_refund(refund.clone());
+        let router = BankWeb::new_test_in_memory(repository).into_router();
+
+        let data = ResponseData::from(refund.clone());
+        assert_eq!(
+            data,
+            ResponseData {
+                id: refund.id,
+                amount: 2_00,
+                payment_id: payment.id,
+                currency: Some("EUR".into()),
+                status: Some(RefundStatus::Approved),
+                credit_note_reference: Some("CN-1".into()),
+                execute_at: None,
+                inserted_at: Some(refund.inserted_at.assume_utc()),
+                updated_at: Some(refund.updated_at.assume_utc()),
+                line_items: Vec::new(),
+                conversion: None,
+                error: None,
+                payment_refunded_amount: None,
+                payment_refundable_amount: None,
+            }
+        );
+
+        let uri = format!("/api/payments/{}/refunds/{}", payment.id, refund.id);
+        let response = get(&router, uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<ResponseBody>(response)
+                .await
+                .data,
+            data
+        );
+    }
+
+    #[tokio::test]
+    async fn should_reject_refund_of_declined_payment() {
+        let payment_amount = 1205;
+        let (router, payment_response_body) = setup_failed_payment(
+            BankWeb::new_test_with_response("insufficient_funds"),
+            payment_amount,
+            StatusCode::PAYMENT_REQUIRED,
+            Status::Declined,
+        )
+        .await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 2_00, payment_id, StatusCode::NOT_FOUND).await;
+    }
+
+    #[tokio::test]
+    async fn should_reject_full_refund_with_excessive_amount() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 11_00, payment_id, StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
+
+    #[tokio::test]
+    async fn should_reject_partial_refund_with_excessive_amount() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 2_00, payment_id, StatusCode::CREATED).await;
+        do_refund(&router, 9_00, payment_id, StatusCode::UNPROCESSABLE_ENTITY).await;
+    }
+
+    async fn do_overrefund(
+        router: &Router,
+        amount: i32,
+        payment_id: Uuid,
+        admin_token: Option<&str>,
+    ) -> StatusCode {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(format!("/api/payments/{payment_id}/refunds"))
+            .header(CONTENT_TYPE, "application/json");
+        if let Some(admin_token) = admin_token {
+            request = request.header(AUTHORIZATION, format!("Bearer {admin_token}"));
+        }
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: true,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let request = request
+            .body(serde_json::to_vec(&request_body).unwrap().into())
+            .unwrap();
+        send_request(router, request).await.status()
+    }
+
+    async fn refunded_amounts(pool: &sqlx::PgPool, payment_id: Uuid) -> (i32, i32) {
+        let payment = sqlx::query!(
+            "SELECT refunded_amount, overrefunded_amount FROM payments WHERE id = $1",
+            payment_id
+        )
+        .fetch_one(pool)
+        .await
+        .unwrap();
+        (payment.refunded_amount, payment.overrefunded_amount)
+    }
+
+    #[tokio::test]
+    async fn should_reconcile_refunded_amounts() {
+        let bank_web = BankWeb::new_test().await.with_admin_token(ADMIN_TOKEN);
+        let (router, payment_response_body) = setup_successful_payment_with(bank_web, 10_00).await;
+        let payment_id = payment_response_body.data.id;
+        do_refund(&router, 3_00, payment_id, StatusCode::CREATED).await;
+        let unknown_id = Uuid::new_v4();
+
+        let request_body = [(payment_id, 3_00), (payment_id, 4_00), (unknown_id, 0)].map(
+            |(payment_id, expected_refunded_amount)| ReconcileRequestData {
+                payment_id,
+                expected_refunded_amount,
+            },
+        );
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/admin/reconcile-refunds")
+            .header(CONTENT_TYPE, "application/json")
+            .header(AUTHORIZATION, format!("Bearer {ADMIN_TOKEN}"))
+            .body(serde_json::to_vec(&request_body).unwrap().into())
+            .unwrap();
+        let response = send_request(&router, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<ReconcileBody>(response)
+                .await
+                .data,
+            vec![
+                ReconcileData {
+                    payment_id,
+                    matches: true,
+                    actual_refunded_amount: Some(3_00),
+                },
+                ReconcileData {
+                    payment_id,
+                    matches: false,
+                    actual_refunded_amount: Some(3_00),
+                },
+                ReconcileData {
+                    payment_id: unknown_id,
+                    matches: false,
+                    actual_refunded_amount: None,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn should_block_overrefund_unless_allowed_by_admin() {
+        let bank_web = BankWeb::new_test().await.with_admin_token(ADMIN_TOKEN);
+        let pool = bank_web.pool.clone();
+        let (router, payment_response_body) = setup_successful_payment_with(bank_web, 10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 12_00, payment_id, StatusCode::UNPROCESSABLE_ENTITY).await;
+        assert_eq!(
+            do_overrefund(&router, 12_00, payment_id, None).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            do_overrefund(&router, 12_00, payment_id, Some("wrong")).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(refunded_amounts(&pool, payment_id).await, (0, 0));
+    }
+
+    #[tokio::test]
+    async fn should_track_goodwill_overrefund_separately() {
+        let bank_web = BankWeb::new_test().await.with_admin_token(ADMIN_TOKEN);
+        let pool = bank_web.pool.clone();
+        let (router, payment_response_body) = setup_successful_payment_with(bank_web, 10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 3_00, payment_id, StatusCode::CREATED).await;
+        assert_eq!(
+            do_overrefund(&router, 9_00, payment_id, Some(ADMIN_TOKEN)).await,
+            StatusCode::CREATED
+        );
+        assert_eq!(refunded_amounts(&pool, payment_id).await, (10_00, 2_00));
+        // the payment is fully refunded, only goodwill credits remain possible
+        do_refund(&router, 1, payment_id, StatusCode::UNPROCESSABLE_ENTITY).await;
+        assert_eq!(
+            do_overrefund(&router, 1_00, payment_id, Some(ADMIN_TOKEN)).await,
+            StatusCode::CREATED
+        );
+        assert_eq!(refunded_amounts(&pool, payment_id).await, (10_00, 3_00));
+    }
+
+    #[tokio::test]
+    async fn should_return_404_for_sole_refund_of_unrefunded_payment() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        let response = get(&router, format!("/api/payments/{payment_id}/refund")).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn should_return_sole_refund() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 10_00, payment_id, StatusCode::CREATED).await;
+
+        let response = get(&router, format!("/api/payments/{payment_id}/refund")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response_body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(response_body.data.amount, 10_00);
+        assert_eq!(response_body.data.payment_id, payment_id);
+        assert!(!response_body.data.id.is_nil());
+    }
+
+    #[tokio::test]
+    async fn should_return_409_for_sole_refund_of_multiply_refunded_payment() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 2_00, payment_id, StatusCode::CREATED).await;
+        do_refund(&router, 5_00, payment_id, StatusCode::CREATED).await;
+
+        let response = get(&router, format!("/api/payments/{payment_id}/refund")).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn should_page_through_payment_refunds() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+        for _ in 0..5 {
+            do_refund(&router, 1, payment_id, StatusCode::CREATED).await;
+        }
+
+        let mut refund_ids = Vec::new();
+        for (offset, expected_len) in [(0, 2), (2, 2), (4, 1), (6, 0)] {
+            let uri = format!("/api/payments/{payment_id}/refunds?limit=2&offset={offset}");
+            let response = get(&router, uri).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            let body = deserialize_response_body::<ListBody>(response).await;
+            assert_eq!(
+                body.pagination,
+                PaginationData {
+                    limit: 2,
+                    offset,
+                    total: 5
+                }
+            );
+            assert_eq!(body.data.len(), expected_len);
+            refund_ids.extend(body.data.into_iter().map(|refund| refund.id));
+        }
+        refund_ids.sort();
+        refund_ids.dedup();
+        assert_eq!(refund_ids.len(), 5);
+
+        let uri = format!("/api/payments/{payment_id}/refunds?limit=1000000");
+        let body = deserialize_response_body::<ListBody>(get(&router, uri).await).await;
+        assert_eq!(body.pagination.limit, MAX_LIST_LIMIT);
+        assert_eq!(body.data.len(), 5);
+    }
+
+    #[test]
+    fn should_map_too_many_connections_to_503() {
+        let error = || crate::bank::payments::tests::database_error("53300");
+        assert_eq!(
+            status_from_error(CreateError::Database(error())),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+        assert!(payments::database_error_headers(&error())
+            .contains_key(axum::http::header::RETRY_AFTER));
+        assert!(payments::database_error_headers(&sqlx::Error::RowNotFound).is_empty());
+    }
+
+    #[tokio::test]
+    async fn should_page_through_refunds_from_repository() {
+        let repository = Arc::new(InMemoryRepository::default());
+        let payment = repository::tests::new_payment(10_00, Status::Approved);
+        let refunds: Vec<_> = (1..=3)
+            .map(|amount| repository::tests::new_refund(&payment, amount))
+            .collect();
+        for refund in &refunds {
+            repository.insert_refund(refund.clone());
+        }
+        // refunds of other payments aren't listed
+        let other_payment = repository::tests::new_payment(10_00, Status::Approved);
+        repository.insert_refund(repository::tests::new_refund(&other_payment, 1));
+        let router = BankWeb::new_test_in_memory(repository).into_router();
+
+        let uri = format!("/api/payments/{}/refunds?limit=2&offset=1", payment.id);
+        let response = get(&router, uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = deserialize_response_body::<ListBody>(response).await;
+        assert_eq!(
+            body.pagination,
+            PaginationData {
+                limit: 2,
+                offset: 1,
+                total: 3
+            }
+        );
+        assert_eq!(body.data.len(), 2);
+
+        let uri = format!("/api/payments/{}/refunds/{}", payment.id, refunds[0].id);
+        let response = get(&router, uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = deserialize_response_body::<ResponseBody>(response).await;
+        assert_eq!(body.data.id, refunds[0].id);
+        assert_eq!(body.data.amount, 1);
+    }
+
+    async fn check_eligibility(router: &Router, payment_id: Uuid, amount: i32) -> EligibilityData {
+        let uri = format!("/api/payments/{payment_id}/refunds/eligibility?amount={amount}");
+        let response = get(router, uri).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        deserialize_response_body::<EligibilityBody>(response)
+            .await
+            .data
+    }
+
+    #[tokio::test]
+    async fn should_be_eligible_for_refund_within_payment_amount() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 2_00, payment_id, StatusCode::CREATED).await;
+
+        let eligibility = check_eligibility(&router, payment_id, 8_00).await;
+        assert!(eligibility.eligible);
+        assert_eq!(eligibility.reason, None);
+        assert_eq!(eligibility.max_refundable, 8_00);
+
+        do_refund(&router, 8_00, payment_id, StatusCode::CREATED).await;
+    }
+
+    #[tokio::test]
+    async fn should_not_be_eligible_for_refund_with_excessive_amount() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+
+        do_refund(&router, 2_00, payment_id, StatusCode::CREATED).await;
+
+        let eligibility = check_eligibility(&router, payment_id, 9_00).await;
+        assert!(!eligibility.eligible);
+        assert_eq!(
+            eligibility.reason,
+            Some(IneligibilityReason::ExcessiveAmount)
+        );
+        assert_eq!(eligibility.max_refundable, 8_00);
+    }
+
+    #[tokio::test]
+    async fn should_not_be_eligible_for_refund_of_unknown_payment() {
+        let router = BankWeb::new_test().await.into_router();
+
+        let eligibility = check_eligibility(&router, Uuid::new_v4(), 2_00).await;
+        assert!(!eligibility.eligible);
+        assert_eq!(
+            eligibility.reason,
+            Some(IneligibilityReason::PaymentNotFound)
+        );
+    }
+
+    const APPROVAL_THRESHOLD: i32 = 5_00;
+
+    async fn setup_payment_with_approval_threshold(payment_amount: i32) -> (Router, Uuid) {
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .with_refund_approval_threshold(APPROVAL_THRESHOLD);
+        let (router, payment_response_body) =
+            setup_successful_payment_with(bank_web, payment_amount).await;
+        (router, payment_response_body.data.id)
+    }
+
+    async fn request_refund(
+        router: &Router,
+        payment_id: Uuid,
+        amount: i32,
+    ) -> (StatusCode, ResponseData) {
+        let request_body = RequestBody {
+            refund: RequestData {
+                amount,
+                currency: None,
+                destination_card_number: None,
+                allow_overrefund: false,
+                credit_note_reference: None,
+                line_items: None,
+                execute_at: None,
+            },
+        };
+        let uri = format!("/api/payments/{payment_id}/refunds");
+        let response = post(router, uri, &request_body).await;
+        let status_code = response.status();
+        let data = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        (status_code, data)
+    }
+
+    async fn review_refund(router: &Router, refund_id: Uuid, action: &str) -> StatusCode {
+        let uri = format!("/api/refunds/{refund_id}/{action}");
+        post_as_admin(router, uri, &()).await.status()
+    }
+
+    #[rstest]
+    #[case::inside_window(179, StatusCode::CREATED)]
+    #[case::outside_window(181, StatusCode::UNPROCESSABLE_ENTITY)]
+    #[tokio::test]
+    async fn should_only_refund_within_refund_window(
+        #[case] elapsed_days: i64,
+        #[case] expected_status_code: StatusCode,
+    ) {
+        let now = time::OffsetDateTime::from_unix_timestamp(1_000_000_000).unwrap();
+        let clock = MockClock::new(now);
+        let window = time::Duration::days(180);
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_clock(clock.clone())
+            .with_refund_window(window);
+        let (router, payment) = setup_successful_payment_with(bank_web, 10_00).await;
+        assert_eq!(payment.data.refundable_until, Some(now + window));
+
+        clock.advance(time::Duration::days(elapsed_days));
+        let (status_code, _) = request_refund(&router, payment.data.id, 5_00).await;
+        assert_eq!(status_code, expected_status_code);
+    }
+
+    #[tokio::test]
+    async fn should_apply_scheduled_refund_once_due() {
+        // in a century of its own, so that no other test executes the refund early
+        let now = time::OffsetDateTime::from_unix_timestamp(23_036_572_800).unwrap();
+        let clock = MockClock::new(now);
+        let bank_web = BankWeb::new_test().await.with_clock(clock.clone());
+        let pool = bank_web.pool.clone();
+        let account_service = bank_web.account_service.clone();
+        let (router, payment) = setup_successful_payment_with(bank_web, 10_00).await;
+        let schedule_refund = |execute_at| {
+            let request_body = RequestBody {
+                refund: RequestData {
+                    amount: 5_00,
+                    currency: None,
+                    destination_card_number: None,
+                    allow_overrefund: false,
+                    credit_note_reference: None,
+                    line_items: None,
+                    execute_at: Some(execute_at),
+                },
+            };
+            let uri = format!("/api/payments/{}/refunds", payment.data.id);
+            let router = router.clone();
+            async move { post(&router, uri, &request_body).await }
+        };
+
+        let response =
+            schedule_refund(now + refunds::MAX_SCHEDULE_AHEAD + time::Duration::days(1)).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let execute_at = now + time::Duration::days(1);
+        let response = schedule_refund(execute_at).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let refund = deserialize_response_body::<ResponseBody>(response)
+            .await
+            .data;
+        assert_eq!(refund.status, Some(RefundStatus::Scheduled));
+        assert_eq!(refund.execute_at, Some(execute_at));
+        assert_eq!(refund.payment_refunded_amount, Some(0));
+
+        let executed = refunds::execute_due(&pool, &account_service, &clock, None)
+            .await
+            .unwrap();
+        assert!(!executed.iter().any(|executed| executed.id == refund.id));
+
+        clock.advance(time::Duration::days(1));
+        let executed = refunds::execute_due(&pool, &account_service, &clock, None)
+            .await
+            .unwrap();
+        assert!(executed.iter().any(|executed| executed.id == refund.id));
+        let refund = refunds::get(&pool, refund.id).await.unwrap();
+        assert_eq!(refund.status, RefundStatus::Approved);
+        assert_eq!(refund.updated_at.assume_utc(), execute_at);
+        let eligibility = check_eligibility(&router, payment.data.id, 1).await;
+        assert_eq!(eligibility.max_refundable, 5_00);
+    }
+
+    #[tokio::test]
+    async fn should_apply_refund_below_approval_threshold_immediately() {
+        let (router, payment_id) = setup_payment_with_approval_threshold(10_00).await;
+
+        let (status_code, refund) = request_refund(&router, payment_id, APPROVAL_THRESHOLD).await;
+        assert_eq!(status_code, StatusCode::CREATED);
+        assert_eq!(refund.status, Some(RefundStatus::Approved));
+
+        let eligibility = check_eligibility(&router, payment_id, 1).await;
+        assert_eq!(eligibility.max_refundable, 5_00);
+    }
+
+    #[tokio::test]
+    async fn should_hold_refund_above_approval_threshold_until_approved() {
+        let (router, payment_id) = setup_payment_with_approval_threshold(10_00).await;
+
+        let (status_code, refund) = request_refund(&router, payment_id, 6_00).await;
+        assert_eq!(status_code, StatusCode::ACCEPTED);
+        assert_eq!(refund.status, Some(RefundStatus::Pending));
+
+        let eligibility = check_eligibility(&router, payment_id, 1).await;
+        assert_eq!(eligibility.max_refundable, 10_00);
+
+        assert_eq!(
+            review_refund(&router, refund.id, "approve").await,
+            StatusCode::OK
+        );
+        let eligibility = check_eligibility(&router, payment_id, 1).await;
+        assert_eq!(eligibility.max_refundable, 4_00);
+
+        // reviewed refunds can't be reviewed again
+        assert_eq!(
+            review_refund(&router, refund.id, "approve").await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            review_refund(&router, refund.id, "reject").await,
+            StatusCode::CONFLICT
+        );
+    }
+
+    #[rstest]
+    #[tokio::test]
+    async fn should_only_let_operators_review_refunds(#[values("approve", "reject")] action: &str) {
+        let (router, payment_id) = setup_payment_with_approval_threshold(10_00).await;
+
+        let (_, refund) = request_refund(&router, payment_id, 6_00).await;
+        let uri = format!("/api/refunds/{}/{action}", refund.id);
+        let response = post(&router, uri, &()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let eligibility = check_eligibility(&router, payment_id, 1).await;
+        assert_eq!(eligibility.max_refundable, 10_00);
+    }
+
+    #[tokio::test]
+    async fn should_leave_payment_untouched_by_rejected_refund() {
+        let (router, payment_id) = setup_payment_with_approval_threshold(10_00).await;
+
+        let (_, refund) = request_refund(&router, payment_id, 6_00).await;
+        assert_eq!(
+            review_refund(&router, refund.id, "reject").await,
+            StatusCode::OK
+        );
+
+        let eligibility = check_eligibility(&router, payment_id, 1).await;
+        assert_eq!(eligibility.max_refundable, 10_00);
+        assert_eq!(
+            review_refund(&router, refund.id, "approve").await,
+            StatusCode::CONFLICT
+        );
+        assert_eq!(
+            review_refund(&router, Uuid::new_v4(), "approve").await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn should_list_payment_events_in_order() {
+        let (router, payment_response_body) = setup_successful_payment(10_00).await;
+        let payment_id = payment_response_body.data.id;
+        do_refund(&router, 3_00, payment_id, StatusCode::CREATED).await;
+
+        let response = get(&router, format!("/api/payments/{payment_id}/events")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let events = deserialize_response_body::<payments::EventsBody>(response)
+            .await
+            .data;
+
+        let kinds: Vec<_> = events.iter().map(|event| event.kind).collect();
+        assert_eq!(
+            kinds,
+            [PaymentEventKind::Created, PaymentEventKind::Refunded]
+        );
+        assert_eq!(events[0].status, Status::Approved);
+        assert_eq!(events[0].amount, 10_00);
+        assert_eq!(events[1].amount, 3_00);
+        assert!(events[0].inserted_at <= events[1].inserted_at);
+
+        let response = get(&router, format!("/api/payments/{}/events", Uuid::new_v4())).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
     }
 }
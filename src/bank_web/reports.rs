@@ -0,0 +1,758 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::{header::CONTENT_TYPE, StatusCode},
+    response::IntoResponse,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use time::Date;
+
+use super::{merchants::MerchantAuth, BankWeb};
+use crate::bank::{
+    accounts::AccountService,
+    payments::{self, AmountPercentiles, HistogramBucket, HoldsSummary, NetSettlement, Status},
+    refunds::{self, DailyTotal},
+    settlement,
+};
+
+/// Maximum number of days covered by a daily report.
+const MAX_DAILY_REPORT_DAYS: i64 = 366;
+
+/// Parameters of every report: test payments, made in sandbox mode, are left out of reports
+/// unless `?include_test=true`.
+#[derive(Debug, Clone, Copy, Default, Deserialize, Serialize)]
+pub struct ModeParams {
+    #[serde(default)]
+    pub include_test: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatusCountsBody {
+    data: HashMap<Status, i64>,
+}
+
+/// Returns the number of payments in each status.
+pub async fn status_counts<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(mode): Query<ModeParams>,
+) -> (StatusCode, Json<StatusCountsBody>) {
+    let counts = payments::count_by_status(&bank_web.pool, mode.include_test)
+        .await
+        .unwrap();
+
+    (StatusCode::OK, Json(StatusCountsBody { data: counts }))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DailyParams {
+    pub from: Date,
+    pub to: Date,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DailyTotalsBody {
+    data: Vec<DailyTotal>,
+}
+
+/// Returns the number and sum of approved refunds per day, from `from` to `to` included.
+///
+/// Responds with 400 if `to` is before `from`, or if the range exceeds
+/// `MAX_DAILY_REPORT_DAYS` days.
+pub async fn refund_daily_totals<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<DailyParams>,
+    Query(mode): Query<ModeParams>,
+) -> Result<(StatusCode, Json<DailyTotalsBody>), StatusCode> {
+    let days = (params.to - params.from).whole_days() + 1;
+    if !(1..=MAX_DAILY_REPORT_DAYS).contains(&days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let totals = refunds::daily_totals(&bank_web.pool, params.from, params.to, mode.include_test)
+        .await
+        .unwrap();
+
+    Ok((StatusCode::OK, Json(DailyTotalsBody { data: totals })))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistogramParams {
+    /// Size of the buckets, in cents.
+    pub bucket: i32,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HistogramBody {
+    data: Vec<HistogramBucket>,
+}
+
+/// Returns the number of approved payments per bucket of their amount, omitting empty
+/// buckets.
+///
+/// Responds with 400 unless the bucket size is positive.
+pub async fn amount_histogram<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<HistogramParams>,
+    Query(mode): Query<ModeParams>,
+) -> Result<(StatusCode, Json<HistogramBody>), StatusCode> {
+    if params.bucket <= 0 {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let buckets = payments::amount_histogram(&bank_web.pool, params.bucket, mode.include_test)
+        .await
+        .unwrap();
+
+    Ok((StatusCode::OK, Json(HistogramBody { data: buckets })))
+}
+
+/// Returns the percentiles of the amounts of the payments approved from `from` to `to`
+/// included, which are null if none was.
+///
+/// Responds with 400 if `to` is before `from`, or if the range exceeds
+/// `MAX_DAILY_REPORT_DAYS` days.
+pub async fn amount_percentiles<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<DailyParams>,
+    Query(mode): Query<ModeParams>,
+) -> Result<(StatusCode, Json<AmountPercentiles>), StatusCode> {
+    let days = (params.to - params.from).whole_days() + 1;
+    if !(1..=MAX_DAILY_REPORT_DAYS).contains(&days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let percentiles =
+        payments::amount_percentiles(&bank_web.pool, params.from, params.to, mode.include_test)
+            .await
+            .unwrap();
+
+    Ok((StatusCode::OK, Json(percentiles)))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct HoldsVsSettledParams {
+    /// Day the authorizations were made, all of them if omitted.
+    pub date: Option<Date>,
+}
+
+/// Returns the amounts still held by open authorizations versus those captured.
+pub async fn holds_vs_settled<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<HoldsVsSettledParams>,
+    Query(mode): Query<ModeParams>,
+) -> (StatusCode, Json<HoldsSummary>) {
+    let summary = payments::holds_vs_settled(&bank_web.pool, params.date, mode.include_test)
+        .await
+        .unwrap();
+
+    (StatusCode::OK, Json(summary))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NetSettlementParams {
+    pub from: Date,
+    pub to: Date,
+}
+
+/// Returns what the authenticated merchant is owed for its payments approved from `from` to
+/// `to` included: their amounts less their fees and the refunds issued meanwhile.
+///
+/// Responds with 400 if `to` is before `from`, or if the range exceeds
+/// `MAX_DAILY_REPORT_DAYS` days.
+pub async fn net_settlement<T: AccountService + Clone>(
+    MerchantAuth(merchant_id): MerchantAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<NetSettlementParams>,
+    Query(mode): Query<ModeParams>,
+) -> Result<(StatusCode, Json<NetSettlement>), StatusCode> {
+    let days = (params.to - params.from).whole_days() + 1;
+    if !(1..=MAX_DAILY_REPORT_DAYS).contains(&days) {
+        return Err(StatusCode::BAD_REQUEST);
+    }
+    let settlement = payments::net_settlement(
+        &bank_web.pool,
+        merchant_id,
+        params.from,
+        params.to,
+        mode.include_test,
+    )
+    .await
+    .map_err(|e| {
+        if payments::is_too_many_connections(&e) {
+            StatusCode::SERVICE_UNAVAILABLE
+        } else {
+            tracing::error!(error = ?e, "failed to compute net settlement");
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    })?;
+
+    Ok((StatusCode::OK, Json(settlement)))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SettlementParams {
+    pub date: Date,
+}
+
+/// Returns the settlement file of the payments approved on `date`, net of their refunds,
+/// in the fixed-width format of `bank::settlement`.
+pub async fn settlement_file<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<SettlementParams>,
+    Query(mode): Query<ModeParams>,
+) -> Result<impl IntoResponse, StatusCode> {
+    let payments = payments::approved_on(&bank_web.pool, params.date, mode.include_test)
+        .await
+        .unwrap();
+    let file = settlement::render(params.date, &payments).map_err(|e| {
+        tracing::error!(
+            field = e.field,
+            value = e.value,
+            "settlement field overflow"
+        );
+        StatusCode::INTERNAL_SERVER_ERROR
+    })?;
+
+    Ok(([(CONTENT_TYPE, "text/plain; charset=utf-8")], file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::{
+            accounts::DummyService, clock::tests::MockClock, fees::FeeConfig,
+            payment_instruments::Card, payments::Payment,
+        },
+        bank_web::{
+            payments as payments_web,
+            tests::{deserialize_response_body, get, post, send_request},
+        },
+    };
+    use axum::{
+        http::{header::AUTHORIZATION, Request},
+        Router,
+    };
+    use rand::Rng;
+    use strum::IntoEnumIterator;
+    use uuid::Uuid;
+
+    async fn status_counts(router: &Router) -> HashMap<Status, i64> {
+        let response = get(router, "/api/reports/status-counts").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        deserialize_response_body::<StatusCountsBody>(response)
+            .await
+            .data
+    }
+
+    async fn create_payment(router: &Router) {
+        create_payment_of(router, 1_23).await;
+    }
+
+    async fn create_payment_of(router: &Router, amount: i32) {
+        let request_body = payments_web::RequestBody {
+            payment: payments_web::RequestData {
+                amount,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        post(router, "/api/payments", &request_body).await;
+    }
+
+    #[tokio::test]
+    async fn should_count_payments_by_status() {
+        let router = BankWeb::new_test().await.into_router();
+        let declining_router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .into_router();
+
+        let before = status_counts(&router).await;
+
+        create_payment(&router).await;
+        create_payment(&router).await;
+        create_payment(&declining_router).await;
+
+        let after = status_counts(&router).await;
+        for status in Status::iter() {
+            assert!(after.contains_key(&status), "missing {status:?}");
+        }
+        assert!(after[&Status::Approved] >= before[&Status::Approved] + 2);
+        assert!(after[&Status::Declined] > before[&Status::Declined]);
+        // payments created through the API are never left processing, but other tests
+        // insert processing payments concurrently
+        assert!(after[&Status::Processing] >= before[&Status::Processing]);
+    }
+
+    #[tokio::test]
+    async fn should_total_refunds_per_day() {
+        let bank_web = BankWeb::new_test().await;
+        let router = bank_web.clone().into_router();
+        // a random day of the 20th century, which no other test refunds on
+        let day = Date::from_calendar_date(1900, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc() + time::Duration::hours(12));
+        let refund = |amount| {
+            let pool = bank_web.pool.clone();
+            let clock = clock.clone();
+            async move {
+                let payment = Payment::new_test(&pool).await.unwrap();
+                refunds::create(
+                    &pool,
+                    &DummyService::default(),
+                    &clock,
+                    payment.id,
+                    amount,
+                    None,
+                    None,
+                    None,
+                    &[],
+                    false,
+                    None,
+                    None,
+                    None,
+                )
+                .await
+                .unwrap()
+            }
+        };
+        refund(10).await;
+        refund(20).await;
+        clock.advance(time::Duration::days(1));
+        refund(50).await;
+        // pending refunds aren't effective yet
+        let payment = Payment::new_test(&bank_web.pool).await.unwrap();
+        refunds::create(
+            &bank_web.pool,
+            &DummyService::default(),
+            &clock,
+            payment.id,
+            70,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            Some(60),
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let response = get(
+            &router,
+            format!(
+                "/api/reports/refunds/daily?from={}&to={}",
+                day - time::Duration::days(1),
+                day + time::Duration::days(2)
+            ),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let totals = deserialize_response_body::<DailyTotalsBody>(response)
+            .await
+            .data;
+        assert_eq!(
+            totals,
+            [
+                DailyTotal {
+                    day,
+                    count: 2,
+                    amount: 30,
+                },
+                DailyTotal {
+                    day: day + time::Duration::days(1),
+                    count: 1,
+                    amount: 50,
+                },
+            ]
+        );
+
+        for (from, to) in [
+            (day, day - time::Duration::days(1)),
+            (day, day + time::Duration::days(366)),
+        ] {
+            let response = get(
+                &router,
+                format!("/api/reports/refunds/daily?from={from}&to={to}"),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn should_compute_amount_percentiles() {
+        // a random day of the 26th century, which no other test pays on
+        let day = Date::from_calendar_date(2500, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc() + time::Duration::hours(12));
+        let router = BankWeb::new_test()
+            .await
+            .with_clock(clock.clone())
+            .into_router();
+        let declining_router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .with_clock(clock)
+            .into_router();
+        for amount in (1..=11).map(|n| n * 1_00) {
+            create_payment_of(&router, amount).await;
+        }
+        // declined payments aren't counted
+        create_payment_of(&declining_router, 100_000).await;
+
+        let percentiles = |from: Date, to: Date| {
+            let router = router.clone();
+            async move {
+                let response = get(
+                    &router,
+                    format!("/api/reports/amount-percentiles?from={from}&to={to}"),
+                )
+                .await;
+                assert_eq!(response.status(), StatusCode::OK);
+                deserialize_response_body::<AmountPercentiles>(response).await
+            }
+        };
+
+        let AmountPercentiles { p50, p90, p99 } = percentiles(day, day).await;
+        for (percentile, expected) in [(p50, 6_00.0), (p90, 10_00.0), (p99, 10_90.0)] {
+            let percentile = percentile.expect("missing percentile");
+            assert!(
+                (percentile - expected).abs() < 1e-6,
+                "{percentile} isn't {expected}"
+            );
+        }
+        let next_day = day + time::Duration::days(1);
+        assert_eq!(
+            percentiles(next_day, next_day).await,
+            AmountPercentiles {
+                p50: None,
+                p90: None,
+                p99: None,
+            }
+        );
+
+        let response = get(
+            &router,
+            format!("/api/reports/amount-percentiles?from={next_day}&to={day}"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn should_render_settlement_file() {
+        // a random day of the 20th century, which no other test pays on
+        let day = Date::from_calendar_date(1900, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc() + time::Duration::hours(12));
+        let bank_web = BankWeb::new_test().await.with_clock(clock.clone());
+        let router = bank_web.clone().into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = payments_web::RequestBody {
+            payment: payments_web::RequestData {
+                amount: 12_05,
+                currency: None,
+                card_number: card_number.clone(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment_id = deserialize_response_body::<payments_web::ResponseBody>(response)
+            .await
+            .data
+            .id;
+        refunds::create(
+            &bank_web.pool,
+            &DummyService::default(),
+            &clock,
+            payment_id,
+            2_00,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+
+        let response = get(&router, format!("/api/reports/settlement-file?date={day}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[CONTENT_TYPE],
+            "text/plain; charset=utf-8"
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let date = format!(
+            "{:04}{:02}{:02}",
+            day.year(),
+            u8::from(day.month()),
+            day.day()
+        );
+        let last_digits = &card_number[card_number.len() - 4..];
+        assert_eq!(
+            std::str::from_utf8(&body).unwrap(),
+            format!(
+                "D{}{date}   000000001005000000000000000000001005{last_digits}\n\
+                 T{date}00000001000000000001005000000000001005\n",
+                payment_id.simple()
+            )
+        );
+    }
+
+    async fn authorize(router: &Router, amount: i32, hold_amount: i32) -> Uuid {
+        let request_body = payments_web::AuthorizeRequestBody {
+            payment: payments_web::AuthorizeRequestData {
+                amount,
+                card_number: Card::new_test().into(),
+                hold_amount: Some(hold_amount),
+            },
+        };
+        let response = post(router, "/api/payments/authorize", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        deserialize_response_body::<payments_web::ResponseBody>(response)
+            .await
+            .data
+            .id
+    }
+
+    #[tokio::test]
+    async fn should_total_held_and_settled_amounts() {
+        // a random day of the 20th century, which no other test authorizes on
+        let day = Date::from_calendar_date(1900, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc() + time::Duration::hours(12));
+        let router = BankWeb::new_test().await.with_clock(clock).into_router();
+
+        authorize(&router, 10_00, 15_00).await;
+        let payment_id = authorize(&router, 20_00, 25_00).await;
+        let request_body = payments_web::CaptureRequestBody {
+            capture: payments_web::CaptureRequestData {
+                amount: Some(18_00),
+            },
+        };
+        let response = post(
+            &router,
+            format!("/api/payments/{payment_id}/capture"),
+            &request_body,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        // direct payments aren't settled authorizations
+        create_payment(&router).await;
+
+        let response = get(&router, format!("/api/reports/holds-vs-settled?date={day}")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<HoldsSummary>(response).await,
+            HoldsSummary {
+                total_held: 15_00,
+                total_settled: 18_00,
+                count_open_authorizations: 1,
+            }
+        );
+        let response = get(&router, "/api/reports/holds-vs-settled").await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn should_leave_test_payments_out_of_reports_unless_included() {
+        // a random day of the 24th century, which no other test authorizes on
+        let day = Date::from_calendar_date(2300, time::Month::January, 1).unwrap()
+            + time::Duration::days(rand::thread_rng().gen_range(0..36_000));
+        let clock = MockClock::new(day.midnight().assume_utc() + time::Duration::hours(12));
+        let live_router = BankWeb::new_test()
+            .await
+            .with_clock(clock.clone())
+            .into_router();
+        let test_router = BankWeb::new_test()
+            .await
+            .with_clock(clock)
+            .with_sandbox(true)
+            .into_router();
+
+        let live_id = authorize(&live_router, 10_00, 15_00).await;
+        let test_id = authorize(&test_router, 20_00, 25_00).await;
+        for (id, livemode) in [(live_id, true), (test_id, false)] {
+            let response = get(&live_router, format!("/api/payments/{id}")).await;
+            let data = deserialize_response_body::<payments_web::ResponseBody>(response)
+                .await
+                .data;
+            assert_eq!(data.livemode, livemode);
+        }
+
+        let holds_vs_settled = |query: String| {
+            let router = live_router.clone();
+            async move {
+                let response = get(&router, format!("/api/reports/holds-vs-settled?{query}")).await;
+                assert_eq!(response.status(), StatusCode::OK);
+                deserialize_response_body::<HoldsSummary>(response).await
+            }
+        };
+        let summary = holds_vs_settled(format!("date={day}")).await;
+        assert_eq!(summary.total_held, 15_00);
+        assert_eq!(summary.count_open_authorizations, 1);
+        let summary = holds_vs_settled(format!("date={day}&include_test=false")).await;
+        assert_eq!(summary.total_held, 15_00);
+        let summary = holds_vs_settled(format!("date={day}&include_test=true")).await;
+        assert_eq!(summary.total_held, 40_00);
+        assert_eq!(summary.count_open_authorizations, 2);
+    }
+
+    #[tokio::test]
+    async fn should_compute_net_settlement_of_merchant() {
+        let day = Date::from_calendar_date(2000, time::Month::March, 1).unwrap();
+        let clock = MockClock::new(day.midnight().assume_utc() + time::Duration::hours(12));
+        let merchant_id = Uuid::new_v4();
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_merchant_api_key(merchant_id, "merchant key")
+            .with_fees(FeeConfig::new(30, 0))
+            .with_clock(clock.clone());
+        let pool = bank_web.pool.clone();
+        let router = bank_web.into_router();
+        let declining_router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .with_clock(clock.clone())
+            .into_router();
+        let pay = |router: Router, amount| async move {
+            let request_body = payments_web::RequestBody {
+                payment: payments_web::RequestData {
+                    amount,
+                    currency: None,
+                    card_number: Card::new_test().into(),
+                    metadata: Default::default(),
+                    customer_id: None,
+                    merchant_id: Some(merchant_id),
+                    merchant_reference: None,
+                    channel: None,
+                },
+            };
+            let response = post(&router, "/api/payments", &request_body).await;
+            deserialize_response_body::<payments_web::ResponseBody>(response)
+                .await
+                .data
+                .id
+        };
+
+        let refunded_id = pay(router.clone(), 10_00).await;
+        pay(router.clone(), 20_00).await;
+        // declined payments aren't settled
+        pay(declining_router, 5_00).await;
+        refunds::create(
+            &pool,
+            &DummyService::default(),
+            &clock,
+            refunded_id,
+            3_00,
+            None,
+            None,
+            None,
+            &[],
+            false,
+            None,
+            None,
+            None,
+        )
+        .await
+        .unwrap();
+        // the next day is outside the period
+        clock.advance(time::Duration::days(1));
+        pay(router.clone(), 40_00).await;
+
+        let request = |token: &str| {
+            Request::builder()
+                .uri(format!("/api/reports/net-settlement?from={day}&to={day}"))
+                .header(AUTHORIZATION, format!("Bearer {token}"))
+                .body(hyper::Body::empty())
+                .unwrap()
+        };
+        let response = send_request(&router, request("wrong")).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send_request(&router, request("merchant key")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            deserialize_response_body::<NetSettlement>(response).await,
+            NetSettlement {
+                gross: 30_00,
+                refunds: 3_00,
+                fees: 60,
+                net: 26_40,
+            }
+        );
+    }
+
+    async fn amount_histogram(router: &Router, bucket: i32) -> Vec<HistogramBucket> {
+        let response = get(
+            router,
+            format!("/api/reports/amount-histogram?bucket={bucket}"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        deserialize_response_body::<HistogramBody>(response)
+            .await
+            .data
+    }
+
+    #[tokio::test]
+    async fn should_count_approved_payments_per_amount_bucket() {
+        let router = BankWeb::new_test().await.into_router();
+        let declining_router = BankWeb::new_test_with_response("insufficient_funds")
+            .await
+            .into_router();
+        // amounts no other test pays, whose buckets only count the payments made here
+        let bucket = 1_000;
+        let start = 98_765_000;
+        let count = |buckets: &[HistogramBucket], bucket_start: i64| {
+            buckets
+                .iter()
+                .find(|bucket| bucket.bucket_start == bucket_start)
+                .map_or(0, |bucket| bucket.count)
+        };
+        let before = amount_histogram(&router, bucket).await;
+
+        create_payment_of(&router, start).await;
+        create_payment_of(&router, start + 999).await;
+        create_payment_of(&router, start + 1_000).await;
+        create_payment_of(&declining_router, start + 1_000).await;
+
+        let after = amount_histogram(&router, bucket).await;
+        let start = i64::from(start);
+        assert_eq!(count(&after, start) - count(&before, start), 2);
+        assert_eq!(
+            count(&after, start + 1_000) - count(&before, start + 1_000),
+            1
+        );
+        let bucket = after
+            .iter()
+            .find(|bucket| bucket.bucket_start == start)
+            .unwrap();
+        assert_eq!(bucket.bucket_end, start + 1_000);
+        assert!(after
+            .windows(2)
+            .all(|buckets| buckets[0].bucket_start < buckets[1].bucket_start));
+
+        for bucket in [0, -1] {
+            let response = get(
+                &router,
+                format!("/api/reports/amount-histogram?bucket={bucket}"),
+            )
+            .await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+}
@@ -0,0 +1,147 @@
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::{Request, StatusCode},
+    middleware::Next,
+    response::Response,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{admin::AdminAuth, BankWeb};
+use crate::bank::{
+    accounts::AccountService,
+    sandbox::{self, ResetSummary},
+};
+
+/// Header delaying the response by the given number of milliseconds, in sandbox mode only,
+/// to exercise the timeouts of clients and of the server.
+pub const SIMULATE_DELAY_HEADER: &str = "x-simulate-delay-ms";
+/// Longest delay simulated, whatever the header asks for.
+pub const MAX_SIMULATED_DELAY_MS: u64 = 10_000;
+
+/// Sleeps for the delay requested by the `SIMULATE_DELAY_HEADER` before handling the
+/// request. The header is ignored unless in sandbox mode, or when it isn't a number.
+pub async fn simulate_delay<T, B>(
+    State(bank_web): State<BankWeb<T>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let delay_ms = request
+        .headers()
+        .get(SIMULATE_DELAY_HEADER)
+        .and_then(|delay_ms| delay_ms.to_str().ok())
+        .and_then(|delay_ms| delay_ms.parse::<u64>().ok())
+        .filter(|_| bank_web.sandbox);
+    if let Some(delay_ms) = delay_ms {
+        tokio::time::sleep(Duration::from_millis(delay_ms.min(MAX_SIMULATED_DELAY_MS))).await;
+    }
+    next.run(request).await
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ResetBody {
+    data: ResetSummary,
+}
+
+/// Deletes all payments, refunds and what's recorded about them, responding how many rows
+/// were deleted from each table.
+///
+/// Responds with 404 unless both sandbox mode and resets are enabled.
+pub async fn reset<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+) -> Result<(StatusCode, Json<ResetBody>), StatusCode> {
+    if !(bank_web.sandbox && bank_web.sandbox_reset) {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let mut transaction = bank_web.pool.begin().await.unwrap();
+    let summary = sandbox::reset(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+    tracing::warn!(?summary, "reset sandbox data");
+
+    Ok((StatusCode::OK, Json(ResetBody { data: summary })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bank_web::tests::send_request;
+    use axum::http::{header::AUTHORIZATION, Method};
+    use tokio::time::Instant;
+
+    async fn get_delayed(
+        bank_web: BankWeb<crate::bank::accounts::DummyService>,
+        delay_ms: u64,
+    ) -> (StatusCode, Duration) {
+        let router = bank_web.into_router();
+        let request = Request::builder()
+            .method(Method::GET)
+            .uri("/health/live")
+            .header(SIMULATE_DELAY_HEADER, delay_ms.to_string())
+            .body(hyper::Body::empty())
+            .unwrap();
+        let start = Instant::now();
+        let response = send_request(&router, request).await;
+        (response.status(), start.elapsed())
+    }
+
+    async fn post_reset(bank_web: BankWeb<crate::bank::accounts::DummyService>) -> StatusCode {
+        let router = bank_web.with_admin_token("secret").into_router();
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri("/api/admin/reset")
+            .header(AUTHORIZATION, "Bearer secret")
+            .body(hyper::Body::empty())
+            .unwrap();
+        send_request(&router, request).await.status()
+    }
+
+    // resetting for real would delete the rows of the tests running concurrently: see
+    // `bank::sandbox::tests::test_reset` instead
+    #[tokio::test]
+    async fn should_not_reset_unless_enabled_in_sandbox_mode() {
+        let bank_web = BankWeb::new_test().await;
+
+        assert_eq!(
+            post_reset(bank_web.clone().with_sandbox_reset(true)).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            post_reset(bank_web.with_sandbox(true)).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn should_delay_response_in_sandbox_mode() {
+        let bank_web = BankWeb::new_test().await.with_sandbox(true);
+
+        let (status, elapsed) = get_delayed(bank_web, 200).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(elapsed >= Duration::from_millis(200), "{elapsed:?}");
+        assert!(elapsed < Duration::from_secs(2), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn should_ignore_delay_outside_sandbox_mode() {
+        let bank_web = BankWeb::new_test().await;
+
+        let (status, elapsed) = get_delayed(bank_web, 5_000).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+
+    #[tokio::test]
+    async fn should_time_out_delay_exceeding_request_timeout() {
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_sandbox(true)
+            .with_request_timeout(Duration::from_millis(100));
+
+        let (status, elapsed) = get_delayed(bank_web, 5_000).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert!(elapsed < Duration::from_secs(1), "{elapsed:?}");
+    }
+}
@@ -0,0 +1,131 @@
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+
+use super::BankWeb;
+use crate::bank::{accounts::AccountService, payments, tokens};
+
+/// Most cards tokenized at once.
+pub const MAX_BATCH_TOKENIZED_CARDS: usize = 1_000;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchRequestBody {
+    pub cards: Vec<String>,
+}
+
+/// The token of a card of the batch, or why it couldn't be tokenized.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct TokenData {
+    pub token: Option<String>,
+    pub last4: Option<String>,
+    /// Why the card number was rejected, `None` if it was tokenized.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BatchBody {
+    pub tokens: Vec<TokenData>,
+}
+
+/// Tokenizes each of the cards, e.g. when migrating an existing card vault, responding
+/// with their tokens in the order the cards were given.
+///
+/// Cards are validated independently: malformed card numbers are reported in their place
+/// without failing the others. Tokenizing more than `MAX_BATCH_TOKENIZED_CARDS` cards at
+/// once is rejected with 422.
+pub async fn batch<T: AccountService + Clone>(
+    State(bank_web): State<BankWeb<T>>,
+    Json(body): Json<BatchRequestBody>,
+) -> Result<(StatusCode, Json<BatchBody>), StatusCode> {
+    if body.cards.len() > MAX_BATCH_TOKENIZED_CARDS {
+        return Err(StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    let card_numbers: Vec<_> = body
+        .cards
+        .iter()
+        .map(|card_number| payments::validate_card_number(card_number))
+        .collect();
+    let valid_card_numbers: Vec<_> = card_numbers
+        .iter()
+        .filter_map(|card_number| card_number.as_ref().ok().cloned())
+        .collect();
+    let card_tokens =
+        tokens::tokenize_all(&bank_web.pool, bank_web.clock.as_ref(), &valid_card_numbers)
+            .await
+            .unwrap();
+
+    let tokens = card_numbers
+        .into_iter()
+        .map(|card_number| match card_number {
+            Ok(card_number) => TokenData {
+                token: Some(card_tokens[&card_number].clone()),
+                last4: Some(last4(&card_number)),
+                error: None,
+            },
+            Err(e) => TokenData {
+                token: None,
+                last4: None,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect();
+
+    Ok((StatusCode::OK, Json(BatchBody { tokens })))
+}
+
+fn last4(card_number: &str) -> String {
+    card_number
+        .chars()
+        .skip(card_number.chars().count().saturating_sub(4))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::payment_instruments::Card,
+        bank_web::tests::{deserialize_response_body, post},
+    };
+
+    #[tokio::test]
+    async fn should_tokenize_cards_independently() {
+        let router = BankWeb::new_test().await.into_router();
+        let card_number: String = Card::new_test().into();
+        let request_body = BatchRequestBody {
+            cards: vec![
+                card_number.clone(),
+                "not-a-card".into(),
+                "".into(),
+                card_number.clone(),
+            ],
+        };
+
+        let response = post(&router, "/api/tokens/batch", &request_body).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let tokens = deserialize_response_body::<BatchBody>(response)
+            .await
+            .tokens;
+        assert_eq!(tokens.len(), 4);
+        assert!(tokens[0].token.is_some());
+        assert_eq!(tokens[0].last4.as_deref(), Some(&card_number[11..]));
+        assert_eq!(tokens[0].error, None);
+        assert_eq!(
+            tokens[1],
+            TokenData {
+                token: None,
+                last4: None,
+                error: Some("invalid_card_format".into()),
+            }
+        );
+        assert_eq!(tokens[2].error.as_deref(), Some("missing_card_number"));
+        assert_eq!(tokens[3], tokens[0]);
+
+        let request_body = BatchRequestBody {
+            cards: vec![card_number; MAX_BATCH_TOKENIZED_CARDS + 1],
+        };
+        let response = post(&router, "/api/tokens/batch", &request_body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+}
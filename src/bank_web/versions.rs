@@ -0,0 +1,61 @@
+use axum::{
+    http::{HeaderName, HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+
+/// Version of the API, following the crate's semver version.
+pub const API_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Header telling clients and load balancers the `API_VERSION` of the responding server,
+/// e.g. to tell servers apart during rolling deploys.
+pub const API_VERSION_HEADER: &str = "x-api-version";
+
+/// Sets the `API_VERSION_HEADER` on every response, errors included.
+pub async fn set_api_version<B>(request: Request<B>, next: Next<B>) -> Response {
+    let mut response = next.run(request).await;
+    response.headers_mut().insert(
+        HeaderName::from_static(API_VERSION_HEADER),
+        HeaderValue::from_static(API_VERSION),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::payment_instruments::Card,
+        bank_web::{payments, tests::post, BankWeb},
+    };
+    use axum::http::StatusCode;
+
+    #[tokio::test]
+    async fn should_set_api_version_on_successes_and_errors() {
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = |card_number: String| payments::RequestBody {
+            payment: payments::RequestData {
+                amount: 10_00,
+                currency: None,
+                card_number,
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+
+        let response = post(
+            &router,
+            "/api/payments",
+            &request_body(Card::new_test().into()),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[API_VERSION_HEADER], API_VERSION);
+
+        let response = post(&router, "/api/payments", &request_body("not-a-card".into())).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(response.headers()[API_VERSION_HEADER], API_VERSION);
+    }
+}
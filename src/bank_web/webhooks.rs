@@ -0,0 +1,296 @@
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::{admin::AdminAuth, BankWeb};
+use crate::bank::webhooks::{WebhookDelivery, WebhookDeliveryStatus};
+use crate::bank::{accounts::AccountService, webhooks};
+
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 1_000;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListParams {
+    limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[cfg_attr(feature = "camel-case", serde(rename_all = "camelCase"))]
+pub struct DeliveryData {
+    id: Uuid,
+    event_id: Uuid,
+    url: String,
+    payload: serde_json::Value,
+    status: WebhookDeliveryStatus,
+    attempts: i32,
+    last_error: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    next_attempt_at: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    inserted_at: OffsetDateTime,
+}
+
+impl From<WebhookDelivery> for DeliveryData {
+    fn from(delivery: WebhookDelivery) -> Self {
+        Self {
+            id: delivery.id,
+            event_id: delivery.event_id,
+            url: delivery.url,
+            payload: delivery.payload,
+            status: delivery.status,
+            attempts: delivery.attempts,
+            last_error: delivery.last_error,
+            next_attempt_at: delivery.next_attempt_at.assume_utc(),
+            inserted_at: delivery.inserted_at.assume_utc(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListBody {
+    data: Vec<DeliveryData>,
+}
+
+/// Lists the most recent webhook deliveries, newest first, for operators only since their
+/// payloads carry card data.
+pub async fn list_deliveries<T: AccountService + Clone>(
+    _: AdminAuth,
+    State(bank_web): State<BankWeb<T>>,
+    Query(params): Query<ListParams>,
+) -> (StatusCode, Json<ListBody>) {
+    let limit = params
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let deliveries = webhooks::list(&bank_web.pool, limit).await.unwrap();
+
+    (
+        StatusCode::OK,
+        Json(ListBody {
+            data: deliveries.into_iter().map(DeliveryData::from).collect(),
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bank::{
+            payment_instruments::Card,
+            payments::{self as bank_payments, Status},
+            webhooks::{tests::FlakySender, RetryPolicy, UnqueuedDeliveries},
+        },
+        bank_web::{
+            payments,
+            tests::{deserialize_response_body, get, get_as_admin, post, ADMIN_TOKEN},
+        },
+    };
+    use axum::Router;
+
+    async fn list(router: &Router) -> Vec<DeliveryData> {
+        let response = get_as_admin(router, "/api/admin/webhook-deliveries").await;
+        assert_eq!(response.status(), StatusCode::OK);
+        deserialize_response_body::<ListBody>(response).await.data
+    }
+
+    #[tokio::test]
+    async fn should_queue_and_deliver_payment_created_webhook() {
+        let sender = FlakySender::new(1);
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .with_webhook_url(&sender.url);
+        let pool = bank_web.pool.clone();
+        let clock = bank_web.clock.clone();
+        let router = bank_web.into_router();
+
+        let request_body = payments::RequestBody {
+            payment: payments::RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        let payment = deserialize_response_body::<payments::ResponseBody>(response).await;
+
+        let find = |deliveries: Vec<DeliveryData>| {
+            deliveries
+                .into_iter()
+                .find(|delivery| delivery.url == sender.url)
+                .expect("delivery wasn't queued")
+        };
+
+        let delivery = find(list(&router).await);
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+        assert_eq!(delivery.payload["event"], payments::PAYMENT_CREATED_EVENT);
+        assert_eq!(
+            delivery.payload["data"]["id"],
+            payment.data.id.to_string().as_str()
+        );
+
+        let policy = RetryPolicy {
+            max_attempts: 3,
+            base_delay: time::Duration::ZERO,
+        };
+        for _ in 0..2 {
+            webhooks::deliver_due(&pool, clock.as_ref(), &sender, &policy)
+                .await
+                .expect("failed to deliver webhooks");
+        }
+
+        let delivery = find(list(&router).await);
+        assert_eq!(delivery.status, WebhookDeliveryStatus::Delivered);
+        assert_eq!(delivery.attempts, 2);
+    }
+
+    #[tokio::test]
+    async fn should_only_list_deliveries_to_operators() {
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .into_router();
+        let response = get(&router, "/api/admin/webhook-deliveries").await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn should_queue_new_delivery_on_resend() {
+        let sender = FlakySender::new(0);
+        let router = BankWeb::new_test()
+            .await
+            .with_admin_token(ADMIN_TOKEN)
+            .with_webhook_url(&sender.url)
+            .into_router();
+
+        let request_body = payments::RequestBody {
+            payment: payments::RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        let payment = deserialize_response_body::<payments::ResponseBody>(response).await;
+
+        let uri = format!("/api/payments/{}/resend-webhook", payment.data.id);
+        let response = post(&router, &uri, &()).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let deliveries: Vec<_> = list(&router)
+            .await
+            .into_iter()
+            .filter(|delivery| delivery.url == sender.url)
+            .collect();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!(deliveries[0].event_id, deliveries[1].event_id);
+        assert_ne!(deliveries[0].id, deliveries[1].id);
+        for delivery in deliveries {
+            assert_eq!(delivery.status, WebhookDeliveryStatus::Pending);
+            assert_eq!(delivery.payload["event"], payments::PAYMENT_CREATED_EVENT);
+            assert_eq!(
+                delivery.payload["data"]["id"],
+                payment.data.id.to_string().as_str()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn should_reject_resend_of_unknown_payment_or_without_webhook_url() {
+        let router = BankWeb::new_test()
+            .await
+            .with_webhook_url("http://localhost/webhooks")
+            .into_router();
+        let uri = format!("/api/payments/{}/resend-webhook", Uuid::new_v4());
+        let response = post(&router, &uri, &()).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let router = BankWeb::new_test().await.into_router();
+        let request_body = payments::RequestBody {
+            payment: payments::RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        let payment = deserialize_response_body::<payments::ResponseBody>(response).await;
+        let uri = format!("/api/payments/{}/resend-webhook", payment.data.id);
+        let response = post(&router, &uri, &()).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn should_make_payment_and_record_webhook_failing_to_be_queued() {
+        // longer than the deliveries' URL column, so that inserting the delivery fails
+        let url = format!("http://receiver.test/{}", "a".repeat(3_000));
+        let unqueued = UnqueuedDeliveries::default();
+        let bank_web = BankWeb::new_test()
+            .await
+            .with_webhook_url(&url)
+            .with_unqueued_webhooks(unqueued.clone());
+        let pool = bank_web.pool.clone();
+        let clock = bank_web.clock.clone();
+        let router = bank_web.into_router();
+
+        let request_body = payments::RequestBody {
+            payment: payments::RequestData {
+                amount: 1_23,
+                currency: None,
+                card_number: Card::new_test().into(),
+                metadata: Default::default(),
+                customer_id: None,
+                merchant_id: None,
+                merchant_reference: None,
+                channel: None,
+            },
+        };
+        let response = post(&router, "/api/payments", &request_body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let payment = deserialize_response_body::<payments::ResponseBody>(response).await;
+        assert_eq!(
+            bank_payments::get(&pool, payment.data.id)
+                .await
+                .unwrap()
+                .status,
+            Status::Approved
+        );
+
+        let deliveries = unqueued.list();
+        assert_eq!(deliveries.len(), 1);
+        assert_eq!(deliveries[0].url, url);
+        assert_eq!(
+            deliveries[0].payload["event"],
+            payments::PAYMENT_CREATED_EVENT
+        );
+        assert_eq!(
+            deliveries[0].payload["data"]["id"],
+            payment.data.id.to_string().as_str()
+        );
+
+        // the delivery can never be queued, rather than the database being unavailable
+        assert_eq!(unqueued.requeue(&pool, clock.as_ref()).await, 0);
+        assert!(unqueued.list().is_empty());
+    }
+}
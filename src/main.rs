@@ -1,4 +1,4 @@
-use std::{net::SocketAddr, time::Duration};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 
 use dotenvy::dotenv;
 use sqlx::{postgres::PgPoolOptions, PgPool};
@@ -30,19 +30,313 @@ async fn main() {
         .run(&pool)
         .await
         .expect("failed to run sqlx migrations");
+    if let Err(e) = bank::schema::validate(&pool).await {
+        panic!("{e}");
+    }
 
-    let account_service = bank::accounts::DummyService::default();
-    let router = BankWeb::new(pool, account_service).into_router();
+    let card_fingerprint_key = bank::fingerprints::FingerprintKey::new(
+        std::env::var("CARD_FINGERPRINT_KEY").expect("CARD_FINGERPRINT_KEY must be in environment"),
+    );
+    let backfilled = bank::payments::backfill_card_fingerprints(&pool, &card_fingerprint_key)
+        .await
+        .expect("failed to backfill card fingerprints");
+    if backfilled > 0 {
+        tracing::info!(count = backfilled, "backfilled card fingerprints");
+    }
+
+    // e.g. `5/30s`: holds failing fast for 30 seconds after 5 in a row were unavailable
+    let account_service: bank::accounts::DynAccountService =
+        match std::env::var("ACCOUNT_SERVICE_CIRCUIT_BREAKER") {
+            Ok(config) => Arc::new(bank::circuit_breaker::CircuitBreaker::new(
+                bank::accounts::DummyService::default(),
+                config
+                    .parse()
+                    .expect("ACCOUNT_SERVICE_CIRCUIT_BREAKER has an invalid value"),
+                Arc::new(bank::clock::SystemClock),
+            )),
+            Err(_) => Arc::new(bank::accounts::DummyService::default()),
+        };
+    let mut fees = bank::fees::FeeConfig::new(
+        env_or_default("PAYMENT_FEE_FLAT"),
+        env_or_default("PAYMENT_FEE_BASIS_POINTS"),
+    );
+    // e.g. `half_even`, `half_up` or `floor`
+    if let Ok(rounding) = std::env::var("PAYMENT_FEE_ROUNDING") {
+        let rounding = rounding
+            .parse()
+            .expect("PAYMENT_FEE_ROUNDING has an invalid value");
+        fees = fees.with_rounding(rounding);
+    }
+    let mut bank_web = BankWeb::new(pool.clone(), account_service.clone(), card_fingerprint_key)
+        .with_fees(fees)
+        .with_balance_precheck(env_or_default("BALANCE_PRECHECK"));
+    // e.g. `8/100`: at most 8 holds placed at once, with 100 more waiting for their turn
+    if let Ok(limit) = std::env::var("ACCOUNT_SERVICE_CONCURRENCY") {
+        let limit = limit
+            .parse()
+            .expect("ACCOUNT_SERVICE_CONCURRENCY has an invalid value");
+        bank_web = bank_web.with_account_service_concurrency(limit);
+    }
+    if let Ok(currency) = std::env::var("DEFAULT_CURRENCY") {
+        let currency = bank::payments::validate_currency(&currency)
+            .expect("DEFAULT_CURRENCY has an invalid value");
+        bank_web = bank_web.with_default_currency(currency);
+    }
+    // e.g. `EUR:50-1000000,JPY:1-100000000`
+    if let Ok(amount_bounds) = std::env::var("AMOUNT_BOUNDS") {
+        for entry in amount_bounds.split(',') {
+            let (currency, bounds) = entry
+                .split_once(':')
+                .expect("AMOUNT_BOUNDS has an invalid value");
+            let bounds = bounds.parse().expect("AMOUNT_BOUNDS has an invalid value");
+            bank_web = bank_web.with_amount_bounds(currency.trim().to_ascii_uppercase(), bounds);
+        }
+    }
+    // e.g. `8f0c6a3e-1b7d-4d2a-9c5e-2f4b6d8a0c1e:EUR|USD,...`: merchants not listed accept
+    // every currency
+    if let Ok(merchant_currencies) = std::env::var("MERCHANT_CURRENCIES") {
+        for entry in merchant_currencies.split(',') {
+            let (merchant_id, currencies) = entry
+                .split_once(':')
+                .expect("MERCHANT_CURRENCIES has an invalid value");
+            let merchant_id = merchant_id
+                .trim()
+                .parse()
+                .expect("MERCHANT_CURRENCIES has an invalid value");
+            let currencies = currencies.split('|').map(|currency| {
+                bank::payments::validate_currency(currency.trim())
+                    .expect("MERCHANT_CURRENCIES has an invalid value")
+            });
+            bank_web = bank_web.with_merchant_currencies(merchant_id, currencies);
+        }
+    }
+    if let Ok(webhook_url) = std::env::var("WEBHOOK_URL") {
+        bank_web = bank_web.with_webhook_url(webhook_url);
+    }
+    if let Ok(threshold) = std::env::var("REFUND_APPROVAL_THRESHOLD") {
+        let threshold = threshold
+            .parse()
+            .expect("REFUND_APPROVAL_THRESHOLD has an invalid value");
+        bank_web = bank_web.with_refund_approval_threshold(threshold);
+    }
+    if let Ok(days) = std::env::var("REFUND_WINDOW_DAYS") {
+        let days = days
+            .parse()
+            .expect("REFUND_WINDOW_DAYS has an invalid value");
+        bank_web = bank_web.with_refund_window(time::Duration::days(days));
+    }
+    if let Ok(window) = std::env::var("ACCEPTING_WINDOW") {
+        let window = window
+            .parse()
+            .expect("ACCEPTING_WINDOW has an invalid value");
+        bank_web = bank_web.with_accepting_window(window);
+    }
+    // e.g. `500000/24h`
+    if let Ok(velocity_limit) = std::env::var("VELOCITY_LIMIT") {
+        let velocity_limit = velocity_limit
+            .parse()
+            .expect("VELOCITY_LIMIT has an invalid value");
+        bank_web = bank_web.with_velocity_limit(velocity_limit);
+    }
+    if let Ok(limit) = std::env::var("DAILY_CARD_LIMIT") {
+        let limit = limit
+            .parse()
+            .expect("DAILY_CARD_LIMIT has an invalid value");
+        bank_web = bank_web.with_daily_card_limit(limit);
+    }
+    // e.g. `8f0c6a3e-1b7d-4d2a-9c5e-2f4b6d8a0c1e,...`: the merchants opted in
+    if let Ok(merchant_ids) = std::env::var("IMPLICIT_IDEMPOTENCY_MERCHANTS") {
+        let merchant_ids = merchant_ids
+            .split(',')
+            .map(|merchant_id| {
+                merchant_id
+                    .trim()
+                    .parse()
+                    .expect("IMPLICIT_IDEMPOTENCY_MERCHANTS has an invalid value")
+            })
+            .collect();
+        let window_secs = std::env::var("IMPLICIT_IDEMPOTENCY_WINDOW_SECS")
+            .expect("IMPLICIT_IDEMPOTENCY_WINDOW_SECS must be in environment")
+            .parse()
+            .expect("IMPLICIT_IDEMPOTENCY_WINDOW_SECS has an invalid value");
+        bank_web = bank_web.with_implicit_idempotency(bank::merchants::ImplicitIdempotency::new(
+            merchant_ids,
+            time::Duration::seconds(window_secs),
+        ));
+    }
+    if let Ok(proxies) = std::env::var("TRUSTED_PROXIES") {
+        let proxies = proxies
+            .split(',')
+            .map(|proxy| {
+                proxy
+                    .trim()
+                    .parse()
+                    .expect("TRUSTED_PROXIES has an invalid value")
+            })
+            .collect();
+        bank_web = bank_web.with_trusted_proxies(proxies);
+    }
+    if let Ok(admin_token) = std::env::var("ADMIN_TOKEN") {
+        bank_web = bank_web.with_admin_token(admin_token);
+    }
+    // e.g. `8f0c6a3e-1b7d-4d2a-9c5e-2f4b6d8a0c1e:sk_live_...,...`
+    if let Ok(merchant_api_keys) = std::env::var("MERCHANT_API_KEYS") {
+        for entry in merchant_api_keys.split(',') {
+            let (merchant_id, api_key) = entry
+                .split_once(':')
+                .expect("MERCHANT_API_KEYS has an invalid value");
+            let merchant_id = merchant_id
+                .trim()
+                .parse()
+                .expect("MERCHANT_API_KEYS has an invalid value");
+            bank_web = bank_web.with_merchant_api_key(merchant_id, api_key.trim());
+        }
+    }
+    let sandbox = env_or_default("SANDBOX");
+    bank_web = bank_web.with_sandbox(sandbox);
+    bank_web = bank_web.with_sandbox_reset(env_or_default("SANDBOX_RESET"));
+    if let Ok(magic_amounts) = std::env::var("SANDBOX_MAGIC_AMOUNTS") {
+        let magic_amounts = magic_amounts
+            .parse()
+            .expect("SANDBOX_MAGIC_AMOUNTS has an invalid value");
+        bank_web = bank_web.with_magic_amounts(magic_amounts);
+    }
+    // e.g. `42,4000`
+    if let Ok(test_card_bins) = std::env::var("TEST_CARD_BINS") {
+        let test_card_bins = test_card_bins
+            .parse()
+            .expect("TEST_CARD_BINS has an invalid value");
+        bank_web = bank_web.with_test_card_bins(test_card_bins);
+    }
+    if let Ok(path) = std::env::var("PAYMENT_QUEUE_PATH") {
+        let queue = bank::payment_queue::PaymentQueue::new(path);
+        bank::payment_queue::spawn_worker(
+            pool.clone(),
+            account_service.clone(),
+            queue.clone(),
+            Duration::from_secs(5),
+        );
+        bank_web = bank_web.with_payment_queue(queue);
+    }
+    if let Ok(mask_pan) = std::env::var("MASK_PAN") {
+        let mask_pan = mask_pan.parse().expect("MASK_PAN has an invalid value");
+        bank_web = bank_web.with_mask_pan(mask_pan);
+    }
+    if let Ok(display) = std::env::var("CARD_NUMBER_DISPLAY") {
+        let display = display
+            .parse()
+            .expect("CARD_NUMBER_DISPLAY has an invalid value");
+        bank_web = bank_web.with_card_number_display(display);
+    }
+    if let Ok(strict_payment_ids) = std::env::var("STRICT_PAYMENT_IDS") {
+        let strict_payment_ids = strict_payment_ids
+            .parse()
+            .expect("STRICT_PAYMENT_IDS has an invalid value");
+        bank_web = bank_web.with_strict_payment_ids(strict_payment_ids);
+    }
+    if let Ok(timeout_ms) = std::env::var("REQUEST_TIMEOUT_MS") {
+        let timeout_ms = timeout_ms
+            .parse()
+            .expect("REQUEST_TIMEOUT_MS has an invalid value");
+        bank_web = bank_web.with_request_timeout(Duration::from_millis(timeout_ms));
+    }
+    bank_web = bank_web.with_require_https(env_or_default("REQUIRE_HTTPS"));
+    bank_web = bank_web.with_expose_database_errors(env_or_default("EXPOSE_DATABASE_ERRORS"));
+    bank_web = bank_web.with_collapse_not_approved(env_or_default("COLLAPSE_NOT_APPROVED"));
+    bank_web = bank_web.with_demote_duplicate_cards(env_or_default("DEMOTE_DUPLICATE_CARDS"));
+    if let Ok(retries) = std::env::var("HOLD_RETRIES") {
+        let retries = retries.parse().expect("HOLD_RETRIES has an invalid value");
+        bank_web = bank_web.with_hold_retries(retries);
+    }
+    let status_changes = bank::payment_events::StatusChanges::default();
+    bank_web = bank_web.with_status_changes(status_changes.clone());
+    // settles every processing payment, so sandbox only
+    if let Some(delay_secs) = std::env::var("SANDBOX_SETTLEMENT_DELAY_SECS")
+        .ok()
+        .filter(|_| sandbox)
+    {
+        let delay = time::Duration::seconds(
+            delay_secs
+                .parse()
+                .expect("SANDBOX_SETTLEMENT_DELAY_SECS has an invalid value"),
+        );
+        bank::payments::spawn_settler(
+            pool.clone(),
+            Arc::new(bank::clock::SystemClock),
+            fees,
+            delay,
+            status_changes,
+            Duration::from_secs(1),
+        );
+        bank_web = bank_web.with_settlement_delay(delay);
+    }
+    if let Ok(hours) = std::env::var("AUTO_CAPTURE_DELAY_HOURS") {
+        let hours = hours
+            .parse()
+            .expect("AUTO_CAPTURE_DELAY_HOURS has an invalid value");
+        bank_web = bank_web.with_auto_capture_delay(time::Duration::hours(hours));
+        bank_web.spawn_auto_capturer(Duration::from_secs(60));
+    }
+    bank_web.spawn_refund_executor(Duration::from_secs(60));
+    let unqueued_webhooks = bank::webhooks::UnqueuedDeliveries::default();
+    bank_web = bank_web.with_unqueued_webhooks(unqueued_webhooks.clone());
+    let router = bank_web.into_router();
+
+    if let Ok(days) = std::env::var("PAYMENT_RETENTION_DAYS") {
+        let days = days
+            .parse()
+            .expect("PAYMENT_RETENTION_DAYS has an invalid value");
+        bank::payments::spawn_archiver(
+            pool.clone(),
+            Arc::new(bank::clock::SystemClock),
+            time::Duration::days(days),
+            Duration::from_secs(60 * 60),
+        );
+    }
+
+    if let Ok(hours) = std::env::var("DANGLING_HOLD_TIMEOUT_HOURS") {
+        let hours = hours
+            .parse()
+            .expect("DANGLING_HOLD_TIMEOUT_HOURS has an invalid value");
+        bank::payments::spawn_dangling_hold_sweeper(
+            pool.clone(),
+            account_service,
+            Arc::new(bank::clock::SystemClock),
+            time::Duration::hours(hours),
+            Duration::from_secs(60 * 60),
+        );
+    }
+
+    bank::webhooks::spawn_worker(
+        pool,
+        Arc::new(bank::clock::SystemClock),
+        Arc::new(bank::webhooks::HttpSender::default()),
+        bank::webhooks::RetryPolicy::default(),
+        unqueued_webhooks,
+        Duration::from_secs(5),
+    );
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 4000));
     tracing::info!("listening on http://{}", addr);
 
     axum::Server::bind(&addr)
-        .serve(router.into_make_service())
+        .serve(router.into_make_service_with_connect_info::<SocketAddr>())
         .await
         .expect("failed to serve");
 }
 
+/// Parses the `key` environment variable, falling back to the type's default when unset.
+fn env_or_default<T: std::str::FromStr + Default>(key: &str) -> T {
+    std::env::var(key)
+        .ok()
+        .map(|value| {
+            value
+                .parse()
+                .unwrap_or_else(|_| panic!("{key} has an invalid value"))
+        })
+        .unwrap_or_default()
+}
+
 pub fn init_tracing() {
     use opentelemetry_otlp::WithExportConfig;
     use tracing_subscriber::prelude::*;